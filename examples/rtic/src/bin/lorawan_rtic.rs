@@ -0,0 +1,161 @@
+//! This example runs on the RAK4631 WisBlock, which has an nRF52840 MCU and Semtech Sx126x radio.
+//! It demonstrates driving the LoRaWAN device from RTIC 2 tasks: the SysTick monotonic serves as
+//! LoRaWAN timer, DIO1 is serviced by a GPIOTE hardware task and the SPI bus is arbitrated, so
+//! that further devices on the bus can be used from other tasks.
+#![no_std]
+#![no_main]
+
+use {defmt_rtt as _, panic_probe as _};
+
+// Load optional override values for EUIs and APPKEY generated by build.rs from
+// environment values
+include!(concat!(env!("OUT_DIR"), "/lorawan_keys.rs"));
+
+// Fallback values
+const DEFAULT_DEVEUI: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 0];
+const DEFAULT_APPEUI: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 0];
+const DEFAULT_APPKEY: [u8; 16] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+
+#[rtic::app(device = embassy_nrf::pac, dispatchers = [SWI0_EGU0])]
+mod app {
+    use super::*;
+    use defmt::*;
+    use embassy_nrf::gpio::{Input, Level, Output, OutputDrive, Pin as _, Pull};
+    use embassy_nrf::pac::gpiote::vals::{Mode, Polarity};
+    use embassy_nrf::peripherals::{RNG, TWISPI1};
+    use embassy_nrf::rng::Rng;
+    use embassy_nrf::{bind_interrupts, pac, rng, spim};
+    use lora_phy::iv::GenericSx126xInterfaceVariant;
+    use lora_phy::lorawan_radio::LorawanRadio;
+    use lora_phy::sx126x::{self, Sx1262, Sx126x, TcxoCtrlVoltage};
+    use lora_phy::LoRa;
+    use lora_rtic_examples::{IrqPin, IrqSignal, MonotonicTimer, SharedSpiDevice};
+    use lorawan_device::async_device::{region, Device, JoinMode, JoinResponse};
+    use lorawan_device::{AppEui, AppKey, DevEui};
+    use rtic_monotonics::systick::prelude::*;
+    use rtic_sync::arbiter::Arbiter;
+
+    // warning: set these appropriately for the region
+    const LORAWAN_REGION: region::Region = region::Region::EU868;
+    const MAX_TX_POWER: u8 = 14;
+
+    systick_monotonic!(Mono, 1_000);
+
+    bind_interrupts!(struct Irqs {
+        TWISPI1 => spim::InterruptHandler<TWISPI1>;
+        RNG => rng::InterruptHandler<RNG>;
+    });
+
+    /// GPIOTE channel of the DIO1 line (P1.15).
+    const DIO1_CHANNEL: usize = 0;
+
+    static RADIO_IRQ: IrqSignal = IrqSignal::new();
+
+    type Spi = spim::Spim<'static, TWISPI1>;
+    type RadioSpi = SharedSpiDevice<'static, Spi, Output<'static>, Mono>;
+    type RadioIv = GenericSx126xInterfaceVariant<Output<'static>, IrqPin<Input<'static>>>;
+    type Radio = LorawanRadio<Sx126x<RadioSpi, RadioIv, Sx1262>, Mono, MAX_TX_POWER>;
+    type LorawanDevice = Device<Radio, MonotonicTimer<Mono, 1_000>, Rng<'static, RNG>>;
+
+    #[shared]
+    struct Shared {}
+
+    #[local]
+    struct Local {
+        /// Moved into the device once the `lorawan` task starts.
+        radio_parts: Option<(RadioSpi, RadioIv, Rng<'static, RNG>)>,
+    }
+
+    #[init(local = [spi_bus: Option<Arbiter<Spi>> = None])]
+    fn init(cx: init::Context) -> (Shared, Local) {
+        let p = embassy_nrf::init(Default::default());
+        Mono::start(cx.core.SYST, 64_000_000);
+
+        let nss = Output::new(p.P1_10.degrade(), Level::High, OutputDrive::Standard);
+        let reset = Output::new(p.P1_06.degrade(), Level::High, OutputDrive::Standard);
+        let dio1 = Input::new(p.P1_15.degrade(), Pull::Down);
+        let busy = Input::new(p.P1_14.degrade(), Pull::None);
+        let rf_switch_rx = Output::new(p.P1_05.degrade(), Level::Low, OutputDrive::Standard);
+        let rf_switch_tx = Output::new(p.P1_07.degrade(), Level::Low, OutputDrive::Standard);
+
+        // Raise the GPIOTE interrupt on the rising edge of DIO1, serviced by `radio_irq`.
+        pac::GPIOTE.config(DIO1_CHANNEL).write(|w| {
+            w.set_mode(Mode::EVENT);
+            w.set_psel(15);
+            w.set_port(true);
+            w.set_polarity(Polarity::LO_TO_HI);
+        });
+        pac::GPIOTE.intenset().write(|w| w.set_in(DIO1_CHANNEL, true));
+
+        let mut spi_config = spim::Config::default();
+        spi_config.frequency = spim::Frequency::M16;
+        let spim = spim::Spim::new(p.TWISPI1, Irqs, p.P1_11, p.P1_13, p.P1_12, spi_config);
+        // Other devices on this bus get their own `SharedSpiDevice` with their chip select.
+        let spi_bus: &'static Arbiter<Spi> = cx.local.spi_bus.insert(Arbiter::new(spim));
+        let spi = SharedSpiDevice::new(spi_bus, nss, Mono);
+
+        let iv = GenericSx126xInterfaceVariant::new(
+            reset,
+            IrqPin::new(dio1, &RADIO_IRQ),
+            busy,
+            Some(rf_switch_rx),
+            Some(rf_switch_tx),
+        )
+        .unwrap();
+
+        lorawan::spawn().ok();
+
+        (Shared {}, Local { radio_parts: Some((spi, iv, Rng::new(p.RNG, Irqs))) })
+    }
+
+    /// Wakes the radio driver awaiting DIO1 in the `lorawan` task.
+    #[task(binds = GPIOTE, priority = 2)]
+    fn radio_irq(_: radio_irq::Context) {
+        pac::GPIOTE.events_in(DIO1_CHANNEL).write_value(0);
+        RADIO_IRQ.signal();
+    }
+
+    #[task(priority = 1, local = [radio_parts])]
+    async fn lorawan(cx: lorawan::Context) {
+        let (spi, iv, rng) = cx.local.radio_parts.take().unwrap();
+        let config = sx126x::Config {
+            chip: Sx1262,
+            tcxo_ctrl: Some(TcxoCtrlVoltage::Ctrl1V7),
+            use_dcdc: true,
+            rx_boost: false,
+        };
+        let lora = LoRa::new(Sx126x::new(spi, iv, config), true, Mono).await.unwrap();
+
+        let radio: Radio = lora.into();
+        let region: region::Configuration = region::Configuration::new(LORAWAN_REGION);
+        let mut device: LorawanDevice = Device::new(region, radio, MonotonicTimer::new(), rng);
+
+        let join_mode = JoinMode::OTAA {
+            deveui: DevEui::from(DEVEUI.unwrap_or(DEFAULT_DEVEUI)),
+            appeui: AppEui::from(APPEUI.unwrap_or(DEFAULT_APPEUI)),
+            appkey: AppKey::from(APPKEY.unwrap_or(DEFAULT_APPKEY)),
+        };
+
+        info!("Joining LoRaWAN network");
+        loop {
+            let join_result = device.join(&join_mode).await;
+            if let Ok(JoinResponse::JoinSuccess) = join_result {
+                break;
+            }
+            info!("Join failed: {:?}. Retrying in 30 seconds..", join_result);
+            Mono::delay(30.secs()).await;
+        }
+        info!("LoRaWAN network joined!");
+
+        loop {
+            match device.send(b"PING", 1, false).await {
+                Ok(response) => info!("Uplink sent: {:?}", response),
+                Err(e) => warn!("Uplink failed: {:?}", e),
+            }
+            while let Some(downlink) = device.take_downlink() {
+                info!("Downlink on port {}: {:?}", downlink.fport, downlink.data.as_slice());
+            }
+            Mono::delay(60.secs()).await;
+        }
+    }
+}
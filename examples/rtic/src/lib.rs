@@ -0,0 +1,192 @@
+//! Glue for driving the async [`lorawan_device::async_device::Device`] from RTIC 2 tasks:
+//!
+//! - [`IrqSignal`] and [`IrqPin`] wake the radio driver from an RTIC hardware task bound to the
+//!   interrupt of the DIO1 (sx126x) or DIO0 (sx127x) line, for HALs whose GPIOs don't implement
+//!   [`embedded_hal_async::digital::Wait`] or when the GPIO interrupt is owned by the application.
+//! - [`MonotonicTimer`] implements [`lorawan_device::async_device::radio::Timer`] on top of an RTIC
+//!   monotonic, so that no other time driver is needed.
+//! - [`SharedSpiDevice`] shares the SPI bus of the radio with other RTIC tasks. The shared devices
+//!   of `embedded-hal-bus` are blocking only, while `lora-phy` needs an async
+//!   [`embedded_hal_async::spi::SpiDevice`], so the bus is arbitrated by `rtic-sync` instead.
+#![no_std]
+
+use core::future::poll_fn;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Poll;
+
+use embedded_hal::digital::{ErrorType, InputPin};
+use embedded_hal_async::digital::Wait;
+use lorawan_device::async_device::radio::Timer;
+use rtic_common::waker_registration::CriticalSectionWakerRegistration;
+use rtic_time::Monotonic;
+
+/// SPI device of the radio on a bus shared with other tasks.
+pub type SharedSpiDevice<'a, BUS, CS, DELAY> = rtic_sync::arbiter::spi::ArbiterDevice<'a, BUS, CS, DELAY>;
+
+/// Radio interrupt flag, set from the RTIC hardware task of the IRQ line and awaited by
+/// [`IrqPin`]. Meant to be placed in a `static`.
+pub struct IrqSignal {
+    pending: AtomicBool,
+    waker: CriticalSectionWakerRegistration,
+}
+
+impl IrqSignal {
+    pub const fn new() -> Self {
+        Self {
+            pending: AtomicBool::new(false),
+            waker: CriticalSectionWakerRegistration::new(),
+        }
+    }
+
+    /// Flag the interrupt and wake the task waiting for it. Call this from the hardware task after
+    /// clearing the interrupt of the GPIO peripheral.
+    pub fn signal(&self) {
+        self.pending.store(true, Ordering::Release);
+        self.waker.wake();
+    }
+
+    fn take(&self) -> bool {
+        self.pending.swap(false, Ordering::AcqRel)
+    }
+}
+
+impl Default for IrqSignal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// IRQ line of the radio implementing [`Wait`] for the `InterfaceVariant`s of `lora-phy`, such as
+/// [`lora_phy::iv::GenericSx126xInterfaceVariant`]. Only the rising edge is supported, which is
+/// what the interface variants wait for.
+///
+/// The level of the pin is checked as well, so that an interrupt which fired before waiting (or a
+/// line which is still high) isn't missed.
+pub struct IrqPin<P> {
+    pin: P,
+    signal: &'static IrqSignal,
+}
+
+impl<P: InputPin> IrqPin<P> {
+    pub fn new(pin: P, signal: &'static IrqSignal) -> Self {
+        Self { pin, signal }
+    }
+}
+
+impl<P: InputPin> ErrorType for IrqPin<P> {
+    type Error = P::Error;
+}
+
+impl<P: InputPin> Wait for IrqPin<P> {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        poll_fn(|cx| {
+            self.signal.waker.register(cx.waker());
+            let pending = self.signal.take();
+            match self.pin.is_high() {
+                Ok(high) if high || pending => Poll::Ready(Ok(())),
+                Ok(_) => Poll::Pending,
+                Err(e) => Poll::Ready(Err(e)),
+            }
+        })
+        .await
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        // Not needed by the interface variants, the line goes low once the radio IRQ is cleared.
+        while self.pin.is_high()? {
+            self.wait_for_any_edge().await?;
+        }
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_high().await
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        self.wait_for_low().await
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        poll_fn(|cx| {
+            self.signal.waker.register(cx.waker());
+            if self.signal.take() {
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+/// [`Timer`] backed by an RTIC monotonic with a `u32` tick counter at `HZ` ticks per second, such
+/// as the monotonics of `rtic-monotonics` (eg: `systick_monotonic!(Mono, 1_000)`).
+///
+/// The tick counter wraps (after about 49 days at 1 kHz), so delays are split into steps of at
+/// most half its range, and [`Timer::elapsed_ms`] is only known up to half the range after a reset.
+pub struct MonotonicTimer<M: Monotonic, const HZ: u32> {
+    start: M::Instant,
+}
+
+/// Longest step of a delay, so that the monotonic can tell the wrapped tick counter apart from
+/// an instant in the past.
+const MAX_DELAY_TICKS: u32 = u32::MAX / 2;
+
+impl<M, const HZ: u32> MonotonicTimer<M, HZ>
+where
+    M: Monotonic<Instant = fugit::TimerInstantU32<HZ>, Duration = fugit::TimerDurationU32<HZ>>,
+{
+    /// The monotonic has to be started before creating the timer.
+    pub fn new() -> Self {
+        Self { start: M::now() }
+    }
+
+    /// Ticks of `millis` milliseconds, rounded up.
+    fn ticks(millis: u64) -> u64 {
+        millis.saturating_mul(HZ as u64).div_ceil(1000)
+    }
+
+    async fn delay_ticks(mut ticks: u64) {
+        while ticks > 0 {
+            let step = ticks.min(MAX_DELAY_TICKS as u64) as u32;
+            M::delay(fugit::TimerDurationU32::<HZ>::from_ticks(step)).await;
+            ticks -= step as u64;
+        }
+    }
+}
+
+impl<M, const HZ: u32> Default for MonotonicTimer<M, HZ>
+where
+    M: Monotonic<Instant = fugit::TimerInstantU32<HZ>, Duration = fugit::TimerDurationU32<HZ>>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M, const HZ: u32> Timer for MonotonicTimer<M, HZ>
+where
+    M: Monotonic<Instant = fugit::TimerInstantU32<HZ>, Duration = fugit::TimerDurationU32<HZ>>,
+{
+    fn reset(&mut self) {
+        self.start = M::now();
+    }
+
+    async fn at(&mut self, millis: u64) {
+        let elapsed = M::now()
+            .checked_duration_since(self.start)
+            .map_or(0, |elapsed| elapsed.ticks());
+        Self::delay_ticks(Self::ticks(millis).saturating_sub(elapsed as u64)).await;
+    }
+
+    async fn delay_ms(&mut self, millis: u64) {
+        Self::delay_ticks(Self::ticks(millis)).await;
+    }
+
+    fn elapsed_ms(&self) -> Option<u64> {
+        M::now()
+            .checked_duration_since(self.start)
+            .map(|elapsed| elapsed.to_millis() as u64)
+    }
+}
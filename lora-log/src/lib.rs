@@ -0,0 +1,226 @@
+#![no_std]
+#![deny(rust_2018_idioms)]
+#![warn(missing_docs)]
+#![doc = include_str!("../README.md")]
+
+/// Level of a log message, or of a filter entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Detailed tracing, eg: of every register access
+    Trace,
+    /// Information useful for debugging
+    Debug,
+    /// Noteworthy events
+    Info,
+    /// Unexpected but recoverable conditions
+    Warn,
+    /// Failures
+    Error,
+    /// Filter level which disables all messages
+    Off,
+}
+
+/// Whether a message of `level` logged in `module_path` passes the `filter` (see the crate
+/// documentation). Evaluated at compile time by the logging macros.
+pub const fn enabled(filter: Option<&str>, module_path: &str, level: Level) -> bool {
+    match filter {
+        Some(filter) => level as u8 >= min_level(filter.as_bytes(), module_path.as_bytes()) as u8,
+        None => true,
+    }
+}
+
+/// The level of the most specific filter entry for the module.
+const fn min_level(filter: &[u8], module: &[u8]) -> Level {
+    let mut level = Level::Trace;
+    // length of the module path of the entry applied so far, -1 if none
+    let mut specificity: isize = -1;
+    let mut start = 0;
+    while start <= filter.len() {
+        let mut end = start;
+        while end < filter.len() && filter[end] != b',' {
+            end += 1;
+        }
+        let (s, e) = trim(filter, start, end);
+        let mut eq = s;
+        while eq < e && filter[eq] != b'=' {
+            eq += 1;
+        }
+        if s == e {
+            // empty entry
+        } else if eq == e {
+            if specificity <= 0 {
+                level = parse_level(filter, s, e);
+                specificity = 0;
+            }
+        } else {
+            let (path_start, path_end) = trim(filter, s, eq);
+            let (level_start, level_end) = trim(filter, eq + 1, e);
+            let len = (path_end - path_start) as isize;
+            if len >= specificity && contains(filter, path_start, path_end, module) {
+                level = parse_level(filter, level_start, level_end);
+                specificity = len;
+            }
+        }
+        start = end + 1;
+    }
+    level
+}
+
+/// `filter[start..end]` without leading and trailing spaces
+const fn trim(filter: &[u8], mut start: usize, mut end: usize) -> (usize, usize) {
+    while start < end && filter[start] == b' ' {
+        start += 1;
+    }
+    while end > start && filter[end - 1] == b' ' {
+        end -= 1;
+    }
+    (start, end)
+}
+
+/// Whether `filter[start..end]` is `expected`
+const fn equals(filter: &[u8], start: usize, end: usize, expected: &[u8]) -> bool {
+    if end - start != expected.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < expected.len() {
+        if filter[start + i] != expected[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Whether the module path `filter[start..end]` is `module` or one of its parents
+const fn contains(filter: &[u8], start: usize, end: usize, module: &[u8]) -> bool {
+    let len = end - start;
+    if module.len() < len {
+        return false;
+    }
+    let mut i = 0;
+    while i < len {
+        if filter[start + i] != module[i] {
+            return false;
+        }
+        i += 1;
+    }
+    module.len() == len
+        || (module.len() > len + 1 && module[len] == b':' && module[len + 1] == b':')
+}
+
+const fn parse_level(filter: &[u8], start: usize, end: usize) -> Level {
+    if equals(filter, start, end, b"trace") {
+        Level::Trace
+    } else if equals(filter, start, end, b"debug") {
+        Level::Debug
+    } else if equals(filter, start, end, b"info") {
+        Level::Info
+    } else if equals(filter, start, end, b"warn") {
+        Level::Warn
+    } else if equals(filter, start, end, b"error") {
+        Level::Error
+    } else if equals(filter, start, end, b"off") {
+        Level::Off
+    } else {
+        panic!("invalid level in LORA_LOG")
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+#[collapse_debuginfo(yes)]
+macro_rules! __log {
+    ($defmt:ident, $level:ident, $s:literal $(, $x:expr)* $(,)?) => {
+        {
+            #[cfg(feature = "defmt-03")]
+            {
+                const ENABLED: bool = $crate::enabled(
+                    ::core::option_env!("LORA_LOG"),
+                    ::core::module_path!(),
+                    $crate::Level::$level,
+                );
+                if ENABLED {
+                    ::defmt::$defmt!($s $(, $x)*);
+                }
+            }
+            #[cfg(not(feature = "defmt-03"))]
+            let _ = ($( & $x ),*);
+        }
+    };
+}
+
+/// Log a message at [`Level::Trace`]
+#[macro_export]
+#[collapse_debuginfo(yes)]
+macro_rules! trace {
+    ($($args:tt)*) => {
+        $crate::__log!(trace, Trace, $($args)*)
+    };
+}
+
+/// Log a message at [`Level::Debug`]
+#[macro_export]
+#[collapse_debuginfo(yes)]
+macro_rules! debug {
+    ($($args:tt)*) => {
+        $crate::__log!(debug, Debug, $($args)*)
+    };
+}
+
+/// Log a message at [`Level::Info`]
+#[macro_export]
+#[collapse_debuginfo(yes)]
+macro_rules! info {
+    ($($args:tt)*) => {
+        $crate::__log!(info, Info, $($args)*)
+    };
+}
+
+/// Log a message at [`Level::Warn`]
+#[macro_export]
+#[collapse_debuginfo(yes)]
+macro_rules! warn {
+    ($($args:tt)*) => {
+        $crate::__log!(warn, Warn, $($args)*)
+    };
+}
+
+/// Log a message at [`Level::Error`]
+#[macro_export]
+#[collapse_debuginfo(yes)]
+macro_rules! error {
+    ($($args:tt)*) => {
+        $crate::__log!(error, Error, $($args)*)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILTER: Option<&str> =
+        Some("info, lora_phy = off,lora_phy::sx126x=debug,lorawan_device::mac=trace");
+
+    #[test]
+    fn unfiltered() {
+        assert!(enabled(None, "lora_phy::sx126x", Level::Trace));
+        assert!(enabled(Some(""), "lora_phy::sx126x", Level::Trace));
+    }
+
+    #[test]
+    fn most_specific_entry() {
+        assert!(!enabled(FILTER, "lora_phy", Level::Error));
+        assert!(!enabled(FILTER, "lora_phy::sx127x", Level::Error));
+        assert!(!enabled(FILTER, "lora_phy::sx126x", Level::Trace));
+        assert!(enabled(FILTER, "lora_phy::sx126x::radio_kind_params", Level::Debug));
+        assert!(enabled(FILTER, "lorawan_device::mac::session", Level::Trace));
+        // the default applies to other modules, including those which share a prefix
+        assert!(!enabled(FILTER, "lorawan_device::macro", Level::Debug));
+        assert!(enabled(FILTER, "lorawan_device::async_device", Level::Info));
+        assert!(!enabled(FILTER, "lora_phyx", Level::Debug));
+    }
+
+    // evaluated at compile time, like in the logging macros
+    const _: () = assert!(!enabled(Some("warn"), "lora_log", Level::Info));
+}
@@ -129,6 +129,11 @@ impl BaseBandModulationParams {
         Self { sf, bw, cr, ldro, t_sym_us }
     }
 
+    /// Duration of a symbol in microseconds.
+    pub const fn symbol_duration_us(&self) -> u32 {
+        self.t_sym_us
+    }
+
     pub const fn delay_in_symbols(&self, delay_in_ms: u32) -> u16 {
         (delay_in_ms * 1000 / self.t_sym_us) as u16
     }
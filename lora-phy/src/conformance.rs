@@ -0,0 +1,336 @@
+//! Checks of the parameters a [`RadioKind`] implementation creates against the
+//! [`Capabilities`] it reports, as a conformance suite for radio drivers maintained outside of
+//! this crate (eg: SDR frontends or proprietary chips).
+//!
+//! A driver implements [`RadioKind`], creating its parameters with [`ModulationParams::new`] and
+//! [`PacketParams::new`] and reading them back with their accessors when configuring the chip,
+//! and reports [`Chip::Other`] as its chip. Wrapped in [`LoRa`](crate::LoRa) it is then usable
+//! like the built-in drivers, including as the radio of `lorawan-device` through
+//! `LorawanRadio` (with the `lorawan-radio` feature).
+//!
+//! [`run`] reads the capabilities from the chip and checks that:
+//! - the capabilities are consistent,
+//! - modulation parameters are created for every supported combination of spreading factor,
+//!   bandwidth and coding rate, with the low data rate optimization both ends of a link expect,
+//! - unsupported combinations are rejected,
+//! - packet parameters are created as requested, with the preamble only ever lengthened.
+//!
+//! [`check_params`] runs the checks which don't access the chip.
+use crate::mod_params::*;
+use crate::mod_traits::RadioKind;
+
+/// A check which did not give the expected result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Failure {
+    /// Name of the check, one of [`CHECKS`].
+    pub check: &'static str,
+}
+
+/// Names of the checks, in the order run by [`run`].
+pub const CHECKS: [&str; 5] = [
+    "capabilities",
+    "modulation_params",
+    "unsupported_modulation",
+    "packet_params",
+    "preamble_length",
+];
+
+const SPREADING_FACTORS: [SpreadingFactor; 8] = [
+    SpreadingFactor::_5,
+    SpreadingFactor::_6,
+    SpreadingFactor::_7,
+    SpreadingFactor::_8,
+    SpreadingFactor::_9,
+    SpreadingFactor::_10,
+    SpreadingFactor::_11,
+    SpreadingFactor::_12,
+];
+
+const CODING_RATES: [CodingRate; 4] = [CodingRate::_4_5, CodingRate::_4_6, CodingRate::_4_7, CodingRate::_4_8];
+
+/// Frequency used for the checks, if supported by the chip
+const FREQUENCY_IN_HZ: u32 = 868_100_000;
+
+/// Read the capabilities from the chip and run all checks, stopping at the first failure.
+pub async fn run<RK: RadioKind>(radio_kind: &mut RK) -> Result<Capabilities, Failure> {
+    let capabilities = radio_kind.capabilities().await.map_err(|_| fail("capabilities"))?;
+    check_params(radio_kind, &capabilities)?;
+    Ok(capabilities)
+}
+
+/// Run the checks which don't access the chip, stopping at the first failure.
+pub fn check_params<RK: RadioKind>(radio_kind: &RK, capabilities: &Capabilities) -> Result<(), Failure> {
+    check_capabilities(capabilities)?;
+    modulation_params(radio_kind, capabilities)?;
+    unsupported_modulation(radio_kind, capabilities)?;
+    packet_params(radio_kind, capabilities)?;
+    preamble_length(radio_kind, capabilities)
+}
+
+fn fail(check: &'static str) -> Failure {
+    Failure { check }
+}
+
+fn check_capabilities(capabilities: &Capabilities) -> Result<(), Failure> {
+    if capabilities.min_frequency_in_hz <= capabilities.max_frequency_in_hz
+        && capabilities.min_spreading_factor.factor() <= 12
+        && !capabilities.bandwidths.is_empty()
+    {
+        Ok(())
+    } else {
+        Err(fail("capabilities"))
+    }
+}
+
+/// Frequencies to check: a common one within the range of the chip and the lowest one, where
+/// wide bandwidths may not be available.
+fn frequencies(capabilities: &Capabilities) -> [u32; 2] {
+    [
+        FREQUENCY_IN_HZ.clamp(capabilities.min_frequency_in_hz, capabilities.max_frequency_in_hz),
+        capabilities.min_frequency_in_hz,
+    ]
+}
+
+fn modulation_params<RK: RadioKind>(radio_kind: &RK, capabilities: &Capabilities) -> Result<(), Failure> {
+    for frequency_in_hz in frequencies(capabilities) {
+        for spreading_factor in SPREADING_FACTORS {
+            for &bandwidth in capabilities.bandwidths {
+                if !capabilities.supports(spreading_factor, bandwidth, frequency_in_hz) {
+                    continue;
+                }
+                for coding_rate in CODING_RATES {
+                    let params = radio_kind
+                        .create_modulation_params(spreading_factor, bandwidth, coding_rate, frequency_in_hz)
+                        .map_err(|_| fail("modulation_params"))?;
+                    let expected = ModulationParams::new(spreading_factor, bandwidth, coding_rate, frequency_in_hz);
+                    if params.spreading_factor() != spreading_factor
+                        || params.bandwidth() != bandwidth
+                        || params.coding_rate() != coding_rate
+                        || params.frequency_in_hz() != frequency_in_hz
+                        || params.low_data_rate_optimize() != expected.low_data_rate_optimize()
+                    {
+                        return Err(fail("modulation_params"));
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn unsupported_modulation<RK: RadioKind>(radio_kind: &RK, capabilities: &Capabilities) -> Result<(), Failure> {
+    for frequency_in_hz in frequencies(capabilities) {
+        for spreading_factor in SPREADING_FACTORS {
+            for &bandwidth in ALL_BANDWIDTHS {
+                if !capabilities.supports(spreading_factor, bandwidth, frequency_in_hz)
+                    && radio_kind
+                        .create_modulation_params(spreading_factor, bandwidth, CodingRate::_4_5, frequency_in_hz)
+                        .is_ok()
+                {
+                    return Err(fail("unsupported_modulation"));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Modulation parameters at SF7 (or the lowest supported spreading factor above) and the first
+/// supported bandwidth, which all chips support in explicit header mode.
+fn common_modulation_params<RK: RadioKind>(
+    radio_kind: &RK,
+    capabilities: &Capabilities,
+    check: &'static str,
+) -> Result<ModulationParams, Failure> {
+    let spreading_factor = if capabilities.min_spreading_factor.factor() > 7 {
+        capabilities.min_spreading_factor
+    } else {
+        SpreadingFactor::_7
+    };
+    let frequency_in_hz = frequencies(capabilities)[0];
+    let bandwidth = *capabilities
+        .bandwidths
+        .iter()
+        .find(|&&bandwidth| capabilities.supports(spreading_factor, bandwidth, frequency_in_hz))
+        .ok_or(fail(check))?;
+    radio_kind
+        .create_modulation_params(spreading_factor, bandwidth, CodingRate::_4_5, frequency_in_hz)
+        .map_err(|_| fail(check))
+}
+
+fn packet_params<RK: RadioKind>(radio_kind: &RK, capabilities: &Capabilities) -> Result<(), Failure> {
+    let modulation_params = common_modulation_params(radio_kind, capabilities, "packet_params")?;
+    for (implicit_header, payload_length, crc_on, iq_inverted) in [
+        (false, 255, true, false),
+        (false, 0, false, true),
+        (true, 17, false, false),
+    ] {
+        let params = radio_kind
+            .create_packet_params(
+                8,
+                implicit_header,
+                payload_length,
+                crc_on,
+                iq_inverted,
+                &modulation_params,
+            )
+            .map_err(|_| fail("packet_params"))?;
+        if params.implicit_header() != implicit_header
+            || params.payload_length() != payload_length
+            || params.crc_on() != crc_on
+            || params.iq_inverted() != iq_inverted
+        {
+            return Err(fail("packet_params"));
+        }
+    }
+    Ok(())
+}
+
+fn preamble_length<RK: RadioKind>(radio_kind: &RK, capabilities: &Capabilities) -> Result<(), Failure> {
+    let modulation_params = common_modulation_params(radio_kind, capabilities, "preamble_length")?;
+    for preamble_length in [radio_kind.min_preamble_length(), 8, u16::MAX] {
+        let params = radio_kind
+            .create_packet_params(preamble_length, false, 255, true, false, &modulation_params)
+            .map_err(|_| fail("preamble_length"))?;
+        if params.preamble_length() < preamble_length {
+            return Err(fail("preamble_length"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use core::convert::Infallible;
+
+    use embedded_hal_async::delay::DelayNs;
+    use embedded_hal_async::spi::{ErrorType, Operation, SpiDevice};
+
+    use super::*;
+    use crate::mod_traits::InterfaceVariant;
+    use crate::sx126x::{self, Sx1262, Sx126x};
+    use crate::sx127x::{self, Sx1276, Sx127x};
+
+    struct NoSpi;
+
+    impl ErrorType for NoSpi {
+        type Error = Infallible;
+    }
+
+    impl SpiDevice for NoSpi {
+        async fn transaction(&mut self, _operations: &mut [Operation<'_, u8>]) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    struct NoIv;
+
+    impl InterfaceVariant for NoIv {
+        async fn reset(&mut self, _delay: &mut impl DelayNs) -> Result<(), RadioError> {
+            Ok(())
+        }
+        async fn wait_on_busy(&mut self) -> Result<(), RadioError> {
+            Ok(())
+        }
+        async fn await_irq(&mut self) -> Result<(), RadioError> {
+            Ok(())
+        }
+        async fn enable_rf_switch_rx(&mut self) -> Result<(), RadioError> {
+            Ok(())
+        }
+        async fn enable_rf_switch_tx(&mut self) -> Result<(), RadioError> {
+            Ok(())
+        }
+        async fn disable_rf_switch(&mut self) -> Result<(), RadioError> {
+            Ok(())
+        }
+    }
+
+    fn sx1276() -> Sx127x<NoSpi, NoIv, Sx1276> {
+        let config = sx127x::Config {
+            chip: Sx1276,
+            tcxo_used: false,
+            tx_boost: false,
+            rx_boost: false,
+        };
+        Sx127x::new(NoSpi, NoIv, config)
+    }
+
+    const SX1276_CAPABILITIES: Capabilities = Capabilities {
+        chip: Chip::Sx1276,
+        version: Some(0x12),
+        min_frequency_in_hz: 137_000_000,
+        max_frequency_in_hz: 1_020_000_000,
+        min_spreading_factor: SpreadingFactor::_6,
+        bandwidths: ALL_BANDWIDTHS,
+        max_output_power: 20,
+        fsk: true,
+        lr_fhss: false,
+    };
+
+    #[test]
+    fn builtin_drivers_conform() {
+        assert_eq!(check_params(&sx1276(), &SX1276_CAPABILITIES), Ok(()));
+
+        let config = sx126x::Config {
+            chip: Sx1262,
+            tcxo_ctrl: None,
+            use_dcdc: false,
+            rx_boost: false,
+        };
+        let sx1262 = Sx126x::new(NoSpi, NoIv, config);
+        let capabilities = Capabilities {
+            chip: Chip::Sx1262,
+            version: None,
+            min_frequency_in_hz: 150_000_000,
+            max_frequency_in_hz: 960_000_000,
+            min_spreading_factor: SpreadingFactor::_5,
+            bandwidths: ALL_BANDWIDTHS,
+            max_output_power: 22,
+            fsk: true,
+            lr_fhss: true,
+        };
+        assert_eq!(check_params(&sx1262, &capabilities), Ok(()));
+    }
+
+    #[test]
+    fn mismatched_capabilities() {
+        let capabilities = SX1276_CAPABILITIES;
+        let sx1276 = sx1276();
+
+        let wrong = Capabilities {
+            min_frequency_in_hz: 1_020_000_000,
+            max_frequency_in_hz: 137_000_000,
+            ..capabilities
+        };
+        assert_eq!(check_params(&sx1276, &wrong), Err(fail("capabilities")));
+
+        let wrong = Capabilities {
+            min_spreading_factor: SpreadingFactor::_5,
+            ..capabilities
+        };
+        assert_eq!(check_params(&sx1276, &wrong), Err(fail("modulation_params")));
+
+        let wrong = Capabilities {
+            bandwidths: &[Bandwidth::_125KHz],
+            ..capabilities
+        };
+        assert_eq!(check_params(&sx1276, &wrong), Err(fail("unsupported_modulation")));
+    }
+
+    #[test]
+    fn params_accessors() {
+        let params = ModulationParams::new(SpreadingFactor::_12, Bandwidth::_125KHz, CodingRate::_4_5, 868_100_000);
+        assert_eq!(params.spreading_factor(), SpreadingFactor::_12);
+        assert_eq!(params.bandwidth(), Bandwidth::_125KHz);
+        assert_eq!(params.coding_rate(), CodingRate::_4_5);
+        assert_eq!(params.frequency_in_hz(), 868_100_000);
+        assert!(params.low_data_rate_optimize());
+
+        let params = PacketParams::new(8, true, 17, false, true);
+        assert_eq!(params.preamble_length(), 8);
+        assert_eq!(params.implicit_payload_length(), Some(17));
+        assert!(!params.crc_on() && params.iq_inverted());
+    }
+}
@@ -0,0 +1,128 @@
+//! Carrier sense multiple access (CSMA) for peer-to-peer use of LoRa, where several nodes share a
+//! channel without a gateway scheduling them. Before transmitting, channel activity detection (CAD)
+//! is run. If the channel is busy, or the node decides not to transmit on a clear channel (see
+//! [`CsmaParams::persistence`]), it backs off for a random number of slots and tries again.
+//!
+//! This crate has no random number generator, so random values are provided by the caller.
+
+use super::*;
+
+/// Configuration of the carrier sense before a transmission
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct CsmaParams {
+    /// Number of channel activity detections before giving up.
+    pub max_attempts: u8,
+    /// Probability (out of 256) of transmitting when the channel is clear. 255 transmits
+    /// immediately on a clear channel (1-persistent CSMA); lower values spread the transmissions of
+    /// nodes which have been waiting for the same busy channel.
+    pub persistence: u8,
+    /// Duration of one backoff slot, typically the airtime of a short packet.
+    pub backoff_slot_ms: u32,
+    /// The backoff is a random number of slots between 1 and this value.
+    pub max_backoff_slots: u16,
+}
+
+impl Default for CsmaParams {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            persistence: 255,
+            backoff_slot_ms: 50,
+            max_backoff_slots: 8,
+        }
+    }
+}
+
+impl CsmaParams {
+    fn transmit_on_clear_channel(&self, random: u32) -> bool {
+        self.persistence == u8::MAX || ((random & 0xff) as u8) < self.persistence
+    }
+
+    fn backoff_ms(&self, random: u32) -> u32 {
+        let slots = random % self.max_backoff_slots.max(1) as u32 + 1;
+        slots * self.backoff_slot_ms
+    }
+}
+
+impl<RK, DLY> LoRa<RK, DLY>
+where
+    RK: RadioKind,
+    DLY: DelayNs,
+{
+    /// Wait until the channel described by `mdltn_params` may be used, as configured by `params`.
+    /// Returns `false` if the channel was not available within `params.max_attempts` channel
+    /// activity detections. `random` is called for every decision and backoff.
+    ///
+    /// On success, the transmission should be prepared and started without further delay.
+    ///
+    /// # Warning
+    /// This function is not safe to drop or cancel, as it calls `cad`, which must run to completion to avoid radio lockups.
+    /// Do not call this function within a select branch or in any context where it may be prematurely canceled.
+    pub async fn csma(
+        &mut self,
+        mdltn_params: &ModulationParams,
+        params: &CsmaParams,
+        random: &mut impl FnMut() -> u32,
+    ) -> Result<bool, RadioError> {
+        for _ in 0..params.max_attempts {
+            self.prepare_for_cad(mdltn_params).await?;
+            let busy = self.cad(mdltn_params).await?;
+            if !busy && params.transmit_on_clear_channel(random()) {
+                return Ok(true);
+            }
+            let backoff_ms = params.backoff_ms(random());
+            trace!(
+                "CSMA: channel {}, backing off {} ms",
+                if busy { "busy" } else { "clear" },
+                backoff_ms
+            );
+            self.delay.delay_ms(backoff_ms).await;
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persistence() {
+        let params = CsmaParams::default();
+        assert!(params.transmit_on_clear_channel(0xff));
+
+        let params = CsmaParams {
+            persistence: 64,
+            ..Default::default()
+        };
+        assert!(params.transmit_on_clear_channel(63));
+        assert!(!params.transmit_on_clear_channel(64));
+        // only the lowest byte is used
+        assert!(params.transmit_on_clear_channel(0x1_00));
+
+        let params = CsmaParams {
+            persistence: 0,
+            ..Default::default()
+        };
+        assert!(!params.transmit_on_clear_channel(0));
+    }
+
+    #[test]
+    fn backoff() {
+        let params = CsmaParams {
+            backoff_slot_ms: 10,
+            max_backoff_slots: 4,
+            ..Default::default()
+        };
+        assert_eq!(params.backoff_ms(0), 10);
+        assert_eq!(params.backoff_ms(3), 40);
+        assert_eq!(params.backoff_ms(4), 10);
+
+        let params = CsmaParams {
+            max_backoff_slots: 0,
+            ..params
+        };
+        assert_eq!(params.backoff_ms(7), 10);
+    }
+}
@@ -0,0 +1,215 @@
+//! Experimental constrained flooding for mesh experiments on top of peer-to-peer LoRa. Every node
+//! rebroadcasts the frames it has not seen before after a random delay, until their hop limit (TTL)
+//! is used up. Frames are identified by their origin and sequence number, which are kept in a
+//! small cache to drop the copies received from other relays.
+//!
+//! Every frame starts with a header of [`HEADER_LEN`] bytes: the origin node and the sequence
+//! number (both `u16`, little endian) and the TTL, followed by the payload.
+//!
+//! This crate has no random number generator, so random values are provided by the caller.
+
+use super::*;
+
+/// Length of the header preceding the payload of every frame
+pub const HEADER_LEN: usize = 5;
+
+/// Configuration of the flooding relay
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct FloodingParams {
+    /// Number of transmissions of a frame originated by this node, including the original one.
+    pub ttl: u8,
+    /// Frames are forwarded after a random delay between 0 and this value, so that the relays
+    /// which received the same frame do not transmit at the same time.
+    pub max_forward_delay_ms: u32,
+}
+
+impl Default for FloodingParams {
+    fn default() -> Self {
+        Self {
+            ttl: 3,
+            max_forward_delay_ms: 500,
+        }
+    }
+}
+
+/// A frame received for the first time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct FloodFrame<'a> {
+    /// Node which originated the frame
+    pub origin: u16,
+    /// Sequence number assigned by the origin
+    pub sequence: u16,
+    /// Remaining transmissions, including the forwarding by this node
+    pub ttl: u8,
+    /// Payload of the frame, without the header
+    pub payload: &'a [u8],
+    /// Delay after which the frame should be forwarded, if its TTL allows it
+    pub forward_delay_ms: Option<u32>,
+}
+
+/// Flooding state of a node, with a duplicate cache of `C` frames
+pub struct Flooding<const C: usize = 16> {
+    node: u16,
+    params: FloodingParams,
+    sequence: u16,
+    seen: [Option<(u16, u16)>; C],
+    next_seen: usize,
+}
+
+impl<const C: usize> Flooding<C> {
+    /// Create the flooding state of the node with the given (network-wide unique) identifier
+    pub fn new(node: u16, params: FloodingParams) -> Self {
+        Self {
+            node,
+            params,
+            sequence: 0,
+            seen: [None; C],
+            next_seen: 0,
+        }
+    }
+
+    /// Identifier of this node
+    pub fn node(&self) -> u16 {
+        self.node
+    }
+
+    /// Header of the next frame originated by this node. The frame is recorded as seen, so that
+    /// copies forwarded back by other relays are dropped.
+    pub fn next_header(&mut self) -> [u8; HEADER_LEN] {
+        let sequence = self.sequence;
+        self.sequence = self.sequence.wrapping_add(1);
+        self.remember(self.node, sequence);
+        let [origin_lo, origin_hi] = self.node.to_le_bytes();
+        let [sequence_lo, sequence_hi] = sequence.to_le_bytes();
+        [origin_lo, origin_hi, sequence_lo, sequence_hi, self.params.ttl]
+    }
+
+    /// Handle a received frame, returning it if it has not been seen before. If it should be
+    /// forwarded, its TTL is decremented in `frame`, which is then ready to be transmitted again
+    /// after [`FloodFrame::forward_delay_ms`]. `random` determines the forwarding delay.
+    pub fn handle_rx<'a>(&mut self, frame: &'a mut [u8], random: u32) -> Option<FloodFrame<'a>> {
+        if frame.len() < HEADER_LEN {
+            return None;
+        }
+        let origin = u16::from_le_bytes([frame[0], frame[1]]);
+        let sequence = u16::from_le_bytes([frame[2], frame[3]]);
+        let ttl = frame[4];
+        if origin == self.node || self.seen.contains(&Some((origin, sequence))) {
+            trace!("Flooding: dropping duplicate {} of {}", sequence, origin);
+            return None;
+        }
+        self.remember(origin, sequence);
+        let forward_delay_ms = (ttl > 1).then(|| {
+            frame[4] = ttl - 1;
+            random % self.params.max_forward_delay_ms.saturating_add(1)
+        });
+        Some(FloodFrame {
+            origin,
+            sequence,
+            ttl,
+            payload: &frame[HEADER_LEN..],
+            forward_delay_ms,
+        })
+    }
+
+    fn remember(&mut self, origin: u16, sequence: u16) {
+        if let Some(slot) = self.seen.get_mut(self.next_seen) {
+            *slot = Some((origin, sequence));
+            self.next_seen = (self.next_seen + 1) % C;
+        }
+    }
+}
+
+impl<RK, DLY> LoRa<RK, DLY>
+where
+    RK: RadioKind,
+    DLY: DelayNs,
+{
+    /// Originate a flooded frame carrying `payload`.
+    ///
+    /// # Warning
+    /// This function is not safe to drop or cancel, as it calls `tx`, which must run to completion to avoid radio lockups.
+    /// Do not call this function within a select branch or in any context where it may be prematurely canceled.
+    pub async fn flood_send<const C: usize>(
+        &mut self,
+        flooding: &mut Flooding<C>,
+        mdltn_params: &ModulationParams,
+        tx_pkt_params: &mut PacketParams,
+        output_power: i32,
+        payload: &[u8],
+    ) -> Result<(), RadioError> {
+        let header = flooding.next_header();
+        self.prepare_for_tx_parts(mdltn_params, tx_pkt_params, output_power, &[&header, payload])
+            .await?;
+        self.tx().await
+    }
+
+    /// Forward a frame returned by [`Flooding::handle_rx`] after waiting for its forwarding delay.
+    ///
+    /// # Warning
+    /// This function is not safe to drop or cancel, as it calls `tx`, which must run to completion to avoid radio lockups.
+    /// Do not call this function within a select branch or in any context where it may be prematurely canceled.
+    pub async fn flood_forward(
+        &mut self,
+        frame: &[u8],
+        forward_delay_ms: u32,
+        mdltn_params: &ModulationParams,
+        tx_pkt_params: &mut PacketParams,
+        output_power: i32,
+    ) -> Result<(), RadioError> {
+        self.delay.delay_ms(forward_delay_ms).await;
+        self.prepare_for_tx(mdltn_params, tx_pkt_params, output_power, frame)
+            .await?;
+        self.tx().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwarding_and_duplicates() {
+        let params = FloodingParams {
+            ttl: 2,
+            max_forward_delay_ms: 100,
+        };
+        let mut origin = Flooding::<4>::new(1, params);
+        let mut relay = Flooding::<4>::new(2, params);
+
+        let mut frame = [0; HEADER_LEN + 2];
+        frame[..HEADER_LEN].copy_from_slice(&origin.next_header());
+        frame[HEADER_LEN..].copy_from_slice(b"hi");
+        let mut received_frame = frame;
+        let received = relay.handle_rx(&mut received_frame, 250).unwrap();
+        assert_eq!((received.origin, received.sequence, received.ttl), (1, 0, 2));
+        assert_eq!(received.payload, b"hi");
+        assert_eq!(received.forward_delay_ms, Some(48));
+
+        // the copy forwarded by the relay is dropped by both nodes
+        let mut forwarded = frame;
+        assert!(relay.handle_rx(&mut forwarded, 0).is_none());
+        forwarded[4] = 1;
+        assert!(origin.handle_rx(&mut forwarded, 0).is_none());
+
+        // the TTL is used up once a frame has been forwarded
+        let mut other = Flooding::<4>::new(3, params);
+        let received = other.handle_rx(&mut forwarded, 0).unwrap();
+        assert_eq!(received.forward_delay_ms, None);
+
+        assert!(relay.handle_rx(&mut [0; HEADER_LEN - 1], 0).is_none());
+    }
+
+    #[test]
+    fn cache_evicts_oldest() {
+        let mut node = Flooding::<2>::new(9, FloodingParams::default());
+        let mut frames = [[1, 0, 0, 0, 1], [1, 0, 1, 0, 1], [1, 0, 2, 0, 1]];
+        for frame in frames.iter_mut() {
+            assert!(node.handle_rx(frame, 0).is_some());
+        }
+        assert!(node.handle_rx(&mut frames[2], 0).is_none());
+        assert!(node.handle_rx(&mut frames[0], 0).is_some());
+    }
+}
@@ -0,0 +1,91 @@
+//! Downlink scheduling for single-channel gateway and device-to-device Class A emulation. A Class A
+//! device only listens for a short while at fixed delays after the end of its uplink (RX1 and
+//! RX2), so the emulated gateway has to start its downlink at an exact point in time, with the IQ
+//! polarity and payload CRC setting of a downlink.
+//!
+//! This crate has no clock, so the current time is provided by the caller, in microseconds of any
+//! monotonic clock which is also used to timestamp the uplinks.
+
+use super::*;
+
+/// A downlink to be transmitted at a given time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct ScheduledDownlink {
+    /// Time at which the transmission starts.
+    pub tx_at_us: u64,
+    /// Output power in dBm.
+    pub output_power: i32,
+    /// Number of preamble symbols, 8 for LoRaWAN.
+    pub preamble_length: u16,
+}
+
+impl ScheduledDownlink {
+    /// Schedule a downlink in the Class A receive window opening `rx_delay_ms` after the end of the
+    /// uplink (eg: 1000 ms for RX1 and 2000 ms for RX2 with the LoRaWAN default delays).
+    pub fn class_a(uplink_end_us: u64, rx_delay_ms: u32, output_power: i32) -> Self {
+        Self {
+            tx_at_us: uplink_end_us + rx_delay_ms as u64 * 1000,
+            output_power,
+            preamble_length: 8,
+        }
+    }
+
+    fn wait_us(&self, now_us: u64) -> Result<u32, RadioError> {
+        let wait_us = self.tx_at_us.checked_sub(now_us).ok_or(RadioError::DownlinkTooLate)?;
+        u32::try_from(wait_us).map_err(|_| RadioError::InvalidConfiguration)
+    }
+}
+
+impl<RK, DLY> LoRa<RK, DLY>
+where
+    RK: RadioKind,
+    DLY: DelayNs,
+{
+    /// Transmit `payload` as a downlink at `downlink.tx_at_us`, with inverted IQ and without
+    /// payload CRC. The sync word is the one this instance has been created with, which is the
+    /// same for uplinks and downlinks. `now_us` is called once the radio has been prepared, so the
+    /// preparation time does not delay the transmission; if `tx_at_us` has passed by then,
+    /// [`RadioError::DownlinkTooLate`] is returned without transmitting.
+    ///
+    /// # Warning
+    /// This function is not safe to drop or cancel, as it calls `tx`, which must run to completion to avoid radio lockups.
+    /// Do not call this function within a select branch or in any context where it may be prematurely canceled.
+    pub async fn schedule_downlink(
+        &mut self,
+        mdltn_params: &ModulationParams,
+        downlink: &ScheduledDownlink,
+        payload: &[u8],
+        now_us: impl FnOnce() -> u64,
+    ) -> Result<(), RadioError> {
+        let mut tx_pkt_params =
+            self.create_tx_packet_params_for(Direction::DownlinkToDevice, downlink.preamble_length, mdltn_params)?;
+        self.prepare_for_tx(mdltn_params, &mut tx_pkt_params, downlink.output_power, payload)
+            .await?;
+        let wait_us = match downlink.wait_us(now_us()) {
+            Ok(wait_us) => wait_us,
+            Err(err) => {
+                self.enter_standby().await?;
+                self.radio_mode = RadioMode::Standby;
+                return Err(err);
+            }
+        };
+        trace!("Downlink: transmitting in {} us", wait_us);
+        self.delay.delay_us(wait_us).await;
+        self.tx().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn class_a_timing() {
+        let downlink = ScheduledDownlink::class_a(5_000_000, 1000, 14);
+        assert_eq!(downlink.tx_at_us, 6_000_000);
+        assert_eq!(downlink.wait_us(5_950_000), Ok(50_000));
+        assert_eq!(downlink.wait_us(6_000_000), Ok(0));
+        assert_eq!(downlink.wait_us(6_000_001), Err(RadioError::DownlinkTooLate));
+    }
+}
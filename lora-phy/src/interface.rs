@@ -3,6 +3,10 @@ use embedded_hal_async::spi::{Operation, SpiDevice};
 use crate::mod_params::RadioError::{self, SPI};
 use crate::mod_traits::InterfaceVariant;
 
+/// Every command is a single [`SpiDevice`] transaction, and the waits on the BUSY and IRQ lines
+/// happen once it has completed. The SPI bus is therefore released while the LoRa chip is busy or
+/// an operation is in progress, so that it can be shared with other peripherals, eg: using the
+/// `SpiDevice` implementations of `embedded-hal-bus` or `embassy-embedded-hal`.
 pub(crate) struct SpiInterface<SPI, IV> {
     pub(crate) spi: SPI,
     pub(crate) iv: IV,
@@ -4,7 +4,7 @@ use embedded_hal_async::digital::Wait;
 
 use crate::mod_params::RadioError;
 use crate::mod_params::RadioError::*;
-use crate::mod_traits::InterfaceVariant;
+use crate::mod_traits::{FrontEnd, InterfaceVariant};
 
 /// Base for the InterfaceVariant implementation for the Sx127x for
 /// LoRa P2P operations.
@@ -170,3 +170,64 @@ where
         }
     }
 }
+
+/// InterfaceVariant for boards with an external front-end module (PA/LNA), eg: a SKY66122, between
+/// the LoRa chip and the antenna. The front-end module is enabled after the RF switch of the wrapped
+/// InterfaceVariant and disabled before it, and its transmit gain is taken into account when
+/// setting the output power of the LoRa chip.
+pub struct FrontEndInterfaceVariant<IV, FE> {
+    iv: IV,
+    front_end: FE,
+    bypass: bool,
+}
+
+impl<IV, FE> FrontEndInterfaceVariant<IV, FE>
+where
+    IV: InterfaceVariant,
+    FE: FrontEnd,
+{
+    /// Create an InterfaceVariant controlling `front_end` along with `iv`.
+    pub fn new(iv: IV, front_end: FE) -> Self {
+        Self {
+            iv,
+            front_end,
+            bypass: false,
+        }
+    }
+}
+
+impl<IV, FE> InterfaceVariant for FrontEndInterfaceVariant<IV, FE>
+where
+    IV: InterfaceVariant,
+    FE: FrontEnd,
+{
+    async fn reset(&mut self, delay: &mut impl DelayNs) -> Result<(), RadioError> {
+        self.iv.reset(delay).await
+    }
+    async fn wait_on_busy(&mut self) -> Result<(), RadioError> {
+        self.iv.wait_on_busy().await
+    }
+    async fn await_irq(&mut self) -> Result<(), RadioError> {
+        self.iv.await_irq().await
+    }
+
+    async fn enable_rf_switch_rx(&mut self) -> Result<(), RadioError> {
+        self.iv.enable_rf_switch_rx().await?;
+        self.front_end.enable_rx(self.bypass).await
+    }
+    async fn enable_rf_switch_tx(&mut self) -> Result<(), RadioError> {
+        self.iv.enable_rf_switch_tx().await?;
+        self.front_end.enable_tx(self.bypass).await
+    }
+    async fn disable_rf_switch(&mut self) -> Result<(), RadioError> {
+        self.front_end.disable().await?;
+        self.iv.disable_rf_switch().await
+    }
+
+    fn tx_gain(&self) -> i8 {
+        self.front_end.tx_gain(self.bypass)
+    }
+    fn set_front_end_bypass(&mut self, bypass: bool) {
+        self.bypass = bypass;
+    }
+}
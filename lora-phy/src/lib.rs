@@ -7,14 +7,23 @@
 #![doc = document_features::document_features!(feature_label = r#"<span class="stab portability"><code>{feature}</code></span>"#)]
 #![doc = include_str!("../README.md")]
 
-// This must go FIRST so that all the other modules see its macros.
-pub(crate) mod fmt;
+// Logging macros (trace!, debug!, info!, warn!, error!), see the `lora-log` crate.
+#[macro_use]
+extern crate lora_log;
 
 #[cfg(feature = "lorawan-radio")]
 #[cfg_attr(docsrs, doc(cfg(feature = "lorawan-radio")))]
 /// Provides an implementation of the async LoRaWAN device trait.
 pub mod lorawan_radio;
 
+/// Conformance checks for RadioKind implementations outside of this crate
+pub mod conformance;
+/// Carrier sense before transmitting, for peer-to-peer networks
+pub mod csma;
+/// Experimental flooding relay for mesh experiments over peer-to-peer links
+pub mod flooding;
+/// Downlink scheduling for gateway emulation
+pub mod gateway;
 /// The read/write interface between an embedded framework/MCU combination and a LoRa chip
 pub(crate) mod interface;
 /// InterfaceVariant implementations using `embedded-hal`.
@@ -23,6 +32,10 @@ pub mod iv;
 pub mod mod_params;
 /// Traits implemented externally or internally to support control of LoRa chips
 pub mod mod_traits;
+/// Noise floor estimation of idle channels
+pub mod noise_floor;
+/// Timing measurements of the bus and interrupts of a LoRa chip
+pub mod profiling;
 /// Specific implementation to support Semtech Sx126x chips
 pub mod sx126x;
 /// Specific implementation to support Semtech Sx127x chips
@@ -31,6 +44,8 @@ pub mod sx127x;
 pub use crate::mod_params::RxMode;
 
 pub use embedded_hal_async::delay::DelayNs;
+
+use core::ops::ControlFlow;
 use interface::*;
 use mod_params::*;
 use mod_traits::*;
@@ -52,7 +67,11 @@ where
     radio_mode: RadioMode,
     sync_word: u8,
     cold_start: bool,
-    calibrate_image: bool,
+    image_calibration: ImageCalibration,
+    calibrated_band: Option<ImageCalibrationBand>,
+    temperature_recalibration: TemperatureRecalibration,
+    fallback_mode: FallbackMode,
+    apply_fallback_mode: bool,
 }
 
 impl<RK, DLY> LoRa<RK, DLY>
@@ -68,7 +87,11 @@ where
             radio_mode: RadioMode::Sleep,
             sync_word,
             cold_start: true,
-            calibrate_image: true,
+            image_calibration: ImageCalibration::Auto,
+            calibrated_band: None,
+            temperature_recalibration: TemperatureRecalibration::default(),
+            fallback_mode: FallbackMode::StandbyRc,
+            apply_fallback_mode: false,
         };
         lora.init().await?;
 
@@ -103,6 +126,14 @@ where
         self.radio_kind.process_irq_event(self.radio_mode, None, false).await
     }
 
+    /// Report what the LoRa chip is able to do (frequency range, spreading factors and
+    /// bandwidths, maximum output power and further modulations), allowing generic code to adapt
+    /// to the radio present. The silicon version is read from the chip, where available.
+    pub async fn capabilities(&mut self) -> Result<Capabilities, RadioError> {
+        self.radio_kind.ensure_ready(self.radio_mode).await?;
+        self.radio_kind.capabilities().await
+    }
+
     /// Create modulation parameters for a communication channel
     pub fn create_modulation_params(
         &mut self,
@@ -124,6 +155,7 @@ where
         iq_inverted: bool,
         modulation_params: &ModulationParams,
     ) -> Result<PacketParams, RadioError> {
+        self.validate_preamble_length(preamble_length)?;
         self.radio_kind.create_packet_params(
             preamble_length,
             implicit_header,
@@ -144,6 +176,7 @@ where
         iq_inverted: bool,
         modulation_params: &ModulationParams,
     ) -> Result<PacketParams, RadioError> {
+        self.validate_preamble_length(preamble_length)?;
         self.radio_kind.create_packet_params(
             preamble_length,
             implicit_header,
@@ -154,6 +187,116 @@ where
         )
     }
 
+    /// Create packet parameters for a transmission which wakes up a receiver sampling the channel
+    /// every `wake_interval_ms` (eg: in receive duty cycle mode), using a preamble long enough to
+    /// span the whole interval. See [`ModulationParams::wake_up_preamble_length`].
+    pub fn create_wake_up_tx_packet_params(
+        &mut self,
+        wake_interval_ms: u32,
+        crc_on: bool,
+        iq_inverted: bool,
+        modulation_params: &ModulationParams,
+    ) -> Result<PacketParams, RadioError> {
+        let preamble_length = modulation_params.wake_up_preamble_length(wake_interval_ms)?;
+        self.create_tx_packet_params(preamble_length, false, crc_on, iq_inverted, modulation_params)
+    }
+
+    /// Create packet parameters to transmit a LoRaWAN frame in the given direction, setting IQ
+    /// polarity and payload CRC accordingly. A device transmits [`Direction::UplinkToGateway`]
+    /// frames, a gateway [`Direction::DownlinkToDevice`] frames.
+    pub fn create_tx_packet_params_for(
+        &mut self,
+        direction: Direction,
+        preamble_length: u16,
+        modulation_params: &ModulationParams,
+    ) -> Result<PacketParams, RadioError> {
+        self.create_tx_packet_params(
+            preamble_length,
+            false,
+            direction.crc_on(),
+            direction.iq_inverted(),
+            modulation_params,
+        )
+    }
+
+    /// Create packet parameters to receive a LoRaWAN frame sent in the given direction, setting IQ
+    /// polarity and payload CRC accordingly. A device receives [`Direction::DownlinkToDevice`]
+    /// frames, a gateway [`Direction::UplinkToGateway`] frames.
+    pub fn create_rx_packet_params_for(
+        &mut self,
+        direction: Direction,
+        preamble_length: u16,
+        max_payload_length: u8,
+        modulation_params: &ModulationParams,
+    ) -> Result<PacketParams, RadioError> {
+        self.create_rx_packet_params(
+            preamble_length,
+            false,
+            max_payload_length,
+            direction.crc_on(),
+            direction.iq_inverted(),
+            modulation_params,
+        )
+    }
+
+    /// Create packet parameters following the conventions of `profile`, to transmit or receive (up
+    /// to `max_payload_length` bytes, ignored in implicit header mode). The sync word of the
+    /// profile is applied separately with [`LoRa::set_profile`].
+    pub fn create_profile_packet_params(
+        &mut self,
+        profile: PhyProfile,
+        max_payload_length: u8,
+        modulation_params: &ModulationParams,
+    ) -> Result<PacketParams, RadioError> {
+        let preamble_length = profile.preamble_length();
+        let (crc_on, iq_inverted) = (profile.crc_on(), profile.iq_inverted());
+        match profile.implicit_payload_length(modulation_params.bandwidth) {
+            Some(payload_length) => self.create_implicit_packet_params(
+                preamble_length,
+                payload_length,
+                crc_on,
+                iq_inverted,
+                modulation_params,
+            ),
+            None => self.create_rx_packet_params(
+                preamble_length,
+                false,
+                max_payload_length,
+                crc_on,
+                iq_inverted,
+                modulation_params,
+            ),
+        }
+    }
+
+    /// Create packet parameters for implicit header mode, where no header is transmitted and both
+    /// sides use the same fixed `payload_length` (eg: Class B beacons and many proprietary
+    /// protocols). Implicit header mode is required for SF6 on sx127x.
+    ///
+    /// The parameters may be used both to transmit and to receive. A received packet always has
+    /// exactly `payload_length` bytes, so the receiving buffer must be at least this long.
+    pub fn create_implicit_packet_params(
+        &mut self,
+        preamble_length: u16,
+        payload_length: u8,
+        crc_on: bool,
+        iq_inverted: bool,
+        modulation_params: &ModulationParams,
+    ) -> Result<PacketParams, RadioError> {
+        if payload_length == 0 {
+            return Err(RadioError::PayloadSizeUnexpected(0));
+        }
+        self.validate_preamble_length(preamble_length)?;
+        self.radio_kind.create_packet_params(
+            preamble_length,
+            true,
+            payload_length,
+            crc_on,
+            iq_inverted,
+            modulation_params,
+        )
+    }
+
     /// Initialize the radio for LoRa physical layer communications
     pub async fn init(&mut self) -> Result<(), RadioError> {
         self.cold_start = true;
@@ -169,10 +312,101 @@ where
         self.radio_kind.set_tx_power_and_ramp_time(0, None, false).await?;
         self.radio_kind.set_irq_params(Some(self.radio_mode)).await?;
         self.cold_start = false;
-        self.calibrate_image = true;
+        self.calibrated_band = None;
+        self.temperature_recalibration.calibrated();
+        // the chip falls back to its default mode after a reset or cold sleep
+        self.apply_fallback_mode = self.fallback_mode != FallbackMode::default();
         Ok(())
     }
 
+    /// Change the sync word, eg: for a private network which uses a non-standard one. The radio is
+    /// initialized again with the new sync word before the next operation.
+    pub fn set_sync_word(&mut self, sync_word: u8) {
+        if sync_word != self.sync_word {
+            self.sync_word = sync_word;
+            self.cold_start = true;
+        }
+    }
+
+    /// The sync word in use
+    pub fn sync_word(&self) -> u8 {
+        self.sync_word
+    }
+
+    /// Use the sync word of `profile`, see [`LoRa::set_sync_word`].
+    pub fn set_profile(&mut self, profile: PhyProfile) {
+        self.set_sync_word(profile.sync_word());
+    }
+
+    /// Choose when the image rejection is calibrated for the frequency in use. Calibration takes
+    /// a few milliseconds, which may be too long before opening a receive window.
+    pub fn set_image_calibration(&mut self, image_calibration: ImageCalibration) {
+        self.image_calibration = image_calibration;
+    }
+
+    /// Recalibrate the oscillators and the image rejection before the next operation once the
+    /// temperature reported via [`LoRa::report_temperature`] differs by at least
+    /// `max_delta_celsius` from the temperature of the last calibration. Disabled by default.
+    pub fn set_temperature_recalibration(&mut self, max_delta_celsius: Option<u8>) {
+        self.temperature_recalibration.max_delta_celsius = max_delta_celsius;
+    }
+
+    /// Report the current temperature (eg: read from the MCU or a board sensor) for
+    /// [`LoRa::set_temperature_recalibration`]
+    pub fn report_temperature(&mut self, temperature_celsius: i8) {
+        self.temperature_recalibration.report(temperature_celsius);
+    }
+
+    /// Band the image rejection has been calibrated for since the last cold start, if any
+    pub fn calibrated_band(&self) -> Option<ImageCalibrationBand> {
+        self.calibrated_band
+    }
+
+    /// Enable or disable boosted reception, overriding the board configuration. Boosted reception
+    /// improves the sensitivity by a few dB at the cost of a higher current.
+    pub fn set_rx_boost(&mut self, rx_boost: bool) {
+        self.radio_kind.set_rx_boost(rx_boost);
+    }
+
+    /// Whether boosted reception is enabled
+    pub fn rx_boost(&self) -> bool {
+        self.radio_kind.rx_boost()
+    }
+
+    /// Bypass the amplifiers of an external front-end module (see
+    /// [`FrontEndInterfaceVariant`](iv::FrontEndInterfaceVariant)), eg: when the output power of
+    /// the LoRa chip alone is sufficient. Takes effect with the next send or receive operation.
+    pub fn set_front_end_bypass(&mut self, bypass: bool) {
+        self.radio_kind.set_front_end_bypass(bypass);
+    }
+
+    /// Choose the mode the radio falls back to after a transmission or reception, trading idle
+    /// current against the time needed to start the next operation (eg: a receive window shortly
+    /// after a transmission). Applied when the next operation is prepared. The sx127x always falls
+    /// back to standby, which it also does when [`FallbackMode::FrequencySynthesis`] is requested.
+    pub fn set_fallback_mode(&mut self, fallback_mode: FallbackMode) {
+        self.fallback_mode = fallback_mode;
+        self.apply_fallback_mode = true;
+    }
+
+    /// The mode the radio has last been placed in
+    pub fn mode(&self) -> RadioMode {
+        self.radio_mode
+    }
+
+    /// Whether the configuration loaded by [`LoRa::prepare_for_tx`] is still in place, so that
+    /// [`LoRa::tx`] may be called. It is consumed by the transmission, whether successful or not.
+    pub fn is_prepared_for_tx(&self) -> bool {
+        self.radio_mode == RadioMode::Transmit
+    }
+
+    /// Whether the configuration loaded by [`LoRa::prepare_for_rx`] is still in place, so that
+    /// [`LoRa::start_rx`] may be called. It is lost when a reception fails (except in continuous
+    /// mode) and when the radio is put to sleep.
+    pub fn is_prepared_for_rx(&self) -> bool {
+        matches!(self.radio_mode, RadioMode::Receive(_))
+    }
+
     /// Place the LoRa physical layer in standby mode
     pub async fn enter_standby(&mut self) -> Result<(), RadioError> {
         self.radio_kind.set_standby().await
@@ -181,12 +415,22 @@ where
     /// Place the LoRa physical layer in low power mode, specifying cold or
     /// warm start (if chip supports it)
     pub async fn sleep(&mut self, warm_start_if_possible: bool) -> Result<(), RadioError> {
+        let options = if warm_start_if_possible {
+            SleepOptions::warm()
+        } else {
+            SleepOptions::cold()
+        };
+        self.sleep_with_options(options).await
+    }
+
+    /// Place the LoRa physical layer in low power mode, see [`SleepOptions`]. With
+    /// [`SleepOptions::rtc_wakeup`], the chip returns to standby on its own when its RTC times out;
+    /// the next operation wakes it as usual either way.
+    pub async fn sleep_with_options(&mut self, options: SleepOptions) -> Result<(), RadioError> {
         if self.radio_mode != RadioMode::Sleep {
             self.radio_kind.ensure_ready(self.radio_mode).await?;
-            self.radio_kind
-                .set_sleep(warm_start_if_possible, &mut self.delay)
-                .await?;
-            if !warm_start_if_possible {
+            self.radio_kind.set_sleep(options, &mut self.delay).await?;
+            if !options.warm_start {
                 self.cold_start = true;
             }
             self.radio_mode = RadioMode::Sleep;
@@ -202,6 +446,27 @@ where
         output_power: i32,
         buffer: &[u8],
     ) -> Result<(), RadioError> {
+        self.prepare_for_tx_parts(mdltn_params, tx_pkt_params, output_power, &[buffer])
+            .await
+    }
+
+    /// Prepare the radio for a transmit operation of a payload made of several buffers, which are
+    /// written to the chip one after the other. Saves protocol layers from assembling the payload
+    /// in a contiguous staging buffer.
+    pub async fn prepare_for_tx_parts(
+        &mut self,
+        mdltn_params: &ModulationParams,
+        tx_pkt_params: &mut PacketParams,
+        output_power: i32,
+        parts: &[&[u8]],
+    ) -> Result<(), RadioError> {
+        let len = parts.iter().map(|part| part.len()).sum();
+        trace!(
+            "TX: {} bytes at {} Hz, {} dBm",
+            len,
+            mdltn_params.frequency_in_hz,
+            output_power
+        );
         self.prepare_modem(mdltn_params.frequency_in_hz).await?;
 
         self.radio_kind.set_modulation_params(mdltn_params).await?;
@@ -214,10 +479,10 @@ where
             self.radio_mode = RadioMode::Standby;
         }
 
-        tx_pkt_params.set_payload_length(buffer.len())?;
+        tx_pkt_params.set_payload_length(len)?;
         self.radio_kind.set_packet_params(tx_pkt_params).await?;
         self.radio_kind.set_channel(mdltn_params.frequency_in_hz).await?;
-        self.radio_kind.set_payload(buffer).await?;
+        self.radio_kind.set_payload_parts(parts).await?;
         self.radio_mode = RadioMode::Transmit;
         self.radio_kind.set_irq_params(Some(self.radio_mode)).await?;
         Ok(())
@@ -235,6 +500,7 @@ where
                 self.wait_for_irq().await?;
                 match self.radio_kind.process_irq_event(self.radio_mode, None, true).await {
                     Ok(Some(IrqState::Done | IrqState::PreambleReceived)) => {
+                        trace!("TX done");
                         self.radio_mode = RadioMode::Standby;
                         return Ok(());
                     }
@@ -259,7 +525,7 @@ where
         mdltn_params: &ModulationParams,
         rx_pkt_params: &PacketParams,
     ) -> Result<(), RadioError> {
-        trace!("RX mode: {}", listen_mode);
+        trace!("RX mode: {} at {} Hz", listen_mode, mdltn_params.frequency_in_hz);
         self.prepare_modem(mdltn_params.frequency_in_hz).await?;
 
         self.radio_kind.set_modulation_params(mdltn_params).await?;
@@ -301,19 +567,62 @@ where
         packet_params: &PacketParams,
         receiving_buffer: &mut [u8],
     ) -> Result<(u8, PacketStatus), RadioError> {
+        self.wait_for_rx_done().await?;
+        let received_len = self.radio_kind.get_rx_payload(packet_params, receiving_buffer).await?;
+        let rx_pkt_status = self.radio_kind.get_rx_packet_status().await?;
+        trace!("RX done: {} bytes, {}", received_len, rx_pkt_status);
+        Ok((received_len, rx_pkt_status))
+    }
+
+    /// Wait for a previously started receive to complete, leaving the payload in the radio buffer.
+    /// Returns the payload length, the payload may then be retrieved in chunks with
+    /// [`LoRa::get_rx_payload_chunk`] (eg: to stream it into external flash on devices with little RAM).
+    ///
+    /// # Warning
+    /// Same as [`LoRa::complete_rx`], this function is not safe to drop or cancel. In continuous receive mode,
+    /// the payload may be overwritten by the next packet before it is fully read.
+    pub async fn complete_rx_deferred(
+        &mut self,
+        packet_params: &PacketParams,
+    ) -> Result<(u8, PacketStatus), RadioError> {
+        self.wait_for_rx_done().await?;
+        let received_len = self.radio_kind.get_rx_payload_chunk(packet_params, 0, &mut []).await?;
+        let rx_pkt_status = self.radio_kind.get_rx_packet_status().await?;
+        trace!("RX done: {} bytes, {}", received_len, rx_pkt_status);
+        Ok((received_len, rx_pkt_status))
+    }
+
+    /// Copy the part of the received payload starting at `offset` into `chunk`, after
+    /// [`LoRa::complete_rx_deferred`]. Returns the number of bytes copied, which is zero once
+    /// `offset` reaches the end of the payload.
+    pub async fn get_rx_payload_chunk(
+        &mut self,
+        packet_params: &PacketParams,
+        offset: u8,
+        chunk: &mut [u8],
+    ) -> Result<usize, RadioError> {
+        if let RadioMode::Receive(_) = self.radio_mode {
+            let payload_length = self
+                .radio_kind
+                .get_rx_payload_chunk(packet_params, offset, chunk)
+                .await?;
+            Ok(chunk.len().min(payload_length.saturating_sub(offset) as usize))
+        } else {
+            Err(RadioError::InvalidRadioMode)
+        }
+    }
+
+    async fn wait_for_rx_done(&mut self) -> Result<(), RadioError> {
         if let RadioMode::Receive(_) = self.radio_mode {
             loop {
                 match self.radio_kind.process_irq_event(self.radio_mode, None, true).await {
                     Ok(Some(actual_state)) => match actual_state {
                         IrqState::PreambleReceived => (),
-                        IrqState::Done => {
-                            let received_len = self.radio_kind.get_rx_payload(packet_params, receiving_buffer).await?;
-                            let rx_pkt_status = self.radio_kind.get_rx_packet_status().await?;
-                            return Ok((received_len, rx_pkt_status));
-                        }
+                        IrqState::Done => return Ok(()),
                     },
                     Ok(None) => (),
                     Err(err) => {
+                        trace!("RX failed: {}", err);
                         // if in rx continuous mode, allow the caller to determine whether to keep receiving
                         if self.radio_mode != RadioMode::Receive(RxMode::Continuous) {
                             self.radio_kind.ensure_ready(self.radio_mode).await?;
@@ -388,11 +697,37 @@ where
         Ok(())
     }
 
-    /// Get the current RSSI
+    /// Get the instantaneous RSSI (dBm) of the channel the radio is listening to. The RSSI of a
+    /// received packet is part of the [`PacketStatus`] returned with it.
     pub async fn get_rssi(&mut self) -> Result<i16, RadioError> {
         self.radio_kind.get_rssi().await
     }
 
+    /// Start listening (see [`LoRa::listen`]) and pass an RSSI sample (dBm) to `on_rssi` every
+    /// `period_ms` milliseconds, eg: to log the occupancy of a channel, until it returns
+    /// [`ControlFlow::Break`]. The first sample is taken right away. Enable boosted reception
+    /// ([`LoRa::set_rx_boost`]) beforehand to sample with the full sensitivity.
+    ///
+    /// The returned future only waits for the delay and reads the RSSI, so it may be dropped at
+    /// any time, eg: in a select with the trigger of a reception, which is then started with
+    /// [`LoRa::prepare_for_rx`] and [`LoRa::start_rx`].
+    pub async fn listen_with_rssi<B>(
+        &mut self,
+        frequency_in_hz: u32,
+        bandwidth: Bandwidth,
+        period_ms: u32,
+        mut on_rssi: impl FnMut(i16) -> ControlFlow<B>,
+    ) -> Result<B, RadioError> {
+        self.listen(frequency_in_hz, bandwidth).await?;
+        loop {
+            let rssi = self.radio_kind.get_rssi().await?;
+            if let ControlFlow::Break(value) = on_rssi(rssi) {
+                return Ok(value);
+            }
+            self.delay.delay_ms(period_ms).await;
+        }
+    }
+
     /// Prepare the radio for a channel activity detection (CAD) operation
     pub async fn prepare_for_cad(&mut self, mdltn_params: &ModulationParams) -> Result<(), RadioError> {
         self.prepare_modem(mdltn_params.frequency_in_hz).await?;
@@ -465,6 +800,13 @@ where
         self.radio_kind.set_tx_continuous_wave_mode().await
     }
 
+    fn validate_preamble_length(&self, preamble_length: u16) -> Result<(), RadioError> {
+        if preamble_length < self.radio_kind.min_preamble_length() {
+            return Err(RadioError::InvalidPreambleLength);
+        }
+        Ok(())
+    }
+
     async fn prepare_modem(&mut self, frequency_in_hz: u32) -> Result<(), RadioError> {
         self.radio_kind.ensure_ready(self.radio_mode).await?;
         if self.radio_mode != RadioMode::Standby {
@@ -476,9 +818,26 @@ where
             self.do_cold_start().await?;
         }
 
-        if self.calibrate_image {
-            self.radio_kind.calibrate_image(frequency_in_hz).await?;
-            self.calibrate_image = false;
+        if self.temperature_recalibration.is_due() {
+            debug!("Recalibrating after a temperature change");
+            self.radio_kind.calibrate_oscillators().await?;
+            self.temperature_recalibration.calibrated();
+            self.calibrated_band = None;
+        }
+
+        let band = match self.image_calibration {
+            ImageCalibration::Auto => ImageCalibrationBand::from_frequency(frequency_in_hz),
+            ImageCalibration::Band(band) => Some(band),
+            ImageCalibration::Skip => None,
+        };
+        if let Some(band) = band.filter(|band| self.calibrated_band != Some(*band)) {
+            self.radio_kind.calibrate_image(band).await?;
+            self.calibrated_band = Some(band);
+        }
+
+        if self.apply_fallback_mode {
+            self.radio_kind.set_fallback_mode(self.fallback_mode).await?;
+            self.apply_fallback_mode = false;
         }
 
         Ok(())
@@ -1,16 +1,71 @@
 #![allow(missing_docs)]
 
-use super::mod_params::{PacketParams, RadioError};
+use super::csma::CsmaParams;
+use super::mod_params::{Direction, FallbackMode, PacketParams, RadioError};
 use super::mod_traits::RadioKind;
 use super::{DelayNs, LoRa, RxMode};
 
 use lora_modulation::BaseBandModulationParams;
 use lorawan_device::async_device::{
-    radio::{PhyRxTx, RxConfig, RxMode as LorawanRxMode, RxQuality, RxStatus, TxConfig},
+    radio::{ChannelActivity, PhyRxTx, RfConfig, RxConfig, RxMode as LorawanRxMode, RxQuality, RxStatus, TxConfig},
     Timings,
 };
 
 const DEFAULT_RX_WINDOW_LEAD_TIME: u32 = 50;
+const PREAMBLE_LENGTH: u16 = 8;
+
+/// PHY policies of the adapter, see [`LorawanRadio::with_config`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Config {
+    /// Boosted reception for the RX windows; `None` keeps the setting of the board configuration.
+    pub rx_boost: Option<bool>,
+    /// Mode the radio falls back to after a transmission or reception, see
+    /// [`LoRa::set_fallback_mode`].
+    pub fallback_mode: FallbackMode,
+    /// Listen before talk: run channel activity detection before every transmission and back off
+    /// while the channel is busy. Transmissions fail with [`Error::ChannelBusy`] if the channel
+    /// doesn't become available.
+    pub listen_before_talk: Option<ListenBeforeTalk>,
+    /// See [`LorawanRadio::set_rx_window_lead_time`].
+    pub rx_window_lead_time: u32,
+    /// See [`LorawanRadio::set_rx_window_buffer`].
+    pub rx_window_buffer: u32,
+    /// Sync word for private networks with a non-standard one; `None` keeps the sync word the
+    /// [`LoRa`] instance has been created with.
+    pub sync_word: Option<u8>,
+    /// Whether uplinks carry a payload CRC, which LoRaWAN requires.
+    pub uplink_crc: bool,
+    /// Whether the payload CRC is checked for downlinks. LoRaWAN downlinks carry no CRC, so this is
+    /// only useful for private networks which deviate from the specification.
+    pub downlink_crc: bool,
+}
+
+/// Listen before talk settings, see [`Config::listen_before_talk`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct ListenBeforeTalk {
+    /// Channel activity detection and backoff parameters
+    pub csma: CsmaParams,
+    /// Seed of the random backoffs, eg: from a hardware RNG or the DevEUI, so that devices waiting
+    /// for the same busy channel don't back off in lockstep. Zero is replaced by one.
+    pub seed: u32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rx_boost: None,
+            fallback_mode: FallbackMode::StandbyRc,
+            listen_before_talk: None,
+            rx_window_lead_time: DEFAULT_RX_WINDOW_LEAD_TIME,
+            rx_window_buffer: DEFAULT_RX_WINDOW_LEAD_TIME,
+            sync_word: None,
+            uplink_crc: Direction::UplinkToGateway.crc_on(),
+            downlink_crc: Direction::DownlinkToDevice.crc_on(),
+        }
+    }
+}
 
 /// LoRaWAN radio implementation.
 ///
@@ -25,6 +80,11 @@ where
     rx_pkt_params: Option<PacketParams>,
     rx_window_lead_time: u32,
     rx_window_buffer: u32,
+    listen_before_talk: Option<CsmaParams>,
+    uplink_crc: bool,
+    downlink_crc: bool,
+    // state of the xorshift generator randomizing listen before talk backoffs
+    backoff_random: u32,
 }
 
 impl<RK, DLY, const P: u8, const G: i8> From<LoRa<RK, DLY>> for LorawanRadio<RK, DLY, P, G>
@@ -33,12 +93,7 @@ where
     DLY: DelayNs,
 {
     fn from(lora: LoRa<RK, DLY>) -> Self {
-        Self {
-            lora,
-            rx_pkt_params: None,
-            rx_window_lead_time: DEFAULT_RX_WINDOW_LEAD_TIME,
-            rx_window_buffer: DEFAULT_RX_WINDOW_LEAD_TIME,
-        }
+        Self::with_config(lora, Config::default())
     }
 }
 
@@ -47,6 +102,56 @@ where
     RK: RadioKind,
     DLY: DelayNs,
 {
+    /// Create the adapter with the given PHY policies instead of the defaults.
+    pub fn with_config(mut lora: LoRa<RK, DLY>, config: Config) -> Self {
+        if let Some(rx_boost) = config.rx_boost {
+            lora.set_rx_boost(rx_boost);
+        }
+        if config.fallback_mode != FallbackMode::default() {
+            lora.set_fallback_mode(config.fallback_mode);
+        }
+        if let Some(sync_word) = config.sync_word {
+            lora.set_sync_word(sync_word);
+        }
+        Self {
+            lora,
+            rx_pkt_params: None,
+            rx_window_lead_time: config.rx_window_lead_time,
+            rx_window_buffer: config.rx_window_buffer,
+            listen_before_talk: config.listen_before_talk.map(|lbt| lbt.csma),
+            uplink_crc: config.uplink_crc,
+            downlink_crc: config.downlink_crc,
+            // the xorshift generator never leaves zero
+            backoff_random: config.listen_before_talk.map_or(1, |lbt| lbt.seed.max(1)),
+        }
+    }
+
+    /// Enable or disable boosted reception for the following RX windows (eg: only while the
+    /// battery allows for the additional current).
+    pub fn set_rx_boost(&mut self, rx_boost: bool) {
+        self.lora.set_rx_boost(rx_boost);
+    }
+
+    pub fn rx_boost(&self) -> bool {
+        self.lora.rx_boost()
+    }
+
+    /// Change the sync word, see [`LoRa::set_sync_word`].
+    pub fn set_sync_word(&mut self, sync_word: u8) {
+        self.lora.set_sync_word(sync_word);
+    }
+
+    pub fn sync_word(&self) -> u8 {
+        self.lora.sync_word()
+    }
+
+    /// Choose the payload CRC of the following uplinks and downlinks, see [`Config::uplink_crc`]
+    /// and [`Config::downlink_crc`].
+    pub fn set_crc(&mut self, uplink_crc: bool, downlink_crc: bool) {
+        self.uplink_crc = uplink_crc;
+        self.downlink_crc = downlink_crc;
+    }
+
     pub fn set_rx_window_lead_time(&mut self, lt: u32) {
         self.rx_window_lead_time = lt;
     }
@@ -75,6 +180,7 @@ where
 pub enum Error {
     Radio(RadioError),
     NoRxParams,
+    ChannelBusy,
 }
 
 impl From<RadioError> for Error {
@@ -102,9 +208,22 @@ where
             config.rf.bb.cr,
             config.rf.frequency,
         )?;
-        let mut tx_pkt_params = self
-            .lora
-            .create_tx_packet_params(8, false, true, false, &mdltn_params)?;
+        let mut tx_pkt_params =
+            self.lora
+                .create_tx_packet_params(PREAMBLE_LENGTH, false, self.uplink_crc, false, &mdltn_params)?;
+
+        if let Some(params) = &self.listen_before_talk {
+            let state = &mut self.backoff_random;
+            let mut random = || {
+                *state ^= *state << 13;
+                *state ^= *state >> 17;
+                *state ^= *state << 5;
+                *state
+            };
+            if !self.lora.csma(&mdltn_params, params, &mut random).await? {
+                return Err(Error::ChannelBusy);
+            }
+        }
 
         self.lora
             .prepare_for_tx(&mdltn_params, &mut tx_pkt_params, config.pw.into(), buffer)
@@ -120,9 +239,9 @@ where
             config.rf.bb.cr,
             config.rf.frequency,
         )?;
-        let rx_pkt_params = self
-            .lora
-            .create_rx_packet_params(8, false, 255, true, true, &mdltn_params)?;
+        let rx_pkt_params =
+            self.lora
+                .create_rx_packet_params(PREAMBLE_LENGTH, false, 255, self.downlink_crc, true, &mdltn_params)?;
         self.lora
             .prepare_for_rx(RxMode::from(config.mode, config.rf.bb), &mdltn_params, &rx_pkt_params)
             .await?;
@@ -131,7 +250,7 @@ where
     }
 
     async fn rx_single(&mut self, buf: &mut [u8]) -> Result<RxStatus, Self::PhyError> {
-        if let Some(rx_params) = &self.rx_pkt_params {
+        if let Some(rx_params) = self.rx_pkt_params.as_ref().filter(|_| self.lora.is_prepared_for_rx()) {
             match self.lora.rx(rx_params, buf).await {
                 Ok((len, q)) => Ok(RxStatus::Rx(len as usize, RxQuality::new(q.rssi, q.snr as i8))),
                 Err(RadioError::ReceiveTimeout) => Ok(RxStatus::RxTimeout),
@@ -142,7 +261,7 @@ where
         }
     }
     async fn rx_continuous(&mut self, receiving_buffer: &mut [u8]) -> Result<(usize, RxQuality), Self::PhyError> {
-        if let Some(rx_params) = &self.rx_pkt_params {
+        if let Some(rx_params) = self.rx_pkt_params.as_ref().filter(|_| self.lora.is_prepared_for_rx()) {
             match self.lora.rx(rx_params, receiving_buffer).await {
                 Ok((received_len, rx_pkt_status)) => {
                     Ok((
@@ -157,21 +276,36 @@ where
         }
     }
     async fn low_power(&mut self) -> Result<(), Self::PhyError> {
+        // a cold sleep discards the receive configuration
+        self.rx_pkt_params = None;
         self.lora.sleep(false).await.map_err(|e| e.into())
     }
+
+    async fn sample_channel(&mut self, config: RfConfig) -> Result<Option<ChannelActivity>, Self::PhyError> {
+        let mdltn_params =
+            self.lora
+                .create_modulation_params(config.bb.sf, config.bb.bw, config.bb.cr, config.frequency)?;
+        // channel activity detection replaces the receive configuration
+        self.rx_pkt_params = None;
+        self.lora.prepare_for_cad(&mdltn_params).await?;
+        let detected = self.lora.cad(&mdltn_params).await?;
+        Ok(Some(ChannelActivity { detected, rssi: None }))
+    }
+
+    async fn reset(&mut self) -> Result<(), Self::PhyError> {
+        // the chip loses its receive configuration
+        self.rx_pkt_params = None;
+        self.lora.init().await.map_err(|e| e.into())
+    }
 }
 
 impl RxMode {
     fn from(mode: LorawanRxMode, bb: BaseBandModulationParams) -> Self {
         match mode {
             LorawanRxMode::Continuous => RxMode::Continuous,
-            LorawanRxMode::Single { ms } => {
-                // Since both sx126x and sx127x have a preamble-based timeout, we translate
-                // the additional millisecond delay into symbols and add it to the amount of preamble symbols.
-                const PREAMBLE_SYMBOLS: u16 = 13; // 12.25
-                let num_symbols = PREAMBLE_SYMBOLS + bb.delay_in_symbols(ms);
-                RxMode::Single(num_symbols)
-            }
+            // Since both sx126x and sx127x have a preamble-based timeout, the additional
+            // millisecond delay is translated into symbols on top of the preamble.
+            LorawanRxMode::Single { ms } => RxMode::single_for_window(&bb, PREAMBLE_LENGTH, ms),
         }
     }
 }
@@ -1,3 +1,4 @@
+use lora_modulation::BaseBandModulationParams;
 pub use lora_modulation::{Bandwidth, CodingRate, SpreadingFactor};
 
 /// Errors types reported during LoRa physical layer processing
@@ -27,16 +28,25 @@ pub enum RadioError {
     TransmitTimeout,
     ReceiveTimeout,
     DutyCycleUnsupported,
+    RtcWakeupUnsupported,
     RngUnsupported,
+    CapabilitiesUnsupported,
+    InvalidPreambleLength,
+    DownlinkTooLate,
 }
 
-/// Status for a received packet
+/// Status for a received packet. Unlike [`crate::LoRa::get_rssi`], which measures the channel at
+/// the time of the call, all values relate to the received packet.
 #[derive(Clone, Copy)]
 #[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
-#[allow(missing_docs)]
 pub struct PacketStatus {
+    /// Average RSSI of the channel over the packet (dBm), including the noise
     pub rssi: i16,
+    /// Signal to noise ratio of the packet (dB)
     pub snr: i16,
+    /// Estimated strength of the LoRa signal itself (dBm), which is below [`PacketStatus::rssi`]
+    /// for packets received below the noise floor
+    pub signal_rssi: i16,
 }
 
 /// The state of the radio
@@ -82,6 +92,132 @@ pub enum RxMode {
     DutyCycle(DutyCycleParams),
 }
 
+impl RxMode {
+    /// Single shot mode for a reception window whose packet may start up to `window_ms` after the
+    /// receiver has been enabled (the uncertainty of the window opening on both ends). The symbol
+    /// timeout covers this delay, rounded up to whole symbols, plus the preamble of
+    /// `preamble_length` symbols and the 4.25 symbols of sync word and start frame delimiter, so
+    /// that it holds for any spreading factor and bandwidth.
+    ///
+    /// The chips clamp the timeout to their supported range (sx126x: 248, sx127x: 1023 symbols).
+    pub const fn single_for_window(bb: &BaseBandModulationParams, preamble_length: u16, window_ms: u32) -> Self {
+        let t_sym_us = bb.symbol_duration_us();
+        let window_symbols = (window_ms * 1000).div_ceil(t_sym_us) as u16;
+        RxMode::Single(preamble_length.saturating_add(5).saturating_add(window_symbols))
+    }
+
+    /// Longest time (ms) a reception in [`RxMode::single_for_window`] mode takes until it either
+    /// times out or a packet of up to `max_payload_length` bytes (explicit header) is received.
+    /// Meant as the timeout for awaiting the reception, eg: with a raw `lora-phy` setup.
+    pub const fn single_max_duration_ms(
+        bb: &BaseBandModulationParams,
+        preamble_length: u16,
+        window_ms: u32,
+        max_payload_length: u8,
+    ) -> u32 {
+        let timeout_symbols = match Self::single_for_window(bb, preamble_length, window_ms) {
+            RxMode::Single(symbols) => symbols as u32,
+            _ => unreachable!(),
+        };
+        let packet_us = bb.time_on_air_us(None, true, max_payload_length);
+        (timeout_symbols * bb.symbol_duration_us() + packet_us).div_ceil(1000)
+    }
+}
+
+/// Direction of a LoRaWAN frame, which determines its IQ polarity and whether a payload CRC is
+/// present. The same direction is used on both ends of the link: a device transmits with
+/// `UplinkToGateway` and receives with `DownlinkToDevice`, while a gateway (or gateway emulator)
+/// does the opposite. Using the same direction for both ends makes the frames invisible to the
+/// receiver, since IQ inversion differs between uplinks and downlinks. The sync word (public or
+/// private network) on the other hand is the same in both directions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum Direction {
+    /// Frame sent by a device to a gateway: normal IQ polarity and a payload CRC.
+    UplinkToGateway,
+    /// Frame sent by a gateway to a device: inverted IQ polarity and no payload CRC.
+    DownlinkToDevice,
+}
+
+impl Direction {
+    /// Whether IQ is inverted for frames in this direction.
+    pub fn iq_inverted(self) -> bool {
+        matches!(self, Direction::DownlinkToDevice)
+    }
+
+    /// Whether frames in this direction carry a payload CRC.
+    pub fn crc_on(self) -> bool {
+        matches!(self, Direction::UplinkToGateway)
+    }
+}
+
+/// Conventions (sync word, IQ polarity, payload CRC, preamble and header mode) of common kinds of
+/// frames, which need to match on both ends of a link. Apply the sync word with
+/// [`LoRa::set_profile`](crate::LoRa::set_profile) and create the packet parameters with
+/// [`LoRa::create_profile_packet_params`](crate::LoRa::create_profile_packet_params).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum PhyProfile {
+    /// LoRaWAN uplink (public network): normal IQ polarity and a payload CRC.
+    LorawanUplink,
+    /// LoRaWAN downlink (public network): inverted IQ polarity and no payload CRC.
+    LorawanDownlink,
+    /// LoRaWAN Class B beacon: normal IQ polarity, no payload CRC (the beacon carries its own)
+    /// and implicit header mode with a preamble of 10 symbols.
+    ClassBBeacon,
+    /// Peer-to-peer link outside of LoRaWAN: private sync word, normal IQ polarity and a payload
+    /// CRC.
+    Private,
+}
+
+impl PhyProfile {
+    /// Sync word of the profile
+    pub fn sync_word(self) -> u8 {
+        match self {
+            PhyProfile::Private => crate::LORAWAN_PRIVATE_SYNCWORD,
+            _ => crate::LORAWAN_PUBLIC_SYNCWORD,
+        }
+    }
+
+    /// Whether IQ is inverted
+    pub fn iq_inverted(self) -> bool {
+        matches!(self, PhyProfile::LorawanDownlink)
+    }
+
+    /// Whether frames carry a payload CRC
+    pub fn crc_on(self) -> bool {
+        matches!(self, PhyProfile::LorawanUplink | PhyProfile::Private)
+    }
+
+    /// Preamble length (in symbols)
+    pub fn preamble_length(self) -> u16 {
+        match self {
+            PhyProfile::ClassBBeacon => 10,
+            _ => 8,
+        }
+    }
+
+    /// Length of the payload in implicit header mode, which depends on the bandwidth for Class B
+    /// beacons (17 bytes at 125 kHz, eg: EU868, 23 bytes at 500 kHz, eg: US915). `None` in
+    /// explicit header mode.
+    pub fn implicit_payload_length(self, bandwidth: Bandwidth) -> Option<u8> {
+        match (self, bandwidth) {
+            (PhyProfile::ClassBBeacon, Bandwidth::_500KHz) => Some(23),
+            (PhyProfile::ClassBBeacon, _) => Some(17),
+            _ => None,
+        }
+    }
+}
+
+impl From<Direction> for PhyProfile {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::UplinkToGateway => PhyProfile::LorawanUplink,
+            Direction::DownlinkToDevice => PhyProfile::LorawanDownlink,
+        }
+    }
+}
+
 /// Modulation parameters for a send and/or receive communication channel
 pub struct ModulationParams {
     pub(crate) spreading_factor: SpreadingFactor,
@@ -91,6 +227,146 @@ pub struct ModulationParams {
     pub(crate) frequency_in_hz: u32,
 }
 
+impl ModulationParams {
+    /// Modulation parameters with low data rate optimization determined from the spreading factor
+    /// and bandwidth. Meant for [`RadioKind::create_modulation_params`](crate::mod_traits::RadioKind::create_modulation_params)
+    /// implementations, which validate the parameters against the capabilities of their chip
+    /// first.
+    pub fn new(
+        spreading_factor: SpreadingFactor,
+        bandwidth: Bandwidth,
+        coding_rate: CodingRate,
+        frequency_in_hz: u32,
+    ) -> Self {
+        Self {
+            spreading_factor,
+            bandwidth,
+            coding_rate,
+            low_data_rate_optimize: low_data_rate_optimize(spreading_factor, bandwidth, coding_rate),
+            frequency_in_hz,
+        }
+    }
+
+    /// Spreading factor
+    pub fn spreading_factor(&self) -> SpreadingFactor {
+        self.spreading_factor
+    }
+
+    /// Bandwidth
+    pub fn bandwidth(&self) -> Bandwidth {
+        self.bandwidth
+    }
+
+    /// Coding rate
+    pub fn coding_rate(&self) -> CodingRate {
+        self.coding_rate
+    }
+
+    /// Frequency of the channel
+    pub fn frequency_in_hz(&self) -> u32 {
+        self.frequency_in_hz
+    }
+
+    /// Override low data rate optimization, which is otherwise determined from the spreading
+    /// factor and bandwidth. Both ends of a link must use the same setting, so interoperating with
+    /// stacks which choose it differently may require forcing it on or off.
+    pub fn set_low_data_rate_optimize(&mut self, ldro: LowDataRateOptimize) {
+        self.low_data_rate_optimize = match ldro {
+            LowDataRateOptimize::Auto => {
+                low_data_rate_optimize(self.spreading_factor, self.bandwidth, self.coding_rate)
+            }
+            LowDataRateOptimize::On => 0x01,
+            LowDataRateOptimize::Off => 0x00,
+        };
+    }
+
+    /// Duration of a LoRa symbol in microseconds
+    pub fn symbol_duration_us(&self) -> u32 {
+        (1u32 << self.spreading_factor.factor()) * 1_000_000 / self.bandwidth.hz()
+    }
+
+    /// Number of preamble symbols needed to wake up a receiver which samples the channel every
+    /// `wake_interval_ms`: the preamble covers the whole interval, followed by the 8 symbols of a
+    /// regular preamble for the receiver to synchronize on.
+    pub fn wake_up_preamble_length(&self, wake_interval_ms: u32) -> Result<u16, RadioError> {
+        let symbols = (wake_interval_ms as u64 * 1000).div_ceil(self.symbol_duration_us() as u64) + 8;
+        u16::try_from(symbols).map_err(|_| RadioError::InvalidPreambleLength)
+    }
+
+    /// Whether low data rate optimization is enabled
+    pub fn low_data_rate_optimize(&self) -> bool {
+        self.low_data_rate_optimize != 0
+    }
+}
+
+/// Mode the radio enters once a transmission or reception has completed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum FallbackMode {
+    /// Standby with the RC oscillator: lowest idle current, slowest to start the next operation
+    #[default]
+    StandbyRc,
+    /// Standby with the crystal oscillator (or TCXO) running, avoiding its start-up time
+    StandbyXosc,
+    /// Frequency synthesis: the PLL keeps running, so the next operation starts fastest
+    FrequencySynthesis,
+}
+
+/// Options for [`crate::LoRa::sleep_with_options`]
+///
+/// A cold start loses the whole radio configuration (sync word, power, interrupt routing, fallback
+/// mode and image calibration), which is loaded again before the next operation. A warm start
+/// retains it at the cost of a slightly higher sleep current.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct SleepOptions {
+    /// Retain the configuration during sleep, if the chip supports it
+    pub warm_start: bool,
+    /// Keep the chip's RTC running and wake up to standby when it times out, without the MCU
+    /// having to wake the radio. The RTC period is the sleep period last programmed by a
+    /// [`RxMode::DutyCycle`] reception. Only supported by sx126x.
+    pub rtc_wakeup: bool,
+}
+
+impl SleepOptions {
+    /// Sleep with a cold start and without RTC wake-up
+    pub const fn cold() -> Self {
+        Self {
+            warm_start: false,
+            rtc_wakeup: false,
+        }
+    }
+
+    /// Sleep with a warm start and without RTC wake-up
+    pub const fn warm() -> Self {
+        Self {
+            warm_start: true,
+            rtc_wakeup: false,
+        }
+    }
+
+    /// Also wake up on the chip's RTC timeout
+    pub const fn with_rtc_wakeup(self) -> Self {
+        Self {
+            rtc_wakeup: true,
+            ..self
+        }
+    }
+}
+
+/// Low data rate optimization setting of the modulation parameters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum LowDataRateOptimize {
+    /// Enabled for symbol durations of 16.384 ms and above, as recommended by the datasheets
+    #[default]
+    Auto,
+    /// Always enabled
+    On,
+    /// Always disabled
+    Off,
+}
+
 /// Packet parameters for a send or receive communication channel
 pub struct PacketParams {
     pub(crate) preamble_length: u16,  // number of LoRa symbols in the preamble
@@ -101,6 +377,58 @@ pub struct PacketParams {
 }
 
 impl PacketParams {
+    /// Packet parameters, meant for [`RadioKind::create_packet_params`](crate::mod_traits::RadioKind::create_packet_params)
+    /// implementations, which adjust the parameters to the constraints of their chip first (eg:
+    /// a longer preamble for low spreading factors).
+    pub fn new(
+        preamble_length: u16,
+        implicit_header: bool,
+        payload_length: u8,
+        crc_on: bool,
+        iq_inverted: bool,
+    ) -> Self {
+        Self {
+            preamble_length,
+            implicit_header,
+            payload_length,
+            crc_on,
+            iq_inverted,
+        }
+    }
+
+    /// Number of LoRa symbols in the preamble
+    pub fn preamble_length(&self) -> u16 {
+        self.preamble_length
+    }
+
+    /// Whether the header is implicit, ie: not transmitted, since the payload length is known
+    pub fn implicit_header(&self) -> bool {
+        self.implicit_header
+    }
+
+    /// Payload length, which is the maximum length to receive in explicit header mode
+    pub fn payload_length(&self) -> u8 {
+        self.payload_length
+    }
+
+    /// Whether the payload carries a CRC
+    pub fn crc_on(&self) -> bool {
+        self.crc_on
+    }
+
+    /// Whether IQ is inverted
+    pub fn iq_inverted(&self) -> bool {
+        self.iq_inverted
+    }
+
+    /// The fixed payload length if implicit header mode is used.
+    ///
+    /// In implicit header mode no length is transmitted, so the length reported by the radio after
+    /// a reception can't be relied on and the configured length is used instead.
+    pub fn implicit_payload_length(&self) -> Option<u8> {
+        self.implicit_header.then_some(self.payload_length)
+    }
+
     pub(crate) fn set_payload_length(&mut self, payload_length: usize) -> Result<(), RadioError> {
         if payload_length > 255 {
             return Err(RadioError::PayloadSizeUnexpected(payload_length));
@@ -110,6 +438,166 @@ impl PacketParams {
     }
 }
 
+/// LoRa chip
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum Chip {
+    /// Semtech SX1261 (or another SX126x variant using the low power PA)
+    Sx1261,
+    /// Semtech SX1262 (or another SX126x variant using the high power PA)
+    Sx1262,
+    /// STM32WL sub-GHz radio
+    Stm32wl,
+    /// Semtech SX1272
+    Sx1272,
+    /// Semtech SX1276 family (SX1276/77/78/79)
+    Sx1276,
+    /// A radio driven by a [`RadioKind`](crate::mod_traits::RadioKind) implementation outside of
+    /// this crate
+    Other,
+}
+
+/// What the LoRa chip in use is able to do, see [`LoRa::capabilities`](crate::LoRa::capabilities).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Capabilities {
+    /// The chip, as configured by its variant
+    pub chip: Chip,
+    /// Silicon version read from the chip, if the chip provides one (sx127x `RegVersion`). SX126x
+    /// chips have no documented version register, so it is `None` for them.
+    pub version: Option<u8>,
+    /// Lowest supported frequency
+    pub min_frequency_in_hz: u32,
+    /// Highest supported frequency
+    pub max_frequency_in_hz: u32,
+    /// Lowest supported spreading factor
+    pub min_spreading_factor: SpreadingFactor,
+    /// Supported LoRa bandwidths
+    pub bandwidths: &'static [Bandwidth],
+    /// Maximum output power in dBm with the configured power amplifier
+    pub max_output_power: i32,
+    /// Whether the chip supports (G)FSK modulation (not implemented by this crate)
+    pub fsk: bool,
+    /// Whether the chip is able to transmit LR-FHSS (not implemented by this crate)
+    pub lr_fhss: bool,
+}
+
+impl Capabilities {
+    /// Whether LoRa modulation with the given spreading factor and bandwidth is supported at
+    /// the given frequency. On sx127x chips, bandwidths of 250 kHz and above are not available below
+    /// 400 MHz.
+    pub fn supports(&self, spreading_factor: SpreadingFactor, bandwidth: Bandwidth, frequency_in_hz: u32) -> bool {
+        spreading_factor.factor() >= self.min_spreading_factor.factor()
+            && self.bandwidths.contains(&bandwidth)
+            && (self.min_frequency_in_hz..=self.max_frequency_in_hz).contains(&frequency_in_hz)
+            && !(matches!(self.chip, Chip::Sx1272 | Chip::Sx1276)
+                && bandwidth.hz() >= 250_000
+                && frequency_in_hz < 400_000_000)
+    }
+}
+
+/// All LoRa bandwidths
+pub(crate) const ALL_BANDWIDTHS: &[Bandwidth] = &[
+    Bandwidth::_7KHz,
+    Bandwidth::_10KHz,
+    Bandwidth::_15KHz,
+    Bandwidth::_20KHz,
+    Bandwidth::_31KHz,
+    Bandwidth::_41KHz,
+    Bandwidth::_62KHz,
+    Bandwidth::_125KHz,
+    Bandwidth::_250KHz,
+    Bandwidth::_500KHz,
+];
+
+/// Frequency band of the SX126x image calibration (see DS_SX1261-2_V1.2 datasheet chapter 9.2.1)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[allow(missing_docs)]
+pub enum ImageCalibrationBand {
+    _430_440MHz,
+    _470_510MHz,
+    _779_787MHz,
+    _863_870MHz,
+    _902_928MHz,
+}
+
+impl ImageCalibrationBand {
+    /// The band covering the given frequency, if any.
+    pub fn from_frequency(frequency_in_hz: u32) -> Option<Self> {
+        match frequency_in_hz {
+            900_000_001.. => Some(Self::_902_928MHz),
+            850_000_001.. => Some(Self::_863_870MHz),
+            770_000_001.. => Some(Self::_779_787MHz),
+            460_000_001.. => Some(Self::_470_510MHz),
+            425_000_001.. => Some(Self::_430_440MHz),
+            _ => None,
+        }
+    }
+}
+
+/// When the image rejection is calibrated for the frequency in use
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum ImageCalibration {
+    /// Calibrate for the band of the frequency used, whenever it differs from the band calibrated
+    /// last. Hopping between channels of the same band does not recalibrate.
+    #[default]
+    Auto,
+    /// Calibrate once for the given band after a cold start, regardless of the frequency used
+    Band(ImageCalibrationBand),
+    /// Never calibrate, keeping the calibration the chip performs at power on
+    Skip,
+}
+
+/// Recalibration of the chip when the temperature drifts, for nodes exposed to wide temperature
+/// swings. The temperature is reported by the application via [`crate::LoRa::report_temperature`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub(crate) struct TemperatureRecalibration {
+    /// Recalibrate once the temperature differs by at least this many °C from the temperature of
+    /// the last calibration; `None` disables recalibration
+    pub(crate) max_delta_celsius: Option<u8>,
+    temperature: Option<i8>,
+    calibrated_temperature: Option<i8>,
+}
+
+impl TemperatureRecalibration {
+    pub(crate) fn report(&mut self, temperature_celsius: i8) {
+        self.temperature = Some(temperature_celsius);
+    }
+
+    /// The chip has been calibrated (eg: after a cold start), at the last reported temperature
+    pub(crate) fn calibrated(&mut self) {
+        self.calibrated_temperature = self.temperature;
+    }
+
+    /// Whether the temperature drifted enough since the last calibration
+    pub(crate) fn is_due(&mut self) -> bool {
+        let (Some(max_delta), Some(temperature)) = (self.max_delta_celsius, self.temperature) else {
+            return false;
+        };
+        match self.calibrated_temperature {
+            Some(calibrated) => calibrated.abs_diff(temperature) >= max_delta,
+            // the temperature was unknown at the last calibration
+            None => {
+                self.calibrated_temperature = Some(temperature);
+                false
+            }
+        }
+    }
+}
+
+/// Low data rate optimization flag for the modulation parameters, enabled for symbol durations of
+/// 16.384 ms (SF11 at 125 kHz) and above as required by both the SX126x and the SX127x datasheets.
+pub(crate) fn low_data_rate_optimize(
+    spreading_factor: SpreadingFactor,
+    bandwidth: Bandwidth,
+    coding_rate: CodingRate,
+) -> u8 {
+    lora_modulation::BaseBandModulationParams::new(spreading_factor, bandwidth, coding_rate).ldro as u8
+}
+
 /// Receive duty cycle parameters
 #[derive(Clone, Copy, PartialEq)]
 #[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
@@ -119,3 +607,203 @@ pub struct DutyCycleParams {
     /// sleep interval
     pub sleep_time: u32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet_params(implicit_header: bool, payload_length: u8) -> PacketParams {
+        PacketParams {
+            preamble_length: 8,
+            implicit_header,
+            payload_length,
+            crc_on: true,
+            iq_inverted: false,
+        }
+    }
+
+    #[test]
+    fn single_for_window_covers_window_and_preamble() {
+        let sf7 = BaseBandModulationParams::new(SpreadingFactor::_7, Bandwidth::_125KHz, CodingRate::_4_5);
+        let sf12 = BaseBandModulationParams::new(SpreadingFactor::_12, Bandwidth::_125KHz, CodingRate::_4_5);
+        // 1.024ms symbols: 50ms are 48.8 symbols
+        assert!(matches!(RxMode::single_for_window(&sf7, 8, 50), RxMode::Single(62)));
+        // 32.768ms symbols: 50ms are 1.5 symbols
+        assert!(matches!(RxMode::single_for_window(&sf12, 8, 50), RxMode::Single(15)));
+        assert!(matches!(RxMode::single_for_window(&sf12, 8, 0), RxMode::Single(13)));
+
+        // the timeout (15 symbols) plus the 28 symbols after the preamble of a 20 byte packet
+        assert_eq!(RxMode::single_max_duration_ms(&sf12, 8, 50, 20), 1410);
+    }
+
+    #[test]
+    fn phy_profiles() {
+        let uplink = PhyProfile::from(Direction::UplinkToGateway);
+        assert_eq!(uplink, PhyProfile::LorawanUplink);
+        assert!(uplink.crc_on() && !uplink.iq_inverted());
+        let downlink = PhyProfile::from(Direction::DownlinkToDevice);
+        assert!(!downlink.crc_on() && downlink.iq_inverted());
+        assert_eq!(downlink.sync_word(), uplink.sync_word());
+        assert_ne!(PhyProfile::Private.sync_word(), uplink.sync_word());
+
+        let beacon = PhyProfile::ClassBBeacon;
+        assert!(!beacon.crc_on() && !beacon.iq_inverted());
+        assert_eq!(beacon.preamble_length(), 10);
+        assert_eq!(beacon.implicit_payload_length(Bandwidth::_125KHz), Some(17));
+        assert_eq!(beacon.implicit_payload_length(Bandwidth::_500KHz), Some(23));
+        assert_eq!(uplink.implicit_payload_length(Bandwidth::_125KHz), None);
+    }
+
+    #[test]
+    fn capabilities_supports() {
+        let capabilities = Capabilities {
+            chip: Chip::Sx1272,
+            version: Some(0x22),
+            min_frequency_in_hz: 860_000_000,
+            max_frequency_in_hz: 1_020_000_000,
+            min_spreading_factor: SpreadingFactor::_6,
+            bandwidths: &[Bandwidth::_125KHz, Bandwidth::_250KHz, Bandwidth::_500KHz],
+            max_output_power: 20,
+            fsk: true,
+            lr_fhss: false,
+        };
+        assert!(capabilities.supports(SpreadingFactor::_12, Bandwidth::_125KHz, 868_100_000));
+        assert!(capabilities.supports(SpreadingFactor::_6, Bandwidth::_500KHz, 868_100_000));
+        assert!(!capabilities.supports(SpreadingFactor::_5, Bandwidth::_125KHz, 868_100_000));
+        assert!(!capabilities.supports(SpreadingFactor::_7, Bandwidth::_62KHz, 868_100_000));
+        assert!(!capabilities.supports(SpreadingFactor::_7, Bandwidth::_125KHz, 433_000_000));
+
+        // the wide bandwidths are only unavailable at low frequencies on sx127x chips
+        let sx1276 = Capabilities {
+            chip: Chip::Sx1276,
+            min_frequency_in_hz: 137_000_000,
+            bandwidths: ALL_BANDWIDTHS,
+            ..capabilities
+        };
+        assert!(sx1276.supports(SpreadingFactor::_7, Bandwidth::_125KHz, 169_000_000));
+        assert!(!sx1276.supports(SpreadingFactor::_7, Bandwidth::_500KHz, 169_000_000));
+        let sx1262 = Capabilities {
+            chip: Chip::Sx1262,
+            version: None,
+            ..sx1276
+        };
+        assert!(sx1262.supports(SpreadingFactor::_7, Bandwidth::_500KHz, 169_000_000));
+    }
+
+    #[test]
+    fn low_data_rate_optimize_threshold() {
+        let ldro = |sf, bw| low_data_rate_optimize(sf, bw, CodingRate::_4_5);
+        assert_eq!(ldro(SpreadingFactor::_10, Bandwidth::_125KHz), 0);
+        assert_eq!(ldro(SpreadingFactor::_11, Bandwidth::_125KHz), 1);
+        assert_eq!(ldro(SpreadingFactor::_12, Bandwidth::_125KHz), 1);
+        assert_eq!(ldro(SpreadingFactor::_11, Bandwidth::_250KHz), 0);
+        assert_eq!(ldro(SpreadingFactor::_12, Bandwidth::_250KHz), 1);
+        assert_eq!(ldro(SpreadingFactor::_10, Bandwidth::_62KHz), 1);
+        assert_eq!(ldro(SpreadingFactor::_12, Bandwidth::_500KHz), 0);
+    }
+
+    #[test]
+    fn temperature_recalibration() {
+        let mut recalibration = TemperatureRecalibration::default();
+        recalibration.report(20);
+        assert!(!recalibration.is_due());
+
+        recalibration.max_delta_celsius = Some(10);
+        assert!(!recalibration.is_due());
+        recalibration.report(29);
+        assert!(!recalibration.is_due());
+        recalibration.report(10);
+        assert!(recalibration.is_due());
+        recalibration.calibrated();
+        assert!(!recalibration.is_due());
+        recalibration.report(-5);
+        assert!(recalibration.is_due());
+    }
+
+    #[test]
+    fn image_calibration_band() {
+        assert_eq!(
+            ImageCalibrationBand::from_frequency(433_175_000),
+            Some(ImageCalibrationBand::_430_440MHz)
+        );
+        assert_eq!(
+            ImageCalibrationBand::from_frequency(470_300_000),
+            Some(ImageCalibrationBand::_470_510MHz)
+        );
+        assert_eq!(
+            ImageCalibrationBand::from_frequency(868_100_000),
+            Some(ImageCalibrationBand::_863_870MHz)
+        );
+        assert_eq!(
+            ImageCalibrationBand::from_frequency(923_300_000),
+            Some(ImageCalibrationBand::_902_928MHz)
+        );
+        assert_eq!(ImageCalibrationBand::from_frequency(169_400_000), None);
+    }
+
+    #[test]
+    fn low_data_rate_optimize_override() {
+        let mut params = ModulationParams {
+            spreading_factor: SpreadingFactor::_12,
+            bandwidth: Bandwidth::_125KHz,
+            coding_rate: CodingRate::_4_5,
+            low_data_rate_optimize: 0x01,
+            frequency_in_hz: 868_100_000,
+        };
+        params.set_low_data_rate_optimize(LowDataRateOptimize::Off);
+        assert!(!params.low_data_rate_optimize());
+        params.set_low_data_rate_optimize(LowDataRateOptimize::Auto);
+        assert!(params.low_data_rate_optimize());
+        params.spreading_factor = SpreadingFactor::_7;
+        params.set_low_data_rate_optimize(LowDataRateOptimize::On);
+        assert!(params.low_data_rate_optimize());
+    }
+
+    #[test]
+    fn wake_up_preamble_length() {
+        let mut params = ModulationParams {
+            spreading_factor: SpreadingFactor::_7,
+            bandwidth: Bandwidth::_125KHz,
+            coding_rate: CodingRate::_4_5,
+            low_data_rate_optimize: 0x00,
+            frequency_in_hz: 868_100_000,
+        };
+        assert_eq!(params.symbol_duration_us(), 1024);
+        assert_eq!(params.wake_up_preamble_length(1000), Ok(985));
+        assert_eq!(params.wake_up_preamble_length(0), Ok(8));
+        params.bandwidth = Bandwidth::_500KHz;
+        params.spreading_factor = SpreadingFactor::_5;
+        assert_eq!(
+            params.wake_up_preamble_length(5000),
+            Err(RadioError::InvalidPreambleLength)
+        );
+    }
+
+    #[test]
+    fn direction() {
+        assert!(!Direction::UplinkToGateway.iq_inverted());
+        assert!(Direction::UplinkToGateway.crc_on());
+        assert!(Direction::DownlinkToDevice.iq_inverted());
+        assert!(!Direction::DownlinkToDevice.crc_on());
+    }
+
+    #[test]
+    fn implicit_payload_length() {
+        assert_eq!(packet_params(true, 17).implicit_payload_length(), Some(17));
+        assert_eq!(packet_params(false, 17).implicit_payload_length(), None);
+
+        let mut params = packet_params(true, 17);
+        params.set_payload_length(32).unwrap();
+        assert_eq!(params.implicit_payload_length(), Some(32));
+        assert!(params.set_payload_length(256).is_err());
+    }
+
+    #[test]
+    fn sleep_options() {
+        assert_eq!(SleepOptions::default(), SleepOptions::cold());
+        let options = SleepOptions::warm().with_rtc_wakeup();
+        assert!(options.warm_start);
+        assert!(options.rtc_wakeup);
+        assert!(!SleepOptions::cold().with_rtc_wakeup().warm_start);
+    }
+}
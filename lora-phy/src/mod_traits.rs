@@ -18,6 +18,28 @@ pub trait InterfaceVariant {
     async fn enable_rf_switch_tx(&mut self) -> Result<(), RadioError>;
     /// Disable all antennas
     async fn disable_rf_switch(&mut self) -> Result<(), RadioError>;
+    /// Gain (in dB) of an external front-end module in the transmit path. The output power
+    /// requested from the LoRa chip is lowered by this gain.
+    fn tx_gain(&self) -> i8 {
+        0
+    }
+    /// Bypass the amplifiers of an external front-end module, taking effect with the next send or
+    /// receive operation
+    fn set_front_end_bypass(&mut self, _bypass: bool) {}
+}
+
+/// External front-end module (PA/LNA) between a LoRa chip and its antenna, controlled by
+/// [`crate::iv::FrontEndInterfaceVariant`] along with the RF switch of the board.
+#[allow(async_fn_in_trait)]
+pub trait FrontEnd {
+    /// Enable the transmit path, through the PA unless bypassed
+    async fn enable_tx(&mut self, bypass: bool) -> Result<(), RadioError>;
+    /// Enable the receive path, through the LNA unless bypassed
+    async fn enable_rx(&mut self, bypass: bool) -> Result<(), RadioError>;
+    /// Disable the front-end module
+    async fn disable(&mut self) -> Result<(), RadioError>;
+    /// Gain (in dB) of the transmit path, negative for the insertion loss of a bypassed PA
+    fn tx_gain(&self, bypass: bool) -> i8;
 }
 
 /// Specifies an IRQ processing state to run the loop to
@@ -31,8 +53,15 @@ pub enum IrqState {
 
 /// Functions implemented for a specific kind of LoRa chip, called internally by the outward facing
 /// LoRa physical layer API
+///
+/// Radios may also be supported by implementations outside of this crate, which are checked with
+/// the [`conformance`](crate::conformance) suite.
 #[allow(async_fn_in_trait)]
 pub trait RadioKind {
+    /// Get the capabilities of the LoRa chip, reading its version if available
+    async fn capabilities(&mut self) -> Result<Capabilities, RadioError> {
+        Err(RadioError::CapabilitiesUnsupported)
+    }
     /// Initialize lora radio
     async fn init_lora(&mut self, sync_word: u8) -> Result<(), RadioError>;
     /// Create modulation parameters specific to the LoRa chip kind and type
@@ -43,6 +72,21 @@ pub trait RadioKind {
         coding_rate: CodingRate,
         frequency_in_hz: u32,
     ) -> Result<ModulationParams, RadioError>;
+    /// Enable or disable boosted reception (higher sensitivity at the cost of current), taking
+    /// effect with the next reception. Ignored by chips without boosted reception.
+    fn set_rx_boost(&mut self, _rx_boost: bool) {}
+    /// Whether boosted reception is enabled
+    fn rx_boost(&self) -> bool {
+        false
+    }
+    /// Bypass the amplifiers of an external front-end module, taking effect with the next send or
+    /// receive operation
+    fn set_front_end_bypass(&mut self, _bypass: bool) {}
+    /// Shortest preamble (in symbols) the LoRa chip can be configured with; the longest is 65535.
+    /// Defaults to the largest minimum of the supported chips (6 symbols on sx127x).
+    fn min_preamble_length(&self) -> u16 {
+        6
+    }
     /// Create packet parameters specific to the LoRa chip kind and type
     fn create_packet_params(
         &self,
@@ -59,8 +103,13 @@ pub trait RadioKind {
     async fn ensure_ready(&mut self, mode: RadioMode) -> Result<(), RadioError>;
     /// Place the LoRa chip in standby mode
     async fn set_standby(&mut self) -> Result<(), RadioError>;
+    /// Set the mode the LoRa chip falls back to after a transmission or reception. Ignored by chips
+    /// which always fall back to the same mode (eg: sx127x, which returns to standby).
+    async fn set_fallback_mode(&mut self, _fallback_mode: FallbackMode) -> Result<(), RadioError> {
+        Ok(())
+    }
     /// Place the LoRa chip in power-saving mode
-    async fn set_sleep(&mut self, warm_start_if_possible: bool, delay: &mut impl DelayNs) -> Result<(), RadioError>;
+    async fn set_sleep(&mut self, options: SleepOptions, delay: &mut impl DelayNs) -> Result<(), RadioError>;
     /// Set the LoRa chip send and receive buffer base addresses
     async fn set_tx_rx_buffer_base_address(
         &mut self,
@@ -78,12 +127,26 @@ pub trait RadioKind {
     async fn set_modulation_params(&mut self, mdltn_params: &ModulationParams) -> Result<(), RadioError>;
     /// Set the LoRa chip packet parameters prior to sending or receiving packets
     async fn set_packet_params(&mut self, pkt_params: &PacketParams) -> Result<(), RadioError>;
-    /// Set the LoRa chip to support a given communication channel frequency
-    async fn calibrate_image(&mut self, frequency_in_hz: u32) -> Result<(), RadioError>;
+    /// Calibrate the image rejection of the LoRa chip for a frequency band
+    async fn calibrate_image(&mut self, band: ImageCalibrationBand) -> Result<(), RadioError>;
+    /// Re-run the calibration of the internal oscillators, PLL and ADC of the LoRa chip, eg: after
+    /// a temperature change. The image rejection is calibrated separately.
+    async fn calibrate_oscillators(&mut self) -> Result<(), RadioError> {
+        Ok(())
+    }
     /// Set the frequency for a communication channel
     async fn set_channel(&mut self, frequency_in_hz: u32) -> Result<(), RadioError>;
     /// Set a payload for a subsequent send operation
     async fn set_payload(&mut self, payload: &[u8]) -> Result<(), RadioError>;
+    /// Set a payload for a subsequent send operation from several buffers, which are written to
+    /// the chip one after the other (eg: a protocol header and the application data)
+    async fn set_payload_parts(&mut self, parts: &[&[u8]]) -> Result<(), RadioError> {
+        match parts {
+            [] => self.set_payload(&[]).await,
+            [payload] => self.set_payload(payload).await,
+            _ => Err(RadioError::InvalidConfiguration),
+        }
+    }
     /// Perform a transmit operation
     async fn do_tx(&mut self) -> Result<(), RadioError>;
     /// Set up to perform a receive operation (single-shot, continuous, or duty cycle)
@@ -94,9 +157,29 @@ pub trait RadioKind {
         rx_pkt_params: &PacketParams,
         receiving_buffer: &mut [u8],
     ) -> Result<u8, RadioError>;
+    /// Read part of the packet made available as the result of a receive operation, starting at
+    /// `offset` into the payload, filling `chunk` up to the end of the payload. Returns the length
+    /// of the whole payload.
+    ///
+    /// The default implementation reads the whole packet with [`RadioKind::get_rx_payload`] and
+    /// copies the requested part; chips whose buffer can be read at an offset should override it.
+    async fn get_rx_payload_chunk(
+        &mut self,
+        rx_pkt_params: &PacketParams,
+        offset: u8,
+        chunk: &mut [u8],
+    ) -> Result<u8, RadioError> {
+        let mut payload = [0u8; 255];
+        let payload_length = self.get_rx_payload(rx_pkt_params, &mut payload).await?;
+        let start = usize::from(offset).min(payload_length as usize);
+        let len = chunk.len().min(payload_length as usize - start);
+        chunk[..len].copy_from_slice(&payload[start..start + len]);
+        Ok(payload_length)
+    }
     /// Get the RSSI and SNR for the packet made available as the result of a receive operation
     async fn get_rx_packet_status(&mut self) -> Result<PacketStatus, RadioError>;
-    /// Get the current RSSI
+    /// Get the instantaneous RSSI of the channel (eg: while listening), as opposed to the RSSI of a
+    /// received packet which is part of its [`PacketStatus`]
     async fn get_rssi(&mut self) -> Result<i16, RadioError>;
     /// Perform a channel activity detection operation
     async fn do_cad(&mut self, mdltn_params: &ModulationParams) -> Result<(), RadioError>;
@@ -0,0 +1,161 @@
+//! Noise floor estimation: the RSSI of idle channels is sampled (using [`LoRa::listen`]) and
+//! smoothed per channel with an exponential moving average. Comparing the RSSI of received frames
+//! against the noise floor of their channel gives a measure of the channel quality, which may
+//! drive data rate overrides or avoiding noisy channels.
+
+use super::*;
+
+/// Noise floor of one channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct ChannelNoise {
+    /// Frequency of the channel.
+    pub frequency_in_hz: u32,
+    /// Number of samples taken on this channel, saturating.
+    pub samples: u16,
+    // moving average in 1/16 dBm, so that small changes aren't lost to rounding
+    average: i32,
+}
+
+impl ChannelNoise {
+    /// The estimated noise floor in dBm.
+    pub fn noise_floor(&self) -> i16 {
+        (self.average >> 4) as i16
+    }
+}
+
+/// Noise floor estimates of up to `N` channels. When a new channel is sampled while all entries
+/// are in use, the channel with the fewest samples is replaced.
+#[derive(Clone, Debug)]
+pub struct NoiseFloorEstimator<const N: usize> {
+    channels: [Option<ChannelNoise>; N],
+    smoothing: u8,
+}
+
+impl<const N: usize> Default for NoiseFloorEstimator<N> {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+impl<const N: usize> NoiseFloorEstimator<N> {
+    /// Create an estimator which weighs each new sample with 1/2^`smoothing` (eg: 3 for 1/8).
+    pub fn new(smoothing: u8) -> Self {
+        Self {
+            channels: [None; N],
+            smoothing: smoothing.min(8),
+        }
+    }
+
+    /// The estimated noise floor of the given channel in dBm, if it has been sampled.
+    pub fn noise_floor(&self, frequency_in_hz: u32) -> Option<i16> {
+        self.get(frequency_in_hz).map(ChannelNoise::noise_floor)
+    }
+
+    /// How far (in dB) a frame received with `rssi` on the given channel has been above the noise
+    /// floor, if the channel has been sampled.
+    pub fn margin(&self, frequency_in_hz: u32, rssi: i16) -> Option<i16> {
+        self.noise_floor(frequency_in_hz).map(|noise_floor| rssi - noise_floor)
+    }
+
+    /// The estimate of the given channel, if it has been sampled.
+    pub fn get(&self, frequency_in_hz: u32) -> Option<&ChannelNoise> {
+        self.iter().find(|channel| channel.frequency_in_hz == frequency_in_hz)
+    }
+
+    /// Iterate over all sampled channels.
+    pub fn iter(&self) -> impl Iterator<Item = &ChannelNoise> {
+        self.channels.iter().flatten()
+    }
+
+    /// Add an RSSI sample (in dBm) of the given idle channel. The first sample of a channel is
+    /// used as its initial estimate.
+    pub fn add_sample(&mut self, frequency_in_hz: u32, rssi: i16) {
+        let sample = (rssi as i32) << 4;
+        let smoothing = self.smoothing;
+        if let Some(channel) = self
+            .channels
+            .iter_mut()
+            .flatten()
+            .find(|c| c.frequency_in_hz == frequency_in_hz)
+        {
+            channel.average += (sample - channel.average) >> smoothing;
+            channel.samples = channel.samples.saturating_add(1);
+            return;
+        }
+        let slot = match self.channels.iter().position(Option::is_none) {
+            Some(free) => free,
+            None => match (0..N).min_by_key(|&i| self.channels[i].map_or(0, |c| c.samples)) {
+                Some(fewest) => fewest,
+                None => return,
+            },
+        };
+        self.channels[slot] = Some(ChannelNoise {
+            frequency_in_hz,
+            samples: 1,
+            average: sample,
+        });
+    }
+}
+
+impl<RK, DLY> LoRa<RK, DLY>
+where
+    RK: RadioKind,
+    DLY: DelayNs,
+{
+    /// Sample the noise floor of the given channel while it is idle, reading the RSSI `readings`
+    /// times 1 ms apart. The lowest reading is added to `estimator`, so that a frame transmitted
+    /// during the sampling does not distort the estimate, and returned. The radio is left in
+    /// standby.
+    pub async fn sample_noise_floor<const N: usize>(
+        &mut self,
+        estimator: &mut NoiseFloorEstimator<N>,
+        frequency_in_hz: u32,
+        bandwidth: Bandwidth,
+        readings: u8,
+    ) -> Result<i16, RadioError> {
+        self.listen(frequency_in_hz, bandwidth).await?;
+        let mut rssi = i16::MAX;
+        for _ in 0..readings.max(1) {
+            self.delay.delay_ms(1).await;
+            rssi = rssi.min(self.get_rssi().await?);
+        }
+        self.radio_kind.set_standby().await?;
+        self.radio_mode = RadioMode::Standby;
+        trace!("Noise floor sample: {} dBm at {} Hz", rssi, frequency_in_hz);
+        estimator.add_sample(frequency_in_hz, rssi);
+        Ok(rssi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moving_average() {
+        let mut estimator = NoiseFloorEstimator::<2>::default();
+        assert_eq!(estimator.noise_floor(868_100_000), None);
+        estimator.add_sample(868_100_000, -120);
+        assert_eq!(estimator.noise_floor(868_100_000), Some(-120));
+        for _ in 0..32 {
+            estimator.add_sample(868_100_000, -100);
+        }
+        assert_eq!(estimator.noise_floor(868_100_000), Some(-101));
+        assert_eq!(estimator.get(868_100_000).unwrap().samples, 33);
+        assert_eq!(estimator.margin(868_100_000, -90), Some(11));
+    }
+
+    #[test]
+    fn replaces_fewest_samples() {
+        let mut estimator = NoiseFloorEstimator::<2>::default();
+        estimator.add_sample(868_100_000, -120);
+        estimator.add_sample(868_100_000, -120);
+        estimator.add_sample(868_300_000, -110);
+        estimator.add_sample(868_500_000, -115);
+        assert_eq!(estimator.noise_floor(868_100_000), Some(-120));
+        assert_eq!(estimator.noise_floor(868_300_000), None);
+        assert_eq!(estimator.noise_floor(868_500_000), Some(-115));
+        assert_eq!(estimator.iter().count(), 2);
+    }
+}
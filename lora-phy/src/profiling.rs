@@ -0,0 +1,311 @@
+//! Measure where the time of radio operations goes: waiting for the BUSY line, SPI transfers and
+//! the latency between an IRQ and its processing. Missed RX windows are often caused by a slow SPI
+//! HAL or board wiring (eg: a BUSY line which is not connected), which these measurements reveal.
+//!
+//! A [`Profiler`] wraps the SPI device and the [`InterfaceVariant`] of the board before they are
+//! handed to the driver:
+//!
+//! ```ignore
+//! let profiler = Profiler::new(clock);
+//! let radio = Sx126x::new(profiler.spi(spi), profiler.iv(iv), config);
+//! // ... run radio operations ...
+//! let rx = profiler.profile().receive;
+//! info!("SPI: {} us max, IRQ latency: {} us max", rx.spi.max_us, rx.irq_latency.max_us);
+//! ```
+//!
+//! The measurements are aggregated per [`Operation`], which follows the RF switch: from enabling
+//! the transmit or receive path until the next change of the RF switch.
+
+use core::cell::Cell;
+
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::spi::{ErrorType, Operation as SpiOperation, SpiDevice};
+
+use crate::mod_params::RadioError;
+use crate::mod_traits::InterfaceVariant;
+
+/// Monotonic time source of the [`Profiler`]
+pub trait Clock {
+    /// Current time in microseconds
+    fn now_us(&self) -> u64;
+}
+
+/// Radio operation the measurements are attributed to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum Operation {
+    /// The transmit path is enabled
+    Transmit,
+    /// The receive path is enabled, eg: for a reception or channel activity detection
+    Receive,
+    /// Configuration and everything else while the RF switch is disabled
+    Other,
+}
+
+/// Aggregated durations of one kind of measurement
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Stats {
+    /// Number of measurements
+    pub count: u32,
+    /// Sum of all durations
+    pub total_us: u64,
+    /// Longest duration
+    pub max_us: u64,
+}
+
+impl Stats {
+    fn record(&mut self, duration_us: u64) {
+        self.count = self.count.saturating_add(1);
+        self.total_us = self.total_us.saturating_add(duration_us);
+        self.max_us = self.max_us.max(duration_us);
+    }
+
+    /// Average duration, `None` without measurements
+    pub fn mean_us(&self) -> Option<u64> {
+        (self.count > 0).then(|| self.total_us / self.count as u64)
+    }
+}
+
+/// Measurements during one kind of [`Operation`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct OperationProfile {
+    /// Time spent waiting for the radio to become ready (BUSY line)
+    pub busy: Stats,
+    /// Duration of SPI transactions
+    pub spi: Stats,
+    /// Time from an IRQ to the start of its processing, ie: the next SPI transaction
+    pub irq_latency: Stats,
+}
+
+/// Measurements of a [`Profiler`], per [`Operation`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Profile {
+    /// Measurements while transmitting
+    pub transmit: OperationProfile,
+    /// Measurements while receiving
+    pub receive: OperationProfile,
+    /// Measurements while the RF switch is disabled
+    pub other: OperationProfile,
+}
+
+impl Profile {
+    /// Measurements during the given operation
+    pub fn operation(&self, operation: Operation) -> &OperationProfile {
+        match operation {
+            Operation::Transmit => &self.transmit,
+            Operation::Receive => &self.receive,
+            Operation::Other => &self.other,
+        }
+    }
+
+    fn operation_mut(&mut self, operation: Operation) -> &mut OperationProfile {
+        match operation {
+            Operation::Transmit => &mut self.transmit,
+            Operation::Receive => &mut self.receive,
+            Operation::Other => &mut self.other,
+        }
+    }
+}
+
+/// Collects the measurements of the SPI device and InterfaceVariant wrapped with
+/// [`Profiler::spi`] and [`Profiler::iv`]
+pub struct Profiler<C> {
+    clock: C,
+    profile: Cell<Profile>,
+    operation: Cell<Operation>,
+    irq_at: Cell<Option<u64>>,
+}
+
+impl<C: Clock> Profiler<C> {
+    /// Create a profiler measuring with the given clock
+    pub fn new(clock: C) -> Self {
+        Self {
+            clock,
+            profile: Cell::new(Profile::default()),
+            operation: Cell::new(Operation::Other),
+            irq_at: Cell::new(None),
+        }
+    }
+
+    /// Wrap the SPI device of the radio to measure its transactions
+    pub fn spi<SPI>(&self, spi: SPI) -> ProfilingSpi<'_, SPI, C> {
+        ProfilingSpi { spi, profiler: self }
+    }
+
+    /// Wrap the InterfaceVariant of the board to measure BUSY waits and IRQ latencies
+    pub fn iv<IV>(&self, iv: IV) -> ProfilingInterfaceVariant<'_, IV, C> {
+        ProfilingInterfaceVariant { iv, profiler: self }
+    }
+
+    /// Measurements so far
+    pub fn profile(&self) -> Profile {
+        self.profile.get()
+    }
+
+    /// Discard the measurements so far
+    pub fn reset(&self) {
+        self.profile.set(Profile::default());
+    }
+
+    fn now_us(&self) -> u64 {
+        self.clock.now_us()
+    }
+
+    fn record(&self, measure: fn(&mut OperationProfile) -> &mut Stats, since_us: u64) {
+        let duration_us = self.now_us().saturating_sub(since_us);
+        let mut profile = self.profile.get();
+        measure(profile.operation_mut(self.operation.get())).record(duration_us);
+        self.profile.set(profile);
+    }
+
+    fn irq(&self) {
+        self.irq_at.set(Some(self.now_us()));
+    }
+
+    fn spi_transaction_start(&self) -> u64 {
+        if let Some(irq_at) = self.irq_at.take() {
+            self.record(|p| &mut p.irq_latency, irq_at);
+        }
+        self.now_us()
+    }
+}
+
+/// SPI device measured by a [`Profiler`]
+pub struct ProfilingSpi<'a, SPI, C> {
+    spi: SPI,
+    profiler: &'a Profiler<C>,
+}
+
+impl<SPI: ErrorType, C> ErrorType for ProfilingSpi<'_, SPI, C> {
+    type Error = SPI::Error;
+}
+
+impl<SPI, C> SpiDevice for ProfilingSpi<'_, SPI, C>
+where
+    SPI: SpiDevice,
+    C: Clock,
+{
+    async fn transaction(&mut self, operations: &mut [SpiOperation<'_, u8>]) -> Result<(), Self::Error> {
+        let start = self.profiler.spi_transaction_start();
+        let result = self.spi.transaction(operations).await;
+        self.profiler.record(|p| &mut p.spi, start);
+        result
+    }
+}
+
+/// InterfaceVariant measured by a [`Profiler`]
+pub struct ProfilingInterfaceVariant<'a, IV, C> {
+    iv: IV,
+    profiler: &'a Profiler<C>,
+}
+
+impl<IV, C> InterfaceVariant for ProfilingInterfaceVariant<'_, IV, C>
+where
+    IV: InterfaceVariant,
+    C: Clock,
+{
+    async fn reset(&mut self, delay: &mut impl DelayNs) -> Result<(), RadioError> {
+        self.iv.reset(delay).await
+    }
+    async fn wait_on_busy(&mut self) -> Result<(), RadioError> {
+        let start = self.profiler.now_us();
+        let result = self.iv.wait_on_busy().await;
+        self.profiler.record(|p| &mut p.busy, start);
+        result
+    }
+    async fn await_irq(&mut self) -> Result<(), RadioError> {
+        self.iv.await_irq().await?;
+        self.profiler.irq();
+        Ok(())
+    }
+
+    async fn enable_rf_switch_rx(&mut self) -> Result<(), RadioError> {
+        self.profiler.operation.set(Operation::Receive);
+        self.iv.enable_rf_switch_rx().await
+    }
+    async fn enable_rf_switch_tx(&mut self) -> Result<(), RadioError> {
+        self.profiler.operation.set(Operation::Transmit);
+        self.iv.enable_rf_switch_tx().await
+    }
+    async fn disable_rf_switch(&mut self) -> Result<(), RadioError> {
+        self.profiler.operation.set(Operation::Other);
+        self.iv.disable_rf_switch().await
+    }
+
+    fn tx_gain(&self) -> i8 {
+        self.iv.tx_gain()
+    }
+    fn set_front_end_bypass(&mut self, bypass: bool) {
+        self.iv.set_front_end_bypass(bypass);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestClock(Cell<u64>);
+
+    impl Clock for TestClock {
+        fn now_us(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn aggregate_per_operation() {
+        let profiler = Profiler::new(TestClock(Cell::new(0)));
+        let advance = |us| profiler.clock.0.set(profiler.clock.0.get() + us);
+
+        let start = profiler.spi_transaction_start();
+        advance(40);
+        profiler.record(|p| &mut p.spi, start);
+
+        profiler.operation.set(Operation::Receive);
+        profiler.irq();
+        advance(300);
+        let start = profiler.spi_transaction_start();
+        advance(20);
+        profiler.record(|p| &mut p.spi, start);
+        let start = profiler.spi_transaction_start();
+        advance(60);
+        profiler.record(|p| &mut p.spi, start);
+
+        let profile = profiler.profile();
+        assert_eq!(
+            profile.other.spi,
+            Stats {
+                count: 1,
+                total_us: 40,
+                max_us: 40
+            }
+        );
+        let rx = profile.operation(Operation::Receive);
+        assert_eq!(
+            rx.spi,
+            Stats {
+                count: 2,
+                total_us: 80,
+                max_us: 60
+            }
+        );
+        assert_eq!(rx.spi.mean_us(), Some(40));
+        // only the first transaction after the IRQ processes it
+        assert_eq!(
+            rx.irq_latency,
+            Stats {
+                count: 1,
+                total_us: 300,
+                max_us: 300
+            }
+        );
+        assert_eq!(profile.transmit, OperationProfile::default());
+        assert_eq!(profile.transmit.busy.mean_us(), None);
+
+        profiler.reset();
+        assert_eq!(profiler.profile(), Profile::default());
+    }
+}
@@ -11,6 +11,12 @@ use crate::{InterfaceVariant, RadioKind, SpiInterface};
 mod variant;
 pub use variant::*;
 
+/// SPI mode of Sx126x chips, eg: for the configuration of a device on a shared SPI bus
+pub const SPI_MODE: embedded_hal::spi::Mode = embedded_hal::spi::MODE_0;
+
+/// Highest SPI clock frequency supported by Sx126x chips
+pub const MAX_SPI_FREQUENCY_HZ: u32 = 16_000_000;
+
 // Maximum number of registers that can be added to the retention list
 const MAX_NUMBER_REGS_IN_RETENTION: u8 = 4;
 
@@ -49,7 +55,9 @@ pub struct Config<C: Sx126xVariant + Sized> {
     pub tcxo_ctrl: Option<TcxoCtrlVoltage>,
     /// Whether board is using optional DCDC in addition to LDO
     pub use_dcdc: bool,
-    /// Whether to boost receive
+    /// Whether to receive with boosted gain (RxGain 0x96) instead of power saving gain (0x94),
+    /// improving the sensitivity by about 3 dB at the cost of about 2 mA. May be changed at
+    /// runtime with [`LoRa::set_rx_boost`](crate::LoRa::set_rx_boost).
     pub rx_boost: bool,
 }
 
@@ -206,6 +214,35 @@ where
         ];
         self.intf.write(&register_and_evt_clear, false).await
     }
+
+    // Length and start offset in the data buffer of the last received payload
+    async fn get_rx_payload_position(&mut self, rx_pkt_params: &PacketParams) -> Result<(u8, u8), RadioError> {
+        let op_code = [OpCode::GetRxBufferStatus.value()];
+        let mut rx_buffer_status = [0x00u8; 2];
+        let read_status = self.intf.read_with_status(&op_code, &mut rx_buffer_status).await?;
+        if OpStatusErrorMask::is_error(read_status) {
+            return Err(RadioError::OpError(read_status));
+        }
+
+        let mut payload_length_buffer = [0x00u8];
+        if rx_pkt_params.implicit_header {
+            self.intf
+                .read(
+                    &[
+                        OpCode::ReadRegister.value(),
+                        Register::PayloadLength.addr1(),
+                        Register::PayloadLength.addr2(),
+                        0x00u8,
+                    ],
+                    &mut payload_length_buffer,
+                )
+                .await?;
+        } else {
+            payload_length_buffer[0] = rx_buffer_status[0];
+        }
+
+        Ok((payload_length_buffer[0], rx_buffer_status[1]))
+    }
 }
 
 // Convert u8 sync word to two byte value expected by sx126x
@@ -219,6 +256,25 @@ where
     IV: InterfaceVariant,
     C: Sx126xVariant,
 {
+    async fn capabilities(&mut self) -> Result<Capabilities, RadioError> {
+        let max_output_power = match self.config.chip.get_device_sel() {
+            DeviceSel::LowPowerPA => 15,
+            DeviceSel::HighPowerPA => 22,
+        };
+        Ok(Capabilities {
+            chip: self.config.chip.chip(),
+            // sx126x chips have no documented register holding the silicon version
+            version: None,
+            min_frequency_in_hz: 150_000_000,
+            max_frequency_in_hz: 960_000_000,
+            min_spreading_factor: SpreadingFactor::_5,
+            bandwidths: ALL_BANDWIDTHS,
+            max_output_power,
+            fsk: true,
+            lr_fhss: true,
+        })
+    }
+
     async fn init_lora(&mut self, sync_word: u8) -> Result<(), RadioError> {
         // DC-DC regulator setup (default is LDO)
         if self.config.use_dcdc {
@@ -295,24 +351,31 @@ where
         spreading_factor_value(spreading_factor)?;
         bandwidth_value(bandwidth)?;
         coding_rate_value(coding_rate)?;
-        if ((bandwidth == Bandwidth::_250KHz) || (bandwidth == Bandwidth::_500KHz)) && (frequency_in_hz < 400_000_000) {
-            return Err(RadioError::InvalidBandwidthForFrequency);
-        }
 
-        let mut low_data_rate_optimize = 0x00u8;
-        if (((spreading_factor == SpreadingFactor::_11) || (spreading_factor == SpreadingFactor::_12))
-            && (bandwidth == Bandwidth::_125KHz))
-            || ((spreading_factor == SpreadingFactor::_12) && (bandwidth == Bandwidth::_250KHz))
-        {
-            low_data_rate_optimize = 0x01u8;
-        }
-        Ok(ModulationParams {
+        // Low data rate optimization is recommended for symbol durations of 16.38 ms and above,
+        // including the narrow bandwidths
+        Ok(ModulationParams::new(
             spreading_factor,
             bandwidth,
             coding_rate,
-            low_data_rate_optimize,
             frequency_in_hz,
-        })
+        ))
+    }
+
+    fn set_rx_boost(&mut self, rx_boost: bool) {
+        self.config.rx_boost = rx_boost;
+    }
+
+    fn rx_boost(&self) -> bool {
+        self.config.rx_boost
+    }
+
+    fn set_front_end_bypass(&mut self, bypass: bool) {
+        self.intf.iv.set_front_end_bypass(bypass);
+    }
+
+    fn min_preamble_length(&self) -> u16 {
+        1
     }
 
     fn create_packet_params(
@@ -331,13 +394,13 @@ where
             preamble_length = 12;
         }
 
-        Ok(PacketParams {
+        Ok(PacketParams::new(
             preamble_length,
             implicit_header,
             payload_length,
             crc_on,
             iq_inverted,
-        })
+        ))
     }
 
     async fn reset(&mut self, delay: &mut impl DelayNs) -> Result<(), RadioError> {
@@ -363,12 +426,22 @@ where
         self.intf.iv.disable_rf_switch().await
     }
 
-    async fn set_sleep(&mut self, warm_start_if_possible: bool, delay: &mut impl DelayNs) -> Result<(), RadioError> {
+    async fn set_fallback_mode(&mut self, fallback_mode: FallbackMode) -> Result<(), RadioError> {
+        let mode = match fallback_mode {
+            FallbackMode::StandbyRc => 0x20,
+            FallbackMode::StandbyXosc => 0x30,
+            FallbackMode::FrequencySynthesis => 0x40,
+        };
+        let op_code_and_fallback_mode = [OpCode::SetTxFallbackMode.value(), mode];
+        self.intf.write(&op_code_and_fallback_mode, false).await
+    }
+
+    async fn set_sleep(&mut self, options: SleepOptions, delay: &mut impl DelayNs) -> Result<(), RadioError> {
         self.intf.iv.disable_rf_switch().await?;
         let sleep_params = SleepParams {
-            wakeup_rtc: false,
+            wakeup_rtc: options.rtc_wakeup,
             reset: false,
-            warm_start: warm_start_if_possible,
+            warm_start: options.warm_start,
         };
         let op_code_and_sleep_params = [OpCode::SetSleep.value(), sleep_params.value()];
         self.intf.write(&op_code_and_sleep_params, true).await?;
@@ -403,6 +476,7 @@ where
         mdltn_params: Option<&ModulationParams>,
         is_tx_prep: bool,
     ) -> Result<(), RadioError> {
+        let output_power = output_power - i32::from(self.intf.iv.tx_gain());
         let tx_params_power;
         let ramp_time = match is_tx_prep {
             true => RampTime::Ramp40Us,   // for instance, prior to TX or CAD
@@ -523,7 +597,8 @@ where
         ];
         self.intf.write(&op_code_and_mod_params, false).await?;
 
-        // Handle modulation quality with the 500 kHz LoRa bandwidth (see DS_SX1261-2_V1.2 datasheet chapter 15.1)
+        // Handle modulation quality with the 500 kHz LoRa bandwidth (see DS_SX1261-2_V1.2 datasheet chapter 15.1).
+        // This applies to all silicon versions, which is why no version is read at init.
         let mut tx_mod = [0x00u8];
         self.intf
             .read(
@@ -597,26 +672,23 @@ where
         Ok(())
     }
 
+    async fn calibrate_oscillators(&mut self) -> Result<(), RadioError> {
+        // RC64k, RC13M, PLL and ADC, the image rejection being calibrated per band
+        self.intf
+            .write(&[OpCode::Calibrate.value(), 0b0011_1111], false)
+            .await?;
+        self.intf.iv.wait_on_busy().await
+    }
+
     // Calibrate the image rejection based on the given frequency
-    async fn calibrate_image(&mut self, frequency_in_hz: u32) -> Result<(), RadioError> {
-        let mut cal_freq = [0x00u8, 0x00u8];
-
-        if frequency_in_hz > 900000000 {
-            cal_freq[0] = 0xE1;
-            cal_freq[1] = 0xE9;
-        } else if frequency_in_hz > 850000000 {
-            cal_freq[0] = 0xD7;
-            cal_freq[1] = 0xDB;
-        } else if frequency_in_hz > 770000000 {
-            cal_freq[0] = 0xC1;
-            cal_freq[1] = 0xC5;
-        } else if frequency_in_hz > 460000000 {
-            cal_freq[0] = 0x75;
-            cal_freq[1] = 0x81;
-        } else if frequency_in_hz > 425000000 {
-            cal_freq[0] = 0x6B;
-            cal_freq[1] = 0x6F;
-        }
+    async fn calibrate_image(&mut self, band: ImageCalibrationBand) -> Result<(), RadioError> {
+        let cal_freq = match band {
+            ImageCalibrationBand::_430_440MHz => [0x6B, 0x6F],
+            ImageCalibrationBand::_470_510MHz => [0x75, 0x81],
+            ImageCalibrationBand::_779_787MHz => [0xC1, 0xC5],
+            ImageCalibrationBand::_863_870MHz => [0xD7, 0xDB],
+            ImageCalibrationBand::_902_928MHz => [0xE1, 0xE9],
+        };
 
         let op_code_and_cal_freq = [OpCode::CalibrateImage.value(), cal_freq[0], cal_freq[1]];
         self.intf.write(&op_code_and_cal_freq, false).await
@@ -636,8 +708,17 @@ where
     }
 
     async fn set_payload(&mut self, payload: &[u8]) -> Result<(), RadioError> {
-        let op_code_and_offset = [OpCode::WriteBuffer.value(), 0x00u8];
-        self.intf.write_with_payload(&op_code_and_offset, payload, false).await
+        self.set_payload_parts(&[payload]).await
+    }
+
+    async fn set_payload_parts(&mut self, parts: &[&[u8]]) -> Result<(), RadioError> {
+        let mut offset = 0usize;
+        for part in parts {
+            let op_code_and_offset = [OpCode::WriteBuffer.value(), offset as u8];
+            self.intf.write_with_payload(&op_code_and_offset, part, false).await?;
+            offset += part.len();
+        }
+        Ok(())
     }
 
     async fn do_tx(&mut self) -> Result<(), RadioError> {
@@ -714,47 +795,34 @@ where
         rx_pkt_params: &PacketParams,
         receiving_buffer: &mut [u8],
     ) -> Result<u8, RadioError> {
-        let op_code = [OpCode::GetRxBufferStatus.value()];
-        let mut rx_buffer_status = [0x00u8; 2];
-        let read_status = self.intf.read_with_status(&op_code, &mut rx_buffer_status).await?;
-        if OpStatusErrorMask::is_error(read_status) {
-            return Err(RadioError::OpError(read_status));
-        }
-
-        let mut payload_length_buffer = [0x00u8];
-        if rx_pkt_params.implicit_header {
-            self.intf
-                .read(
-                    &[
-                        OpCode::ReadRegister.value(),
-                        Register::PayloadLength.addr1(),
-                        Register::PayloadLength.addr2(),
-                        0x00u8,
-                    ],
-                    &mut payload_length_buffer,
-                )
-                .await?;
-        } else {
-            payload_length_buffer[0] = rx_buffer_status[0];
-        }
-
-        let payload_length = payload_length_buffer[0];
-        let offset = rx_buffer_status[1];
-
+        let (payload_length, _) = self.get_rx_payload_position(rx_pkt_params).await?;
         if (payload_length as usize) > receiving_buffer.len() {
-            Err(RadioError::PayloadSizeMismatch(
+            return Err(RadioError::PayloadSizeMismatch(
                 payload_length as usize,
                 receiving_buffer.len(),
-            ))
-        } else {
+            ));
+        }
+        self.get_rx_payload_chunk(rx_pkt_params, 0, &mut receiving_buffer[..payload_length as usize])
+            .await
+    }
+
+    async fn get_rx_payload_chunk(
+        &mut self,
+        rx_pkt_params: &PacketParams,
+        offset: u8,
+        chunk: &mut [u8],
+    ) -> Result<u8, RadioError> {
+        let (payload_length, start) = self.get_rx_payload_position(rx_pkt_params).await?;
+        let len = chunk.len().min(payload_length.saturating_sub(offset) as usize);
+        if len > 0 {
             self.intf
                 .read(
-                    &[OpCode::ReadBuffer.value(), offset, 0x00u8],
-                    &mut receiving_buffer[..payload_length as usize],
+                    &[OpCode::ReadBuffer.value(), start.wrapping_add(offset), 0x00u8],
+                    &mut chunk[..len],
                 )
                 .await?;
-            Ok(payload_length)
         }
+        Ok(payload_length)
     }
 
     async fn get_rx_packet_status(&mut self) -> Result<PacketStatus, RadioError> {
@@ -764,12 +832,12 @@ where
         if OpStatusErrorMask::is_error(read_status) {
             return Err(RadioError::OpError(read_status));
         }
-        // check this ???
+        // RssiPkt, SnrPkt and SignalRssiPkt (see DS_SX1261-2_V1.2 datasheet chapter 13.5.3)
         let rssi = ((-(pkt_status[0] as i32)) >> 1) as i16;
         let snr = (((pkt_status[1] as i8) + 2) >> 2) as i16;
-        let _signal_rssi = ((-(pkt_status[2] as i32)) >> 1) as i16; // unused currently
+        let signal_rssi = ((-(pkt_status[2] as i32)) >> 1) as i16;
 
-        Ok(PacketStatus { rssi, snr })
+        Ok(PacketStatus { rssi, snr, signal_rssi })
     }
 
     async fn get_rssi(&mut self) -> Result<i16, RadioError> {
@@ -1,4 +1,5 @@
 use super::DeviceSel;
+use crate::mod_params::Chip;
 
 /// Implement this trait on your custom variant or use provided impls
 pub trait Sx126xVariant {
@@ -9,6 +10,14 @@ pub trait Sx126xVariant {
     fn use_dio2_as_rfswitch(&self) -> bool {
         true
     }
+
+    /// the chip, as reported by `LoRa::capabilities`
+    fn chip(&self) -> Chip {
+        match self.get_device_sel() {
+            DeviceSel::LowPowerPA => Chip::Sx1261,
+            DeviceSel::HighPowerPA => Chip::Sx1262,
+        }
+    }
 }
 
 /// Sx1261 uses only LowPowerPA
@@ -44,4 +53,7 @@ impl Sx126xVariant for Stm32wl {
     fn use_dio2_as_rfswitch(&self) -> bool {
         false
     }
+    fn chip(&self) -> Chip {
+        Chip::Stm32wl
+    }
 }
@@ -12,6 +12,22 @@ use crate::mod_params::*;
 use crate::mod_traits::IrqState;
 use crate::{InterfaceVariant, RadioKind, SpiInterface};
 
+/// SPI mode of Sx127x chips, eg: for the configuration of a device on a shared SPI bus
+pub const SPI_MODE: embedded_hal::spi::Mode = embedded_hal::spi::MODE_0;
+
+/// Highest SPI clock frequency supported by Sx127x chips
+pub const MAX_SPI_FREQUENCY_HZ: u32 = 10_000_000;
+
+/// Largest number of payload bytes moved through the FIFO in one SPI transaction. Longer payloads
+/// are streamed in several transactions, which keeps each transfer within the limits of SPI
+/// drivers with small DMA buffers. The FIFO address pointer advances with every byte, so each
+/// transaction continues where the previous one stopped.
+///
+/// In LoRa mode the whole packet fits the 256-byte FIFO, so the chunks are transferred back to
+/// back. FSK mode only has a 64-byte FIFO, and will pace the chunks with the FIFO threshold
+/// interrupt instead.
+pub const FIFO_CHUNK_LEN: usize = 64;
+
 // TCXO flag
 const TCXO_FOR_OSCILLATOR: u8 = 0x10u8;
 
@@ -41,6 +57,20 @@ fn pll_step_to_freq(pll_step: u32) -> u32 {
     (pll_step >> SCALE) * STEP_SCALED
 }
 
+// RSSI of the packet and strength of the signal, from RegPktRssiValue and the raw RegPktSnrValue
+// in quarters of dB, the signal being below the noise floor when SNR < 0
+// Section 5.5.5 of the SX1276 datasheet (3.5.5 of the SX1272 one)
+fn packet_rssi(rssi_offset: i16, packet_rssi: u8, packet_snr: i8) -> (i16, i16) {
+    if packet_snr >= 0 {
+        let rssi = rssi_offset + linearize_rssi(packet_rssi);
+        (rssi, rssi)
+    } else {
+        let rssi = rssi_offset + packet_rssi as i16;
+        // SNR / 4, rounded to the nearest dB
+        (rssi, rssi - (2 - packet_snr as i16) / 4)
+    }
+}
+
 // RSSI requires linearization when SNR >= 0
 // Section 3.5.5 - Note 3
 fn linearize_rssi(rssi: u8) -> i16 {
@@ -60,7 +90,8 @@ pub struct Config<C: Sx127xVariant> {
     /// Whether to use PA_BOOST for transmit instead of RFO (sx1272) or RFO_LF (sx1276).
     /// NB! Depends on board layout.
     pub tx_boost: bool,
-    /// Whether to boost receive
+    /// Whether to receive with the LNA boost of the high frequency port (150% LNA current). May be
+    /// changed at runtime with [`LoRa::set_rx_boost`](crate::LoRa::set_rx_boost).
     pub rx_boost: bool,
 }
 
@@ -108,6 +139,22 @@ where
         self.intf.write_with_payload(&[register.write_addr()], buf, false).await
     }
 
+    // Stream data into the FIFO at the current FIFO address pointer
+    async fn write_fifo(&mut self, data: &[u8]) -> Result<(), RadioError> {
+        for chunk in data.chunks(FIFO_CHUNK_LEN) {
+            self.write_buffer(Register::RegFifo, chunk).await?;
+        }
+        Ok(())
+    }
+
+    // Stream data out of the FIFO from the current FIFO address pointer
+    async fn read_fifo(&mut self, buf: &mut [u8]) -> Result<(), RadioError> {
+        for chunk in buf.chunks_mut(FIFO_CHUNK_LEN) {
+            self.read_buffer(Register::RegFifo, chunk).await?;
+        }
+        Ok(())
+    }
+
     // Set the number of symbols the radio will wait to detect a reception (up to 1023 symbols)
     async fn set_lora_symbol_num_timeout(&mut self, symbol_num: u16) -> Result<(), RadioError> {
         let val = symbol_num.min(SX127X_MAX_LORA_SYMB_NUM_TIMEOUT);
@@ -124,6 +171,15 @@ where
     async fn set_ocp(&mut self, ocp_trim: OcpTrim) -> Result<(), RadioError> {
         self.write_register(Register::RegOcp, ocp_trim.value()).await
     }
+
+    // Length of the last received payload
+    async fn get_rx_payload_length(&mut self, rx_pkt_params: &PacketParams) -> Result<u8, RadioError> {
+        // RegRxNbBytes is not valid in implicit header mode
+        match rx_pkt_params.implicit_payload_length() {
+            Some(payload_length) => Ok(payload_length),
+            None => self.read_register(Register::RegRxNbBytes).await,
+        }
+    }
 }
 
 impl<SPI, IV, C> RadioKind for Sx127x<SPI, IV, C>
@@ -132,6 +188,11 @@ where
     IV: InterfaceVariant,
     C: Sx127xVariant,
 {
+    async fn capabilities(&mut self) -> Result<Capabilities, RadioError> {
+        let version = self.read_register(Register::RegVersion).await?;
+        C::capabilities(version).ok_or(RadioError::CapabilitiesUnsupported)
+    }
+
     async fn init_lora(&mut self, sync_word: u8) -> Result<(), RadioError> {
         if self.config.tcxo_used {
             self.write_register(C::reg_txco(), TCXO_FOR_OSCILLATOR).await?;
@@ -161,21 +222,32 @@ where
             return Err(RadioError::InvalidBandwidthForFrequency);
         }
 
-        // Section 4.1.1.5 and 4.1.1.6
-        let bw_in_hz = u32::from(bandwidth);
-        let symbol_duration = 1000 / (bw_in_hz / (0x01u32 << spreading_factor_value(spreading_factor)?));
-        let mut low_data_rate_optimize = 0x00u8;
-        if symbol_duration > 16 {
-            low_data_rate_optimize = 0x01u8
-        }
-
-        Ok(ModulationParams {
+        // Section 4.1.1.5 and 4.1.1.6: low data rate optimization is mandated for symbol durations
+        // above 16 ms (16.384 ms for SF11 at 125 kHz, which millisecond arithmetic would truncate
+        // to 16 ms)
+        Ok(ModulationParams::new(
             spreading_factor,
             bandwidth,
             coding_rate,
-            low_data_rate_optimize,
             frequency_in_hz,
-        })
+        ))
+    }
+
+    fn set_rx_boost(&mut self, rx_boost: bool) {
+        self.config.rx_boost = rx_boost;
+    }
+
+    fn rx_boost(&self) -> bool {
+        self.config.rx_boost
+    }
+
+    fn set_front_end_bypass(&mut self, bypass: bool) {
+        self.intf.iv.set_front_end_bypass(bypass);
+    }
+
+    // Section 4.1.1.6: the programmed preamble length ranges from 6 to 65535 symbols
+    fn min_preamble_length(&self) -> u16 {
+        6
     }
 
     fn create_packet_params(
@@ -192,18 +264,18 @@ where
             return Err(RadioError::InvalidSF6ExplicitHeaderRequest);
         }
 
-        Ok(PacketParams {
+        Ok(PacketParams::new(
             preamble_length,
             implicit_header,
             payload_length,
             crc_on,
             iq_inverted,
-        })
+        ))
     }
 
     async fn reset(&mut self, delay: &mut impl DelayNs) -> Result<(), RadioError> {
         self.intf.iv.reset(delay).await?;
-        self.set_sleep(false, delay).await?; // ensure sleep mode is entered so that the LoRa mode bit is set
+        self.set_sleep(SleepOptions::cold(), delay).await?; // ensure sleep mode is entered so that the LoRa mode bit is set
         Ok(())
     }
 
@@ -217,8 +289,11 @@ where
         self.intf.iv.disable_rf_switch().await
     }
 
-    async fn set_sleep(&mut self, _warm_start_if_possible: bool, _delay: &mut impl DelayNs) -> Result<(), RadioError> {
-        // Warm start is unavailable for sx127x
+    async fn set_sleep(&mut self, options: SleepOptions, _delay: &mut impl DelayNs) -> Result<(), RadioError> {
+        // Warm start is unavailable for sx127x, and so is waking up without the MCU
+        if options.rtc_wakeup {
+            return Err(RadioError::RtcWakeupUnsupported);
+        }
         self.intf.iv.disable_rf_switch().await?;
         let buf = [Register::RegOpMode.write_addr(), LoRaMode::Sleep.value()];
         // NB! Switching to sleep mode is "sleep" command...
@@ -251,6 +326,7 @@ where
         _mdltn_params: Option<&ModulationParams>,
         is_tx_prep: bool,
     ) -> Result<(), RadioError> {
+        let p_out = p_out - i32::from(self.intf.iv.tx_gain());
         debug!("tx power = {}", p_out);
 
         // Configure tx power and boost
@@ -324,8 +400,8 @@ where
         Ok(())
     }
 
-    // Calibrate the image rejection based on the given frequency
-    async fn calibrate_image(&mut self, _frequency_in_hz: u32) -> Result<(), RadioError> {
+    // Calibrate the image rejection for the given band
+    async fn calibrate_image(&mut self, _band: ImageCalibrationBand) -> Result<(), RadioError> {
         // An automatic process, but can set bit ImageCalStart in RegImageCal, when the device is in Standby mode.
         Ok(())
     }
@@ -341,11 +417,19 @@ where
     }
 
     async fn set_payload(&mut self, payload: &[u8]) -> Result<(), RadioError> {
+        self.set_payload_parts(&[payload]).await
+    }
+
+    async fn set_payload_parts(&mut self, parts: &[&[u8]]) -> Result<(), RadioError> {
         self.write_register(Register::RegFifoAddrPtr, 0x00u8).await?;
         self.write_register(Register::RegPayloadLength, 0x00u8).await?;
-        self.write_buffer(Register::RegFifo, payload).await?;
-        self.write_register(Register::RegPayloadLength, payload.len() as u8)
-            .await
+        // the FIFO address pointer advances with every byte written
+        let mut len = 0;
+        for part in parts {
+            self.write_fifo(part).await?;
+            len += part.len();
+        }
+        self.write_register(Register::RegPayloadLength, len as u8).await
     }
 
     async fn do_tx(&mut self) -> Result<(), RadioError> {
@@ -382,45 +466,46 @@ where
         rx_pkt_params: &PacketParams,
         receiving_buffer: &mut [u8],
     ) -> Result<u8, RadioError> {
-        let payload_length = if rx_pkt_params.implicit_header {
-            rx_pkt_params.payload_length
-        } else {
-            self.read_register(Register::RegRxNbBytes).await?
-        };
+        let payload_length = self.get_rx_payload_length(rx_pkt_params).await?;
         if (payload_length as usize) > receiving_buffer.len() {
             return Err(RadioError::PayloadSizeMismatch(
                 payload_length as usize,
                 receiving_buffer.len(),
             ));
         }
-        let fifo_addr = self.read_register(Register::RegFifoRxCurrentAddr).await?;
-        self.write_register(Register::RegFifoAddrPtr, fifo_addr).await?;
-        self.read_buffer(Register::RegFifo, &mut receiving_buffer[0..payload_length as usize])
-            .await?;
-        self.write_register(Register::RegFifoAddrPtr, 0x00u8).await?;
+        self.get_rx_payload_chunk(rx_pkt_params, 0, &mut receiving_buffer[0..payload_length as usize])
+            .await
+    }
+
+    async fn get_rx_payload_chunk(
+        &mut self,
+        rx_pkt_params: &PacketParams,
+        offset: u8,
+        chunk: &mut [u8],
+    ) -> Result<u8, RadioError> {
+        let payload_length = self.get_rx_payload_length(rx_pkt_params).await?;
+        let len = chunk.len().min(payload_length.saturating_sub(offset) as usize);
+        if len > 0 {
+            let fifo_addr = self.read_register(Register::RegFifoRxCurrentAddr).await?;
+            self.write_register(Register::RegFifoAddrPtr, fifo_addr.wrapping_add(offset))
+                .await?;
+            self.read_fifo(&mut chunk[..len]).await?;
+            self.write_register(Register::RegFifoAddrPtr, 0x00u8).await?;
+        }
 
         Ok(payload_length)
     }
 
     async fn get_rx_packet_status(&mut self) -> Result<PacketStatus, RadioError> {
-        let snr = {
-            let packet_snr = self.read_register(Register::RegPktSnrValue).await?;
-            packet_snr as i8 as i16 / 4
-        };
-
-        let rssi = {
-            let packet_rssi = self.read_register(Register::RegPktRssiValue).await?;
-
-            let rssi_offset = C::rssi_offset(self).await?;
+        let packet_snr = self.read_register(Register::RegPktSnrValue).await? as i8;
+        let snr = packet_snr as i16 / 4;
 
-            if snr >= 0 {
-                rssi_offset + linearize_rssi(packet_rssi)
-            } else {
-                rssi_offset + (packet_rssi as i16) + snr
-            }
-        };
+        let packet_rssi_value = self.read_register(Register::RegPktRssiValue).await?;
+        // the offset depends on the port (HF or LF) used for the frequency
+        let rssi_offset = C::rssi_offset(self).await?;
+        let (rssi, signal_rssi) = packet_rssi(rssi_offset, packet_rssi_value, packet_snr);
 
-        Ok(PacketStatus { rssi, snr })
+        Ok(PacketStatus { rssi, snr, signal_rssi })
     }
 
     async fn get_rssi(&mut self) -> Result<i16, RadioError> {
@@ -543,9 +628,9 @@ where
                 if (irq_flags & IrqMask::CADDone.value()) == IrqMask::CADDone.value() {
                     debug!("CADDone in radio mode {}", radio_mode);
                     // TODO: don't like how we mutate the cad_activity_detected parameter
-                    if cad_activity_detected.is_some() {
+                    if let Some(cad_activity_detected) = cad_activity_detected {
                         // Check if the CAD (Channel Activity Detection) Activity Detected flag is set in irq_flags and then update the reference
-                        *(cad_activity_detected.unwrap()) =
+                        *cad_activity_detected =
                             (irq_flags & IrqMask::CADActivityDetected.value()) == IrqMask::CADActivityDetected.value();
                     }
                     return Ok(Some(IrqState::Done));
@@ -611,6 +696,17 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_packet_rssi() {
+        let offset = SX1276_RSSI_OFFSET_HF;
+        assert_eq!(packet_rssi(offset, 60, 40), (offset + 64, offset + 64));
+        // -7.5 dB SNR
+        assert_eq!(packet_rssi(offset, 60, -30), (offset + 60, offset + 52));
+        // -0.25 dB SNR
+        assert_eq!(packet_rssi(offset, 60, -1), (offset + 60, offset + 60));
+        assert_eq!(packet_rssi(offset, 60, -2), (offset + 60, offset + 59));
+    }
+
     #[test]
     fn test_rssi_linearization() {
         const DELTA: f32 = 0.5;
@@ -14,6 +14,10 @@ pub trait Sx127xVariant {
     where
         Self: Sized;
 
+    /// Capabilities of the chip with the given `RegVersion`, if known for this variant
+    fn capabilities(_version: u8) -> Option<Capabilities> {
+        None
+    }
     fn bandwidth_value(bw: Bandwidth) -> Result<u8, RadioError>;
     fn reg_txco() -> Register;
     async fn set_tx_power<SPI: SpiDevice<u8>, IV: InterfaceVariant>(
@@ -1,9 +1,9 @@
-use crate::mod_params::{ModulationParams, PacketParams, RadioError};
+use crate::mod_params::{Capabilities, Chip, ModulationParams, PacketParams, RadioError};
 use crate::mod_traits::InterfaceVariant;
 use crate::sx127x::radio_kind_params::{coding_rate_value, spreading_factor_value, RampTime, Register, Sx127xVariant};
 use crate::sx127x::{Sx127x, SX1272_RSSI_OFFSET};
 use embedded_hal_async::spi::SpiDevice;
-use lora_modulation::Bandwidth;
+use lora_modulation::{Bandwidth, SpreadingFactor};
 
 /// Sx1272 implements the Sx127xVariant trait
 pub struct Sx1272;
@@ -12,12 +12,29 @@ impl Sx127xVariant for Sx1272 {
     type Data = ();
 
     async fn init_lora<SPI: SpiDevice<u8>, IV: InterfaceVariant>(
-        _radio: &mut Sx127x<SPI, IV, Self>,
+        radio: &mut Sx127x<SPI, IV, Self>,
         _sync_word: u8,
     ) -> Result<(), RadioError> {
+        // no errata is documented for any sx1272 silicon version
+        let chip_version = radio.read_register(Register::RegVersion).await?;
+        debug!("Detected sx1272 v{}", chip_version);
         Ok(())
     }
 
+    fn capabilities(version: u8) -> Option<Capabilities> {
+        Some(Capabilities {
+            chip: Chip::Sx1272,
+            version: Some(version),
+            min_frequency_in_hz: 860_000_000,
+            max_frequency_in_hz: 1_020_000_000,
+            min_spreading_factor: SpreadingFactor::_6,
+            bandwidths: &[Bandwidth::_125KHz, Bandwidth::_250KHz, Bandwidth::_500KHz],
+            max_output_power: 20,
+            fsk: true,
+            lr_fhss: false,
+        })
+    }
+
     fn bandwidth_value(bw: Bandwidth) -> Result<u8, RadioError> {
         match bw {
             Bandwidth::_125KHz => Ok(0x00),
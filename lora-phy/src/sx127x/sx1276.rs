@@ -1,4 +1,4 @@
-use crate::mod_params::{ModulationParams, PacketParams, RadioError};
+use crate::mod_params::{Capabilities, Chip, ModulationParams, PacketParams, RadioError, ALL_BANDWIDTHS};
 use crate::mod_traits::InterfaceVariant;
 use crate::sx127x::radio_kind_params::{
     coding_rate_denominator_value, spreading_factor_value, OcpTrim, PaConfig, PaDac, RampTime, Register, Sx127xVariant,
@@ -7,7 +7,21 @@ use crate::sx127x::{
     pll_step_to_freq, Sx127x, SX1276_RF_MID_BAND_THRESH, SX1276_RSSI_OFFSET_HF, SX1276_RSSI_OFFSET_LF,
 };
 use embedded_hal_async::spi::SpiDevice;
-use lora_modulation::Bandwidth;
+use lora_modulation::{Bandwidth, SpreadingFactor};
+
+/// Silicon version affected by Errata 2.1: Sensitivity optimization with 500 kHz bandwidth
+const ERRATA_2_1_VERSION: u8 = 0x12;
+
+/// Values of RegHighBwOptimize1 and RegHighBwOptimize2 according to Errata 2.1. For all bandwidth
+/// and frequency combinations the errata does not cover, RegHighBwOptimize1 is reset to 0x03 and
+/// RegHighBwOptimize2 is set automatically by the chip.
+fn high_bw_optimize(bandwidth: Bandwidth, frequency_in_hz: u32) -> (u8, Option<u8>) {
+    match (bandwidth, frequency_in_hz) {
+        (Bandwidth::_500KHz, 862_000_000..=1_020_000_000) => (0x02, Some(0x64)),
+        (Bandwidth::_500KHz, 410_000_000..=525_000_000) => (0x02, Some(0x7f)),
+        _ => (0x03, None),
+    }
+}
 
 /// Sx1276 implements the Sx127xVariant trait
 pub struct Sx1276;
@@ -28,12 +42,25 @@ impl Sx127xVariant for Sx1276 {
         let chip_version = radio.read_register(Register::RegVersion).await?;
         debug!("Detected sx1276 v{}", chip_version);
 
-        // Errata 2.1: Sensitivity optimization only applies to version 0x12
-        radio.data.sensitivity_quirk = chip_version == 0x12;
+        radio.data.sensitivity_quirk = chip_version == ERRATA_2_1_VERSION;
 
         Ok(())
     }
 
+    fn capabilities(version: u8) -> Option<Capabilities> {
+        Some(Capabilities {
+            chip: Chip::Sx1276,
+            version: Some(version),
+            min_frequency_in_hz: 137_000_000,
+            max_frequency_in_hz: 1_020_000_000,
+            min_spreading_factor: SpreadingFactor::_6,
+            bandwidths: ALL_BANDWIDTHS,
+            max_output_power: 20,
+            fsk: true,
+            lr_fhss: false,
+        })
+    }
+
     fn bandwidth_value(bw: Bandwidth) -> Result<u8, RadioError> {
         match bw {
             Bandwidth::_7KHz => Ok(0x00),
@@ -132,28 +159,11 @@ impl Sx127xVariant for Sx1276 {
         radio.write_register(Register::RegModemConfig3, config_3).await?;
 
         if radio.data.sensitivity_quirk {
-            // apply Errata 2.1: Sensitivity optimization with 500 kHz bandwidth
-
-            if mdltn_params.bandwidth == Bandwidth::_500KHz {
-                let val_1 = 0x02;
-                match mdltn_params.frequency_in_hz {
-                    862_000..=1_020_000 => {
-                        radio.write_register(Register::RegHighBwOptimize1, val_1).await?;
-                        radio.write_register(Register::RegHighBwOptimize2, 0x64).await?;
-                        return Ok(());
-                    }
-                    410_000..=525_000 => {
-                        radio.write_register(Register::RegHighBwOptimize1, val_1).await?;
-                        radio.write_register(Register::RegHighBwOptimize2, 0x7f).await?;
-                        return Ok(());
-                    }
-                    // fall through...
-                    _ => {}
-                }
+            let (optimize_1, optimize_2) = high_bw_optimize(mdltn_params.bandwidth, mdltn_params.frequency_in_hz);
+            radio.write_register(Register::RegHighBwOptimize1, optimize_1).await?;
+            if let Some(optimize_2) = optimize_2 {
+                radio.write_register(Register::RegHighBwOptimize2, optimize_2).await?;
             }
-
-            // for all other combinations of bandwidth / frequencies, reset to 0x03 (RegHighBwOptimize2 is automatically set by the chip)
-            radio.write_register(Register::RegHighBwOptimize1, 0x03).await?;
         }
 
         Ok(())
@@ -217,3 +227,16 @@ impl Sx127xVariant for Sx1276 {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errata_high_bw_optimize() {
+        assert_eq!(high_bw_optimize(Bandwidth::_500KHz, 903_000_000), (0x02, Some(0x64)));
+        assert_eq!(high_bw_optimize(Bandwidth::_500KHz, 433_000_000), (0x02, Some(0x7f)));
+        assert_eq!(high_bw_optimize(Bandwidth::_500KHz, 780_000_000), (0x03, None));
+        assert_eq!(high_bw_optimize(Bandwidth::_125KHz, 868_100_000), (0x03, None));
+    }
+}
@@ -0,0 +1,192 @@
+//! Ledger of the time on air of all transmissions, per regulatory band and over the last hour and
+//! day, for products which have to provide evidence of duty cycle compliance.
+//!
+//! Time is measured with [`Timer::elapsed_ms`](super::radio::Timer::elapsed_ms) between
+//! transmissions, so the rolling hour and day only advance with timers which implement it. The
+//! ledger is plain data (and serializable with the `serde` feature), so that it can be persisted
+//! and restored with [`Device::set_airtime_ledger`](super::Device::set_airtime_ledger).
+use crate::region::Region;
+
+/// Maximum number of bands of a region.
+pub const MAX_AIRTIME_BANDS: usize = 6;
+
+const SLOT_MS: u64 = 10 * 60 * 1000;
+const HOUR_SLOTS: usize = 6;
+const HOUR_MS: u64 = 60 * 60 * 1000;
+const DAY_HOURS: usize = 24;
+
+/// A frequency range with its own duty cycle limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct AirtimeBand {
+    pub min_frequency: u32,
+    pub max_frequency: u32,
+    /// The band may be used for 1/`duty_cycle` of the time, if limited.
+    pub duty_cycle: Option<u16>,
+}
+
+const fn band(min_frequency: u32, max_frequency: u32, duty_cycle: Option<u16>) -> AirtimeBand {
+    AirtimeBand { min_frequency, max_frequency, duty_cycle }
+}
+
+/// ETSI EN 300 220 sub-bands used by EU868.
+#[cfg(feature = "region-eu868")]
+const EU868_BANDS: [AirtimeBand; 6] = [
+    band(863_000_000, 865_000_000, Some(1000)),
+    band(865_000_000, 868_000_000, Some(100)),
+    band(868_000_000, 868_600_000, Some(100)),
+    band(868_700_000, 869_200_000, Some(1000)),
+    band(869_400_000, 869_650_000, Some(10)),
+    band(869_700_000, 870_000_000, Some(100)),
+];
+
+#[cfg(feature = "region-eu433")]
+const EU433_BANDS: [AirtimeBand; 1] = [band(433_050_000, 434_790_000, Some(100))];
+
+const ANY_BAND: [AirtimeBand; 1] = [band(0, u32::MAX, None)];
+
+/// Bands the time on air is accounted in for the given region.
+pub fn airtime_bands(region: Region) -> &'static [AirtimeBand] {
+    match region {
+        #[cfg(feature = "region-eu868")]
+        Region::EU868 => &EU868_BANDS,
+        #[cfg(feature = "region-eu433")]
+        Region::EU433 => &EU433_BANDS,
+        #[allow(unreachable_patterns)]
+        _ => &ANY_BAND,
+    }
+}
+
+/// Time on air (ms) of a band.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct AirtimeUsage {
+    /// Since the ledger was created.
+    pub total_ms: u64,
+    /// During the last hour, in steps of 10 minutes.
+    pub last_hour_ms: u32,
+    /// During the last day, in steps of one hour.
+    pub last_day_ms: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AirtimeLedger {
+    region: Region,
+    uptime_ms: u64,
+    total_ms: [u64; MAX_AIRTIME_BANDS],
+    /// Ring of 10 minute slots, indexed by the slot number since creation.
+    hour: [[u32; MAX_AIRTIME_BANDS]; HOUR_SLOTS],
+    /// Ring of hourly slots, indexed by the hour since creation.
+    day: [[u32; MAX_AIRTIME_BANDS]; DAY_HOURS],
+    /// Time on air which could not be attributed to any band (ms).
+    unbanded_ms: u64,
+}
+
+impl AirtimeLedger {
+    pub fn new(region: Region) -> Self {
+        Self {
+            region,
+            uptime_ms: 0,
+            total_ms: [0; MAX_AIRTIME_BANDS],
+            hour: [[0; MAX_AIRTIME_BANDS]; HOUR_SLOTS],
+            day: [[0; MAX_AIRTIME_BANDS]; DAY_HOURS],
+            unbanded_ms: 0,
+        }
+    }
+
+    pub fn bands(&self) -> &'static [AirtimeBand] {
+        airtime_bands(self.region)
+    }
+
+    /// Time covered by the ledger, as far as the timer was able to tell.
+    pub fn uptime_ms(&self) -> u64 {
+        self.uptime_ms
+    }
+
+    /// Usage of the band at the given index of [`AirtimeLedger::bands`].
+    pub fn usage(&self, band: usize) -> Option<AirtimeUsage> {
+        self.bands().get(band)?;
+        Some(AirtimeUsage {
+            total_ms: self.total_ms[band],
+            last_hour_ms: self.hour.iter().map(|slot| slot[band]).sum(),
+            last_day_ms: self.day.iter().map(|slot| slot[band]).sum(),
+        })
+    }
+
+    /// Time on air on frequencies outside of all bands.
+    pub fn unbanded_ms(&self) -> u64 {
+        self.unbanded_ms
+    }
+
+    /// Let `elapsed_ms` pass, clearing the slots which fall out of the last hour and day.
+    pub(crate) fn advance(&mut self, elapsed_ms: u64) {
+        let before = self.uptime_ms;
+        self.uptime_ms += elapsed_ms;
+        clear_passed(&mut self.hour, before / SLOT_MS, self.uptime_ms / SLOT_MS);
+        clear_passed(&mut self.day, before / HOUR_MS, self.uptime_ms / HOUR_MS);
+    }
+
+    pub(crate) fn record(&mut self, frequency: u32, time_on_air_ms: u32) {
+        let Some(band) = self
+            .bands()
+            .iter()
+            .position(|band| (band.min_frequency..=band.max_frequency).contains(&frequency))
+        else {
+            self.unbanded_ms += time_on_air_ms as u64;
+            return;
+        };
+        self.total_ms[band] += time_on_air_ms as u64;
+        let slot = (self.uptime_ms / SLOT_MS) as usize % HOUR_SLOTS;
+        self.hour[slot][band] = self.hour[slot][band].saturating_add(time_on_air_ms);
+        let hour = (self.uptime_ms / HOUR_MS) as usize % DAY_HOURS;
+        self.day[hour][band] = self.day[hour][band].saturating_add(time_on_air_ms);
+    }
+}
+
+/// Clear the slots of a ring which were entered since `from` up to and including `to`.
+fn clear_passed<const N: usize>(ring: &mut [[u32; MAX_AIRTIME_BANDS]; N], from: u64, to: u64) {
+    for slot in (from + 1..=to).take(N) {
+        ring[slot as usize % N] = [0; MAX_AIRTIME_BANDS];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const MINUTE: u64 = 60 * 1000;
+
+    #[test]
+    fn rolling_hour_and_day() {
+        let mut ledger = AirtimeLedger::new(Region::EU868);
+        ledger.record(868_100_000, 100);
+        ledger.record(869_525_000, 1_000);
+        ledger.record(915_000_000, 50);
+        assert_eq!(
+            ledger.usage(2),
+            Some(AirtimeUsage { total_ms: 100, last_hour_ms: 100, last_day_ms: 100 })
+        );
+        assert_eq!(ledger.usage(4).unwrap().last_hour_ms, 1_000);
+        assert_eq!(ledger.unbanded_ms(), 50);
+        assert_eq!(ledger.usage(6), None);
+
+        ledger.advance(30 * MINUTE);
+        ledger.record(868_300_000, 200);
+        assert_eq!(ledger.usage(2).unwrap().last_hour_ms, 300);
+
+        // the first uplink has left the last hour
+        ledger.advance(35 * MINUTE);
+        assert_eq!(
+            ledger.usage(2),
+            Some(AirtimeUsage { total_ms: 300, last_hour_ms: 200, last_day_ms: 300 })
+        );
+
+        ledger.advance(24 * 60 * MINUTE);
+        assert_eq!(
+            ledger.usage(2),
+            Some(AirtimeUsage { total_ms: 300, last_hour_ms: 0, last_day_ms: 0 })
+        );
+        assert_eq!(ledger.uptime_ms(), 1_505 * MINUTE);
+    }
+}
@@ -0,0 +1,326 @@
+//! AT-command server which exposes a [`Device`] over a serial port, for modem firmware running the
+//! stack on a radio co-processor which is controlled by a host MCU.
+//!
+//! Commands are terminated by CR or LF and answered by `OK` or `ERROR`, each line terminated by
+//! CR LF. The outcome of a join or an uplink is reported afterwards by an unsolicited result code
+//! (URC). EUIs are written MSB first, keys and payloads in transmission order.
+//!
+//! | Command                 | Response                                                       |
+//! |-------------------------|----------------------------------------------------------------|
+//! | `AT`                    | `OK`                                                           |
+//! | `AT+DEVEUI=<hex>`       | Set the DevEUI; query with `AT+DEVEUI?`                        |
+//! | `AT+APPEUI=<hex>`       | Set the JoinEUI; query with `AT+APPEUI?`                       |
+//! | `AT+APPKEY=<hex>`       | Set the AppKey                                                 |
+//! | `AT+JOIN`               | Join via OTAA; URC `+JOIN: OK` or `+JOIN: FAILED`              |
+//! | `AT+JOINED?`            | `+JOINED: 1` if a session is active, `+JOINED: 0` otherwise    |
+//! | `AT+SEND=<port>:<hex>`  | Unconfirmed uplink; URC `+SEND: OK`                            |
+//! | `AT+CSEND=<port>:<hex>` | Confirmed uplink; URC `+SEND: ACK` or `+SEND: NOACK`           |
+//! | `AT+DR=<dr>`            | Set the data rate; query with `AT+DR?`                         |
+//! | `AT+ADR=<0/1>`          | Enable or disable ADR; query with `AT+ADR?`                    |
+//!
+//! Uplinks which cannot be sent (eg: before joining) are reported with `+SEND: FAILED` and an
+//! expired session with `+SEND: EXPIRED`. Every downlink is reported with `+RECV: <port>:<hex>`
+//! after the outcome of its uplink.
+use super::{radio, Device, JoinResponse, SendResponse, Timings, DR};
+use crate::{AppEui, AppKey, CryptoFactory, DevEui, JoinMode};
+use core::fmt::Write;
+use heapless::{String, Vec};
+use lorawan::string::{decode_hex, ByteOrder};
+use rand_core::RngCore;
+
+/// Maximum length of a command line, enough for the largest uplink payload in hex.
+pub const MAX_LINE_LEN: usize = 512;
+
+/// Serial port (usually a UART) the commands are received on.
+#[allow(async_fn_in_trait)]
+pub trait Serial {
+    type Error;
+
+    /// Wait for at least one byte and return the number of bytes read into `buf`, or `0` at the
+    /// end of the input (eg: once the transport is closed).
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error>;
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+pub struct AtServer<S> {
+    serial: S,
+    line: Vec<u8, MAX_LINE_LEN>,
+    /// The current line exceeded [`MAX_LINE_LEN`] and is discarded up to its end.
+    overflow: bool,
+    deveui: [u8; 8],
+    appeui: [u8; 8],
+    appkey: [u8; 16],
+}
+
+impl<S: Serial> AtServer<S> {
+    pub fn new(serial: S) -> Self {
+        Self {
+            serial,
+            line: Vec::new(),
+            overflow: false,
+            deveui: [0; 8],
+            appeui: [0; 8],
+            appkey: [0; 16],
+        }
+    }
+
+    /// Set the default OTAA credentials, which may be overwritten by `AT+DEVEUI`, `AT+APPEUI` and
+    /// `AT+APPKEY`.
+    pub fn set_credentials(&mut self, deveui: DevEui, appeui: AppEui, appkey: AppKey) {
+        self.deveui.copy_from_slice(deveui.as_ref());
+        self.appeui.copy_from_slice(appeui.as_ref());
+        self.appkey = appkey.inner().0;
+    }
+
+    pub fn serial(&mut self) -> &mut S {
+        &mut self.serial
+    }
+
+    /// Serve commands until the end of the input or until the serial port fails.
+    pub async fn run<R, T, G, C, const N: usize, const D: usize>(
+        &mut self,
+        device: &mut Device<R, T, G, N, D, C>,
+    ) -> Result<(), S::Error>
+    where
+        R: radio::PhyRxTx + Timings,
+        T: radio::Timer,
+        G: RngCore,
+        C: CryptoFactory,
+    {
+        while self.poll(device).await? {}
+        Ok(())
+    }
+
+    /// Read from the serial port once and execute the commands completed by the bytes read.
+    /// Returns `false` at the end of the input.
+    pub async fn poll<R, T, G, C, const N: usize, const D: usize>(
+        &mut self,
+        device: &mut Device<R, T, G, N, D, C>,
+    ) -> Result<bool, S::Error>
+    where
+        R: radio::PhyRxTx + Timings,
+        T: radio::Timer,
+        G: RngCore,
+        C: CryptoFactory,
+    {
+        let mut buf = [0; 64];
+        let len = self.serial.read(&mut buf).await?;
+        if len == 0 {
+            return Ok(false);
+        }
+        for &byte in &buf[..len] {
+            if byte != b'\r' && byte != b'\n' {
+                self.overflow |= self.line.push(byte).is_err();
+                continue;
+            }
+            let line = core::mem::take(&mut self.line);
+            if core::mem::take(&mut self.overflow) {
+                self.reply("ERROR").await?;
+            } else if !line.is_empty() {
+                match core::str::from_utf8(&line) {
+                    Ok(line) => self.execute(device, line.trim()).await?,
+                    Err(_) => self.reply("ERROR").await?,
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    async fn execute<R, T, G, C, const N: usize, const D: usize>(
+        &mut self,
+        device: &mut Device<R, T, G, N, D, C>,
+        line: &str,
+    ) -> Result<(), S::Error>
+    where
+        R: radio::PhyRxTx + Timings,
+        T: radio::Timer,
+        G: RngCore,
+        C: CryptoFactory,
+    {
+        trace!("AT command: {}", line);
+        let (command, argument) = match line.split_once('=') {
+            Some((command, argument)) => (command, Some(argument)),
+            None => (line, None),
+        };
+        let command = Command::parse(command);
+        match (command, argument) {
+            (Some(Command::At), None) => self.reply("OK").await,
+            (Some(Command::DevEui), Some(hex)) => {
+                let eui = decode_hex::<8>(hex, ByteOrder::Lsb).map(|eui| self.deveui = eui);
+                self.reply_ok(eui.is_ok()).await
+            }
+            (Some(Command::AppEui), Some(hex)) => {
+                let eui = decode_hex::<8>(hex, ByteOrder::Lsb).map(|eui| self.appeui = eui);
+                self.reply_ok(eui.is_ok()).await
+            }
+            (Some(Command::AppKey), Some(hex)) => {
+                let key = decode_hex::<16>(hex, ByteOrder::Msb).map(|key| self.appkey = key);
+                self.reply_ok(key.is_ok()).await
+            }
+            (Some(Command::DevEuiQuery), None) => {
+                let mut eui = self.deveui;
+                eui.reverse();
+                self.reply_hex("+DEVEUI: ", &eui).await
+            }
+            (Some(Command::AppEuiQuery), None) => {
+                let mut eui = self.appeui;
+                eui.reverse();
+                self.reply_hex("+APPEUI: ", &eui).await
+            }
+            (Some(Command::Join), None) => {
+                self.reply("OK").await?;
+                let join_mode = JoinMode::OTAA {
+                    deveui: DevEui::from(self.deveui),
+                    appeui: AppEui::from(self.appeui),
+                    appkey: AppKey::from(self.appkey),
+                };
+                match device.join(&join_mode).await {
+                    Ok(JoinResponse::JoinSuccess) => self.reply("+JOIN: OK").await,
+                    _ => self.reply("+JOIN: FAILED").await,
+                }
+            }
+            (Some(Command::JoinedQuery), None) => {
+                self.reply(if device.get_session().is_some() {
+                    "+JOINED: 1"
+                } else {
+                    "+JOINED: 0"
+                })
+                .await
+            }
+            (Some(command @ (Command::Send | Command::ConfirmedSend)), Some(argument)) => {
+                let Some((fport, data)) = parse_uplink(argument) else {
+                    return self.reply("ERROR").await;
+                };
+                self.reply("OK").await?;
+                let confirmed = command == Command::ConfirmedSend;
+                let urc = match device.send(&data, fport, confirmed).await {
+                    Ok(SendResponse::NoAck) => "+SEND: NOACK",
+                    Ok(SendResponse::SessionExpired) => "+SEND: EXPIRED",
+                    Ok(_) if confirmed => "+SEND: ACK",
+                    Ok(_) => "+SEND: OK",
+                    Err(_) => "+SEND: FAILED",
+                };
+                self.reply(urc).await?;
+                while let Some(downlink) = device.take_downlink() {
+                    let mut prefix: String<16> = String::new();
+                    let _ = write!(prefix, "+RECV: {}:", downlink.fport);
+                    self.reply_hex(&prefix, &downlink.data).await?;
+                }
+                Ok(())
+            }
+            (Some(Command::DataRate), Some(argument)) => {
+                let data_rate = argument
+                    .parse::<u8>()
+                    .ok()
+                    .and_then(|dr| DR::try_from(dr).ok())
+                    .filter(|dr| device.mac.region.is_valid_uplink_dr(*dr));
+                if let Some(data_rate) = data_rate {
+                    device.set_datarate(data_rate);
+                }
+                self.reply_ok(data_rate.is_some()).await
+            }
+            (Some(Command::DataRateQuery), None) => {
+                let mut reply: String<16> = String::new();
+                let _ = write!(reply, "+DR: {}", device.get_datarate() as u8);
+                self.reply(&reply).await
+            }
+            (Some(Command::Adr), Some(argument)) => {
+                let adr = match argument {
+                    "0" => Some(false),
+                    "1" => Some(true),
+                    _ => None,
+                };
+                if let Some(adr) = adr {
+                    device.set_adr(adr);
+                }
+                self.reply_ok(adr.is_some()).await
+            }
+            (Some(Command::AdrQuery), None) => {
+                self.reply(if device.get_adr() {
+                    "+ADR: 1"
+                } else {
+                    "+ADR: 0"
+                })
+                .await
+            }
+            _ => self.reply("ERROR").await,
+        }
+    }
+
+    async fn reply(&mut self, line: &str) -> Result<(), S::Error> {
+        self.serial.write(line.as_bytes()).await?;
+        self.serial.write(b"\r\n").await
+    }
+
+    async fn reply_ok(&mut self, ok: bool) -> Result<(), S::Error> {
+        self.reply(if ok {
+            "OK"
+        } else {
+            "ERROR"
+        })
+        .await
+    }
+
+    async fn reply_hex(&mut self, prefix: &str, data: &[u8]) -> Result<(), S::Error> {
+        self.serial.write(prefix.as_bytes()).await?;
+        for byte in data {
+            let mut hex: String<2> = String::new();
+            let _ = write!(hex, "{:02X}", byte);
+            self.serial.write(hex.as_bytes()).await?;
+        }
+        self.serial.write(b"\r\n").await
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    At,
+    DevEui,
+    DevEuiQuery,
+    AppEui,
+    AppEuiQuery,
+    AppKey,
+    Join,
+    JoinedQuery,
+    Send,
+    ConfirmedSend,
+    DataRate,
+    DataRateQuery,
+    Adr,
+    AdrQuery,
+}
+
+impl Command {
+    fn parse(command: &str) -> Option<Self> {
+        const COMMANDS: [(&str, Command); 14] = [
+            ("AT", Command::At),
+            ("AT+DEVEUI", Command::DevEui),
+            ("AT+DEVEUI?", Command::DevEuiQuery),
+            ("AT+APPEUI", Command::AppEui),
+            ("AT+APPEUI?", Command::AppEuiQuery),
+            ("AT+APPKEY", Command::AppKey),
+            ("AT+JOIN", Command::Join),
+            ("AT+JOINED?", Command::JoinedQuery),
+            ("AT+SEND", Command::Send),
+            ("AT+CSEND", Command::ConfirmedSend),
+            ("AT+DR", Command::DataRate),
+            ("AT+DR?", Command::DataRateQuery),
+            ("AT+ADR", Command::Adr),
+            ("AT+ADR?", Command::AdrQuery),
+        ];
+        COMMANDS.iter().find(|(name, _)| name.eq_ignore_ascii_case(command)).map(|(_, c)| *c)
+    }
+}
+
+/// Parse `<port>:<hex>` of `AT+SEND` and `AT+CSEND`.
+fn parse_uplink(argument: &str) -> Option<(u8, Vec<u8, { MAX_LINE_LEN / 2 }>)> {
+    let (fport, hex) = argument.split_once(':').unwrap_or((argument, ""));
+    let fport = fport.parse().ok()?;
+    let mut data = Vec::new();
+    for digits in hex.as_bytes().chunks(2) {
+        let [byte] = decode_hex::<1>(core::str::from_utf8(digits).ok()?, ByteOrder::Msb).ok()?;
+        data.push(byte).ok()?;
+    }
+    Some((fport, data))
+}
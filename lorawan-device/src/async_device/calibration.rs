@@ -0,0 +1,75 @@
+//! RX window timing calibration.
+//!
+//! While enabled, the [`Device`](super::Device) measures for every received downlink how long
+//! the radio had been listening before the downlink preamble started. This requires a
+//! [`Timer`](super::radio::Timer) which implements `elapsed_ms`. Based on these measurements, a
+//! value for [`Timings::get_rx_window_lead_time_ms`](super::Timings::get_rx_window_lead_time_ms)
+//! is suggested, replacing trial-and-error tuning.
+
+/// Measurements gathered during RX window timing calibration.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct RxTimingCalibration {
+    samples: u16,
+    min_gap_ms: i32,
+    max_gap_ms: i32,
+}
+
+impl RxTimingCalibration {
+    pub(crate) fn record(&mut self, gap_ms: i32) {
+        if self.samples == 0 {
+            self.min_gap_ms = gap_ms;
+            self.max_gap_ms = gap_ms;
+        } else {
+            self.min_gap_ms = self.min_gap_ms.min(gap_ms);
+            self.max_gap_ms = self.max_gap_ms.max(gap_ms);
+        }
+        self.samples = self.samples.saturating_add(1);
+    }
+
+    /// Number of downlinks measured.
+    pub fn samples(&self) -> u16 {
+        self.samples
+    }
+
+    /// Shortest time (ms) the radio has been listening before a downlink preamble started.
+    pub fn min_gap_ms(&self) -> Option<i32> {
+        (self.samples > 0).then_some(self.min_gap_ms)
+    }
+
+    /// Longest time (ms) the radio has been listening before a downlink preamble started.
+    pub fn max_gap_ms(&self) -> Option<i32> {
+        (self.samples > 0).then_some(self.max_gap_ms)
+    }
+
+    /// Suggest an RX window lead time which opens the RX windows `margin_ms` before the earliest
+    /// observed downlink preamble. Returns `None` if no downlinks have been measured.
+    ///
+    /// Note that downlinks which arrived before the RX window was opened can't be measured, so
+    /// if no downlinks get received at all, the current lead time needs to be increased first.
+    pub fn suggested_lead_time_ms(&self, current_lead_time_ms: u32, margin_ms: u32) -> Option<u32> {
+        let min_gap_ms = self.min_gap_ms()?;
+        let lead_time_ms = current_lead_time_ms as i64 - min_gap_ms as i64 + margin_ms as i64;
+        Some(lead_time_ms.clamp(0, u32::MAX as i64) as u32)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn suggest_lead_time() {
+        let mut calibration = RxTimingCalibration::default();
+        assert_eq!(calibration.suggested_lead_time_ms(50, 5), None);
+        calibration.record(30);
+        calibration.record(42);
+        assert_eq!(calibration.samples(), 2);
+        assert_eq!(calibration.min_gap_ms(), Some(30));
+        assert_eq!(calibration.max_gap_ms(), Some(42));
+        assert_eq!(calibration.suggested_lead_time_ms(50, 5), Some(25));
+
+        calibration.record(-10);
+        assert_eq!(calibration.suggested_lead_time_ms(50, 5), Some(65));
+    }
+}
@@ -0,0 +1,103 @@
+//! Forwarding of downlinks and device events to `embassy-sync` channels or signals, decoupling
+//! the task driving the [`Device`] from the application tasks consuming its output.
+//!
+//! Forwarding never blocks the device: when a channel is full, downlinks remain buffered in the
+//! device (see [`Device::take_downlink`]) and events are dropped.
+use super::{mac, package, radio, Device, Downlink, Timings};
+use crate::CryptoFactory;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::channel::{Channel, TrySendError};
+use embassy_sync::signal::Signal;
+use rand_core::RngCore;
+
+/// Events of the device, other than downlinks, forwarded to the application.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum DeviceEvent {
+    /// An OTAA or ABP join completed.
+    Joined,
+    /// The session can't be used anymore; the device has to join again.
+    SessionExpired,
+    Security(mac::SecurityEvent),
+    Health(mac::HealthEvent),
+}
+
+/// Destination of forwarded messages, implemented for `embassy-sync` channels and signals.
+pub trait Sink<T> {
+    /// Hand over the message without waiting, or return it if there is no room for it.
+    fn try_send(&self, message: T) -> Result<(), T>;
+}
+
+impl<M: RawMutex, T, const N: usize> Sink<T> for Channel<M, T, N> {
+    fn try_send(&self, message: T) -> Result<(), T> {
+        Channel::try_send(self, message).map_err(|TrySendError::Full(message)| message)
+    }
+}
+
+/// A signal only retains the most recent message.
+impl<M: RawMutex, T: Send> Sink<T> for Signal<M, T> {
+    fn try_send(&self, message: T) -> Result<(), T> {
+        self.signal(message);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Channels {
+    downlinks: Option<&'static (dyn Sink<Downlink> + Sync)>,
+    events: Option<&'static (dyn Sink<DeviceEvent> + Sync)>,
+}
+
+impl<R, T, G, C, P, const N: usize, const D: usize> Device<R, T, G, N, D, C, P>
+where
+    R: radio::PhyRxTx + Timings,
+    T: radio::Timer,
+    G: RngCore,
+    C: CryptoFactory,
+    P: package::Packages,
+{
+    /// Forward the downlinks received from now on to the given channel, once [`Device::join`],
+    /// [`Device::send`] or [`Device::rxc_listen`] returns. Downlinks consumed by registered
+    /// packages are not forwarded, and [`Device::send_recv`] can't see the replies anymore.
+    pub fn set_downlink_channel(&mut self, channel: Option<&'static (dyn Sink<Downlink> + Sync)>) {
+        self.channels.downlinks = channel;
+    }
+
+    /// Forward [`DeviceEvent`]s to the given channel. Security and health events are no longer
+    /// available via [`Device::take_security_event`] and [`Device::take_health_event`].
+    pub fn set_event_channel(&mut self, channel: Option<&'static (dyn Sink<DeviceEvent> + Sync)>) {
+        self.channels.events = channel;
+    }
+
+    pub(crate) fn forward_to_channels(&mut self, event: Option<DeviceEvent>) {
+        if let Some(channel) = self.channels.downlinks {
+            while !self.downlink.is_empty() {
+                // oldest downlink first
+                let downlink = self.downlink.remove(0);
+                if let Err(downlink) = channel.try_send(downlink) {
+                    debug!("Downlink channel full");
+                    // the slot has just been freed; only the heapless backend returns a result
+                    #[allow(clippy::let_unit_value)]
+                    let _ = self.downlink.insert(0, downlink);
+                    break;
+                }
+            }
+        }
+        if let Some(channel) = self.channels.events {
+            let forward = |event: DeviceEvent| {
+                if channel.try_send(event).is_err() {
+                    debug!("Event channel full, dropping {}", event);
+                }
+            };
+            while let Some(event) = self.mac.take_security_event() {
+                forward(DeviceEvent::Security(event));
+            }
+            while let Some(event) = self.mac.take_health_event() {
+                forward(DeviceEvent::Health(event));
+            }
+            if let Some(event) = event {
+                forward(event);
+            }
+        }
+    }
+}
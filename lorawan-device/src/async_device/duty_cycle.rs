@@ -0,0 +1,96 @@
+//! Duty cycle limitation of the uplinks the stack sends on its own (see [`AckPolicy`],
+//! [`MacCommandPolicy`] and [`FramePendingPolicy`]). After every transmission, the channel may only be used again once the
+//! off time required by the configured duty cycle has passed.
+//!
+//! [`AckPolicy`]: super::AckPolicy
+//! [`MacCommandPolicy`]: super::MacCommandPolicy
+//! [`FramePendingPolicy`]: super::FramePendingPolicy
+
+/// Kind of an uplink initiated by the stack itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum StackUplink {
+    /// FPort 0 uplink carrying MAC commands which don't fit into FOpts.
+    MacCommands,
+    /// Empty uplink acknowledging a confirmed downlink.
+    Ack,
+    /// Empty uplink opening RX windows for the downlinks the network has announced with the
+    /// FPending bit.
+    FramePending,
+}
+
+/// An uplink the stack is going to send on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct PendingUplink {
+    pub uplink: StackUplink,
+    /// Milliseconds from now until the uplink may be sent without exceeding the duty cycle.
+    pub earliest_tx_ms: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct DutyCycle {
+    /// The channel may be used for 1/`limit` of the time.
+    limit: Option<u16>,
+    off_time_ms: u64,
+    /// Time known to have passed since the end of the last transmission, eg: until the RX windows
+    /// opened, for timers which can't tell the elapsed time.
+    waited_ms: u64,
+}
+
+impl DutyCycle {
+    pub(crate) fn limit(&self) -> Option<u16> {
+        self.limit
+    }
+
+    pub(crate) fn set_limit(&mut self, limit: Option<u16>) {
+        self.limit = limit;
+        self.off_time_ms = 0;
+    }
+
+    /// A transmission with the given time on air has just ended.
+    pub(crate) fn transmitted(&mut self, time_on_air_ms: u64) {
+        self.off_time_ms = match self.limit {
+            Some(limit) => time_on_air_ms * (limit.max(1) as u64 - 1),
+            None => 0,
+        };
+        self.waited_ms = 0;
+    }
+
+    /// At least `elapsed_ms` have passed since the end of the last transmission.
+    pub(crate) fn waited(&mut self, elapsed_ms: u64) {
+        self.waited_ms = self.waited_ms.max(elapsed_ms);
+    }
+
+    /// Milliseconds until the next transmission is allowed, given the time elapsed since the end
+    /// of the last transmission. If the elapsed time is unknown, only the time known to have
+    /// passed (see [`DutyCycle::waited`]) is deducted from the off time.
+    pub(crate) fn remaining_ms(&self, elapsed_ms: Option<u64>) -> u64 {
+        self.off_time_ms.saturating_sub(elapsed_ms.unwrap_or(self.waited_ms))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn off_time() {
+        let mut duty_cycle = DutyCycle::default();
+        duty_cycle.transmitted(100);
+        assert_eq!(duty_cycle.remaining_ms(None), 0);
+
+        duty_cycle.set_limit(Some(100));
+        duty_cycle.transmitted(100);
+        assert_eq!(duty_cycle.remaining_ms(None), 9_900);
+        assert_eq!(duty_cycle.remaining_ms(Some(2_000)), 7_900);
+        assert_eq!(duty_cycle.remaining_ms(Some(20_000)), 0);
+
+        // the RX windows have been waited for, without knowing the time elapsed since
+        duty_cycle.waited(1_000);
+        duty_cycle.waited(2_000);
+        assert_eq!(duty_cycle.remaining_ms(None), 7_900);
+        duty_cycle.transmitted(100);
+        assert_eq!(duty_cycle.remaining_ms(None), 9_900);
+    }
+}
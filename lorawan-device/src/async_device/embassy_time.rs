@@ -31,4 +31,8 @@ impl Timer for EmbassyTimer {
     async fn delay_ms(&mut self, millis: u64) {
         embassy_time::Timer::after_millis(millis).await
     }
+
+    fn elapsed_ms(&self) -> Option<u64> {
+        Some(self.start.elapsed().as_millis())
+    }
 }
@@ -0,0 +1,578 @@
+//! Firmware update over the air (FUOTA) transport: the application layer clock synchronization
+//! (TS003) and fragmented data block transport (TS004) packages combined into a single
+//! [`Package`], with the received image written to a [`FuotaStorage`].
+//!
+//! This covers receiving and verifying the image only. The remote multicast setup package
+//! (TS005) is not part of it: the multicast group the fragments are usually sent to is handled
+//! with the `multicast` feature, and the fragmentation FPort ([`FRAGMENTATION_PORT`]) then needs
+//! to be within the multicast port range. Neither is the activation of the image: the
+//! application decides when to switch to it, eg: at a device time ([`FuotaSession::device_time`])
+//! agreed on with the network.
+//!
+//! Only uncoded fragments are supported: coded (redundant) fragments are ignored, so every
+//! fragment needs to be received, the missing ones being reported in FragSessionStatusAns.
+//!
+//! The progress of the transfer is reported as [`FuotaEvent`]s, eg:
+//!
+//! ```ignore
+//! let mut device = device.with_packages(FuotaSession::new(flash, uptime_seconds));
+//! ...
+//! while let Some(event) = device.packages().take_event() {
+//!     if let FuotaEvent::ImageReceived { size, crc_ok: true, .. } = event {
+//!         // the image is complete and verified, its activation is up to the application
+//!     }
+//! }
+//! ```
+use super::package::{Package, PackageUplink};
+use heapless::{Deque, Vec};
+
+/// Default FPort of the fragmented data block transport package.
+pub const FRAGMENTATION_PORT: u8 = 201;
+
+/// Default FPort of the clock synchronization package.
+pub const CLOCK_SYNC_PORT: u8 = 202;
+
+/// Largest number of fragments of an image.
+pub const MAX_FRAGMENTS: usize = 2048;
+
+const MAX_EVENTS: usize = 8;
+
+const PACKAGE_VERSION: u8 = 0x00;
+
+const FRAG_SESSION_STATUS: u8 = 0x01;
+const FRAG_SESSION_SETUP: u8 = 0x02;
+const FRAG_SESSION_DELETE: u8 = 0x03;
+const DATA_FRAGMENT: u8 = 0x08;
+
+const APP_TIME: u8 = 0x01;
+const DEVICE_APP_TIME_PERIODICITY: u8 = 0x02;
+const FORCE_DEVICE_RESYNC: u8 = 0x03;
+
+/// Storage of the received image, eg: the inactive slot of a bootloader.
+pub trait FuotaStorage {
+    type Error;
+    /// Prepare the storage for an image of `len` bytes.
+    fn erase(&mut self, len: usize) -> Result<(), Self::Error>;
+    /// Write `data` at `offset` in the image.
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), Self::Error>;
+    /// Read `buf.len()` bytes at `offset` in the image.
+    fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), Self::Error>;
+}
+
+/// Progress of the transfer of a firmware image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum FuotaEvent {
+    /// A fragmentation session has been set up by the network.
+    SessionSetup { index: u8, nb_frag: u16, frag_size: u8, descriptor: u32 },
+    /// A new fragment has been received.
+    FragmentReceived { index: u8, received: u16, missing: u16 },
+    /// All fragments have been received. `crc_ok` is set when the CRC32 of the image matches the
+    /// descriptor of the session, which commonly carries it.
+    ImageReceived { index: u8, size: usize, crc32: u32, crc_ok: bool },
+    /// The fragmentation session has been deleted by the network.
+    SessionDeleted { index: u8 },
+    /// The device time has been corrected by the network (in seconds).
+    TimeCorrected { correction: i32 },
+    /// The image could not be written to or read from the storage.
+    StorageError,
+}
+
+#[derive(Debug)]
+struct FragSession {
+    index: u8,
+    nb_frag: u16,
+    frag_size: u8,
+    padding: u8,
+    descriptor: u32,
+    received: [u32; MAX_FRAGMENTS / 32],
+    nb_received: u16,
+}
+
+impl FragSession {
+    fn is_received(&self, n: u16) -> bool {
+        self.received[n as usize / 32] & (1 << (n % 32)) != 0
+    }
+
+    fn missing(&self) -> u16 {
+        self.nb_frag - self.nb_received
+    }
+
+    fn size(&self) -> usize {
+        self.nb_frag as usize * self.frag_size as usize - self.padding as usize
+    }
+}
+
+/// Firmware update session handling the clock synchronization and fragmentation packages.
+pub struct FuotaSession<S> {
+    storage: S,
+    uptime: fn() -> u32,
+    frag_port: u8,
+    clock_port: u8,
+    session: Option<FragSession>,
+    frag_answers: Vec<u8, 32>,
+    clock_answers: Vec<u8, 16>,
+    time_offset: Option<i64>,
+    token: u8,
+    resync_requests: u8,
+    periodicity: Option<u8>,
+    events: Deque<FuotaEvent, MAX_EVENTS>,
+}
+
+impl<S: FuotaStorage> FuotaSession<S> {
+    /// Create a firmware update session writing the image to `storage`. `uptime` returns the time
+    /// since boot in seconds, from which the device time (GPS epoch) is derived once synchronized.
+    pub fn new(storage: S, uptime: fn() -> u32) -> Self {
+        Self {
+            storage,
+            uptime,
+            frag_port: FRAGMENTATION_PORT,
+            clock_port: CLOCK_SYNC_PORT,
+            session: None,
+            frag_answers: Vec::new(),
+            clock_answers: Vec::new(),
+            time_offset: None,
+            token: 0,
+            resync_requests: 0,
+            periodicity: None,
+            events: Deque::new(),
+        }
+    }
+
+    /// Use other FPorts than [`FRAGMENTATION_PORT`] and [`CLOCK_SYNC_PORT`].
+    pub fn with_ports(mut self, frag_port: u8, clock_port: u8) -> Self {
+        self.frag_port = frag_port;
+        self.clock_port = clock_port;
+        self
+    }
+
+    /// The storage of the image.
+    pub fn storage(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    /// Take the next progress event, if any.
+    pub fn take_event(&mut self) -> Option<FuotaEvent> {
+        self.events.pop_front()
+    }
+
+    /// Number of received and missing fragments of the current session.
+    pub fn progress(&self) -> Option<(u16, u16)> {
+        self.session.as_ref().map(|session| (session.nb_received, session.missing()))
+    }
+
+    /// Request the device time from the network (AppTimeReq) with the next package uplink.
+    pub fn request_clock_sync(&mut self) {
+        self.resync_requests = self.resync_requests.max(1);
+    }
+
+    /// Device time (seconds since the GPS epoch), once synchronized with the network, eg: to
+    /// schedule the activation of a received image.
+    pub fn device_time(&self) -> Option<u32> {
+        self.time_offset.map(|offset| (i64::from((self.uptime)()) + offset) as u32)
+    }
+
+    /// Period (in seconds) at which the network asked for the clock to be synchronized, if it
+    /// did. The application calls [`FuotaSession::request_clock_sync`] accordingly.
+    pub fn clock_sync_period(&self) -> Option<u32> {
+        self.periodicity.map(|period| 128 << period)
+    }
+
+    fn event(&mut self, event: FuotaEvent) {
+        debug!("FUOTA event: {}", event);
+        if self.events.is_full() {
+            self.events.pop_front();
+        }
+        let _ = self.events.push_back(event);
+    }
+
+    fn frag_answer(&mut self, answer: &[u8]) {
+        if self.frag_answers.extend_from_slice(answer).is_err() {
+            warn!("FUOTA: dropping fragmentation answer");
+        }
+    }
+
+    fn clock_answer(&mut self, answer: &[u8]) {
+        if self.clock_answers.extend_from_slice(answer).is_err() {
+            warn!("FUOTA: dropping clock synchronization answer");
+        }
+    }
+
+    fn handle_frag_downlink(&mut self, data: &[u8]) {
+        let mut data = data;
+        while let Some((&cid, rest)) = data.split_first() {
+            data = match cid {
+                PACKAGE_VERSION => {
+                    // PackageIdentifier 3, PackageVersion 1
+                    self.frag_answer(&[PACKAGE_VERSION, 3, 1]);
+                    rest
+                }
+                FRAG_SESSION_STATUS if !rest.is_empty() => {
+                    self.frag_session_status(rest[0]);
+                    &rest[1..]
+                }
+                FRAG_SESSION_SETUP if rest.len() >= 10 => {
+                    self.frag_session_setup(&rest[..10]);
+                    &rest[10..]
+                }
+                FRAG_SESSION_DELETE if !rest.is_empty() => {
+                    self.frag_session_delete(rest[0] & 0b11);
+                    &rest[1..]
+                }
+                // a fragment takes up the rest of the payload
+                DATA_FRAGMENT if rest.len() >= 2 => {
+                    self.data_fragment(rest);
+                    &[]
+                }
+                _ => {
+                    warn!("FUOTA: invalid fragmentation command {}", cid);
+                    &[]
+                }
+            }
+        }
+    }
+
+    fn frag_session_status(&mut self, param: u8) {
+        let participants = param & 1 != 0;
+        let index = (param >> 1) & 0b11;
+        let Some(session) = self.session.as_ref().filter(|session| session.index == index) else {
+            return;
+        };
+        let missing = session.missing();
+        if !participants && missing == 0 {
+            return;
+        }
+        let received = session.nb_received | u16::from(index) << 14;
+        let [received_lo, received_hi] = received.to_le_bytes();
+        let missing = missing.min(255) as u8;
+        self.frag_answer(&[FRAG_SESSION_STATUS, received_lo, received_hi, missing, 0]);
+    }
+
+    fn frag_session_setup(&mut self, req: &[u8]) {
+        let index = (req[0] >> 4) & 0b11;
+        let nb_frag = u16::from_le_bytes([req[1], req[2]]);
+        let frag_size = req[3];
+        let padding = req[5];
+        let descriptor = u32::from_le_bytes([req[6], req[7], req[8], req[9]]);
+        let mut status = index << 6;
+        if self.session.as_ref().is_some_and(|session| session.index != index) {
+            // FragSession index not supported
+            status |= 1 << 2;
+        } else if nb_frag as usize > MAX_FRAGMENTS
+            || nb_frag == 0
+            || frag_size == 0
+            // the padding only completes the last fragment
+            || padding >= frag_size
+        {
+            // Not enough memory
+            status |= 1 << 1;
+        } else {
+            let session = FragSession {
+                index,
+                nb_frag,
+                frag_size,
+                padding,
+                descriptor,
+                received: [0; MAX_FRAGMENTS / 32],
+                nb_received: 0,
+            };
+            if self.storage.erase(session.size()).is_err() {
+                self.event(FuotaEvent::StorageError);
+                status |= 1 << 1;
+            } else {
+                self.session = Some(session);
+                self.event(FuotaEvent::SessionSetup { index, nb_frag, frag_size, descriptor });
+            }
+        }
+        self.frag_answer(&[FRAG_SESSION_SETUP, status]);
+    }
+
+    fn frag_session_delete(&mut self, index: u8) {
+        let mut status = index;
+        if self.session.as_ref().is_some_and(|session| session.index == index) {
+            self.session = None;
+            self.event(FuotaEvent::SessionDeleted { index });
+        } else {
+            // Session does not exist
+            status |= 1 << 2;
+        }
+        self.frag_answer(&[FRAG_SESSION_DELETE, status]);
+    }
+
+    fn data_fragment(&mut self, data: &[u8]) {
+        let index_and_n = u16::from_le_bytes([data[0], data[1]]);
+        let index = (index_and_n >> 14) as u8;
+        let n = index_and_n & 0x3fff;
+        let payload = &data[2..];
+        let Some(session) = self.session.as_mut().filter(|session| session.index == index) else {
+            return;
+        };
+        if n == 0 || n > session.nb_frag || payload.len() != session.frag_size as usize {
+            trace!("FUOTA: ignoring fragment {}", n);
+            return;
+        }
+        let fragment = n - 1;
+        if session.is_received(fragment) {
+            return;
+        }
+        let offset = fragment as usize * session.frag_size as usize;
+        let Some(remaining) = session.size().checked_sub(offset) else {
+            trace!("FUOTA: ignoring fragment {} past the image", n);
+            return;
+        };
+        let len = payload.len().min(remaining);
+        if self.storage.write(offset, &payload[..len]).is_err() {
+            self.event(FuotaEvent::StorageError);
+            return;
+        }
+        session.received[fragment as usize / 32] |= 1 << (fragment % 32);
+        session.nb_received += 1;
+        let (received, missing) = (session.nb_received, session.missing());
+        let (size, descriptor) = (session.size(), session.descriptor);
+        self.event(FuotaEvent::FragmentReceived { index, received, missing });
+        if missing == 0 {
+            match self.image_crc32(size) {
+                Some(crc32) => self.event(FuotaEvent::ImageReceived {
+                    index,
+                    size,
+                    crc32,
+                    crc_ok: crc32 == descriptor,
+                }),
+                None => self.event(FuotaEvent::StorageError),
+            }
+        }
+    }
+
+    fn image_crc32(&mut self, size: usize) -> Option<u32> {
+        let mut crc = !0u32;
+        let mut buf = [0; 64];
+        let mut offset = 0;
+        while offset < size {
+            let chunk = &mut buf[..(size - offset).min(64)];
+            self.storage.read(offset, chunk).ok()?;
+            for byte in chunk.iter() {
+                crc ^= u32::from(*byte);
+                for _ in 0..8 {
+                    crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+                }
+            }
+            offset += chunk.len();
+        }
+        Some(!crc)
+    }
+
+    fn handle_clock_downlink(&mut self, data: &[u8]) {
+        let mut data = data;
+        while let Some((&cid, rest)) = data.split_first() {
+            data = match cid {
+                PACKAGE_VERSION => {
+                    // PackageIdentifier 1, PackageVersion 1
+                    self.clock_answer(&[PACKAGE_VERSION, 1, 1]);
+                    rest
+                }
+                APP_TIME if rest.len() >= 5 => {
+                    let correction = i32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]);
+                    // only the answer to the last request is taken into account
+                    if rest[4] & 0x0f == self.token.wrapping_sub(1) & 0x0f {
+                        // before the first synchronization, the device time is the uptime
+                        let offset = self.time_offset.unwrap_or(0);
+                        self.time_offset = Some(offset + i64::from(correction));
+                        self.resync_requests = 0;
+                        self.event(FuotaEvent::TimeCorrected { correction });
+                    }
+                    &rest[5..]
+                }
+                DEVICE_APP_TIME_PERIODICITY if !rest.is_empty() => {
+                    self.periodicity = Some(rest[0] & 0x0f);
+                    let [t0, t1, t2, t3] = self.device_time().unwrap_or(0).to_le_bytes();
+                    self.clock_answer(&[DEVICE_APP_TIME_PERIODICITY, 0, t0, t1, t2, t3]);
+                    &rest[1..]
+                }
+                FORCE_DEVICE_RESYNC if !rest.is_empty() => {
+                    self.resync_requests = rest[0] & 0b111;
+                    &rest[1..]
+                }
+                _ => {
+                    warn!("FUOTA: invalid clock synchronization command {}", cid);
+                    &[]
+                }
+            }
+        }
+    }
+}
+
+impl<S: FuotaStorage> Package for FuotaSession<S> {
+    fn port(&self) -> u8 {
+        self.frag_port
+    }
+
+    fn handles_port(&self, fport: u8) -> bool {
+        fport == self.frag_port || fport == self.clock_port
+    }
+
+    fn handle_downlink(&mut self, data: &[u8]) {
+        self.handle_frag_downlink(data);
+    }
+
+    fn handle_port_downlink(&mut self, fport: u8, data: &[u8]) {
+        if fport == self.clock_port {
+            self.handle_clock_downlink(data);
+        } else {
+            self.handle_frag_downlink(data);
+        }
+    }
+
+    fn poll_uplink(&mut self, buf: &mut [u8]) -> Option<PackageUplink> {
+        if !self.frag_answers.is_empty() {
+            let len = self.frag_answers.len();
+            buf[..len].copy_from_slice(&self.frag_answers);
+            self.frag_answers.clear();
+            return Some(PackageUplink { len, confirmed: false, fport: None });
+        }
+        if self.resync_requests > 0 {
+            self.resync_requests -= 1;
+            let device_time = self.device_time().unwrap_or_else(|| (self.uptime)());
+            // AnsRequired is set until the clock has been synchronized
+            let param = self.token & 0x0f | u8::from(self.time_offset.is_none()) << 4;
+            self.token = self.token.wrapping_add(1);
+            self.clock_answer(&[APP_TIME]);
+            self.clock_answer(&device_time.to_le_bytes());
+            self.clock_answer(&[param]);
+        }
+        if self.clock_answers.is_empty() {
+            return None;
+        }
+        let len = self.clock_answers.len();
+        buf[..len].copy_from_slice(&self.clock_answers);
+        self.clock_answers.clear();
+        Some(PackageUplink { len, confirmed: false, fport: Some(self.clock_port) })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Ram([u8; 16]);
+
+    impl FuotaStorage for Ram {
+        type Error = ();
+
+        fn erase(&mut self, len: usize) -> Result<(), ()> {
+            (len <= self.0.len()).then(|| self.0.fill(0xff)).ok_or(())
+        }
+
+        fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), ()> {
+            self.0[offset..offset + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), ()> {
+            buf.copy_from_slice(&self.0[offset..offset + buf.len()]);
+            Ok(())
+        }
+    }
+
+    fn uptime() -> u32 {
+        100
+    }
+
+    fn poll(fuota: &mut FuotaSession<Ram>) -> Option<(Option<u8>, std::vec::Vec<u8>)> {
+        let mut buf = [0; 64];
+        fuota.poll_uplink(&mut buf).map(|uplink| (uplink.fport, buf[..uplink.len].to_vec()))
+    }
+
+    #[test]
+    fn fragmented_image() {
+        let mut fuota = FuotaSession::new(Ram([0; 16]), uptime);
+        assert!(fuota.handles_port(FRAGMENTATION_PORT) && fuota.handles_port(CLOCK_SYNC_PORT));
+
+        // FragSessionSetupReq: index 1, 3 fragments of 4 bytes, 2 bytes of padding, CRC32 of
+        // "0123456789" as descriptor
+        let setup = [0x02, 0x10, 3, 0, 4, 0, 2, 0xc6, 0xc7, 0x84, 0xa6];
+        fuota.handle_port_downlink(FRAGMENTATION_PORT, &setup);
+        assert_eq!(poll(&mut fuota), Some((None, vec![0x02, 0x40])));
+        assert_eq!(
+            fuota.take_event(),
+            Some(FuotaEvent::SessionSetup {
+                index: 1,
+                nb_frag: 3,
+                frag_size: 4,
+                descriptor: 0xa684_c7c6
+            })
+        );
+
+        fuota.handle_port_downlink(FRAGMENTATION_PORT, &[0x08, 0x03, 0x40, b'8', b'9', 0, 0]);
+        fuota.handle_port_downlink(FRAGMENTATION_PORT, &[0x08, 0x01, 0x40, b'0', b'1', b'2', b'3']);
+        // coded fragments are ignored
+        fuota.handle_port_downlink(FRAGMENTATION_PORT, &[0x08, 0x04, 0x40, 1, 2, 3, 4]);
+        assert_eq!(fuota.progress(), Some((2, 1)));
+
+        // FragSessionStatusReq from the devices missing fragments
+        fuota.handle_port_downlink(FRAGMENTATION_PORT, &[0x01, 0x02]);
+        assert_eq!(poll(&mut fuota), Some((None, vec![0x01, 0x02, 0x40, 1, 0])));
+
+        fuota.handle_port_downlink(FRAGMENTATION_PORT, &[0x08, 0x02, 0x40, b'4', b'5', b'6', b'7']);
+        assert_eq!(&fuota.storage().0[..10], b"0123456789");
+        let events: std::vec::Vec<_> = core::iter::from_fn(|| fuota.take_event()).collect();
+        assert_eq!(
+            events.last(),
+            Some(&FuotaEvent::ImageReceived {
+                index: 1,
+                size: 10,
+                crc32: 0xa684_c7c6,
+                crc_ok: true
+            })
+        );
+
+        fuota.handle_port_downlink(FRAGMENTATION_PORT, &[0x01, 0x02]);
+        assert_eq!(poll(&mut fuota), None);
+        fuota.handle_port_downlink(FRAGMENTATION_PORT, &[0x03, 0x01, 0x03, 0x02]);
+        assert_eq!(poll(&mut fuota), Some((None, vec![0x03, 0x01, 0x03, 0x06])));
+        assert_eq!(fuota.progress(), None);
+    }
+
+    #[test]
+    fn padding_beyond_last_fragment() {
+        let mut fuota = FuotaSession::new(Ram([0; 16]), uptime);
+        // FragSessionSetupReq: index 0, 2 fragments of 4 bytes, 6 bytes of padding
+        let setup = [0x02, 0x00, 2, 0, 4, 0, 6, 0, 0, 0, 0];
+        fuota.handle_port_downlink(FRAGMENTATION_PORT, &setup);
+        // Not enough memory
+        assert_eq!(poll(&mut fuota), Some((None, vec![0x02, 0x02])));
+        assert_eq!(fuota.take_event(), None);
+
+        // the last fragment would start past the end of the image
+        fuota.handle_port_downlink(FRAGMENTATION_PORT, &[0x08, 0x02, 0x00, 1, 2, 3, 4]);
+        assert_eq!(fuota.progress(), None);
+        assert_eq!(fuota.storage().0, [0; 16]);
+    }
+
+    #[test]
+    fn clock_sync() {
+        let mut fuota = FuotaSession::new(Ram([0; 16]), uptime);
+        assert_eq!(fuota.device_time(), None);
+        fuota.request_clock_sync();
+        assert_eq!(poll(&mut fuota), Some((Some(CLOCK_SYNC_PORT), vec![0x01, 100, 0, 0, 0, 0x10])));
+        // AppTimeAns with a stale token is ignored
+        fuota.handle_port_downlink(CLOCK_SYNC_PORT, &[0x01, 0x10, 0, 0, 0, 0x05]);
+        assert_eq!(fuota.device_time(), None);
+        fuota.handle_port_downlink(CLOCK_SYNC_PORT, &[0x01, 0x10, 0, 0, 0, 0x00]);
+        assert_eq!(fuota.device_time(), Some(116));
+        assert_eq!(fuota.take_event(), Some(FuotaEvent::TimeCorrected { correction: 16 }));
+
+        fuota.handle_port_downlink(CLOCK_SYNC_PORT, &[0x00, 0x02, 0x01, 0x03, 0x02]);
+        assert_eq!(fuota.clock_sync_period(), Some(256));
+        // the answers to PackageVersionReq and DeviceAppTimePeriodicityReq, then the AppTimeReq
+        // requested by ForceDeviceResyncReq
+        assert_eq!(
+            poll(&mut fuota),
+            Some((
+                Some(CLOCK_SYNC_PORT),
+                vec![0x00, 1, 1, 0x02, 0, 116, 0, 0, 0, 0x01, 116, 0, 0, 0, 0x01]
+            ))
+        );
+        assert_eq!(poll(&mut fuota), Some((Some(CLOCK_SYNC_PORT), vec![0x01, 116, 0, 0, 0, 0x02])));
+        assert_eq!(poll(&mut fuota), None);
+    }
+}
@@ -0,0 +1,105 @@
+//! Passive network detection before joining in regions with a fixed channel plan (US915, AU915).
+//!
+//! Networks often serve only one of the eight 125 kHz subbands, in which case most join requests
+//! of the standard round robin are sent on channels no gateway listens to. Before joining,
+//! [`Device::scan_join_channels`] samples every 125 kHz join channel with
+//! [`PhyRxTx::sample_channel`](radio::PhyRxTx::sample_channel) (eg: channel activity detection)
+//! and lets the join requests visit the subbands in order of the observed activity.
+use super::{package, radio, Device, Error, Timings};
+use crate::region::Subband;
+use crate::CryptoFactory;
+use core::cmp::Reverse;
+use rand_core::RngCore;
+
+/// Activity observed on the channels of a subband.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct SubbandActivity {
+    pub subband: Subband,
+    /// Number of samples in which a LoRa preamble was detected.
+    pub detections: u16,
+    /// Highest RSSI (dBm) measured on any channel of the subband.
+    pub max_rssi: Option<i16>,
+}
+
+impl SubbandActivity {
+    fn is_active(&self) -> bool {
+        self.detections > 0 || self.max_rssi.is_some()
+    }
+}
+
+/// Result of [`Device::scan_join_channels`]: the subbands, most active first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct JoinScan {
+    subbands: [SubbandActivity; 8],
+}
+
+impl JoinScan {
+    fn new(mut subbands: [SubbandActivity; 8]) -> Self {
+        subbands.sort_unstable_by_key(|s| {
+            (Reverse(s.detections), Reverse(s.max_rssi), s.subband as usize)
+        });
+        Self { subbands }
+    }
+
+    /// Activity of every subband, most active first.
+    pub fn subbands(&self) -> &[SubbandActivity; 8] {
+        &self.subbands
+    }
+
+    /// The most active subband, `None` if no activity was observed at all.
+    pub fn most_active(&self) -> Option<Subband> {
+        Some(self.subbands[0]).filter(SubbandActivity::is_active).map(|s| s.subband)
+    }
+
+    /// The subbands, most active first.
+    pub fn order(&self) -> [Subband; 8] {
+        self.subbands.map(|s| s.subband)
+    }
+}
+
+impl<R, T, G, C, P, const N: usize, const D: usize> Device<R, T, G, N, D, C, P>
+where
+    R: radio::PhyRxTx + Timings,
+    T: radio::Timer,
+    G: RngCore,
+    C: CryptoFactory,
+    P: package::Packages,
+{
+    /// Sample each 125 kHz join channel `samples` times and order the subbands of the following
+    /// join attempts by the observed activity (see `US915::set_join_order`). Every subband is
+    /// still attempted once per round, so the join succeeds even if the scan was misled.
+    ///
+    /// The join order is left unchanged if no activity was observed. Returns `None` for regions
+    /// with a dynamic channel plan and radios which can't sample a channel.
+    pub async fn scan_join_channels(
+        &mut self,
+        samples: u8,
+    ) -> Result<Option<JoinScan>, Error<R::PhyError>> {
+        let mut activity =
+            Subband::ALL.map(|subband| SubbandActivity { subband, detections: 0, max_rssi: None });
+        for subband in activity.iter_mut() {
+            for channel in subband.subband.channels() {
+                let Some(rf) = self.mac.region.join_channel_rf(channel) else {
+                    return Ok(None);
+                };
+                for _ in 0..samples {
+                    let Some(sample) = self.radio.sample_channel(rf).await.map_err(Error::Radio)?
+                    else {
+                        return Ok(None);
+                    };
+                    subband.detections += sample.detected as u16;
+                    subband.max_rssi = subband.max_rssi.max(sample.rssi);
+                }
+            }
+        }
+        self.radio.low_power().await.map_err(Error::Radio)?;
+
+        let scan = JoinScan::new(activity);
+        if scan.most_active().is_some() {
+            self.mac.region.set_join_order(Some(scan.order()));
+        }
+        Ok(Some(scan))
+    }
+}
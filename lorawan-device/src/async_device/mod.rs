@@ -4,19 +4,39 @@ use super::mac::{self, FcntDown, Frame, Mac, Window};
 pub use super::{
     mac::{NetworkCredentials, SendData, Session},
     region::{self, Region},
-    Downlink, JoinMode,
+    Class, DeviceClass, Downlink, JoinMode,
 };
 use heapless::Vec;
 use rand_core::RngCore;
 
 pub use crate::region::DR;
 use crate::{
+    collections::{self, Collection},
     radio::{RadioBuffer, RfConfig, RxConfig},
-    rng,
+    rng, CryptoFactory,
 };
 
+pub mod airtime;
+pub mod at;
+pub mod calibration;
+#[cfg(feature = "embassy-sync")]
+pub mod channels;
+pub mod duty_cycle;
+pub mod fuota;
+#[cfg(any(feature = "region-us915", feature = "region-au915"))]
+pub mod join_scan;
+pub mod package;
 pub mod radio;
+pub mod range_test;
+pub mod send_ext;
+mod spacing;
+mod suspend;
+pub use suspend::Suspended;
+pub mod tap;
+pub mod telemetry;
+mod yield_now;
 use lorawan::default_crypto::DefaultFactory;
+use yield_now::yield_now;
 
 #[cfg(feature = "embassy-time")]
 mod embassy_time;
@@ -55,28 +75,133 @@ use self::radio::RxStatus;
 ///   providing a random seed
 /// - N: The size of the radio buffer. Generally, this should be set to 256 to support the largest possible LoRa frames.
 /// - D: The amount of downlinks that may be buffered. This is used to support Class C operation. See below for more.
+///   With the `alloc` feature, the downlinks are buffered on the heap and D is ignored.
+/// - C: The crypto backend, which defaults to the software AES implementation. See
+///   [`new_with_crypto`](Device::new_with_crypto) to pass a backend instance, eg: one backed by a
+///   secure element or an enum choosing between backends at runtime.
 ///
 /// Note that the const generics N and D are used to configure the size of the radio buffer and the number of downlinks
 /// that may be buffered. The defaults are 256 and 1 respectively which should be fine for Class A devices. **For Class
 /// C operation**, it is recommended to increase D to at least 2, if not 3. This is because during the RX1/RX2 windows
 /// after a Class A transmit, it is possible to receive Class C downlinks (in additional to any RX1/RX2 responses!).
-pub struct Device<R, T, G, const N: usize = 256, const D: usize = 1>
+///
+/// The generic P holds the application layer [`package`]s owned by the device, none by default
+/// (see [`Device::with_packages`]).
+pub struct Device<R, T, G, const N: usize = 256, const D: usize = 1, C = DefaultFactory, P = ()>
 where
     R: radio::PhyRxTx + Timings,
     T: radio::Timer,
     G: RngCore,
+    C: CryptoFactory,
+    P: package::Packages,
 {
     radio: R,
     /// Access to provided (pseudo)-random number generator.
     pub rng: G,
+    crypto: C,
     timer: T,
     mac: Mac,
     radio_buffer: RadioBuffer<N>,
-    downlink: Vec<Downlink, D>,
+    downlink: collections::Vec<Downlink, D>,
+    rx_timing: Option<calibration::RxTimingCalibration>,
+    duty_cycle: duty_cycle::DutyCycle,
+    spacing: spacing::UplinkSpacing,
+    airtime: airtime::AirtimeLedger,
+    packages: P,
+    queue: send_ext::UplinkQueue,
+    #[cfg(feature = "embassy-sync")]
+    channels: channels::Channels,
+    ack_policy: AckPolicy,
+    mac_command_policy: MacCommandPolicy,
+    frame_pending_policy: FramePendingPolicy,
+    radio_error_policy: RadioErrorPolicy,
+    late_window: Option<u32>,
+    rx_window_hook: Option<fn(RxWindowEvent)>,
+    uplink_report: Option<telemetry::UplinkReport>,
+    stack_error: Option<Error<R::PhyError>>,
     #[cfg(feature = "class-c")]
     class_c: bool,
 }
 
+/// Determines how confirmed downlinks get acknowledged.
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AckPolicy {
+    /// Set the ACK bit on the next uplink sent by the application.
+    #[default]
+    NextUplink,
+    /// Send an empty uplink acknowledging the downlink as soon as it has been received, for
+    /// network servers which otherwise retransmit the downlink aggressively.
+    Immediate,
+}
+
+/// Determines how MAC commands (eg: answers to network requests) which don't fit into the FOpts
+/// field of a single uplink are transmitted.
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MacCommandPolicy {
+    /// Spread the MAC commands over the FOpts of subsequent uplinks.
+    #[default]
+    FOpts,
+    /// Send the remaining MAC commands in the FRMPayload of an FPort 0 uplink right after the
+    /// current uplink. Such an uplink can also be sent explicitly by sending empty data on
+    /// FPort 0.
+    FPortZero,
+}
+
+/// Determines how downlinks announced by the network with the FPending bit are fetched.
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FramePendingPolicy {
+    /// Leave it to the application to send the next uplink (see [`Device::is_frame_pending`]).
+    #[default]
+    Ignore,
+    /// Send an empty uplink right away, and again as long as the downlinks received this way
+    /// have the FPending bit set, up to [`MAX_FRAME_PENDING_POLLS`] times in a row. Polling stops
+    /// while the downlink buffer (see the const generic D of [`Device`]) is full, until the
+    /// application has taken a downlink.
+    Poll,
+}
+
+/// Maximum number of empty uplinks sent in a row for [`FramePendingPolicy::Poll`].
+pub const MAX_FRAME_PENDING_POLLS: usize = 8;
+
+/// Determines how errors reported by the radio while transmitting an uplink (or join request)
+/// and during its RX windows are handled. With either policy, an uplink which has been
+/// transmitted uses up its frame counter, even if its RX windows fail.
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RadioErrorPolicy {
+    /// Return the error.
+    #[default]
+    Propagate,
+    /// Reset the radio (see [`PhyRxTx::reset`](radio::PhyRxTx::reset)) and carry on: an RX
+    /// window which fails is skipped as if nothing had been received, and a transmission which
+    /// fails is retried after `retry_delay_ms`, up to `max_retries` times before the error is
+    /// returned.
+    Recover { max_retries: u8, retry_delay_ms: u32 },
+}
+
+/// Class A receive window, see [`Device::set_rx_window_hook`].
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RxWindow {
+    Rx1,
+    Rx2,
+    /// The window opened after RX2, see [`Device::set_late_downlink_window`].
+    Late,
+}
+
+/// Reported to the hook set with [`Device::set_rx_window_hook`] around every receive window.
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RxWindowEvent {
+    /// The radio is about to be configured for the window.
+    Opening(RxWindow),
+    /// The window has closed, with or without a downlink.
+    Closed(RxWindow),
+}
+
 #[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
 #[derive(Debug)]
 pub enum Error<R> {
@@ -188,6 +313,63 @@ where
         rng: G,
         session: Option<Session>,
     ) -> Self {
+        Device::new_with_crypto_and_session(region, radio, timer, rng, DefaultFactory, session)
+    }
+
+    /// Create a new [`Device`] from a [`provisioning`](crate::provisioning) blob, eg: as written
+    /// to flash during factory programming. The region, sub-band, Class C and RX2 settings are
+    /// applied to the device; the returned [`JoinMode`] is to be passed to
+    /// [`join`](Device::join).
+    pub fn from_provisioning(
+        blob: &[u8],
+        radio: R,
+        timer: T,
+        rng: G,
+    ) -> Result<(Self, JoinMode), crate::provisioning::Error> {
+        let provisioning = crate::provisioning::Provisioning::decode(blob)?;
+        let mut device = Device::new(provisioning.region_configuration(), radio, timer, rng);
+        if let Some(rx2) = provisioning.rx2 {
+            device.mac.set_rx2(rx2.frequency, rx2.data_rate);
+        }
+        #[cfg(feature = "class-c")]
+        if provisioning.class_c {
+            device.enable_class_c();
+        }
+        Ok((device, provisioning.join_mode))
+    }
+}
+
+impl<R, T, G, C, const N: usize, const D: usize> Device<R, T, G, N, D, C>
+where
+    R: radio::PhyRxTx + Timings,
+    T: radio::Timer,
+    G: RngCore,
+    C: CryptoFactory,
+{
+    /// Create a new [`Device`] which uses the given crypto backend for encrypting, decrypting and
+    /// authenticating frames as well as for deriving session keys. Since the backend is passed as
+    /// an instance, a single firmware image may pick it at runtime, eg: after detecting whether a
+    /// secure element is fitted.
+    pub fn new_with_crypto(
+        region: region::Configuration,
+        radio: R,
+        timer: T,
+        rng: G,
+        crypto: C,
+    ) -> Self {
+        Device::new_with_crypto_and_session(region, radio, timer, rng, crypto, None)
+    }
+
+    /// Create a new [`Device`] with the given crypto backend and provide an optional [`Session`].
+    pub fn new_with_crypto_and_session(
+        region: region::Configuration,
+        radio: R,
+        timer: T,
+        rng: G,
+        crypto: C,
+        session: Option<Session>,
+    ) -> Self {
+        let airtime = airtime::AirtimeLedger::new(region.get_current_region());
         let mut mac = Mac::new(region, R::MAX_RADIO_POWER, R::ANTENNA_GAIN);
         if let Some(session) = session {
             mac.set_session(session);
@@ -195,15 +377,41 @@ where
         Self {
             radio,
             rng,
+            crypto,
             mac,
             radio_buffer: RadioBuffer::new(),
             timer,
-            downlink: Vec::new(),
+            downlink: Default::default(),
+            rx_timing: None,
+            duty_cycle: Default::default(),
+            spacing: Default::default(),
+            airtime,
+            packages: (),
+            queue: Default::default(),
+            #[cfg(feature = "embassy-sync")]
+            channels: Default::default(),
+            ack_policy: AckPolicy::NextUplink,
+            mac_command_policy: MacCommandPolicy::FOpts,
+            frame_pending_policy: FramePendingPolicy::Ignore,
+            radio_error_policy: RadioErrorPolicy::Propagate,
+            late_window: None,
+            rx_window_hook: None,
+            uplink_report: None,
+            stack_error: None,
             #[cfg(feature = "class-c")]
             class_c: false,
         }
     }
+}
 
+impl<R, T, G, C, P, const N: usize, const D: usize> Device<R, T, G, N, D, C, P>
+where
+    R: radio::PhyRxTx + Timings,
+    T: radio::Timer,
+    G: RngCore,
+    C: CryptoFactory,
+    P: package::Packages,
+{
     /// Enables Class C behavior. Note that Class C downlinks are not possible until a confirmed
     /// uplink is sent to the LNS.
     #[cfg(feature = "class-c")]
@@ -230,8 +438,7 @@ where
     #[cfg(feature = "multicast")]
     /// Set the McKEKey for multicast session key derivation by providing a McRootKey.
     pub fn set_multicast_ke_key(&mut self, mc_root_key: McRootKey) {
-        let crypto = DefaultFactory;
-        let key = lorawan::keys::McKEKey::derive_from(&crypto, &mc_root_key);
+        let key = lorawan::keys::McKEKey::derive_from(&self.crypto, &mc_root_key);
         self.mac.multicast.mc_k_e_key = Some(key);
     }
 
@@ -240,8 +447,7 @@ where
     /// GenAppKey. The McRootKey is derived from this using `McRootKey = aes128_encrypt(GenAppKey, 0x00 | pad16) `
     /// and then the McKEKey is derived from the McRootKey.
     pub fn set_multicast_ke_key_from_gen_app_key(&mut self, key: GenAppKey) {
-        let crypto = DefaultFactory;
-        let mc_root_key = McRootKey::derive_from_gen_app_key(&crypto, &key);
+        let mc_root_key = McRootKey::derive_from_gen_app_key(&self.crypto, &key);
         self.set_multicast_ke_key(mc_root_key);
     }
 
@@ -250,8 +456,7 @@ where
     /// GenAppKey. The McRootKey is derived from this using `McRootKey = aes128_encrypt(AppKey, 0x20 | pad16) `
     /// and then the McKEKey is derived from the McRootKey.
     pub fn set_multicast_ke_key_from_app_key(&mut self, key: AppKey) {
-        let crypto = DefaultFactory;
-        let mc_root_key = McRootKey::derive_from_app_key(&crypto, &key);
+        let mc_root_key = McRootKey::derive_from_app_key(&self.crypto, &key);
         self.set_multicast_ke_key(mc_root_key);
     }
 
@@ -274,10 +479,50 @@ where
         self.class_c = false;
     }
 
+    /// Switch between Class A and Class C at runtime, eg: for devices which are mains-powered only
+    /// part of the time. As the device is idle between calls, the switch happens at a frame
+    /// boundary: switching to Class A stops listening immediately, while Class C listening starts
+    /// after the RX windows of the next uplink. With `indicate`, the switch is announced to a
+    /// LoRaWAN 1.1 network (see [`Device::indicate_device_mode`]); the class is left unchanged if
+    /// this fails because the device is not joined. Class B is not supported and fails with
+    /// [`mac::Error::UnsupportedClass`].
+    #[cfg(feature = "class-c")]
+    pub async fn set_class(
+        &mut self,
+        class: Class,
+        indicate: bool,
+    ) -> Result<(), Error<R::PhyError>> {
+        let class = DeviceClass::try_from(class)?;
+        if indicate {
+            self.mac.indicate_device_mode(class)?;
+        }
+        let class_c = class == DeviceClass::C;
+        if self.class_c && !class_c {
+            self.radio.low_power().await.map_err(Error::Radio)?;
+        }
+        self.class_c = class_c;
+        Ok(())
+    }
+
+    #[cfg(feature = "class-c")]
+    pub fn get_class(&self) -> Class {
+        if self.class_c {
+            Class::C
+        } else {
+            Class::A
+        }
+    }
+
     pub fn get_session(&mut self) -> Option<&Session> {
         self.mac.get_session()
     }
 
+    /// The session keys and DevAddr of the current session. Only available while session key
+    /// export is enabled, see [`Device::set_session_key_export`].
+    pub fn get_session_keys(&self) -> Option<mac::SessionKeys> {
+        self.mac.get_session_keys().filter(|_| self.mac.get_session_key_export())
+    }
+
     pub fn get_region(&mut self) -> &region::Configuration {
         &self.mac.region
     }
@@ -295,9 +540,440 @@ where
         self.mac.configuration.data_rate
     }
 
-    /// Set the data rate being used by this device. This overrides the region default.
+    /// Set the data rate being used by this device. This overrides the region default, also
+    /// after a join.
     pub fn set_datarate(&mut self, datarate: DR) {
-        self.mac.configuration.data_rate = datarate;
+        self.mac.set_datarate(datarate);
+    }
+
+    /// Number of transmissions of each unconfirmed uplink, as requested by the network with the
+    /// NbTrans field of LinkADRReq (1 by default). Repetitions are sent with the same FCnt on a
+    /// newly selected channel once the RX windows of the previous transmission have closed (and
+    /// the uplink spacing has passed), until a downlink is received.
+    pub fn get_nb_trans(&self) -> u8 {
+        self.mac.configuration.nb_trans
+    }
+
+    /// Per-channel uplink statistics gathered by this device.
+    pub fn get_channel_stats(&self) -> &region::ChannelStats {
+        self.mac.region.channel_stats()
+    }
+
+    /// Temporarily deprioritize channels which consistently fail (no acknowledgement or
+    /// undecodable downlinks) when picking the next uplink channel. Disabled by default.
+    pub fn set_channel_avoidance(&mut self, avoidance: Option<region::ChannelAvoidance>) {
+        self.mac.region.set_channel_avoidance(avoidance);
+    }
+
+    /// Only use the channels on which downlinks have been received in RX1 (if any) when picking
+    /// the next uplink channel, eg: for a private network with a single gateway serving a subset
+    /// of the default channels. Disabled by default and reversible, as the per-channel statistics
+    /// are kept.
+    pub fn set_channel_pruning(&mut self, pruning: bool) {
+        self.mac.region.set_channel_pruning(pruning);
+    }
+
+    /// Switch to DevNonce counter mode (LoRaWAN 1.0.4 and 1.1), where `next` is the DevNonce of
+    /// the next join request, or back to random DevNonces with `None`. The counter must be
+    /// persisted across resets (see [`Device::get_dev_nonce_counter`]), as DevNonces must never
+    /// be reused. Joining fails with [`mac::Error::DevNonceExhausted`] once all DevNonces have been
+    /// used.
+    pub fn set_dev_nonce_counter(&mut self, next: Option<u32>) {
+        self.mac.set_dev_nonce_counter(next)
+    }
+
+    /// The DevNonce of the next join request in counter mode (`65536` once exhausted).
+    pub fn get_dev_nonce_counter(&self) -> Option<u32> {
+        self.mac.get_dev_nonce_counter()
+    }
+
+    /// Number of join requests left in counter mode before the device needs to be re-provisioned.
+    /// Falling to [`mac::DEV_NONCE_LOW_THRESHOLD`] is reported by [`mac::SecurityEvent::DevNonceLow`].
+    pub fn dev_nonces_remaining(&self) -> Option<u32> {
+        self.mac.dev_nonces_remaining()
+    }
+
+    /// Number of dropped downlinks and expired sessions by reason.
+    pub fn get_security_counters(&self) -> mac::SecurityCounters {
+        self.mac.get_security_counters()
+    }
+
+    /// Diagnose downlinks failing MIC verification: instead of
+    /// [`SecurityEvent::MicFailure`](mac::SecurityEvent::MicFailure), a
+    /// [`SecurityEvent::MicFailureDiagnosed`](mac::SecurityEvent::MicFailureDiagnosed) with the
+    /// likely cause is reported. Meant for test benches and bring-up, as every MIC failure costs
+    /// several additional MIC computations. The frames are still dropped.
+    pub fn set_mic_diagnostics(&mut self, enabled: bool) {
+        self.mac.set_mic_diagnostics(enabled);
+    }
+
+    /// Take the oldest security event (eg: a replayed downlink or a MIC failure) which has not
+    /// been taken yet. Only the most recent events are retained.
+    pub fn take_security_event(&mut self) -> Option<mac::SecurityEvent> {
+        self.mac.take_security_event()
+    }
+
+    /// Choose whether downlinks whose frame counter advanced by a large gap are dropped (the
+    /// default, with a gap of [`mac::MAX_FCNT_GAP`]) or accepted, see [`mac::FcntGapPolicy`].
+    pub fn set_fcnt_gap_policy(&mut self, policy: mac::FcntGapPolicy) {
+        self.mac.set_fcnt_gap_policy(policy);
+    }
+
+    pub fn get_fcnt_gap_policy(&self) -> mac::FcntGapPolicy {
+        self.mac.get_fcnt_gap_policy()
+    }
+
+    /// Reset the downlink frame counter of the session, so that the next downlink is accepted
+    /// whatever its frame counter, eg: after the network lost the frame counters of an ABP
+    /// device. Reported as [`SecurityEvent::FcntDownResynced`](mac::SecurityEvent::FcntDownResynced),
+    /// since it weakens the replay protection until the next downlink.
+    pub fn resync_fcnt_down(&mut self) -> Result<(), mac::Error> {
+        self.mac.resync_fcnt_down()
+    }
+
+    /// Periodically check whether the network still responds after consecutive uplinks without
+    /// downlink, see [`HealthCheck`](mac::HealthCheck). Disabled (`None`) by default.
+    pub fn set_health_check(&mut self, policy: Option<mac::HealthCheck>) {
+        self.mac.set_health_check(policy);
+    }
+
+    pub fn get_health_check(&self) -> Option<mac::HealthCheck> {
+        self.mac.get_health_check()
+    }
+
+    /// Take the oldest session health event which has not been taken yet. On
+    /// [`HealthEvent::RejoinRequired`](mac::HealthEvent::RejoinRequired), the application should
+    /// join again.
+    pub fn take_health_event(&mut self) -> Option<mac::HealthEvent> {
+        self.mac.take_health_event()
+    }
+
+    /// Choose the data rates of consecutive OTAA join requests, see
+    /// [`JoinDatarates`](mac::JoinDatarates). By default, the configured data rate is used.
+    pub fn set_join_datarates(&mut self, datarates: mac::JoinDatarates) {
+        self.mac.set_join_datarates(datarates);
+    }
+
+    pub fn get_join_datarates(&self) -> mac::JoinDatarates {
+        self.mac.get_join_datarates()
+    }
+
+    /// The number, frequency and modulation of the most recent join request.
+    pub fn last_join_attempt(&self) -> Option<mac::JoinAttempt> {
+        self.mac.last_join_attempt()
+    }
+
+    /// Hand the session keys and DevAddr derived by every successful OTAA join to the
+    /// application via `take_exported_session_keys`, eg: to mirror the session in a gateway
+    /// bridge or migration tool. Disabled by default, as the keys should not leave the device
+    /// unless necessary.
+    pub fn set_session_key_export(&mut self, enabled: bool) {
+        self.mac.set_session_key_export(enabled)
+    }
+
+    /// Take the session keys of the last OTAA join, if session key export is enabled and they
+    /// have not been taken yet.
+    pub fn take_exported_session_keys(&mut self) -> Option<mac::SessionKeys> {
+        self.mac.take_exported_session_keys()
+    }
+
+    /// Class B channel settings requested by the network.
+    pub fn get_class_b_settings(&self) -> mac::ClassBSettings {
+        self.mac.get_class_b_settings()
+    }
+
+    /// Retrieve the application-defined EIRP ceiling (in dBm), if any.
+    pub fn get_max_eirp(&self) -> Option<i8> {
+        self.mac.get_max_eirp()
+    }
+
+    /// Limit the EIRP (in dBm) of all transmissions below the regional limit, e.g. to preserve
+    /// battery or to respect enclosure constraints. The limit also applies when the network
+    /// requests a higher TX power via LinkADRReq. Pass `None` to remove the limit.
+    pub fn set_max_eirp(&mut self, max_eirp: Option<i8>) {
+        self.mac.set_max_eirp(max_eirp);
+    }
+
+    pub fn get_battery_level(&self) -> mac::BatteryLevel {
+        self.mac.get_battery_level()
+    }
+
+    /// Update the battery level, which is reported to the network in DevStatusAns and drives the
+    /// [`mac::BatteryPolicy`].
+    pub fn set_battery_level(&mut self, level: mac::BatteryLevel) {
+        self.mac.set_battery_level(level)
+    }
+
+    pub fn get_battery_policy(&self) -> Option<mac::BatteryPolicy> {
+        self.mac.get_battery_policy()
+    }
+
+    /// While the battery is low, raise the data rate of data uplinks and limit their EIRP below
+    /// what the network requested via LinkADRReq, trading range for a longer battery life. Pass
+    /// `None` to always follow the network.
+    pub fn set_battery_policy(&mut self, policy: Option<mac::BatteryPolicy>) {
+        self.mac.set_battery_policy(policy)
+    }
+
+    /// Whether the [`mac::BatteryPolicy`] currently considers the battery low.
+    pub fn is_battery_low(&self) -> bool {
+        self.mac.is_battery_low()
+    }
+
+    /// Limit the MAC commands piggybacked in the FOpts of data uplinks to `len` bytes (at most 15,
+    /// the default), preserving the room for the application payload, eg: at DR0. The remaining
+    /// MAC commands are sent according to the [`MacCommandPolicy`], eg: in an FPort 0 uplink.
+    pub fn set_max_fopts_len(&mut self, len: u8) {
+        self.mac.set_max_fopts_len(len);
+    }
+
+    pub fn get_max_fopts_len(&self) -> u8 {
+        self.mac.get_max_fopts_len()
+    }
+
+    /// Require the SNR of recent downlinks to confirm a margin before accepting a LinkADRReq
+    /// which raises the data rate, avoiding back and forth between data rates on marginal links.
+    /// Disabled (`None`) by default.
+    pub fn set_adr_margin(&mut self, margin: Option<mac::AdrMargin>) {
+        self.mac.set_adr_margin(margin);
+    }
+
+    pub fn get_adr_margin(&self) -> Option<mac::AdrMargin> {
+        self.mac.get_adr_margin()
+    }
+
+    /// Largest application payload which can be sent by the next uplink, given the current data
+    /// rate, dwell time setting and MAC commands pending for FOpts. Sending a larger payload fails
+    /// with [`mac::Error::PayloadTooLarge`].
+    pub fn max_payload_len(&self) -> usize {
+        self.mac.max_app_payload_len()
+    }
+
+    /// Replace the LoRaWAN receive delays, eg: for test networks with unusual latencies. This
+    /// breaks interoperability with regular networks.
+    #[cfg(feature = "test-mode")]
+    pub fn set_rx_delay_overrides(&mut self, overrides: mac::RxDelayOverrides) {
+        self.mac.set_rx_delay_overrides(overrides)
+    }
+
+    #[cfg(feature = "test-mode")]
+    pub fn get_rx_delay_overrides(&self) -> mac::RxDelayOverrides {
+        self.mac.get_rx_delay_overrides()
+    }
+
+    /// Set an ordered list of RX2 frequency and data rate candidates replacing the regional RX2
+    /// default, for networks which changed their RX2 parameters (eg: Helium). The next candidate is
+    /// tried whenever the RX windows pass without a downlink, until a downlink is received in RX2.
+    /// Parameters set by the network via RXParamSetupReq take precedence.
+    pub fn set_rx2_profiles(&mut self, profiles: Vec<mac::Rx2Profile, { mac::MAX_RX2_PROFILES }>) {
+        self.mac.set_rx2_profiles(profiles);
+    }
+
+    pub fn get_rx2_profiles(&self) -> &[mac::Rx2Profile] {
+        self.mac.get_rx2_profiles()
+    }
+
+    /// The RX2 candidate currently in use, if any are set.
+    pub fn get_rx2_profile(&self) -> Option<mac::Rx2Profile> {
+        self.mac.get_rx2_profile()
+    }
+
+    /// Summary of the current regional configuration (enabled channels, data rate limits, RX2
+    /// settings and duty cycle limits), eg: to report it for remote diagnostics.
+    pub fn describe_region(&self) -> region::RegionSummary {
+        self.mac.describe_region()
+    }
+
+    /// The regional state set up by the network (enabled channels, dwell time and RX settings),
+    /// to be persisted separately from the session, eg: by ABP devices which don't persist their
+    /// frame counters.
+    pub fn get_regional_state(&self) -> mac::RegionalState {
+        self.mac.get_regional_state()
+    }
+
+    /// Restore the regional state after a reset, before or after joining via ABP.
+    pub fn restore_regional_state(
+        &mut self,
+        state: &mac::RegionalState,
+    ) -> Result<(), mac::RegionalStateError> {
+        self.mac.restore_regional_state(state)
+    }
+
+    /// Determines which FPorts [`send`](Self::send) accepts, see [`mac::FPortPolicy`].
+    pub fn set_fport_policy(&mut self, policy: mac::FPortPolicy) {
+        self.mac.set_fport_policy(policy);
+    }
+
+    pub fn get_fport_policy(&self) -> mac::FPortPolicy {
+        self.mac.get_fport_policy()
+    }
+
+    pub fn get_uplink_dwell_time(&self) -> bool {
+        self.mac.get_uplink_dwell_time()
+    }
+
+    /// Limit uplinks to 400 ms time on air as required by some regulations (eg: AS923 in Japan),
+    /// which lowers the maximum payload size. Disabled by default.
+    pub fn set_uplink_dwell_time(&mut self, enabled: bool) {
+        self.mac.set_uplink_dwell_time(enabled)
+    }
+
+    pub fn get_downlink_dwell_time(&self) -> bool {
+        self.mac.get_downlink_dwell_time()
+    }
+
+    /// Limit downlinks to 400 ms time on air, which raises the lowest RX1 data rate in AS923 to
+    /// DR2. Disabled by default.
+    pub fn set_downlink_dwell_time(&mut self, enabled: bool) {
+        self.mac.set_downlink_dwell_time(enabled)
+    }
+
+    pub fn get_adr(&self) -> bool {
+        self.mac.get_adr()
+    }
+
+    /// Set the ADR bit in uplinks, allowing the network to control data rate and TX power. While
+    /// enabled, the device also backs off (restores default TX power, lowers data rate, re-enables
+    /// channels) when the network stops responding. Disabled by default.
+    pub fn set_adr(&mut self, enabled: bool) {
+        self.mac.set_adr(enabled);
+    }
+
+    /// Inform the network that the application switches to the given class (LoRaWAN 1.1). The
+    /// DeviceModeInd MAC command is sent with every uplink until the network confirms the switch,
+    /// which is reported by [`Device::take_device_mode_conf`].
+    pub fn indicate_device_mode(&mut self, class: DeviceClass) -> Result<(), Error<R::PhyError>> {
+        Ok(self.mac.indicate_device_mode(class)?)
+    }
+
+    /// Take the class confirmed by the network via DeviceModeConf since the last call, if any.
+    pub fn take_device_mode_conf(&mut self) -> Option<DeviceClass> {
+        self.mac.take_device_mode_conf()
+    }
+
+    /// Restore the regional defaults of the channels, data rate, TX power, RX parameters and the
+    /// other settings controlled by the network, as is done on every join. Application settings
+    /// (eg: ADR, battery level, the data rate, RX2 and uplink dwell time it set) and the session
+    /// are kept. Useful for ABP devices, which don't join, eg: after switching to a new session
+    /// with [`Device::join`].
+    pub fn restore_regional_defaults(&mut self) {
+        self.mac.restore_regional_defaults();
+    }
+
+    /// To be called by LoRaWAN 1.1 ABP devices after a reboot, once the session has been
+    /// restored. Reverts the MAC layer to its default settings and sends the ResetInd MAC command
+    /// with every uplink until the network confirms the reset.
+    pub fn indicate_reset(&mut self) -> Result<(), Error<R::PhyError>> {
+        Ok(self.mac.indicate_reset()?)
+    }
+
+    /// Returns `true` while the network has not confirmed the reset indicated by
+    /// [`Device::indicate_reset`].
+    pub fn is_reset_ind_pending(&self) -> bool {
+        self.mac.is_reset_ind_pending()
+    }
+
+    pub fn get_ack_policy(&self) -> AckPolicy {
+        self.ack_policy
+    }
+
+    pub fn set_ack_policy(&mut self, ack_policy: AckPolicy) {
+        self.ack_policy = ack_policy;
+    }
+
+    pub fn get_mac_command_policy(&self) -> MacCommandPolicy {
+        self.mac_command_policy
+    }
+
+    pub fn set_mac_command_policy(&mut self, mac_command_policy: MacCommandPolicy) {
+        self.mac_command_policy = mac_command_policy;
+    }
+
+    pub fn get_frame_pending_policy(&self) -> FramePendingPolicy {
+        self.frame_pending_policy
+    }
+
+    pub fn set_frame_pending_policy(&mut self, frame_pending_policy: FramePendingPolicy) {
+        self.frame_pending_policy = frame_pending_policy;
+    }
+
+    pub fn get_radio_error_policy(&self) -> RadioErrorPolicy {
+        self.radio_error_policy
+    }
+
+    pub fn set_radio_error_policy(&mut self, radio_error_policy: RadioErrorPolicy) {
+        self.radio_error_policy = radio_error_policy;
+    }
+
+    /// Open a third receive window with the RX2 parameters `delay_ms` after the start of RX2,
+    /// whenever RX1 and RX2 pass without a downlink. This is not compliant with the LoRaWAN
+    /// specification, but salvages downlinks of (private) network servers with poor scheduling
+    /// precision. Disabled by default.
+    pub fn set_late_downlink_window(&mut self, delay_ms: Option<u32>) {
+        self.late_window = delay_ms;
+    }
+
+    pub fn get_late_downlink_window(&self) -> Option<u32> {
+        self.late_window
+    }
+
+    /// Call `hook` right before each receive window following an uplink (or join request) is
+    /// opened and right after it has closed, eg: to power an external LNA or switch the DC-DC
+    /// converter to a low-noise mode only while receiving. The hook runs in the timing critical
+    /// path of the RX windows and should return quickly. Class C reception is not reported.
+    pub fn set_rx_window_hook(&mut self, hook: Option<fn(RxWindowEvent)>) {
+        self.rx_window_hook = hook;
+    }
+
+    /// Returns `true` if the last downlink had the FPending bit set, ie: the network has more
+    /// downlinks queued for the device, and no uplink has been sent since.
+    pub fn is_frame_pending(&self) -> bool {
+        self.mac.is_frame_pending()
+    }
+
+    /// Returns `true` if a confirmed downlink has been received which will be acknowledged by the
+    /// next uplink.
+    pub fn is_ack_pending(&self) -> bool {
+        self.mac.is_ack_pending()
+    }
+
+    /// Start measuring the timing of received downlinks relative to the opening of their RX
+    /// window, discarding any previous measurements. See [`calibration`] for details.
+    pub fn enable_rx_timing_calibration(&mut self) {
+        self.rx_timing = Some(calibration::RxTimingCalibration::default());
+    }
+
+    /// Stop measuring downlink timings and return the measurements gathered so far.
+    pub fn disable_rx_timing_calibration(&mut self) -> Option<calibration::RxTimingCalibration> {
+        self.rx_timing.take()
+    }
+
+    pub fn get_rx_timing_calibration(&self) -> Option<&calibration::RxTimingCalibration> {
+        self.rx_timing.as_ref()
+    }
+
+    /// Send `uplinks` empty confirmed uplinks on `fport` while measuring the timing of the
+    /// acknowledgements and return the suggested RX window lead time (see
+    /// [`calibration::RxTimingCalibration::suggested_lead_time_ms`]). The suggestion is based on
+    /// the current [`Timings`] of the radio and needs to be applied by the radio implementation,
+    /// eg: via `LorawanRadio::set_rx_window_lead_time`. Keep the RX window buffer at or below the
+    /// suggested lead time.
+    pub async fn calibrate_rx_timing(
+        &mut self,
+        uplinks: u8,
+        fport: u8,
+        margin_ms: u32,
+    ) -> Result<Option<u32>, Error<R::PhyError>> {
+        self.enable_rx_timing_calibration();
+        for _ in 0..uplinks {
+            if let Err(e) = self.send(&[], fport, true).await {
+                self.rx_timing = None;
+                return Err(e);
+            }
+        }
+        let calibration = self.disable_rx_timing_calibration().unwrap_or_default();
+        debug!("RX timing calibration finished: {}", calibration);
+        Ok(calibration.suggested_lead_time_ms(self.radio.get_rx_window_lead_time_ms(), margin_ms))
     }
 
     /// Join the LoRaWAN network asynchronously. The returned future completes when
@@ -308,30 +984,58 @@ where
     /// Note that for a Class C enabled device, you must repeatedly send *confirmed* uplink until
     /// LoRaWAN Network Server (LNS) confirmation after joining.
     pub async fn join(&mut self, join_mode: &JoinMode) -> Result<JoinResponse, Error<R::PhyError>> {
-        match join_mode {
+        let response = match join_mode {
             JoinMode::OTAA { deveui, appeui, appkey } => {
-                let (tx_config, _) = self.mac.join_otaa::<G, N>(
+                let (tx_config, _) = self.mac.join_otaa(
+                    &self.crypto,
                     &mut self.rng,
                     NetworkCredentials::new(*appeui, *deveui, *appkey),
                     &mut self.radio_buffer,
-                );
+                )?;
+                self.wait_for_spacing().await;
 
                 // Transmit the join payload
-                let ms = self
-                    .radio
-                    .tx(tx_config, self.radio_buffer.as_ref_for_read())
-                    .await
-                    .map_err(Error::Radio)?;
+                let ms = self.transmit(tx_config).await?;
+                self.transmitted(&tx_config.rf);
 
                 // Receive join response within RX window
                 self.timer.reset();
-                Ok(self.rx_downlink(&Frame::Join, ms).await?.into())
+                self.rx_downlink(&Frame::Join, ms).await?.into()
             }
             JoinMode::ABP { nwkskey, appskey, devaddr } => {
                 self.mac.join_abp(*nwkskey, *appskey, *devaddr);
-                Ok(JoinResponse::JoinSuccess)
+                JoinResponse::JoinSuccess
             }
-        }
+        };
+        #[cfg(feature = "embassy-sync")]
+        self.forward_to_channels(
+            matches!(response, JoinResponse::JoinSuccess).then_some(channels::DeviceEvent::Joined),
+        );
+        Ok(response)
+    }
+
+    /// Join via ABP, continuing the frame counters of an existing session instead of starting
+    /// from zero, eg: when migrating a device from another stack. `fcnt_up` is the counter of the
+    /// next uplink and `fcnt_down` the counter of the last downlink, as supplied by the network
+    /// server.
+    ///
+    /// Fails with [`mac::Error::FcntRewound`] if the device is already joined with the same
+    /// DevAddr and either counter is lower than the current one, and with
+    /// [`mac::Error::FcntExhausted`] if a counter is exhausted.
+    pub fn join_abp_with_fcnt(
+        &mut self,
+        nwkskey: crate::NwkSKey,
+        appskey: crate::AppSKey,
+        devaddr: crate::DevAddr<[u8; 4]>,
+        fcnt_up: u32,
+        fcnt_down: u32,
+    ) -> Result<(), Error<R::PhyError>> {
+        self.mac
+            .join_abp_with_fcnt(nwkskey, appskey, devaddr, fcnt_up, fcnt_down)
+            .map_err(Error::Mac)?;
+        #[cfg(feature = "embassy-sync")]
+        self.forward_to_channels(Some(channels::DeviceEvent::Joined));
+        Ok(())
     }
 
     /// Send data on a given port with the expected confirmation. If downlink data is provided, the
@@ -350,22 +1054,289 @@ where
         fport: u8,
         confirmed: bool,
     ) -> Result<SendResponse, Error<R::PhyError>> {
+        Ok(self.send_with_report(data, fport, confirmed).await?.0)
+    }
+
+    /// Like [`send`](Device::send), with the FPort, payload and confirmation of an
+    /// [`Uplink`](crate::Uplink).
+    pub async fn send_uplink(
+        &mut self,
+        uplink: &crate::Uplink<'_>,
+    ) -> Result<SendResponse, Error<R::PhyError>> {
+        self.send(uplink.payload(), uplink.fport().value(), uplink.is_confirmed()).await
+    }
+
+    /// Like [`send`](Device::send), additionally returning what was transmitted for the uplink:
+    /// channel, data rate, power, time on air and when the transmission started and ended.
+    /// Uplinks the stack sends on its own afterwards (see [`Device::pending_stack_uplink`]) are
+    /// not included, but can be followed with [`Device::last_uplink_report`].
+    pub async fn send_with_report(
+        &mut self,
+        data: &[u8],
+        fport: u8,
+        confirmed: bool,
+    ) -> Result<(SendResponse, telemetry::UplinkReport), Error<R::PhyError>> {
+        self.mac.check_fport(fport, data)?;
+        let response = self.uplink(Some(&SendData { data, fport, confirmed })).await?;
+        let response = self.dispatch_package_response(response);
+        // set by every uplink transmitted
+        let report = self.uplink_report.unwrap();
+        self.stack_uplinks().await;
+        #[cfg(feature = "embassy-sync")]
+        self.forward_to_channels(
+            matches!(response, mac::Response::SessionExpired)
+                .then_some(channels::DeviceEvent::SessionExpired),
+        );
+        Ok((response.into(), report))
+    }
+
+    /// Send the uplinks the stack sends on its own after an application uplink or a Class C
+    /// downlink, followed by those of the packages and the queued uplinks which may be sent right
+    /// away. Their errors are kept for [`Device::take_stack_error`], so that the response to the
+    /// application's uplink isn't lost.
+    async fn stack_uplinks(&mut self) {
+        if let Err(error) = self.follow_up().await {
+            warn!("Uplink sent by the stack failed");
+            self.stack_error = Some(error);
+        }
+        if let Err(error) = self.service_packages().await {
+            warn!("Package uplink failed");
+            self.stack_error = Some(error);
+        }
+        if let Err(error) = self.flush_ready_queue().await {
+            warn!("Queued uplink failed");
+            self.stack_error = Some(error);
+        }
+    }
+
+    /// Take the error of the last uplink the stack, a package or the uplink queue sent on its own
+    /// (see [`Device::pending_stack_uplink`], [`Device::service_packages`] and
+    /// [`Device::enqueue`]). Such errors don't fail [`Device::send`], which returns the response
+    /// to the application's uplink.
+    pub fn take_stack_error(&mut self) -> Option<Error<R::PhyError>> {
+        self.stack_error.take()
+    }
+
+    /// What was transmitted for the most recent uplink, including uplinks sent by the stack on
+    /// its own.
+    pub fn last_uplink_report(&self) -> Option<telemetry::UplinkReport> {
+        self.uplink_report
+    }
+
+    /// Send an empty uplink (without FPort) which acknowledges the last confirmed downlink and
+    /// carries any pending MAC commands. Useful when the application has no data to send but the
+    /// network server shouldn't retransmit the downlink.
+    pub async fn send_ack(&mut self) -> Result<SendResponse, Error<R::PhyError>> {
+        Ok(self.uplink(None).await?.into())
+    }
+
+    /// Transmit a data uplink (or an empty ACK uplink if `send_data` is `None`) and wait for the
+    /// downlink within the RX windows.
+    async fn uplink(
+        &mut self,
+        send_data: Option<&SendData<'_>>,
+    ) -> Result<mac::Response, Error<R::PhyError>> {
         // Prepare transmission buffer
-        let (tx_config, _fcnt_up) = self.mac.send::<G, N>(
-            &mut self.rng,
-            &mut self.radio_buffer,
-            &SendData { data, fport, confirmed },
-        )?;
-        // Transmit our data packet
-        let ms = self
-            .radio
-            .tx(tx_config, self.radio_buffer.as_ref_for_read())
-            .await
-            .map_err(Error::Radio)?;
+        let (tx_config, fcnt) = match send_data {
+            Some(send_data) => {
+                self.mac.send(&self.crypto, &mut self.rng, &mut self.radio_buffer, send_data)?
+            }
+            None => self.mac.send_ack(&self.crypto, &mut self.rng, &mut self.radio_buffer)?,
+        };
+        let repetitions = self.mac.plan_repetitions();
+        // the radio buffer is reused for the RX windows
+        let frame = (repetitions > 0).then(|| self.radio_buffer.clone());
+        let mut tx_config = tx_config;
+        let mut retries = 0;
+        loop {
+            self.wait_for_spacing().await;
+            let datarate = self.mac.uplink_data_rate();
+            // Transmit our data packet
+            let uptime_ms = self.airtime.uptime_ms();
+            let tx_start_ms = self.timer.elapsed_ms().map(|elapsed| uptime_ms + elapsed);
+            let ms = self.transmit(tx_config).await?;
+            let tx_done_ms = self.timer.elapsed_ms().map(|elapsed| uptime_ms + elapsed);
+            let time_on_air_us = self.transmitted(&tx_config.rf);
+            self.uplink_report = Some(telemetry::UplinkReport {
+                fcnt,
+                fport: send_data.map(|send_data| send_data.fport),
+                rf: tx_config.rf,
+                datarate,
+                power: tx_config.pw,
+                time_on_air_us,
+                retries,
+                tx_start_ms,
+                tx_done_ms,
+            });
+
+            // Wait for received data within window
+            self.timer.reset();
+            let response = self.rx_downlink(&Frame::Data, ms).await?;
+            match &frame {
+                Some(frame)
+                    if retries < repetitions && matches!(response, mac::Response::RxComplete) =>
+                {
+                    retries += 1;
+                    debug!("Repeating uplink FCnt {} ({} of {})", fcnt, retries, repetitions);
+                    self.radio_buffer = frame.clone();
+                    tx_config = self.mac.repetition_tx_config(&mut self.rng);
+                }
+                _ => return Ok(response),
+            }
+        }
+    }
+
+    /// The uplink the stack is going to send on its own after the current one, as required by
+    /// the configured [`MacCommandPolicy`], [`AckPolicy`] and [`FramePendingPolicy`], along with its earliest
+    /// transmission time allowed by the duty cycle limit.
+    pub fn pending_stack_uplink(&self) -> Option<duty_cycle::PendingUplink> {
+        let uplink = if self.mac_command_policy == MacCommandPolicy::FPortZero
+            && self.mac.mac_commands_exceed_fopts()
+        {
+            duty_cycle::StackUplink::MacCommands
+        } else if self.ack_policy == AckPolicy::Immediate && self.mac.is_ack_pending() {
+            duty_cycle::StackUplink::Ack
+        } else if self.frame_pending_policy == FramePendingPolicy::Poll
+            && self.mac.is_frame_pending()
+            && !self.downlink.is_at_capacity()
+        {
+            duty_cycle::StackUplink::FramePending
+        } else {
+            return None;
+        };
+        let elapsed_ms = self.timer.elapsed_ms();
+        let earliest_tx_ms =
+            self.duty_cycle.remaining_ms(elapsed_ms).max(self.spacing.remaining_ms(elapsed_ms));
+        Some(duty_cycle::PendingUplink { uplink, earliest_tx_ms })
+    }
 
-        // Wait for received data within window
-        self.timer.reset();
-        Ok(self.rx_downlink(&Frame::Data, ms).await?.into())
+    /// Limit the uplinks initiated by the stack (see [`Device::pending_stack_uplink`]) to the
+    /// given duty cycle: the channel is used for at most 1/`limit` of the time, eg: 100 for 1%.
+    /// Application uplinks are not delayed. Disabled by default.
+    pub fn set_duty_cycle_limit(&mut self, limit: Option<u16>) {
+        self.duty_cycle.set_limit(limit);
+    }
+
+    /// The duty cycle limit of the uplinks initiated by the stack, see
+    /// [`Device::set_duty_cycle_limit`].
+    pub fn get_duty_cycle_limit(&self) -> Option<u16> {
+        self.duty_cycle.limit()
+    }
+
+    /// Wait at least `slack_ms` after the RX windows of an uplink (or join request) have closed
+    /// before transmitting the next one. Applies to all uplinks, including those of packages,
+    /// the uplink queue and the stack itself: rather than being rejected, an uplink sent early is
+    /// delayed until the spacing has passed. Disabled by default.
+    pub fn set_uplink_spacing(&mut self, slack_ms: Option<u32>) {
+        self.spacing.set_slack_ms(slack_ms);
+    }
+
+    pub fn get_uplink_spacing(&self) -> Option<u32> {
+        self.spacing.slack_ms()
+    }
+
+    /// Milliseconds from now until the next uplink may be transmitted without being delayed by
+    /// the uplink spacing (see [`Device::set_uplink_spacing`]).
+    pub fn next_uplink_delay_ms(&self) -> u64 {
+        self.spacing.remaining_ms(self.timer.elapsed_ms())
+    }
+
+    /// Wait for the uplink spacing to pass, letting other tasks run after encrypting the payload
+    /// in any case.
+    async fn wait_for_spacing(&mut self) {
+        let delay_ms = self.next_uplink_delay_ms();
+        if delay_ms > 0 {
+            debug!("Delaying the uplink by {} ms for the uplink spacing", delay_ms);
+            self.timer.delay_ms(delay_ms).await;
+        } else {
+            yield_now().await;
+        }
+    }
+
+    /// Time on air of all transmissions per band, over the last hour and day.
+    pub fn airtime_ledger(&self) -> &airtime::AirtimeLedger {
+        &self.airtime
+    }
+
+    /// Restore a persisted ledger, eg: after a reset.
+    pub fn set_airtime_ledger(&mut self, ledger: airtime::AirtimeLedger) {
+        self.airtime = ledger;
+    }
+
+    /// Send any uplink required by the configured [`MacCommandPolicy`], [`AckPolicy`] and
+    /// [`FramePendingPolicy`]. After polling for a pending downlink, the downlink received may
+    /// require another follow-up uplink.
+    async fn follow_up(&mut self) -> Result<(), Error<R::PhyError>> {
+        let mut polls = 0;
+        while let Some(pending) = self.pending_stack_uplink() {
+            if pending.earliest_tx_ms > 0 {
+                debug!(
+                    "Delaying {} by {} ms for duty cycle",
+                    pending.uplink, pending.earliest_tx_ms
+                );
+                self.timer.delay_ms(pending.earliest_tx_ms).await;
+            }
+            match pending.uplink {
+                duty_cycle::StackUplink::MacCommands => {
+                    let send_data = SendData { data: &[], fport: 0, confirmed: false };
+                    let response = self.uplink(Some(&send_data)).await?;
+                    debug!("MAC commands sent on FPort 0: {}", response);
+                }
+                duty_cycle::StackUplink::Ack => {
+                    let response = self.uplink(None).await?;
+                    debug!("Immediate ACK sent: {}", response);
+                }
+                duty_cycle::StackUplink::FramePending => {
+                    let response = self.uplink(None).await?;
+                    debug!("Polled for pending downlink: {}", response);
+                    self.dispatch_package_downlinks();
+                    polls += 1;
+                    if polls < MAX_FRAME_PENDING_POLLS {
+                        continue;
+                    }
+                    debug!("Stopped polling for pending downlinks after {} uplinks", polls);
+                }
+            }
+            break;
+        }
+        Ok(())
+    }
+
+    /// Transmit the frame in the radio buffer, retrying as configured by the
+    /// [`RadioErrorPolicy`].
+    async fn transmit(&mut self, tx_config: radio::TxConfig) -> Result<u32, Error<R::PhyError>> {
+        let mut retries = 0;
+        loop {
+            let error = match self.radio.tx(tx_config, self.radio_buffer.as_ref_for_read()).await {
+                Ok(ms) => return Ok(ms),
+                Err(error) => error,
+            };
+            let RadioErrorPolicy::Recover { max_retries, retry_delay_ms } = self.radio_error_policy
+            else {
+                return Err(Error::Radio(error));
+            };
+            self.radio.reset().await.map_err(Error::Radio)?;
+            if retries == max_retries {
+                return Err(Error::Radio(error));
+            }
+            retries += 1;
+            warn!("Transmission failed, retry {} in {} ms", retries, retry_delay_ms);
+            self.timer.delay_ms(retry_delay_ms.into()).await;
+        }
+    }
+
+    /// Record the time on air of the frame just transmitted from the radio buffer, returning it
+    /// in µs.
+    fn transmitted(&mut self, rf: &RfConfig) -> u32 {
+        // LoRa frames can't exceed 255 bytes
+        let len = self.radio_buffer.as_ref_for_read().len().min(u8::MAX as usize) as u8;
+        let time_on_air_us = rf.bb.time_on_air_us(Some(8), true, len);
+        let time_on_air_ms = time_on_air_us / 1000;
+        self.duty_cycle.transmitted(time_on_air_ms as u64);
+        // the timer has been reset at the end of the previous transmission
+        self.airtime.advance(self.timer.elapsed_ms().unwrap_or(0));
+        self.airtime.record(rf.frequency, time_on_air_ms);
+        time_on_air_us
     }
 
     /// Take the downlink data from the device. This is typically called after a
@@ -466,17 +1437,20 @@ where
                 RxcWindowResponse::Rx(sz, q, timeout_fut) => {
                     debug!("RXC window received {} bytes.", sz);
                     self.radio_buffer.set_pos(sz);
-                    let mac_response = self.mac.handle_rxc::<N, D>(
-                        &mut self.radio_buffer,
-                        &mut self.downlink,
+                    let mac_response = self.mac.handle_rxc(
+                        &self.crypto,
+                        self.radio_buffer.as_mut_for_read(),
+                        Some(&mut self.downlink),
                         q.snr(),
                         &rx_config.rf,
                     )?;
+                    yield_now().await;
                     match Self::handle_mac_response(
                         &mut self.radio_buffer,
                         &mut self.mac,
                         &mut self.radio,
                         &mut self.rng,
+                        &self.crypto,
                         mac_response,
                         Some(rx_config),
                     )
@@ -512,6 +1486,30 @@ where
         &mut self,
         frame: &Frame,
         window_delay: u32,
+    ) -> Result<mac::Response, Error<R::PhyError>> {
+        let result = self.rx_windows(frame, window_delay).await;
+        self.spacing.windows_closed(self.timer.elapsed_ms());
+        match result {
+            Err(Error::Radio(error)) => {
+                // The frame counter of the uplink is used up, whether or not the windows failed
+                let response = self.mac.rx2_complete();
+                match self.radio_error_policy {
+                    RadioErrorPolicy::Propagate => Err(Error::Radio(error)),
+                    RadioErrorPolicy::Recover { .. } => {
+                        warn!("Radio error between RX windows, skipping the remaining windows");
+                        self.radio.reset().await.map_err(Error::Radio)?;
+                        Ok(response)
+                    }
+                }
+            }
+            result => result,
+        }
+    }
+
+    async fn rx_windows(
+        &mut self,
+        frame: &Frame,
+        window_delay: u32,
     ) -> Result<mac::Response, Error<R::PhyError>> {
         self.radio_buffer.clear();
 
@@ -521,14 +1519,20 @@ where
         debug!("Starting RX1 in {} ms.", rx1_start_delay);
         // sleep or RXC
         let _ = self.between_windows(rx1_start_delay).await?;
+        self.duty_cycle.waited(rx1_start_delay.into());
+        trace!(
+            "RX1 window opening at {} ms (target: {} ms).",
+            self.timer.elapsed_ms(),
+            rx1_start_delay
+        );
 
         // RX1
         let rx_config =
             self.mac.get_rx_config(self.radio.get_rx_window_buffer(), frame, &Window::_1);
         debug!("Configuring RX1 window with config {}.", rx_config);
-        self.radio.setup_rx(rx_config).await.map_err(Error::Radio)?;
-
-        if let Some(response) = self.rx_listen(&rx_config.rf).await? {
+        let response = self.rx_window(rx_config, RxWindow::Rx1).await?;
+        trace!("RX1 window closed at {} ms.", self.timer.elapsed_ms());
+        if let Some(response) = response {
             debug!("RX1 received {}", response);
             return Ok(response);
         }
@@ -538,21 +1542,79 @@ where
         debug!("RX1 did not receive anything. Awaiting RX2 for {} ms.", rx2_start_delay);
         // sleep or RXC
         let _ = self.between_windows(rx2_start_delay).await?;
+        self.duty_cycle.waited(rx2_start_delay.into());
+        trace!(
+            "RX2 window opening at {} ms (target: {} ms).",
+            self.timer.elapsed_ms(),
+            rx2_start_delay
+        );
 
         // RX2
         let rx_config =
             self.mac.get_rx_config(self.radio.get_rx_window_buffer(), frame, &Window::_2);
         debug!("Configuring RX2 window with config {}.", rx_config);
-        self.radio.setup_rx(rx_config).await.map_err(Error::Radio)?;
-
-        if let Some(response) = self.rx_listen(&rx_config.rf).await? {
+        let response = self.rx_window(rx_config, RxWindow::Rx2).await?;
+        trace!("RX2 window closed at {} ms.", self.timer.elapsed_ms());
+        if let Some(response) = response {
             debug!("RX2 received {}", response);
             return Ok(response);
         }
+
+        if let Some(late_window) = self.late_window {
+            let late_start_delay = rx2_start_delay + late_window;
+            debug!(
+                "RX2 did not receive anything. Awaiting late window for {} ms.",
+                late_start_delay
+            );
+            let _ = self.between_windows(late_start_delay).await?;
+            self.duty_cycle.waited(late_start_delay.into());
+            // same parameters as RX2
+            let response = self.rx_window(rx_config, RxWindow::Late).await?;
+            trace!("Late window closed at {} ms.", self.timer.elapsed_ms());
+            if let Some(response) = response {
+                debug!("Late window received {}", response);
+                return Ok(response);
+            }
+        }
         debug!("RX2 did not receive anything.");
         Ok(self.mac.rx2_complete())
     }
 
+    /// Open an RX window. With [`RadioErrorPolicy::Recover`], the window is skipped if the radio
+    /// reports an error.
+    async fn rx_window(
+        &mut self,
+        rx_config: RxConfig,
+        window: RxWindow,
+    ) -> Result<Option<mac::Response>, Error<R::PhyError>> {
+        if let Some(hook) = self.rx_window_hook {
+            hook(RxWindowEvent::Opening(window));
+        }
+        let result = match self.radio.setup_rx(rx_config).await {
+            Ok(()) => {
+                let mac_window = match window {
+                    RxWindow::Rx1 => Window::_1,
+                    // the late window uses the parameters of RX2
+                    RxWindow::Rx2 | RxWindow::Late => Window::_2,
+                };
+                self.rx_listen(&rx_config.rf, &mac_window).await
+            }
+            Err(error) => Err(Error::Radio(error)),
+        };
+        if let Some(hook) = self.rx_window_hook {
+            hook(RxWindowEvent::Closed(window));
+        }
+        match (result, self.radio_error_policy) {
+            (Err(Error::Radio(_)), RadioErrorPolicy::Recover { .. }) => {
+                warn!("Radio error in RX window, skipping it");
+                self.radio.reset().await.map_err(Error::Radio)?;
+                self.radio_buffer.clear();
+                Ok(None)
+            }
+            (result, _) => result,
+        }
+    }
+
     /// Helper function to handle MAC responses and perform common actions
     #[allow(unused_variables)]
     async fn handle_mac_response(
@@ -560,6 +1622,7 @@ where
         mac: &mut Mac,
         radio: &mut R,
         rng: &mut G,
+        crypto: &C,
         response: mac::Response,
         rx_config: Option<RxConfig>,
     ) -> Result<Option<mac::Response>, Error<R::PhyError>> {
@@ -574,7 +1637,7 @@ where
             #[cfg(feature = "certification")]
             mac::Response::UplinkPrepared => {
                 let (tx_config, _fcnt_up) =
-                    mac.certification_setup_send::<G, N>(rng, radio_buffer)?;
+                    mac.certification_setup_send(crypto, rng, radio_buffer)?;
                 radio.tx(tx_config, radio_buffer.as_ref_for_read()).await.map_err(Error::Radio)?;
                 Ok(Some(mac.rx2_complete()))
             }
@@ -582,7 +1645,7 @@ where
             mac::Response::Multicast(mut response) => {
                 if response.is_transmit_request() {
                     let (tx_config, _fcnt_up) =
-                        mac.multicast_setup_send::<G, N>(rng, radio_buffer)?;
+                        mac.multicast_setup_send(crypto, rng, radio_buffer)?;
                     radio
                         .tx(tx_config, radio_buffer.as_ref_for_read())
                         .await
@@ -608,22 +1671,29 @@ where
     async fn rx_listen(
         &mut self,
         rf_config: &RfConfig,
+        window: &Window,
     ) -> Result<Option<mac::Response>, Error<R::PhyError>> {
+        let window_open = self.timer.elapsed_ms();
         let response =
             match self.radio.rx_single(self.radio_buffer.as_mut()).await.map_err(Error::Radio)? {
                 RxStatus::Rx(s, q) => {
+                    self.record_rx_timing(rf_config, window_open, s);
                     self.radio_buffer.set_pos(s);
-                    let mac_response = self.mac.handle_rx::<N, D>(
+                    let mac_response = self.mac.handle_rx(
+                        &self.crypto,
                         &mut self.radio_buffer,
                         &mut self.downlink,
                         q.snr(),
                         rf_config,
+                        window,
                     );
+                    yield_now().await;
                     Self::handle_mac_response(
                         &mut self.radio_buffer,
                         &mut self.mac,
                         &mut self.radio,
                         &mut self.rng,
+                        &self.crypto,
                         mac_response,
                         None,
                     )
@@ -635,6 +1705,19 @@ where
         Ok(response)
     }
 
+    fn record_rx_timing(&mut self, rf_config: &RfConfig, window_open: Option<u64>, len: usize) {
+        let Some(calibration) = &mut self.rx_timing else {
+            return;
+        };
+        if let (Some(window_open), Some(rx_done)) = (window_open, self.timer.elapsed_ms()) {
+            // LoRaWAN downlinks use an explicit header and an 8 symbol preamble.
+            let time_on_air_ms = rf_config.bb.time_on_air_us(Some(8), true, len as u8) / 1000;
+            let gap_ms = rx_done as i64 - time_on_air_ms as i64 - window_open as i64;
+            trace!("Downlink preamble started {} ms after the RX window opened.", gap_ms);
+            calibration.record(gap_ms as i32);
+        }
+    }
+
     /// When not involved in sending and RX1/RX2 windows, a class C configured device will be
     /// listening to RXC frames. The caller is expected to be awaiting this message at all times.
     #[cfg(feature = "class-c")]
@@ -644,26 +1727,94 @@ where
             let (sz, q) =
                 self.radio.rx_continuous(self.radio_buffer.as_mut()).await.map_err(Error::Radio)?;
             self.radio_buffer.set_pos(sz);
-            let mac_response = self.mac.handle_rxc::<N, D>(
-                &mut self.radio_buffer,
-                &mut self.downlink,
+            let mac_response = self.mac.handle_rxc(
+                &self.crypto,
+                self.radio_buffer.as_mut_for_read(),
+                Some(&mut self.downlink),
                 q.snr(),
                 &rx_config.rf,
             )?;
+            yield_now().await;
             if let Some(response) = Self::handle_mac_response(
                 &mut self.radio_buffer,
                 &mut self.mac,
                 &mut self.radio,
                 &mut self.rng,
+                &self.crypto,
                 mac_response,
                 Some(rx_config),
             )
             .await?
             {
+                let response = self.dispatch_package_response(response);
+                self.rxc_complete(&response).await;
                 return Ok(response.into());
             }
         }
     }
+
+    /// Like [`rxc_listen`](Device::rxc_listen), but receives into `buf` and decrypts the frame in
+    /// place. The application payload of a downlink is returned as a slice of `buf` instead of
+    /// being copied into the downlink queue, so it is neither available through
+    /// [`take_downlink`](Device::take_downlink) nor handed to packages or channels.
+    #[cfg(feature = "class-c")]
+    pub async fn rxc_listen_in_place<'a>(
+        &mut self,
+        buf: &'a mut [u8],
+    ) -> Result<(ListenResponse, Option<DownlinkRef<'a>>), Error<R::PhyError>> {
+        let rx_config = self.mac.get_rxc_config();
+        loop {
+            let (sz, q) = self.radio.rx_continuous(buf).await.map_err(Error::Radio)?;
+            let mac_response =
+                self.mac.handle_rxc(&self.crypto, &mut buf[..sz], None, q.snr(), &rx_config.rf)?;
+            yield_now().await;
+            if let Some(response) = Self::handle_mac_response(
+                &mut self.radio_buffer,
+                &mut self.mac,
+                &mut self.radio,
+                &mut self.rng,
+                &self.crypto,
+                mac_response,
+                Some(rx_config),
+            )
+            .await?
+            {
+                self.rxc_complete(&response).await;
+                let frame: &'a [u8] = &buf[..sz];
+                let downlink = match response {
+                    mac::Response::DownlinkReceived(_) => mac::frm_payload(frame),
+                    #[cfg(feature = "multicast")]
+                    mac::Response::Multicast(multicast::Response::DownlinkReceived { .. }) => {
+                        mac::frm_payload(frame)
+                    }
+                    _ => None,
+                };
+                let downlink = downlink.map(|(fport, data)| DownlinkRef { fport, data });
+                return Ok((response.into(), downlink));
+            }
+        }
+    }
+
+    #[cfg(feature = "class-c")]
+    #[allow(unused_variables)]
+    async fn rxc_complete(&mut self, response: &mac::Response) {
+        self.stack_uplinks().await;
+        #[cfg(feature = "embassy-sync")]
+        self.forward_to_channels(
+            matches!(response, mac::Response::SessionExpired)
+                .then_some(channels::DeviceEvent::SessionExpired),
+        );
+    }
+}
+
+/// The application payload of a Class C downlink, decrypted in place in a buffer owned by the
+/// caller (see [`Device::rxc_listen_in_place`]).
+#[cfg(feature = "class-c")]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[derive(Debug, PartialEq, Eq)]
+pub struct DownlinkRef<'a> {
+    pub fport: u8,
+    pub data: &'a [u8],
 }
 
 /// Allows to fine-tune the beginning and end of the receive windows for a specific board and runtime.
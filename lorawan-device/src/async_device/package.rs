@@ -0,0 +1,249 @@
+//! Application layer packages (eg: clock synchronization, remote multicast setup, fragmented data
+//! block transport or user-defined protocols) which each own an FPort. Registered packages receive
+//! the downlinks on their FPort and may send uplinks on it, which the device transmits after the
+//! application's uplinks.
+//!
+//! The device owns its packages, which are set with [`Device::with_packages`]: either a single
+//! package or a tuple of up to [`MAX_PACKAGES`] packages. They remain accessible through
+//! [`Device::packages`], eg: to take the events of a
+//! [`FuotaSession`](super::fuota::FuotaSession).
+use super::{mac, radio, Device, Error, SendData, Timings};
+use crate::CryptoFactory;
+use rand_core::RngCore;
+
+/// Maximum number of packages owned by a device.
+pub const MAX_PACKAGES: usize = 4;
+
+/// Largest uplink payload a package may provide.
+pub const MAX_PACKAGE_PAYLOAD: usize = 242;
+
+/// An application layer package owning an FPort.
+pub trait Package {
+    /// The FPort of the package's downlinks and uplinks.
+    fn port(&self) -> u8;
+    /// Handle a downlink received on the package's FPort.
+    fn handle_downlink(&mut self, data: &[u8]);
+    /// Write the next uplink of the package into `buf`, if the package has one to send.
+    fn poll_uplink(&mut self, buf: &mut [u8]) -> Option<PackageUplink>;
+    /// Whether the package handles the downlinks on `fport`. Packages combining several protocols
+    /// (eg: [`FuotaSession`](super::fuota::FuotaSession)) own further FPorts besides
+    /// [`Package::port`].
+    fn handles_port(&self, fport: u8) -> bool {
+        fport == self.port()
+    }
+    /// Handle a downlink received on `fport`, one of the FPorts handled by the package.
+    fn handle_port_downlink(&mut self, fport: u8, data: &[u8]) {
+        let _ = fport;
+        self.handle_downlink(data);
+    }
+}
+
+/// An uplink requested by a [`Package`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct PackageUplink {
+    /// Number of bytes the package wrote at the start of the buffer passed to
+    /// [`Package::poll_uplink`], ie: the length of the uplink payload. Lengths beyond
+    /// [`MAX_PACKAGE_PAYLOAD`] are truncated.
+    pub len: usize,
+    /// Whether the uplink is sent as a confirmed uplink.
+    pub confirmed: bool,
+    /// FPort of the uplink, if not [`Package::port`].
+    pub fport: Option<u8>,
+}
+
+/// The packages owned by a [`Device`]: none (`()`), a single [`Package`] or a tuple of up to
+/// [`MAX_PACKAGES`] packages.
+pub trait Packages {
+    /// Number of packages.
+    fn count(&self) -> usize;
+    /// The package at `index`, if `index < self.count()`.
+    fn package(&mut self, index: usize) -> Option<&mut dyn Package>;
+
+    /// The package handling the downlinks on `fport`, if any.
+    fn find(&mut self, fport: u8) -> Option<&mut dyn Package> {
+        let index =
+            (0..self.count()).find(|&i| self.package(i).is_some_and(|p| p.handles_port(fport)))?;
+        self.package(index)
+    }
+
+    /// An FPort handled by two of the packages, if any.
+    fn conflicting_port(&mut self) -> Option<u8> {
+        for i in 0..self.count() {
+            for j in 0..i {
+                let port = self.package(i)?.port();
+                let other = self.package(j)?.port();
+                if self.package(j)?.handles_port(port) {
+                    return Some(port);
+                }
+                if self.package(i)?.handles_port(other) {
+                    return Some(other);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Packages for () {
+    fn count(&self) -> usize {
+        0
+    }
+
+    fn package(&mut self, _index: usize) -> Option<&mut dyn Package> {
+        None
+    }
+}
+
+impl<P: Package> Packages for P {
+    fn count(&self) -> usize {
+        1
+    }
+
+    fn package(&mut self, index: usize) -> Option<&mut dyn Package> {
+        (index == 0).then_some(self as &mut dyn Package)
+    }
+}
+
+macro_rules! impl_packages_tuple {
+    ($count:literal: $($index:tt $package:ident),+) => {
+        impl<$($package: Package),+> Packages for ($($package,)+) {
+            fn count(&self) -> usize {
+                $count
+            }
+
+            fn package(&mut self, index: usize) -> Option<&mut dyn Package> {
+                match index {
+                    $($index => Some(&mut self.$index),)+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+impl_packages_tuple!(2: 0 A, 1 B);
+impl_packages_tuple!(3: 0 A, 1 B, 2 C);
+impl_packages_tuple!(4: 0 A, 1 B, 2 C, 3 D);
+
+impl<R, T, G, C, P, const N: usize, const D: usize> Device<R, T, G, N, D, C, P>
+where
+    R: radio::PhyRxTx + Timings,
+    T: radio::Timer,
+    G: RngCore,
+    C: CryptoFactory,
+    P: Packages,
+{
+    /// Hand the given packages to the device, which then receives the downlinks on their FPorts.
+    /// Packages set previously are dropped.
+    ///
+    /// # Panics
+    ///
+    /// If two of the packages handle the same FPort.
+    pub fn with_packages<Q: Packages>(self, mut packages: Q) -> Device<R, T, G, N, D, C, Q> {
+        if let Some(port) = packages.conflicting_port() {
+            panic!("FPort {} is handled by two packages", port);
+        }
+        Device {
+            radio: self.radio,
+            rng: self.rng,
+            crypto: self.crypto,
+            timer: self.timer,
+            mac: self.mac,
+            radio_buffer: self.radio_buffer,
+            downlink: self.downlink,
+            rx_timing: self.rx_timing,
+            duty_cycle: self.duty_cycle,
+            spacing: self.spacing,
+            airtime: self.airtime,
+            packages,
+            queue: self.queue,
+            #[cfg(feature = "embassy-sync")]
+            channels: self.channels,
+            ack_policy: self.ack_policy,
+            mac_command_policy: self.mac_command_policy,
+            frame_pending_policy: self.frame_pending_policy,
+            radio_error_policy: self.radio_error_policy,
+            late_window: self.late_window,
+            rx_window_hook: self.rx_window_hook,
+            uplink_report: self.uplink_report,
+            stack_error: self.stack_error,
+            #[cfg(feature = "class-c")]
+            class_c: self.class_c,
+        }
+    }
+
+    /// The packages owned by the device.
+    pub fn packages(&mut self) -> &mut P {
+        &mut self.packages
+    }
+
+    /// Hand the buffered downlinks on the FPorts of the packages to these packages. Other
+    /// downlinks remain available via [`Device::take_downlink`]. Returns whether the most recent
+    /// downlink was handed to a package.
+    pub(crate) fn dispatch_package_downlinks(&mut self) -> bool {
+        if self.packages.count() == 0 {
+            return false;
+        }
+        let packages = &mut self.packages;
+        let latest = self.downlink.len().wrapping_sub(1);
+        let mut index = 0;
+        let mut consumed = false;
+        self.downlink.retain(|downlink| {
+            let handled = match packages.find(downlink.fport) {
+                Some(package) => {
+                    package.handle_port_downlink(downlink.fport, &downlink.data);
+                    true
+                }
+                None => false,
+            };
+            if handled && index == latest {
+                consumed = true;
+            }
+            index += 1;
+            !handled
+        });
+        consumed
+    }
+
+    /// Hand the downlink of `response` to the packages. If a package handles it, the application
+    /// isn't told about it: [`mac::Response::DownlinkReceived`] becomes
+    /// [`mac::Response::RxComplete`].
+    pub(crate) fn dispatch_package_response(&mut self, response: mac::Response) -> mac::Response {
+        match response {
+            mac::Response::DownlinkReceived(_) if self.dispatch_package_downlinks() => {
+                mac::Response::RxComplete
+            }
+            response => response,
+        }
+    }
+
+    /// Deliver pending downlinks to the registered packages and send the uplinks they request,
+    /// at most one per package. Uplinks initiated this way respect the duty cycle limit (see
+    /// [`Device::set_duty_cycle_limit`]). Called by [`Device::send`] after the application
+    /// uplink, which keeps its errors for [`Device::take_stack_error`].
+    pub async fn service_packages(&mut self) -> Result<(), Error<R::PhyError>> {
+        self.dispatch_package_downlinks();
+        let mut buf = [0; MAX_PACKAGE_PAYLOAD];
+        for i in 0..self.packages.count() {
+            let Some(package) = self.packages.package(i) else {
+                continue;
+            };
+            let Some(uplink) = package.poll_uplink(&mut buf) else {
+                continue;
+            };
+            let fport = uplink.fport.unwrap_or_else(|| package.port());
+            let remaining_ms = self.duty_cycle.remaining_ms(self.timer.elapsed_ms());
+            if remaining_ms > 0 {
+                debug!("Delaying package uplink on FPort {} by {} ms", fport, remaining_ms);
+                self.timer.delay_ms(remaining_ms).await;
+            }
+            let data = &buf[..uplink.len.min(MAX_PACKAGE_PAYLOAD)];
+            let send_data = SendData { data, fport, confirmed: uplink.confirmed };
+            let response = self.uplink(Some(&send_data)).await?;
+            debug!("Package uplink sent on FPort {}: {}", fport, response);
+            self.dispatch_package_downlinks();
+        }
+        Ok(())
+    }
+}
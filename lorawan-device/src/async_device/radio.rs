@@ -14,6 +14,16 @@ pub enum RxStatus {
     RxTimeout,
 }
 
+/// What a radio observed while sampling a channel, see [`PhyRxTx::sample_channel`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct ChannelActivity {
+    /// Channel activity detection (CAD) found a LoRa preamble.
+    pub detected: bool,
+    /// Channel RSSI (dBm), if the radio measured it.
+    pub rssi: Option<i16>,
+}
+
 /// An asynchronous timer that allows the state machine to await
 /// between RX windows.
 #[allow(async_fn_in_trait)]
@@ -25,6 +35,12 @@ pub trait Timer {
 
     /// Delay for millis milliseconds
     async fn delay_ms(&mut self, millis: u64);
+
+    /// Milliseconds elapsed since the last reset, if the timer is able to tell. Used for tracing
+    /// when the RX windows are actually opened and closed compared to their target.
+    fn elapsed_ms(&self) -> Option<u64> {
+        None
+    }
 }
 
 /// An asynchronous radio implementation that can transmit and receive data.
@@ -62,4 +78,21 @@ pub trait PhyRxTx: Sized {
     async fn low_power(&mut self) -> Result<(), Self::PhyError> {
         Ok(())
     }
+
+    /// Sample the channel for LoRa activity with the given configuration, eg: by channel activity
+    /// detection (CAD) or an RSSI measurement. Used by the pre-join scan, see
+    /// [`Device::scan_join_channels`](super::Device::scan_join_channels). Radios which can't
+    /// sample a channel return `None`, which is the default.
+    async fn sample_channel(
+        &mut self,
+        _config: RfConfig,
+    ) -> Result<Option<ChannelActivity>, Self::PhyError> {
+        Ok(None)
+    }
+
+    /// Bring the radio back into a known state after it reported an error, eg: by resetting the
+    /// chip. Used by [`RadioErrorPolicy::Recover`](super::RadioErrorPolicy::Recover).
+    async fn reset(&mut self) -> Result<(), Self::PhyError> {
+        self.low_power().await
+    }
 }
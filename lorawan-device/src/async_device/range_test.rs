@@ -0,0 +1,126 @@
+//! Over-the-air range test: periodic uplinks carrying a sequence number at a fixed data rate and
+//! EIRP. An application server which echoes these uplinks back on the same FPort allows the
+//! device to verify the downlink path as well, comparable to vendor "PER/range test" firmware.
+//!
+//! Each uplink payload consists of the sequence number (4 bytes, big endian), the data rate index
+//! and the EIRP ceiling in dBm (`0x7f` if none).
+use super::{package, radio, Device, Error, SendResponse, Timings, DR};
+use crate::CryptoFactory;
+use rand_core::RngCore;
+
+/// Parameters of a range test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct RangeTest {
+    /// FPort of the test uplinks and of the expected echoes.
+    pub fport: u8,
+    pub datarate: DR,
+    /// EIRP ceiling (dBm) for the test uplinks; `None` transmits at the maximum power.
+    pub max_eirp: Option<i8>,
+    /// Delay between two test uplinks.
+    pub interval_ms: u64,
+    /// Send the test uplinks as confirmed uplinks, so that every uplink received by the network
+    /// is answered with a downlink.
+    pub confirmed: bool,
+}
+
+/// Statistics accumulated by [`Device::range_test`]. They may be kept across several calls, in
+/// which case the sequence numbers continue.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct RangeTestStats {
+    /// Sequence number of the next test uplink.
+    pub sequence: u32,
+    /// Test uplinks sent.
+    pub sent: u32,
+    /// Test uplinks which have been answered by any downlink.
+    pub downlinks: u32,
+    /// Downlinks on the test FPort echoing the sequence number of the uplink.
+    pub echoes: u32,
+    /// Downlinks on the test FPort which did not echo the sequence number of the uplink.
+    pub bad_echoes: u32,
+}
+
+impl RangeTestStats {
+    /// Share of the test uplinks which have been answered by any downlink, in percent.
+    pub fn downlink_rate_percent(&self) -> Option<u8> {
+        (self.sent > 0).then(|| (self.downlinks as u64 * 100 / self.sent as u64) as u8)
+    }
+}
+
+impl<R, T, G, C, P, const N: usize, const D: usize> Device<R, T, G, N, D, C, P>
+where
+    R: radio::PhyRxTx + Timings,
+    T: radio::Timer,
+    G: RngCore,
+    C: CryptoFactory,
+    P: package::Packages,
+{
+    /// Run a range test of `uplinks` test uplinks, accumulating the results into `stats`.
+    ///
+    /// ADR is disabled during the test and the data rate, ADR and EIRP ceiling settings are
+    /// restored afterwards. Downlinks on the test FPort are consumed by the test; other downlinks
+    /// remain available via [`Device::take_downlink`].
+    pub async fn range_test(
+        &mut self,
+        test: &RangeTest,
+        stats: &mut RangeTestStats,
+        uplinks: u32,
+    ) -> Result<(), Error<R::PhyError>> {
+        let datarate = self.get_datarate();
+        let adr = self.get_adr();
+        let max_eirp = self.get_max_eirp();
+        self.set_adr(false);
+        self.set_max_eirp(test.max_eirp);
+
+        let mut result = Ok(());
+        for i in 0..uplinks {
+            if i > 0 {
+                self.timer.delay_ms(test.interval_ms).await;
+            }
+            result = self.range_test_uplink(test, stats).await;
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.set_datarate(datarate);
+        self.set_adr(adr);
+        self.set_max_eirp(max_eirp);
+        result
+    }
+
+    async fn range_test_uplink(
+        &mut self,
+        test: &RangeTest,
+        stats: &mut RangeTestStats,
+    ) -> Result<(), Error<R::PhyError>> {
+        // set for every uplink, as the network may still change it via LinkADRReq
+        self.set_datarate(test.datarate);
+        let sequence = stats.sequence.to_be_bytes();
+        let mut payload = [0; 6];
+        payload[..4].copy_from_slice(&sequence);
+        payload[4] = test.datarate as u8;
+        payload[5] = test.max_eirp.unwrap_or(0x7f) as u8;
+
+        let response = self.send(&payload, test.fport, test.confirmed).await?;
+        stats.sequence = stats.sequence.wrapping_add(1);
+        stats.sent += 1;
+        if let SendResponse::DownlinkReceived(_) = response {
+            stats.downlinks += 1;
+        }
+        self.downlink.retain(|downlink| {
+            if downlink.fport != test.fport {
+                return true;
+            }
+            if downlink.data.starts_with(&sequence) {
+                stats.echoes += 1;
+            } else {
+                stats.bad_echoes += 1;
+            }
+            false
+        });
+        debug!("Range test: {}", stats);
+        Ok(())
+    }
+}
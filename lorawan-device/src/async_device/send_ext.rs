@@ -0,0 +1,154 @@
+//! Helpers for typical application flows on top of [`Device::send`]: a fire-and-forget uplink
+//! queue and request/response exchanges which wait for the reply on a given FPort, so that the
+//! application doesn't need its own state machine for either.
+use super::{package, radio, Device, Downlink, Error, SendData, Timings};
+use crate::collections::{self, Collection};
+use crate::CryptoFactory;
+use heapless::Vec;
+use rand_core::RngCore;
+
+/// Maximum number of uplinks waiting in the queue of a device, unless the `alloc` feature is
+/// enabled.
+pub const MAX_QUEUED_UPLINKS: usize = 4;
+
+/// Largest payload of a queued uplink.
+pub const MAX_QUEUED_PAYLOAD: usize = 242;
+
+/// Delay between two empty uplinks sent by [`Device::send_recv`] to give the network another
+/// opportunity for the reply.
+pub const POLL_INTERVAL_MS: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum EnqueueError {
+    /// [`MAX_QUEUED_UPLINKS`] uplinks are already waiting to be sent (without the `alloc`
+    /// feature).
+    QueueFull,
+    /// The payload is larger than [`MAX_QUEUED_PAYLOAD`].
+    PayloadTooLarge,
+    /// The FPort is not allowed by the [`FPortPolicy`](crate::mac::FPortPolicy).
+    ReservedFPort,
+}
+
+#[derive(Debug)]
+pub(crate) struct QueuedUplink {
+    data: Vec<u8, MAX_QUEUED_PAYLOAD>,
+    fport: u8,
+}
+
+pub(crate) type UplinkQueue = collections::Deque<QueuedUplink, MAX_QUEUED_UPLINKS>;
+
+impl<R, T, G, C, P, const N: usize, const D: usize> Device<R, T, G, N, D, C, P>
+where
+    R: radio::PhyRxTx + Timings,
+    T: radio::Timer,
+    G: RngCore,
+    C: CryptoFactory,
+    P: package::Packages,
+{
+    /// Queue an unconfirmed uplink without waiting for its transmission. Queued uplinks are sent
+    /// at the next legal opportunity, once the duty cycle limit (see
+    /// [`Device::set_duty_cycle_limit`]) and the uplink spacing (see
+    /// [`Device::set_uplink_spacing`]) allow it:
+    /// - after the uplinks of [`Device::send`] or a class C downlink handled by
+    ///   [`Device::rxc_listen`], if they may be sent right away. Their errors are kept for
+    ///   [`Device::take_stack_error`].
+    /// - when calling [`Device::flush_queue`], which waits for the opportunity. Applications
+    ///   without uplinks of their own call it after [`Device::next_queued_uplink_ms`].
+    pub fn enqueue(&mut self, data: &[u8], fport: u8) -> Result<(), EnqueueError> {
+        self.mac.check_fport(fport, data).map_err(|_| EnqueueError::ReservedFPort)?;
+        let data = Vec::from_slice(data).map_err(|_| EnqueueError::PayloadTooLarge)?;
+        self.queue.try_push(QueuedUplink { data, fport }).map_err(|_| EnqueueError::QueueFull)
+    }
+
+    /// Number of uplinks waiting in the queue.
+    pub fn queued_uplinks(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Milliseconds from now until the next queued uplink may be transmitted, or `None` if the
+    /// queue is empty.
+    pub fn next_queued_uplink_ms(&self) -> Option<u64> {
+        if self.queue.is_empty() {
+            return None;
+        }
+        let elapsed_ms = self.timer.elapsed_ms();
+        Some(self.duty_cycle.remaining_ms(elapsed_ms).max(self.spacing.remaining_ms(elapsed_ms)))
+    }
+
+    /// Send all queued uplinks. Like the uplinks of packages, they respect the duty cycle limit
+    /// (see [`Device::set_duty_cycle_limit`]). Downlinks received meanwhile remain available via
+    /// [`Device::take_downlink`].
+    pub async fn flush_queue(&mut self) -> Result<(), Error<R::PhyError>> {
+        while let Some(queued) = self.queue.pop_front() {
+            self.wait_for_duty_cycle(0).await;
+            self.send_queued(queued).await?;
+        }
+        Ok(())
+    }
+
+    /// Send the queued uplinks which may be transmitted right away, leaving the others for the
+    /// next opportunity.
+    pub(crate) async fn flush_ready_queue(&mut self) -> Result<(), Error<R::PhyError>> {
+        while self.next_queued_uplink_ms() == Some(0) {
+            let Some(queued) = self.queue.pop_front() else {
+                break;
+            };
+            self.send_queued(queued).await?;
+        }
+        Ok(())
+    }
+
+    async fn send_queued(&mut self, queued: QueuedUplink) -> Result<(), Error<R::PhyError>> {
+        let send_data = SendData { data: &queued.data, fport: queued.fport, confirmed: false };
+        let response = self.uplink(Some(&send_data)).await?;
+        debug!("Queued uplink sent on FPort {}: {}", queued.fport, response);
+        self.dispatch_package_downlinks();
+        Ok(())
+    }
+
+    /// Send an unconfirmed uplink and wait for a downlink on the same FPort. If the reply is not
+    /// received in the RX windows of the uplink, empty uplinks are sent every
+    /// [`POLL_INTERVAL_MS`] (or later, as allowed by the duty cycle limit) to open further RX
+    /// windows, until the reply arrives or `timeout_ms` has passed since the uplink.
+    ///
+    /// Downlinks on other FPorts remain available via [`Device::take_downlink`].
+    pub async fn send_recv(
+        &mut self,
+        data: &[u8],
+        fport: u8,
+        timeout_ms: u64,
+    ) -> Result<Option<Downlink>, Error<R::PhyError>> {
+        self.send(data, fport, false).await?;
+        let mut waited_ms = 0;
+        loop {
+            if let Some(reply) = self.take_reply(fport) {
+                return Ok(Some(reply));
+            }
+            // the timer has been reset at the end of the last uplink
+            waited_ms += self.timer.elapsed_ms().unwrap_or(0);
+            if waited_ms >= timeout_ms {
+                return Ok(None);
+            }
+            waited_ms += self.wait_for_duty_cycle(POLL_INTERVAL_MS).await;
+            let response = self.uplink(None).await?;
+            debug!("Polled for reply on FPort {}: {}", fport, response);
+            self.dispatch_package_downlinks();
+        }
+    }
+
+    fn take_reply(&mut self, fport: u8) -> Option<Downlink> {
+        let i = self.downlink.iter().position(|downlink| downlink.fport == fport)?;
+        Some(self.downlink.remove(i))
+    }
+
+    /// Wait for at least `min_ms` or until the duty cycle allows the next uplink, whichever is
+    /// later. Returns the time waited.
+    async fn wait_for_duty_cycle(&mut self, min_ms: u64) -> u64 {
+        let delay_ms = self.duty_cycle.remaining_ms(self.timer.elapsed_ms()).max(min_ms);
+        if delay_ms > 0 {
+            self.timer.delay_ms(delay_ms).await;
+        }
+        delay_ms
+    }
+}
@@ -0,0 +1,58 @@
+//! Minimum spacing between uplinks: after the RX windows of an uplink have closed, the next uplink
+//! (of the application, a package, the uplink queue or the stack itself) waits for a configurable
+//! slack, eg: for network servers which need time to schedule downlinks or regulations which
+//! require a gap between transmissions.
+
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct UplinkSpacing {
+    slack_ms: Option<u32>,
+    /// Set once the RX windows of an uplink have closed, with the time (since the last timer
+    /// reset) if known.
+    windows_closed: Option<Option<u64>>,
+}
+
+impl UplinkSpacing {
+    pub(crate) fn slack_ms(&self) -> Option<u32> {
+        self.slack_ms
+    }
+
+    pub(crate) fn set_slack_ms(&mut self, slack_ms: Option<u32>) {
+        self.slack_ms = slack_ms;
+    }
+
+    /// The RX windows of the last uplink (or join request) have closed at `elapsed_ms`.
+    pub(crate) fn windows_closed(&mut self, elapsed_ms: Option<u64>) {
+        self.windows_closed = Some(elapsed_ms);
+    }
+
+    /// Milliseconds until the next uplink may be sent. If the time elapsed since the RX windows
+    /// closed is unknown, the whole slack is assumed to be remaining.
+    pub(crate) fn remaining_ms(&self, elapsed_ms: Option<u64>) -> u64 {
+        let (Some(slack_ms), Some(closed_ms)) = (self.slack_ms, self.windows_closed) else {
+            return 0;
+        };
+        let since_ms = match (closed_ms, elapsed_ms) {
+            (Some(closed_ms), Some(elapsed_ms)) => elapsed_ms.saturating_sub(closed_ms),
+            _ => 0,
+        };
+        (slack_ms as u64).saturating_sub(since_ms)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn remaining() {
+        let mut spacing = UplinkSpacing::default();
+        spacing.windows_closed(Some(2_000));
+        assert_eq!(spacing.remaining_ms(Some(2_000)), 0);
+        spacing.set_slack_ms(Some(500));
+        assert_eq!(spacing.remaining_ms(Some(2_100)), 400);
+        assert_eq!(spacing.remaining_ms(Some(3_000)), 0);
+        assert_eq!(spacing.remaining_ms(None), 500);
+        spacing.windows_closed(None);
+        assert_eq!(spacing.remaining_ms(Some(3_000)), 500);
+    }
+}
@@ -0,0 +1,141 @@
+//! Device state which outlives the peripherals, for products which power off the MCU (or at least
+//! the radio) between uplinks and rebuild the peripherals on every wake.
+#[cfg(feature = "embassy-sync")]
+use super::channels;
+use super::{
+    airtime, calibration, duty_cycle, package, send_ext, spacing, AckPolicy, Device,
+    FramePendingPolicy, MacCommandPolicy, RadioErrorPolicy, Timings,
+};
+use super::{mac::Mac, radio, Downlink, Session};
+use crate::radio::RadioBuffer;
+use crate::{collections, CryptoFactory};
+use lorawan::default_crypto::DefaultFactory;
+use rand_core::RngCore;
+
+/// The complete state of a [`Device`] (session, MAC and region configuration, pending downlinks
+/// and policies) without its radio, timer and RNG, but with its packages. Keep it in memory which
+/// is retained during sleep and [`resume`](Suspended::resume) it with freshly initialized
+/// peripherals on wake.
+pub struct Suspended<const D: usize = 1, P = ()> {
+    mac: Mac,
+    downlink: collections::Vec<Downlink, D>,
+    rx_timing: Option<calibration::RxTimingCalibration>,
+    duty_cycle: duty_cycle::DutyCycle,
+    spacing: spacing::UplinkSpacing,
+    airtime: airtime::AirtimeLedger,
+    packages: P,
+    queue: send_ext::UplinkQueue,
+    #[cfg(feature = "embassy-sync")]
+    channels: channels::Channels,
+    ack_policy: AckPolicy,
+    mac_command_policy: MacCommandPolicy,
+    frame_pending_policy: FramePendingPolicy,
+    radio_error_policy: RadioErrorPolicy,
+    late_window: Option<u32>,
+    rx_window_hook: Option<fn(super::RxWindowEvent)>,
+    uplink_report: Option<super::telemetry::UplinkReport>,
+    #[cfg(feature = "class-c")]
+    class_c: bool,
+}
+
+impl<const D: usize, P: package::Packages> Suspended<D, P> {
+    /// The current session, if the device is joined.
+    pub fn get_session(&self) -> Option<&Session> {
+        self.mac.get_session()
+    }
+
+    /// Rebuild the [`Device`] with the given peripherals. The radio buffer is not part of the
+    /// suspended state, so its size `N` may be chosen freely.
+    pub fn resume<R, T, G, const N: usize>(
+        self,
+        radio: R,
+        timer: T,
+        rng: G,
+    ) -> Device<R, T, G, N, D, DefaultFactory, P>
+    where
+        R: radio::PhyRxTx + Timings,
+        T: radio::Timer,
+        G: RngCore,
+    {
+        self.resume_with_crypto(radio, timer, rng, DefaultFactory)
+    }
+
+    /// Like [`resume`](Suspended::resume), for a [`Device`] using the given crypto backend (see
+    /// [`Device::new_with_crypto`]). The backend is not part of the suspended state.
+    pub fn resume_with_crypto<R, T, G, C, const N: usize>(
+        self,
+        radio: R,
+        timer: T,
+        rng: G,
+        crypto: C,
+    ) -> Device<R, T, G, N, D, C, P>
+    where
+        R: radio::PhyRxTx + Timings,
+        T: radio::Timer,
+        G: RngCore,
+        C: CryptoFactory,
+    {
+        Device {
+            radio,
+            rng,
+            crypto,
+            timer,
+            mac: self.mac,
+            radio_buffer: RadioBuffer::new(),
+            downlink: self.downlink,
+            rx_timing: self.rx_timing,
+            duty_cycle: self.duty_cycle,
+            spacing: self.spacing,
+            airtime: self.airtime,
+            packages: self.packages,
+            queue: self.queue,
+            #[cfg(feature = "embassy-sync")]
+            channels: self.channels,
+            ack_policy: self.ack_policy,
+            mac_command_policy: self.mac_command_policy,
+            frame_pending_policy: self.frame_pending_policy,
+            radio_error_policy: self.radio_error_policy,
+            late_window: self.late_window,
+            rx_window_hook: self.rx_window_hook,
+            uplink_report: self.uplink_report,
+            stack_error: None,
+            #[cfg(feature = "class-c")]
+            class_c: self.class_c,
+        }
+    }
+}
+
+impl<R, T, G, C, P, const N: usize, const D: usize> Device<R, T, G, N, D, C, P>
+where
+    R: radio::PhyRxTx + Timings,
+    T: radio::Timer,
+    G: RngCore,
+    C: CryptoFactory,
+    P: package::Packages,
+{
+    /// Extract the device state before powering off, dropping the radio, timer, RNG and crypto
+    /// backend as well as the error of the last stack uplink, if any.
+    pub fn suspend(self) -> Suspended<D, P> {
+        Suspended {
+            mac: self.mac,
+            downlink: self.downlink,
+            rx_timing: self.rx_timing,
+            duty_cycle: self.duty_cycle,
+            spacing: self.spacing,
+            airtime: self.airtime,
+            packages: self.packages,
+            queue: self.queue,
+            #[cfg(feature = "embassy-sync")]
+            channels: self.channels,
+            ack_policy: self.ack_policy,
+            mac_command_policy: self.mac_command_policy,
+            frame_pending_policy: self.frame_pending_policy,
+            radio_error_policy: self.radio_error_policy,
+            late_window: self.late_window,
+            rx_window_hook: self.rx_window_hook,
+            uplink_report: self.uplink_report,
+            #[cfg(feature = "class-c")]
+            class_c: self.class_c,
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Packet capture tap which reports every raw PHYPayload sent or received by a radio to a
+//! user-provided [`PacketSink`], eg: for streaming frames to Wireshark's LoRaTap dissector over
+//! RTT or a serial port.
+use super::radio::{ChannelActivity, PhyRxTx, RfConfig, RxConfig, RxQuality, RxStatus, TxConfig};
+use super::Timings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum Direction {
+    Uplink,
+    Downlink,
+}
+
+/// A raw frame captured by [`PacketTap`] along with its radio metadata.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct TapFrame<'a> {
+    pub direction: Direction,
+    /// The raw PHYPayload.
+    pub data: &'a [u8],
+    pub rf: RfConfig,
+    /// Output power (dBm) for uplinks.
+    pub power: Option<i8>,
+    /// Signal quality for downlinks.
+    pub quality: Option<RxQuality>,
+}
+
+/// Receives the frames captured by [`PacketTap`].
+pub trait PacketSink {
+    fn frame(&mut self, frame: &TapFrame<'_>);
+}
+
+/// Wraps a [`PhyRxTx`] implementation and reports every transmitted and received frame to a
+/// [`PacketSink`]. As it implements [`PhyRxTx`] itself, it can be passed to
+/// [`Device`](super::Device) in place of the radio.
+pub struct PacketTap<R, S> {
+    radio: R,
+    sink: S,
+    rf: Option<RfConfig>,
+}
+
+impl<R: PhyRxTx, S: PacketSink> PacketTap<R, S> {
+    pub fn new(radio: R, sink: S) -> Self {
+        Self { radio, sink, rf: None }
+    }
+
+    pub fn radio(&self) -> &R {
+        &self.radio
+    }
+
+    pub fn radio_mut(&mut self) -> &mut R {
+        &mut self.radio
+    }
+
+    pub fn sink_mut(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    pub fn into_inner(self) -> (R, S) {
+        (self.radio, self.sink)
+    }
+
+    fn downlink(&mut self, data: &[u8], quality: RxQuality) {
+        if let Some(rf) = self.rf {
+            self.sink.frame(&TapFrame {
+                direction: Direction::Downlink,
+                data,
+                rf,
+                power: None,
+                quality: Some(quality),
+            });
+        }
+    }
+}
+
+impl<R: PhyRxTx, S: PacketSink> PhyRxTx for PacketTap<R, S> {
+    type PhyError = R::PhyError;
+
+    const ANTENNA_GAIN: i8 = R::ANTENNA_GAIN;
+
+    const MAX_RADIO_POWER: u8 = R::MAX_RADIO_POWER;
+
+    async fn tx(&mut self, config: TxConfig, buf: &[u8]) -> Result<u32, Self::PhyError> {
+        self.sink.frame(&TapFrame {
+            direction: Direction::Uplink,
+            data: buf,
+            rf: config.rf,
+            power: Some(config.pw),
+            quality: None,
+        });
+        self.radio.tx(config, buf).await
+    }
+
+    async fn setup_rx(&mut self, config: RxConfig) -> Result<(), Self::PhyError> {
+        self.rf = Some(config.rf);
+        self.radio.setup_rx(config).await
+    }
+
+    async fn rx_continuous(
+        &mut self,
+        rx_buf: &mut [u8],
+    ) -> Result<(usize, RxQuality), Self::PhyError> {
+        let (len, quality) = self.radio.rx_continuous(rx_buf).await?;
+        self.downlink(&rx_buf[..len], quality);
+        Ok((len, quality))
+    }
+
+    async fn rx_single(&mut self, buf: &mut [u8]) -> Result<RxStatus, Self::PhyError> {
+        let status = self.radio.rx_single(buf).await?;
+        if let RxStatus::Rx(len, quality) = status {
+            self.downlink(&buf[..len], quality);
+        }
+        Ok(status)
+    }
+
+    async fn low_power(&mut self) -> Result<(), Self::PhyError> {
+        self.radio.low_power().await
+    }
+
+    async fn reset(&mut self) -> Result<(), Self::PhyError> {
+        self.radio.reset().await
+    }
+
+    async fn sample_channel(
+        &mut self,
+        config: RfConfig,
+    ) -> Result<Option<ChannelActivity>, Self::PhyError> {
+        self.radio.sample_channel(config).await
+    }
+}
+
+impl<R: Timings, S> Timings for PacketTap<R, S> {
+    fn get_rx_window_lead_time_ms(&self) -> u32 {
+        self.radio.get_rx_window_lead_time_ms()
+    }
+
+    fn get_rx_window_buffer(&self) -> u32 {
+        self.radio.get_rx_window_buffer()
+    }
+}
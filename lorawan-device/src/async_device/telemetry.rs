@@ -0,0 +1,35 @@
+//! Radio activity of individual uplinks, for applications which log it precisely, eg: to model the
+//! power consumption of a device.
+use super::DR;
+use crate::mac::FcntUp;
+use crate::radio::RfConfig;
+
+/// What was transmitted for an uplink, see [`Device::send_with_report`](super::Device::send_with_report)
+/// and [`Device::last_uplink_report`](super::Device::last_uplink_report).
+///
+/// The timestamps are the time since the device was created (or the
+/// [airtime ledger](super::airtime) was restored), measured with
+/// [`Timer::elapsed_ms`](super::radio::Timer::elapsed_ms). They are `None` with timers which
+/// don't implement it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct UplinkReport {
+    /// Frame counter of the uplink.
+    pub fcnt: FcntUp,
+    /// FPort of the uplink, `None` for an empty uplink which only acknowledges a downlink.
+    pub fport: Option<u8>,
+    /// Frequency (channel) and modulation the uplink was transmitted with.
+    pub rf: RfConfig,
+    /// Data rate of the uplink.
+    pub datarate: DR,
+    /// Output power requested from the radio (dBm).
+    pub power: i8,
+    /// Time on air of the uplink (µs), computed from its length and modulation.
+    pub time_on_air_us: u32,
+    /// Number of times the frame was transmitted before this transmission.
+    pub retries: u8,
+    /// When the radio was handed the frame.
+    pub tx_start_ms: Option<u64>,
+    /// When the radio reported the transmission as done.
+    pub tx_done_ms: Option<u64>,
+}
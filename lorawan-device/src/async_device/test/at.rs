@@ -0,0 +1,71 @@
+use super::*;
+use crate::async_device::at::{AtServer, Serial};
+
+#[derive(Default)]
+struct TestSerial {
+    input: std::vec::Vec<u8>,
+    output: std::vec::Vec<u8>,
+}
+
+impl Serial for TestSerial {
+    type Error = ();
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let len = core::cmp::min(buf.len(), self.input.len());
+        buf[..len].copy_from_slice(&self.input[..len]);
+        self.input.drain(..len);
+        Ok(len)
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), ()> {
+        self.output.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+fn take_output(server: &mut AtServer<TestSerial>) -> std::string::String {
+    std::string::String::from_utf8(core::mem::take(&mut server.serial().output)).unwrap()
+}
+
+#[tokio::test]
+async fn test_at_configuration() {
+    let (_radio, _timer, mut async_device) = setup();
+    let mut server = AtServer::new(TestSerial::default());
+    server.serial().input = b"AT\r\nat+deveui=0102030405060708\nAT+DEVEUI?\r\nAT+APPKEY=00\r\n\
+        AT+DR=3\r\nAT+DR?\r\nAT+ADR=1\r\nAT+ADR?\r\nAT+JOINED?\r\nAT+SEND=3:0102\r\nAT+FOO\r\n"
+        .to_vec();
+    // returns at the end of the input
+    server.run(&mut async_device).await.unwrap();
+    assert_eq!(
+        take_output(&mut server),
+        "OK\r\nOK\r\n+DEVEUI: 0102030405060708\r\nERROR\r\nOK\r\n+DR: 3\r\nOK\r\n+ADR: 1\r\n\
+        +JOINED: 0\r\nOK\r\n+SEND: FAILED\r\nERROR\r\n"
+    );
+}
+
+#[tokio::test]
+async fn test_at_undefined_datarate() {
+    let (_radio, _timer, mut async_device) = setup();
+    let mut server = AtServer::new(TestSerial::default());
+    // US915 doesn't define DR14 and reserves DR8 for downlinks
+    server.serial().input = b"AT+DR=2\r\nAT+DR=14\r\nAT+DR=8\r\nAT+DR=15\r\nAT+DR?\r\n".to_vec();
+    server.run(&mut async_device).await.unwrap();
+    assert_eq!(take_output(&mut server), "OK\r\nERROR\r\nERROR\r\nERROR\r\n+DR: 2\r\n");
+}
+
+#[tokio::test]
+async fn test_at_send_with_downlink() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    let mut server = AtServer::new(TestSerial::default());
+    server.serial().input = b"AT+CSEND=3:0a0b0c\r\n".to_vec();
+    let task = tokio::spawn(async move {
+        server.poll(&mut async_device).await.unwrap();
+        server
+    });
+    // Trigger beginning of RX1
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_echo).await;
+
+    let mut server = task.await.unwrap();
+    assert_eq!(take_output(&mut server), "OK\r\n+SEND: ACK\r\n+RECV: 3:0A0B0C\r\n");
+}
@@ -1,9 +1,11 @@
 use super::util;
-use crate::async_device::{ListenResponse, SendResponse};
+use crate::async_device::{Error, ListenResponse, SendResponse};
 use crate::radio::RfConfig;
 use crate::test_util::{get_key, Uplink};
+use crate::Class;
 use lorawan::creator::DataPayloadCreator;
 use lorawan::default_crypto::DefaultFactory;
+use lorawan::types::DeviceClass;
 
 pub fn class_c_downlink<const FCNT_DOWN: u32>(
     _uplink: Option<Uplink>,
@@ -98,3 +100,45 @@ async fn test_class_c_async_down() {
     }
     let _ = device.take_downlink().unwrap();
 }
+
+#[tokio::test]
+async fn test_class_c_async_down_in_place() {
+    let (radio, _timer, mut async_device) = util::setup_with_session_class_c().await;
+    // Run the device
+    let task = tokio::spawn(async move {
+        let mut buf = [0; 256];
+        let (response, downlink) = async_device.rxc_listen_in_place(&mut buf).await.unwrap();
+        let downlink = downlink.map(|downlink| (downlink.fport, downlink.data.to_vec()));
+        (async_device, response, downlink)
+    });
+
+    radio.handle_rxtx(class_c_downlink::<1>).await;
+    let (mut device, response, downlink) = task.await.unwrap();
+    assert!(matches!(response, ListenResponse::DownlinkReceived(1)));
+    assert_eq!(downlink, Some((3, vec![1, 2, 3])));
+    // The payload is not copied into the downlink queue
+    assert!(device.take_downlink().is_none());
+}
+
+#[tokio::test]
+async fn test_set_class() {
+    let (_radio, _timer, mut async_device) = util::setup();
+    assert!(matches!(
+        async_device.set_class(Class::C, true).await,
+        Err(Error::Mac(crate::mac::Error::NotJoined))
+    ));
+    assert_eq!(async_device.get_class(), Class::A);
+
+    let (_radio, _timer, mut async_device) = util::setup_with_session();
+    assert!(matches!(
+        async_device.set_class(Class::B, true).await,
+        Err(Error::Mac(crate::mac::Error::UnsupportedClass))
+    ));
+    assert_eq!(async_device.get_class(), Class::A);
+    assert_eq!(async_device.mac.configuration.device_mode_ind, None);
+    async_device.set_class(Class::C, true).await.unwrap();
+    assert_eq!(async_device.get_class(), Class::C);
+    assert_eq!(async_device.mac.configuration.device_mode_ind, Some(DeviceClass::C));
+    async_device.set_class(Class::A, false).await.unwrap();
+    assert_eq!(async_device.get_class(), Class::A);
+}
@@ -95,6 +95,56 @@ async fn linkadrreq_dynamic() {
     assert_eq!(data, [3, 6]);
 }
 
+#[tokio::test]
+#[cfg(feature = "region-eu868")]
+async fn linkadrreq_nb_trans() {
+    use lorawan::parser::{DataHeader, DataPayload, PhyPayload};
+
+    let (radio, timer, mut device) =
+        util::session_with_region(crate::region::EU868::new_eu868().into());
+    assert_eq!(device.get_nb_trans(), 1);
+    let task = tokio::spawn(async move {
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+
+    fn nb_trans_2(_uplink: Option<Uplink>, _config: RfConfig, buf: &mut [u8]) -> usize {
+        // LinkADRReq - keep data rate and TX power, mask = 0b111, NbTrans = 2
+        build_frm_payload(buf, "03FF070002", 2)
+    }
+
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(nb_trans_2).await;
+
+    let (mut device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(_))));
+    assert_eq!(device.mac.get_session().unwrap().uplink.mac_commands(), [3, 7]);
+    assert_eq!(device.get_nb_trans(), 2);
+
+    let task = tokio::spawn(async move {
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+    // both transmissions carry the same FCnt
+    for _ in 0..2 {
+        // RX1 and RX2
+        timer.fire_most_recent().await;
+        let mut uplink = radio.get_last_uplink().await;
+        let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() else {
+            panic!("Did not decode PhyPayload::Data!");
+        };
+        assert_eq!(data.fhdr().fcnt(), 1);
+        radio.handle_timeout().await;
+        timer.fire_most_recent().await;
+        radio.handle_timeout().await;
+    }
+
+    let (device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::RxComplete)));
+    assert_eq!(device.last_uplink_report().unwrap().retries, 1);
+    assert_eq!(device.mac.get_session().unwrap().fcnt_up, 2);
+}
+
 #[tokio::test]
 #[cfg(feature = "region-us915")]
 async fn linkadrreq_fixed_125khz_extra_mask() {
@@ -376,6 +426,65 @@ async fn linkadrreq_dynamic_invalid() {
     assert_eq!(data, [3, 6, 3, 6, 3, 6]);
 }
 
+#[tokio::test]
+#[cfg(feature = "region-eu868")]
+async fn linkadrreq_dynamic_fsk_rejected() {
+    let (radio, timer, mut device) =
+        util::session_with_region(crate::region::EU868::new_eu868().into());
+    let datarate = device.get_datarate();
+    let task = tokio::spawn(async move {
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+
+    fn fsk_datarate(_uplink: Option<Uplink>, _config: RfConfig, buf: &mut [u8]) -> usize {
+        // LinkADRReq, DR7 (FSK), 0, 0700, 01
+        build_frm_payload(buf, "0370070001", 2)
+    }
+
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(fsk_datarate).await;
+
+    let (mut device, response) = task.await.unwrap();
+    match response {
+        Ok(SendResponse::DownlinkReceived(_)) => {}
+        _ => panic!(),
+    }
+
+    // Data rate NACKed, channel mask and TX power ACKed
+    let session = device.mac.get_session().unwrap();
+    assert_eq!(session.uplink.mac_commands(), [3, 5]);
+    assert_eq!(device.get_datarate(), datarate);
+}
+
+#[tokio::test]
+#[cfg(feature = "region-eu868")]
+async fn linkadrreq_without_snr_margin() {
+    let (radio, timer, mut device) =
+        util::session_with_region(crate::region::EU868::new_eu868().into());
+    device.set_adr_margin(Some(crate::mac::AdrMargin { min_margin_db: 10, samples: 1 }));
+    let datarate = device.get_datarate();
+    let task = tokio::spawn(async move {
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+
+    fn sf7_datarate(_uplink: Option<Uplink>, _config: RfConfig, buf: &mut [u8]) -> usize {
+        // LinkADRReq, SF7BW125, 0, 0700, 01
+        build_frm_payload(buf, "0350070001", 2)
+    }
+
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(sf7_datarate).await;
+
+    let (mut device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(_))));
+    // 0 dB SNR is only 7.5 dB above the SF7 floor: data rate NACKed
+    let session = device.mac.get_session().unwrap();
+    assert_eq!(session.uplink.mac_commands(), [3, 5]);
+    assert_eq!(device.get_datarate(), datarate);
+}
+
 fn newchannelreq_invalid_eu868(
     _uplink: Option<Uplink>,
     _config: RfConfig,
@@ -498,6 +607,67 @@ async fn newchannelreq_fixed_region_ignore() {
     }
 }
 
+fn txparamsetupreq(_uplink: Option<Uplink>, _config: RfConfig, buf: &mut [u8]) -> usize {
+    // TxParamSetupReq - DownlinkDwellTime = 1, UplinkDwellTime = 1, MaxEIRP = 13 (30 dBm)
+    build_frm_payload(buf, "093d", 2)
+}
+
+#[tokio::test]
+#[cfg(feature = "region-as923-1")]
+async fn txparamsetupreq_as923() {
+    let (radio, timer, mut device) = util::session_with_region(crate::region::Configuration::new(
+        crate::region::Region::AS923_1,
+    ));
+
+    let task = tokio::spawn(async move {
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(txparamsetupreq).await;
+
+    let (device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(_))));
+
+    let session = device.mac.get_session().unwrap();
+    assert_eq!(session.uplink.mac_commands(), [9]);
+    assert!(device.mac.configuration.uplink_dwell_time);
+    assert!(device.mac.region.get_downlink_dwell_time());
+    assert_eq!(device.mac.configuration.network_max_eirp, Some(30));
+
+    // The parameters are kept in the regional state
+    let state = device.mac.get_regional_state();
+    assert!(state.uplink_dwell_time);
+    assert!(state.downlink_dwell_time);
+    assert_eq!(state.max_eirp, Some(30));
+}
+
+#[tokio::test]
+#[cfg(feature = "region-eu868")]
+async fn txparamsetupreq_eu868_ignored() {
+    let (radio, timer, mut device) =
+        util::session_with_region(crate::region::EU868::new_eu868().into());
+
+    let task = tokio::spawn(async move {
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(txparamsetupreq).await;
+
+    let (device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(_))));
+
+    // Regions without TxParamSetupReq don't answer and keep their parameters
+    let session = device.mac.get_session().unwrap();
+    assert_eq!(parse_uplink_mac_commands(session.uplink.mac_commands()).count(), 0);
+    assert!(!device.mac.configuration.uplink_dwell_time);
+    assert!(!device.mac.region.get_downlink_dwell_time());
+    assert_eq!(device.mac.configuration.network_max_eirp, None);
+}
+
 #[tokio::test]
 #[cfg(all(feature = "region-us915", feature = "experimental"))]
 // TODO: Finalize RXParamSetupReq/RXTimingSetupReq
@@ -564,3 +734,359 @@ async fn maccommands_in_frmpayload() {
         panic!("Session not joined?");
     }
 }
+
+#[tokio::test]
+async fn mac_commands_exceeding_fopts_on_fport_zero() {
+    use lorawan::parser::{DataHeader, DataPayload, FRMPayload, PhyPayload};
+
+    fn dev_status_reqs(_uplink: Option<Uplink>, _config: RfConfig, buf: &mut [u8]) -> usize {
+        // 8 * DevStatusReq, answered by 24 bytes of DevStatusAns
+        build_frm_payload(buf, "0606060606060606", 1)
+    }
+
+    let (radio, timer, mut device) = util::setup_with_session();
+    device.set_datarate(crate::region::DR::_3);
+    device.set_mac_command_policy(crate::async_device::MacCommandPolicy::FPortZero);
+
+    let task = tokio::spawn(async move {
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+
+    // Handle reception in RX1
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(dev_status_reqs).await;
+
+    // The answers are sent in an FPort 0 uplink right away
+    tokio::time::sleep(tokio::time::Duration::from_millis(5)).await;
+    timer.fire_most_recent().await;
+    let mut uplink = radio.get_last_uplink().await;
+    if let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() {
+        assert_eq!(data.f_port(), Some(0));
+        let fcnt = data.fhdr().fcnt() as u32;
+        let data = data
+            .decrypt(Some(&get_key().into()), Some(&get_key().into()), fcnt, &DefaultFactory)
+            .unwrap();
+        assert_eq!(data.fhdr().data().len(), 0);
+        if let FRMPayload::MACCommands(cmds) = data.frm_payload() {
+            assert_eq!(parse_uplink_mac_commands(cmds.data()).count(), 8);
+        } else {
+            panic!("Expected MAC commands in FRMPayload");
+        }
+    } else {
+        panic!("Unable to parse data uplink");
+    }
+    radio.handle_timeout().await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+
+    let (device, response) = task.await.unwrap();
+    match response {
+        Ok(SendResponse::DownlinkReceived(1)) => {}
+        _ => panic!(),
+    }
+    assert!(device.mac.get_session().unwrap().uplink.mac_commands().is_empty());
+}
+
+#[tokio::test]
+async fn mac_commands_exceeding_max_fopts_len() {
+    use lorawan::parser::{DataHeader, DataPayload, PhyPayload};
+
+    fn dev_status_req(_uplink: Option<Uplink>, _config: RfConfig, buf: &mut [u8]) -> usize {
+        build_frm_payload(buf, "06", 1)
+    }
+
+    let (radio, timer, mut device) = util::setup_with_session();
+    device.set_max_fopts_len(0);
+    device.set_mac_command_policy(crate::async_device::MacCommandPolicy::FPortZero);
+    assert_eq!(device.get_max_fopts_len(), 0);
+
+    let task = tokio::spawn(async move {
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(dev_status_req).await;
+
+    // DevStatusAns would fit into FOpts, but is sent in an FPort 0 uplink
+    tokio::time::sleep(tokio::time::Duration::from_millis(5)).await;
+    timer.fire_most_recent().await;
+    let mut uplink = radio.get_last_uplink().await;
+    let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() else {
+        panic!("Unable to parse data uplink");
+    };
+    assert_eq!(data.f_port(), Some(0));
+    assert!(data.fhdr().data().is_empty());
+    radio.handle_timeout().await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+
+    let (device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(1))));
+    assert!(device.mac.get_session().unwrap().uplink.mac_commands().is_empty());
+}
+
+#[tokio::test]
+async fn adrparamsetupreq_adr_backoff() {
+    use lorawan::parser::{DataHeader, DataPayload, PhyPayload};
+
+    fn adr_param_setup_req(_uplink: Option<Uplink>, _config: RfConfig, buf: &mut [u8]) -> usize {
+        // ADR_ACK_LIMIT = 2, ADR_ACK_DELAY = 1
+        build_frm_payload(buf, "0c10", 1)
+    }
+
+    let (radio, timer, mut device) = util::setup_with_session();
+    device.set_adr(true);
+    device.set_datarate(crate::region::DR::_3);
+
+    let task = tokio::spawn(async move {
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(adr_param_setup_req).await;
+    let (mut device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(1))));
+    assert_eq!(device.mac.configuration.adr_ack_limit, 2);
+    assert_eq!(device.mac.configuration.adr_ack_delay, 1);
+
+    // Uplinks which are not answered by the network
+    for i in 0..4 {
+        let task = tokio::spawn(async move {
+            let response = device.send(&[1, 2, 3], 3, false).await;
+            (device, response)
+        });
+        timer.fire_most_recent().await;
+        let mut uplink = radio.get_last_uplink().await;
+        if let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() {
+            let fctrl = data.fhdr().fctrl();
+            assert!(fctrl.adr());
+            // ADRACKReq is set once ADR_ACK_LIMIT uplinks went unanswered
+            assert_eq!(fctrl.adr_ack_req(), i >= 2);
+            if i == 0 {
+                // ADRParamSetupAns
+                assert_eq!(data.fhdr().data(), [0x0c]);
+            }
+        } else {
+            panic!("Unable to parse data uplink");
+        }
+        radio.handle_timeout().await;
+        timer.fire_most_recent().await;
+        radio.handle_timeout().await;
+        let (d, response) = task.await.unwrap();
+        assert!(matches!(response, Ok(SendResponse::RxComplete)));
+        device = d;
+    }
+
+    // After a further ADR_ACK_DELAY unanswered uplinks, the data rate was lowered
+    assert_eq!(device.mac.configuration.data_rate, crate::region::DR::_2);
+}
+
+#[tokio::test]
+async fn adr_backoff_shrinks_max_payload_len() {
+    fn adr_param_setup_req(_uplink: Option<Uplink>, _config: RfConfig, buf: &mut [u8]) -> usize {
+        // ADR_ACK_LIMIT = 2, ADR_ACK_DELAY = 1
+        build_frm_payload(buf, "0c10", 1)
+    }
+
+    let (radio, timer, mut device) = util::setup_with_session();
+    device.set_adr(true);
+    device.set_datarate(crate::region::DR::_3);
+
+    let task = tokio::spawn(async move {
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(adr_param_setup_req).await;
+    let (mut device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(1))));
+
+    // Uplinks which are not answered by the network, up to the one which triggers the backoff
+    for _ in 0..3 {
+        let task = tokio::spawn(async move {
+            let response = device.send(&[1, 2, 3], 3, false).await;
+            (device, response)
+        });
+        timer.fire_most_recent().await;
+        radio.handle_timeout().await;
+        timer.fire_most_recent().await;
+        radio.handle_timeout().await;
+        let (d, response) = task.await.unwrap();
+        assert!(matches!(response, Ok(SendResponse::RxComplete)));
+        device = d;
+    }
+
+    // The payload fits at DR3, but the backoff lowers the data rate before it is sent
+    let max_len = device.max_payload_len();
+    let data = [0; 256];
+    let response = device.send(&data[..max_len], 3, false).await;
+    assert!(matches!(
+        response,
+        Err(crate::async_device::Error::Mac(crate::mac::Error::PayloadTooLarge { max_len: len }))
+            if len < max_len && len == device.max_payload_len()
+    ));
+    assert_eq!(device.mac.configuration.data_rate, crate::region::DR::_2);
+}
+
+#[tokio::test]
+async fn devicemodeind_confirmed() {
+    use crate::DeviceClass;
+    use lorawan::parser::{DataHeader, DataPayload, PhyPayload};
+
+    fn device_mode_conf(uplink: Option<Uplink>, _config: RfConfig, buf: &mut [u8]) -> usize {
+        let mut uplink = uplink.unwrap();
+        if let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() {
+            // DeviceModeInd for Class C
+            assert_eq!(data.fhdr().data(), [0x20, 0x02]);
+        } else {
+            panic!("Unable to parse data uplink");
+        }
+        build_frm_payload(buf, "2002", 1)
+    }
+
+    let (radio, timer, mut device) = util::setup_with_session();
+    device.indicate_device_mode(DeviceClass::C).unwrap();
+
+    let task = tokio::spawn(async move {
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(device_mode_conf).await;
+
+    let (mut device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(1))));
+    assert_eq!(device.take_device_mode_conf(), Some(DeviceClass::C));
+    assert_eq!(device.take_device_mode_conf(), None);
+    assert!(device.mac.get_session().unwrap().uplink.mac_commands().is_empty());
+}
+
+#[tokio::test]
+async fn resetind_until_resetconf() {
+    use lorawan::parser::{DataHeader, DataPayload, PhyPayload};
+
+    fn no_reset_conf(uplink: Option<Uplink>, _config: RfConfig, buf: &mut [u8]) -> usize {
+        let mut uplink = uplink.unwrap();
+        if let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() {
+            // ResetInd for LoRaWAN 1.1
+            assert_eq!(data.fhdr().data(), [0x01, 0x01]);
+        } else {
+            panic!("Unable to parse data uplink");
+        }
+        // DevStatusReq
+        build_frm_payload(buf, "06", 1)
+    }
+
+    fn reset_conf(uplink: Option<Uplink>, _config: RfConfig, buf: &mut [u8]) -> usize {
+        let mut uplink = uplink.unwrap();
+        if let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() {
+            // ResetInd is repeated along with the DevStatusAns
+            assert_eq!(&data.fhdr().data()[..3], [0x06, 0xff, 0x00]);
+            assert_eq!(&data.fhdr().data()[3..], [0x01, 0x01]);
+        } else {
+            panic!("Unable to parse data uplink");
+        }
+        build_frm_payload(buf, "0101", 2)
+    }
+
+    let (radio, timer, mut device) = util::setup_with_session();
+    // as if set by the network; a data rate set by the application would be kept
+    device.mac.configuration.data_rate = crate::region::DR::_3;
+    device.indicate_reset().unwrap();
+    // MAC settings are reverted to their defaults
+    assert_eq!(device.mac.configuration.data_rate, crate::region::DR::_0);
+    assert!(device.is_reset_ind_pending());
+
+    for handler in [no_reset_conf as crate::test_util::RxTxHandler, reset_conf] {
+        let task = tokio::spawn(async move {
+            let response = device.send(&[1, 2, 3], 3, false).await;
+            (device, response)
+        });
+        timer.fire_most_recent().await;
+        radio.handle_rxtx(handler).await;
+        let (d, response) = task.await.unwrap();
+        assert!(matches!(response, Ok(SendResponse::DownlinkReceived(_))));
+        device = d;
+    }
+    assert!(!device.is_reset_ind_pending());
+    assert!(device.mac.get_session().unwrap().uplink.mac_commands().is_empty());
+}
+
+#[tokio::test]
+async fn beaconfreqreq_pingslotchannelreq() {
+    use crate::mac::ClassBSettings;
+    use crate::region::DR;
+
+    fn class_b_channels(_uplink: Option<Uplink>, _config: RfConfig, buf: &mut [u8]) -> usize {
+        // BeaconFreqReq for 923.3 MHz, PingSlotChannelReq for the default frequency at DR4 (not
+        // usable for downlinks in US915) and PingSlotChannelReq for 923.9 MHz at DR8
+        build_frm_payload(buf, "1368e28c110000000411d8f98c08", 1)
+    }
+
+    let (radio, timer, mut device) = util::setup_with_session();
+    let task = tokio::spawn(async move {
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(class_b_channels).await;
+    let (device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(1))));
+    assert_eq!(
+        device.get_class_b_settings(),
+        ClassBSettings {
+            beacon_frequency: Some(923_300_000),
+            ping_slot_frequency: Some(923_900_000),
+            ping_slot_data_rate: Some(DR::_8),
+        }
+    );
+    // BeaconFreqAns, PingSlotChannelAns (DR rejected), PingSlotChannelAns
+    assert_eq!(
+        device.mac.get_session().unwrap().uplink.mac_commands(),
+        [0x13, 0x01, 0x11, 0x01, 0x11, 0x03]
+    );
+}
+
+#[tokio::test]
+async fn health_check_linkcheckreq() {
+    use crate::mac::{HealthCheck, HealthEvent};
+    use lorawan::parser::{DataHeader, DataPayload, PhyPayload};
+
+    fn link_check_ans(uplink: Option<Uplink>, _config: RfConfig, buf: &mut [u8]) -> usize {
+        let mut uplink = uplink.unwrap();
+        if let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() {
+            // LinkCheckReq
+            assert_eq!(data.fhdr().data(), [0x02]);
+        } else {
+            panic!("Unable to parse data uplink");
+        }
+        build_frm_payload(buf, "020a01", 1)
+    }
+
+    let (radio, timer, mut device) = util::setup_with_session();
+    device.set_health_check(Some(HealthCheck { uplinks: 1, ..Default::default() }));
+
+    let task = tokio::spawn(async move {
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    let (mut device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::RxComplete)));
+    assert_eq!(device.take_health_event(), None);
+
+    let task = tokio::spawn(async move {
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(link_check_ans).await;
+    let (mut device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(1))));
+    assert_eq!(device.take_health_event(), Some(HealthEvent::CheckSent));
+    assert_eq!(device.take_health_event(), Some(HealthEvent::CheckPassed));
+}
@@ -5,6 +5,7 @@ use crate::{
     test_util::*,
 };
 use lorawan::default_crypto::DefaultFactory;
+use lorawan::parser::{DataHeader, DataPayload, PhyPayload};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
@@ -15,11 +16,13 @@ mod radio;
 use radio::TestRadio;
 
 mod util;
-pub(crate) use util::{setup, setup_with_session};
+pub(crate) use util::{setup, setup_with_fcnt, setup_with_session};
 
 #[cfg(feature = "certification")]
 mod certification;
 
+mod at;
+
 mod maccommands;
 
 #[cfg(feature = "class-c")]
@@ -31,96 +34,1049 @@ mod multicast;
 type Device = crate::async_device::Device<TestRadio, TestTimer, rand_core::OsRng, 512, 4>;
 
 #[tokio::test]
-async fn test_join_rx1() {
-    let (radio, timer, mut async_device) = setup();
-    // Run the device
-    let async_device =
-        tokio::spawn(async move { async_device.join(&get_otaa_credentials()).await });
+async fn test_join_rx1() {
+    let (radio, timer, mut async_device) = setup();
+    // Run the device
+    let async_device =
+        tokio::spawn(async move { async_device.join(&get_otaa_credentials()).await });
+
+    // Trigger beginning of RX1
+    timer.fire_most_recent().await;
+    // Trigger handling of JoinAccept
+    radio.handle_rxtx(handle_join_request::<3>).await;
+
+    // Await the device to return and verify state
+    if let Ok(JoinResponse::JoinSuccess) = async_device.await.unwrap() {
+        assert_eq!(1, timer.get_armed_count().await);
+    } else {
+        panic!();
+    }
+}
+
+#[tokio::test]
+async fn test_join_keeps_app_settings() {
+    use crate::provisioning::{Provisioning, Rx2};
+
+    let provisioning = Provisioning {
+        join_mode: get_otaa_credentials(),
+        region: region::Region::US915,
+        subband: None,
+        class_c: false,
+        rx2: Some(Rx2 { frequency: 923_900_000, data_rate: region::DR::_10 }),
+    };
+    let mut blob = [0; crate::provisioning::MAX_LEN];
+    let len = provisioning.encode(&mut blob).unwrap();
+    let (radio, mock_radio) = TestRadio::new();
+    let (timer, mock_timer) = TestTimer::new();
+    let (mut async_device, join_mode) =
+        Device::from_provisioning(&blob[..len], mock_radio, mock_timer, rand::rngs::OsRng).unwrap();
+    async_device.set_datarate(region::DR::_2);
+    async_device.set_uplink_dwell_time(true);
+
+    let async_device = tokio::spawn(async move {
+        let response = async_device.join(&join_mode).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_join_request::<3>).await;
+
+    let (async_device, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(JoinResponse::JoinSuccess)));
+    let configuration = async_device.mac.configuration;
+    assert_eq!(configuration.rx2_frequency, Some(923_900_000));
+    assert_eq!(configuration.rx2_data_rate, Some(region::DR::_10));
+    assert_eq!(configuration.data_rate, region::DR::_2);
+    assert!(configuration.uplink_dwell_time);
+}
+
+#[tokio::test]
+async fn test_join_scan() {
+    let (radio, timer, mut async_device) = setup();
+    // the test radio can't sample channels by default
+    assert_eq!(async_device.scan_join_channels(1).await.unwrap(), None);
+
+    // a gateway on subband 2 (903.9 MHz to 905.3 MHz)
+    radio
+        .set_channel_activity(|rf| {
+            let active = (903_900_000..=905_300_000).contains(&rf.frequency);
+            crate::async_device::radio::ChannelActivity {
+                detected: active,
+                rssi: active.then_some(-90),
+            }
+        })
+        .await;
+    let scan = async_device.scan_join_channels(2).await.unwrap().unwrap();
+    assert_eq!(scan.most_active(), Some(region::Subband::_2));
+    assert_eq!(scan.subbands()[0].detections, 16);
+    assert_eq!(scan.subbands()[0].max_rssi, Some(-90));
+    assert_eq!(scan.subbands()[1].detections, 0);
+
+    let async_device = tokio::spawn(async move {
+        let response = async_device.join(&get_otaa_credentials()).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_join_request::<3>).await;
+    let (_, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(JoinResponse::JoinSuccess)));
+    let frequency = radio.get_last_uplink().await.get_tx_config().rf.frequency;
+    assert!((903_900_000..=905_300_000).contains(&frequency));
+}
+
+/// Delegates to [`DefaultFactory`], counting the MAC calculators created.
+struct CountingFactory(Arc<std::sync::atomic::AtomicUsize>);
+
+impl CryptoFactory for CountingFactory {
+    type E = <DefaultFactory as CryptoFactory>::E;
+    type D = <DefaultFactory as CryptoFactory>::D;
+    type M = <DefaultFactory as CryptoFactory>::M;
+
+    fn new_enc(&self, key: &lorawan::keys::AES128) -> Self::E {
+        DefaultFactory.new_enc(key)
+    }
+
+    fn new_dec(&self, key: &lorawan::keys::AES128) -> Self::D {
+        DefaultFactory.new_dec(key)
+    }
+
+    fn new_mac(&self, key: &lorawan::keys::AES128) -> Self::M {
+        self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        DefaultFactory.new_mac(key)
+    }
+}
+
+#[tokio::test]
+async fn test_join_with_crypto_instance() {
+    let (radio, mock_radio) = TestRadio::new();
+    let (timer, mock_timer) = TestTimer::new();
+    let macs = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let mut async_device: crate::async_device::Device<_, _, _, 512, 4, _> =
+        crate::async_device::Device::new_with_crypto(
+            region::US915::default().into(),
+            mock_radio,
+            mock_timer,
+            rand::rngs::OsRng,
+            CountingFactory(macs.clone()),
+        );
+    let async_device =
+        tokio::spawn(async move { async_device.join(&get_otaa_credentials()).await });
+
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_join_request::<3>).await;
+
+    assert!(matches!(async_device.await.unwrap(), Ok(JoinResponse::JoinSuccess)));
+    // The join request MIC and the join accept MIC
+    assert_eq!(2, macs.load(std::sync::atomic::Ordering::Relaxed));
+}
+
+#[tokio::test]
+async fn test_session_key_export() {
+    let (radio, timer, mut async_device) = setup();
+    async_device.set_session_key_export(true);
+    let async_device = tokio::spawn(async move {
+        let response = async_device.join(&get_otaa_credentials()).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_join_request::<3>).await;
+
+    let (mut async_device, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(JoinResponse::JoinSuccess)));
+    let keys = async_device.take_exported_session_keys().unwrap();
+    let session = async_device.get_session().unwrap();
+    assert_eq!(keys.devaddr, session.devaddr);
+    assert_eq!(keys.nwkskey, session.nwkskey);
+    assert_eq!(keys.appskey, session.appskey);
+    // keys are handed over only once
+    assert!(async_device.take_exported_session_keys().is_none());
+    assert_eq!(async_device.get_session_keys().map(|keys| keys.devaddr), Some(keys.devaddr));
+    async_device.set_session_key_export(false);
+    assert!(async_device.get_session_keys().is_none());
+}
+
+#[tokio::test]
+async fn test_join_rx2() {
+    let (radio, timer, mut async_device) = setup();
+    // Run the device
+    let async_device =
+        tokio::spawn(async move { async_device.join(&get_otaa_credentials()).await });
+
+    // Trigger beginning of RX1
+    timer.fire_most_recent().await;
+    // Trigger end of RX1
+    radio.handle_timeout().await;
+    // Trigger start of RX2
+    timer.fire_most_recent().await;
+    // Pass the join request handler
+    radio.handle_rxtx(handle_join_request::<4>).await;
+
+    // Await the device to return and verify state
+    if async_device.await.unwrap().is_ok() {
+        assert_eq!(2, timer.get_armed_count().await);
+    } else {
+        panic!();
+    }
+}
+
+#[tokio::test]
+async fn test_packet_tap() {
+    use crate::async_device::tap::{Direction, PacketSink, PacketTap, TapFrame};
+
+    #[derive(Default, Clone)]
+    struct Sink(Arc<std::sync::Mutex<std::vec::Vec<(Direction, usize)>>>);
+
+    impl PacketSink for Sink {
+        fn frame(&mut self, frame: &TapFrame<'_>) {
+            self.0.lock().unwrap().push((frame.direction, frame.data.len()));
+        }
+    }
+
+    let (radio, mock_radio) = TestRadio::new();
+    let (timer, mock_timer) = TestTimer::new();
+    let sink = Sink::default();
+    let mut async_device: crate::async_device::Device<_, _, _, 512, 4> =
+        crate::async_device::Device::new(
+            region::US915::default().into(),
+            PacketTap::new(mock_radio, sink.clone()),
+            mock_timer,
+            rand::rngs::OsRng,
+        );
+    let async_device =
+        tokio::spawn(async move { async_device.join(&get_otaa_credentials()).await });
+
+    // Trigger beginning of RX1
+    timer.fire_most_recent().await;
+    // Trigger handling of JoinAccept
+    radio.handle_rxtx(handle_join_request::<5>).await;
+
+    assert!(matches!(async_device.await.unwrap(), Ok(JoinResponse::JoinSuccess)));
+    // JoinRequest is 23 bytes, JoinAccept without CFList is 17 bytes
+    assert_eq!(*sink.0.lock().unwrap(), [(Direction::Uplink, 23), (Direction::Downlink, 17)]);
+}
+
+#[tokio::test]
+async fn test_packet_tap_reset() {
+    use crate::async_device::radio::{PhyRxTx, RxConfig, RxStatus};
+    use crate::async_device::tap::{PacketSink, PacketTap, TapFrame};
+
+    struct Sink;
+
+    impl PacketSink for Sink {
+        fn frame(&mut self, _frame: &TapFrame<'_>) {}
+    }
+
+    /// Counts the calls of `reset` and `low_power`.
+    #[derive(Default)]
+    struct Radio {
+        resets: usize,
+        low_power: usize,
+    }
+
+    impl PhyRxTx for Radio {
+        type PhyError = ();
+
+        const MAX_RADIO_POWER: u8 = 26;
+
+        const ANTENNA_GAIN: i8 = 0;
+
+        async fn tx(&mut self, _config: TxConfig, _buf: &[u8]) -> Result<u32, ()> {
+            Ok(0)
+        }
+
+        async fn setup_rx(&mut self, _config: RxConfig) -> Result<(), ()> {
+            Ok(())
+        }
+
+        async fn rx_continuous(&mut self, _buf: &mut [u8]) -> Result<(usize, RxQuality), ()> {
+            Err(())
+        }
+
+        async fn rx_single(&mut self, _buf: &mut [u8]) -> Result<RxStatus, ()> {
+            Ok(RxStatus::RxTimeout)
+        }
+
+        async fn low_power(&mut self) -> Result<(), ()> {
+            self.low_power += 1;
+            Ok(())
+        }
+
+        async fn reset(&mut self) -> Result<(), ()> {
+            self.resets += 1;
+            Ok(())
+        }
+    }
+
+    let mut tap = PacketTap::new(Radio::default(), Sink);
+    tap.reset().await.unwrap();
+    assert_eq!(tap.radio().resets, 1);
+    assert_eq!(tap.radio().low_power, 0);
+}
+
+#[tokio::test]
+async fn test_no_join_accept() {
+    let (radio, timer, mut async_device) = setup();
+    // Run the device
+    let async_device =
+        tokio::spawn(async move { async_device.join(&get_otaa_credentials()).await });
+
+    // Trigger beginning of RX1
+    timer.fire_most_recent().await;
+    // Trigger end of RX1
+    radio.handle_timeout().await;
+    // Trigger start of RX2
+    timer.fire_most_recent().await;
+    // Trigger end of RX2
+    radio.handle_timeout().await;
+
+    // Await the device to return and verify state
+    let response = async_device.await.unwrap();
+    if let Ok(JoinResponse::NoJoinAccept) = response {
+        assert_eq!(2, timer.get_armed_count().await);
+    } else {
+        panic!("Unexpected response: {response:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_unconfirmed_uplink_no_downlink() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    let send_await_complete = Arc::new(Mutex::new(false));
+
+    // Run the device
+    let complete = send_await_complete.clone();
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+
+        let mut complete = complete.lock().await;
+        *complete = true;
+        response
+    });
+    // Trigger beginning of RX1
+    timer.fire_most_recent().await;
+    assert!(!*send_await_complete.lock().await);
+    // Trigger end of RX1
+    radio.handle_timeout().await;
+    // Trigger start of RX2
+    timer.fire_most_recent().await;
+    assert!(!*send_await_complete.lock().await);
+    // Trigger end of RX2
+    radio.handle_timeout().await;
+
+    match async_device.await.unwrap() {
+        Ok(SendResponse::RxComplete) => (),
+        _ => panic!(),
+    }
+    assert!(*send_await_complete.lock().await);
+}
+
+#[tokio::test]
+async fn test_suspend_and_resume() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    async_device.set_ack_policy(AckPolicy::Immediate);
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    let (async_device, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::RxComplete)));
+
+    let suspended = async_device.suspend();
+    assert_eq!(suspended.get_session().unwrap().fcnt_up, 1);
+
+    // Wake up with fresh peripherals
+    let (radio, mock_radio) = TestRadio::new();
+    let (timer, mock_timer) = TestTimer::new();
+    let mut async_device: Device = suspended.resume(mock_radio, mock_timer, rand::rngs::OsRng);
+    assert_eq!(async_device.get_ack_policy(), AckPolicy::Immediate);
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<1, 0>).await;
+    let (async_device, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(0))));
+    assert_eq!(async_device.mac.get_session().unwrap().fcnt_up, 2);
+}
+
+/// Echo the FRMPayload of an uplink back on the same FPort
+fn handle_data_uplink_with_echo(
+    uplink: Option<Uplink>,
+    _config: RfConfig,
+    rx_buffer: &mut [u8],
+) -> usize {
+    let mut uplink = uplink.unwrap();
+    let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() else {
+        panic!("Did not decode PhyPayload::Data!");
+    };
+    let fcnt = data.fhdr().fcnt() as u32;
+    let uplink = data
+        .decrypt(Some(&get_key().into()), Some(&get_key().into()), fcnt, &DefaultFactory)
+        .unwrap();
+    let lorawan::parser::FRMPayload::Data(payload) = uplink.frm_payload() else {
+        panic!("Uplink without data");
+    };
+    let payload = payload.to_vec();
+    let mut phy = lorawan::creator::DataPayloadCreator::new(rx_buffer).unwrap();
+    phy.set_f_port(uplink.f_port().unwrap());
+    phy.set_dev_addr(&[0; 4]);
+    phy.set_uplink(false);
+    phy.set_fcnt(fcnt);
+    phy.build(&payload, [], &get_key().into(), &get_key().into(), &DefaultFactory).unwrap().len()
+}
+
+/// Answer with an empty downlink announcing more downlinks with the FPending bit
+fn handle_data_uplink_with_frame_pending(
+    _uplink: Option<Uplink>,
+    _config: RfConfig,
+    rx_buffer: &mut [u8],
+) -> usize {
+    let mut phy = lorawan::creator::DataPayloadCreator::new(rx_buffer).unwrap();
+    phy.set_dev_addr(&[0; 4]);
+    phy.set_uplink(false);
+    phy.set_fctrl(&lorawan::parser::FCtrl::new(0x10, false));
+    phy.set_fcnt(0);
+    phy.build(&[], [], &get_key().into(), &get_key().into(), &DefaultFactory).unwrap().len()
+}
+
+#[tokio::test]
+async fn test_frame_pending_policy() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    async_device.set_frame_pending_policy(FramePendingPolicy::Poll);
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_frame_pending).await;
+    // An empty uplink is sent right away to fetch the pending downlink
+    tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+    timer.fire_most_recent().await;
+    let mut uplink = radio.get_last_uplink().await;
+    let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() else {
+        panic!("Did not decode PhyPayload::Data!");
+    };
+    assert_eq!(data.f_port(), None);
+    assert_eq!(data.fhdr().fcnt(), 1);
+    radio.handle_timeout().await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+
+    let (async_device, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(0))));
+    assert!(!async_device.is_frame_pending());
+}
+
+/// Answer every uplink with a downlink announcing more downlinks with the FPending bit, carrying
+/// data on FPort 3 if `DATA`
+fn handle_data_uplink_with_endless_frame_pending<const DATA: bool>(
+    uplink: Option<Uplink>,
+    _config: RfConfig,
+    rx_buffer: &mut [u8],
+) -> usize {
+    let mut uplink = uplink.unwrap();
+    let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() else {
+        panic!("Did not decode PhyPayload::Data!");
+    };
+    let fcnt = data.fhdr().fcnt() as u32;
+    let mut phy = lorawan::creator::DataPayloadCreator::new(rx_buffer).unwrap();
+    phy.set_dev_addr(&[0; 4]);
+    phy.set_uplink(false);
+    phy.set_fctrl(&lorawan::parser::FCtrl::new(0x10, false));
+    phy.set_fcnt(fcnt);
+    let payload: &[u8] = if DATA {
+        phy.set_f_port(3);
+        &[1]
+    } else {
+        &[]
+    };
+    phy.build(payload, [], &get_key().into(), &get_key().into(), &DefaultFactory).unwrap().len()
+}
+
+#[tokio::test]
+async fn test_frame_pending_poll_bound() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    async_device.set_frame_pending_policy(FramePendingPolicy::Poll);
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_endless_frame_pending::<false>).await;
+    for _ in 0..MAX_FRAME_PENDING_POLLS {
+        tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+        timer.fire_most_recent().await;
+        radio.handle_rxtx(handle_data_uplink_with_endless_frame_pending::<false>).await;
+    }
+
+    // The device gives up polling although the network keeps announcing downlinks
+    let (async_device, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(0))));
+    assert!(async_device.is_frame_pending());
+    assert_eq!(async_device.mac.get_fcnt_up(), Some(1 + MAX_FRAME_PENDING_POLLS as u32));
+}
+
+#[cfg(not(feature = "alloc"))]
+#[tokio::test]
+async fn test_frame_pending_poll_stops_when_full() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    async_device.set_frame_pending_policy(FramePendingPolicy::Poll);
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_endless_frame_pending::<true>).await;
+    // The downlink buffer of the test device holds 4 downlinks
+    for _ in 0..3 {
+        tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+        timer.fire_most_recent().await;
+        radio.handle_rxtx(handle_data_uplink_with_endless_frame_pending::<true>).await;
+    }
+
+    let (mut async_device, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(0))));
+    assert!(async_device.is_frame_pending());
+    for _ in 0..4 {
+        assert_eq!(async_device.take_downlink().unwrap().fport, 3);
+    }
+    assert!(async_device.take_downlink().is_none());
+}
+
+#[tokio::test]
+async fn test_range_test() {
+    use range_test::{RangeTest, RangeTestStats};
+
+    let (radio, timer, mut async_device) = setup_with_session();
+    async_device.set_datarate(DR::_3);
+    async_device.set_adr(true);
+    let test = RangeTest {
+        fport: 10,
+        datarate: DR::_1,
+        max_eirp: Some(14),
+        interval_ms: 10_000,
+        confirmed: false,
+    };
+    let async_device = tokio::spawn(async move {
+        let mut stats = RangeTestStats { sequence: 7, ..Default::default() };
+        let response = async_device.range_test(&test, &mut stats, 2).await;
+        (async_device, stats, response)
+    });
+    // First uplink is echoed in RX1
+    timer.fire_most_recent().await;
+    let mut uplink = radio.get_last_uplink().await;
+    assert_eq!(uplink.get_tx_config().pw, 14);
+    let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() else {
+        panic!("Did not decode PhyPayload::Data!");
+    };
+    assert_eq!(data.f_port(), Some(10));
+    radio.handle_rxtx(handle_data_uplink_with_echo).await;
+    // Interval, once the downlink has been processed
+    tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+    timer.fire_most_recent().await;
+    // Second uplink is lost
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+
+    let (mut async_device, stats, response) = async_device.await.unwrap();
+    assert!(response.is_ok());
+    assert_eq!(
+        stats,
+        RangeTestStats { sequence: 9, sent: 2, downlinks: 1, echoes: 1, bad_echoes: 0 }
+    );
+    assert_eq!(stats.downlink_rate_percent(), Some(50));
+    assert!(async_device.take_downlink().is_none());
+    // settings are restored
+    assert_eq!(async_device.get_datarate(), DR::_3);
+    assert!(async_device.get_adr());
+    assert_eq!(async_device.get_max_eirp(), None);
+}
+
+/// Package on FPort 10 echoing the downlinks it received.
+#[derive(Default)]
+struct EchoPackage(std::vec::Vec<u8>);
+
+impl package::Package for EchoPackage {
+    fn port(&self) -> u8 {
+        10
+    }
+
+    fn handle_downlink(&mut self, data: &[u8]) {
+        self.0.extend_from_slice(data);
+    }
+
+    fn poll_uplink(&mut self, buf: &mut [u8]) -> Option<package::PackageUplink> {
+        (!self.0.is_empty()).then(|| {
+            buf[..self.0.len()].copy_from_slice(&self.0);
+            package::PackageUplink { len: self.0.len(), confirmed: false, fport: None }
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_package() {
+    let (radio, timer, async_device) = setup_with_session();
+    let mut async_device = async_device.with_packages(EchoPackage::default());
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 10, false).await;
+        (async_device, response)
+    });
+    // The application uplink is echoed in RX1
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_echo).await;
+    // The package answers with an uplink on its port
+    tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+    timer.fire_most_recent().await;
+    let mut uplink = radio.get_last_uplink().await;
+    let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() else {
+        panic!("Did not decode PhyPayload::Data!");
+    };
+    assert_eq!(data.f_port(), Some(10));
+    assert_eq!(data.fhdr().fcnt(), 1);
+    radio.handle_timeout().await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+
+    let (mut async_device, response) = async_device.await.unwrap();
+    // the downlink was consumed by the package
+    assert!(matches!(response, Ok(SendResponse::RxComplete)));
+    assert!(async_device.take_downlink().is_none());
+    assert_eq!(async_device.packages().0, [1, 2, 3]);
+    assert!(async_device.take_stack_error().is_none());
+}
+
+#[tokio::test]
+async fn test_package_uplink_error() {
+    let (radio, timer, async_device) = setup_with_session();
+    let mut async_device = async_device.with_packages(EchoPackage::default());
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 10, false).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_echo).await;
+    // The uplink of the package fails
+    radio.fail_tx(1).await;
+
+    let (mut async_device, response) = async_device.await.unwrap();
+    // the failure doesn't turn into an error of the application uplink
+    assert!(matches!(response, Ok(SendResponse::RxComplete)));
+    assert!(matches!(async_device.take_stack_error(), Some(Error::Radio(_))));
+}
+
+#[test]
+#[should_panic(expected = "FPort 10 is handled by two packages")]
+fn test_package_port_conflict() {
+    let (_radio, _timer, async_device) = setup_with_session();
+    let _ = async_device.with_packages((EchoPackage::default(), EchoPackage::default()));
+}
+
+#[tokio::test]
+async fn test_send_recv() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    assert_eq!(async_device.enqueue(&[0; 243], 2), Err(send_ext::EnqueueError::PayloadTooLarge));
+    assert!(async_device.enqueue(&[4, 5], 2).is_ok());
+    let async_device = tokio::spawn(async move {
+        let reply = async_device.send_recv(&[1, 2, 3], 3, 60_000).await;
+        (async_device, reply)
+    });
+    // The request is answered in RX1
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_echo).await;
+    // The queued uplink follows
+    tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+    timer.fire_most_recent().await;
+    let mut uplink = radio.get_last_uplink().await;
+    let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() else {
+        panic!("Did not decode PhyPayload::Data!");
+    };
+    assert_eq!(data.f_port(), Some(2));
+    radio.handle_timeout().await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+
+    let (mut async_device, reply) = async_device.await.unwrap();
+    let reply = reply.unwrap().unwrap();
+    assert_eq!(reply.fport, 3);
+    assert_eq!(reply.data, [1, 2, 3]);
+    assert_eq!(async_device.queued_uplinks(), 0);
+    assert!(async_device.take_downlink().is_none());
+}
+
+#[tokio::test]
+async fn test_queue_error_kept_apart() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    assert!(async_device.enqueue(&[4, 5], 2).is_ok());
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<0, 0>).await;
+    // The queued uplink fails
+    radio.fail_tx(1).await;
+
+    let (mut async_device, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(0))));
+    assert!(matches!(async_device.take_stack_error(), Some(Error::Radio(_))));
+    assert_eq!(async_device.queued_uplinks(), 0);
+}
+
+#[tokio::test]
+async fn test_queue_waits_for_duty_cycle() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    async_device.set_duty_cycle_limit(Some(100));
+    assert_eq!(async_device.next_queued_uplink_ms(), None);
+    assert!(async_device.enqueue(&[4, 5], 2).is_ok());
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<0, 0>).await;
+
+    // send doesn't wait for the off time of its uplink to send the queued one
+    let (async_device, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(0))));
+    assert_eq!(async_device.queued_uplinks(), 1);
+    assert!(async_device.next_queued_uplink_ms().unwrap() > 0);
+    assert_eq!(timer.get_armed_count().await, 1);
+}
+
+#[tokio::test]
+async fn test_payload_too_large() {
+    let (_radio, _timer, mut async_device) = setup_with_session();
+    let max_len = async_device.max_payload_len();
+    assert!(max_len > 0);
+    let data = [0; 256];
+    let response = async_device.send(&data[..max_len + 1], 3, false).await;
+    assert!(matches!(
+        response,
+        Err(Error::Mac(mac::Error::PayloadTooLarge { max_len: len })) if len == max_len
+    ));
+    assert_eq!(async_device.get_session().unwrap().fcnt_up, 0);
+}
+
+#[tokio::test]
+async fn test_reserved_fport() {
+    let (_radio, _timer, mut async_device) = setup_with_session();
+    let response = async_device.send(&[1, 2, 3], 224, false).await;
+    assert!(matches!(response, Err(Error::Mac(mac::Error::ReservedFPort { fport: 224 }))));
+    let response = async_device.send(&[1, 2, 3], 0, false).await;
+    assert!(matches!(response, Err(Error::Mac(mac::Error::ReservedFPort { fport: 0 }))));
+    assert_eq!(async_device.enqueue(&[1], 255), Err(send_ext::EnqueueError::ReservedFPort));
+    assert_eq!(async_device.get_session().unwrap().fcnt_up, 0);
+
+    async_device.set_fport_policy(mac::FPortPolicy::AllowReserved);
+    assert_eq!(async_device.enqueue(&[1], 255), Ok(()));
+}
+
+#[tokio::test]
+async fn test_send_uplink() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    let async_device = tokio::spawn(async move {
+        let uplink = crate::Uplink::builder(3).payload(&[1, 2, 3]).build().unwrap();
+        let response = async_device.send_uplink(&uplink).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<0, 0>).await;
+
+    let (async_device, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(0))));
+    let report = async_device.last_uplink_report().unwrap();
+    assert_eq!(report.fport, Some(3));
+}
+
+#[cfg(feature = "embassy-sync")]
+#[tokio::test]
+async fn test_channels() {
+    use channels::{DeviceEvent, Sink};
+
+    struct Collect<T>(std::sync::Mutex<std::vec::Vec<T>>);
+
+    impl<T> Sink<T> for Collect<T> {
+        fn try_send(&self, message: T) -> Result<(), T> {
+            self.0.lock().unwrap().push(message);
+            Ok(())
+        }
+    }
+
+    let downlinks = Box::leak(Box::new(Collect(std::sync::Mutex::new(std::vec::Vec::new()))));
+    let events = Box::leak(Box::new(Collect(std::sync::Mutex::new(std::vec::Vec::new()))));
+    let (radio, timer, mut async_device) = setup();
+    async_device.set_downlink_channel(Some(downlinks));
+    async_device.set_event_channel(Some(events));
+    let async_device = tokio::spawn(async move {
+        let join_mode = JoinMode::ABP {
+            nwkskey: get_key().into(),
+            appskey: get_key().into(),
+            devaddr: get_dev_addr(),
+        };
+        async_device.join(&join_mode).await.unwrap();
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_echo).await;
+
+    let (mut async_device, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(_))));
+    assert!(async_device.take_downlink().is_none());
+    let downlinks = downlinks.0.lock().unwrap();
+    assert_eq!(downlinks.len(), 1);
+    assert_eq!(downlinks[0].data, [1, 2, 3]);
+    assert_eq!(*events.0.lock().unwrap(), [DeviceEvent::Joined]);
+}
+
+#[tokio::test]
+async fn test_uplink_spacing() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    async_device.set_uplink_spacing(Some(1_000));
+    assert_eq!(async_device.get_uplink_spacing(), Some(1_000));
+    // the first uplink is not delayed
+    assert_eq!(async_device.next_uplink_delay_ms(), 0);
+
+    let mut armed_count = 0;
+    for fcnt in 0..2u16 {
+        let task = tokio::spawn(async move {
+            let response = async_device.send(&[1, 2, 3], 3, false).await;
+            (async_device, response)
+        });
+        if fcnt > 0 {
+            // the uplink waits for the spacing before its transmission
+            timer.fire_most_recent().await;
+            armed_count += 1;
+            assert_eq!(timer.get_armed_count().await, armed_count);
+        }
+        // RX1 and RX2
+        timer.fire_most_recent().await;
+        let mut uplink = radio.get_last_uplink().await;
+        let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() else {
+            panic!("Did not decode PhyPayload::Data!");
+        };
+        assert_eq!(data.fhdr().fcnt(), fcnt);
+        radio.handle_timeout().await;
+        timer.fire_most_recent().await;
+        radio.handle_timeout().await;
+        armed_count += 2;
+
+        let (device, response) = task.await.unwrap();
+        assert!(matches!(response, Ok(SendResponse::RxComplete)));
+        async_device = device;
+    }
+    // without a timer which tells the elapsed time, the whole slack is remaining
+    assert_eq!(async_device.next_uplink_delay_ms(), 1_000);
+    assert_eq!(timer.get_armed_count().await, armed_count);
+}
+
+#[tokio::test]
+async fn test_max_eirp_limits_tx_power() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    async_device.set_max_eirp(Some(14));
+
+    let async_device = tokio::spawn(async move { async_device.send(&[1, 2, 3], 3, false).await });
+    // Trigger beginning of RX1
+    timer.fire_most_recent().await;
+    assert_eq!(radio.get_last_uplink().await.get_tx_config().pw, 14);
+    // Trigger end of RX1
+    radio.handle_timeout().await;
+    // Trigger start of RX2
+    timer.fire_most_recent().await;
+    // Trigger end of RX2
+    radio.handle_timeout().await;
+
+    match async_device.await.unwrap() {
+        Ok(SendResponse::RxComplete) => (),
+        _ => panic!(),
+    }
+}
+
+#[tokio::test]
+async fn test_uplink_report() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    let datarate = async_device.get_datarate();
+
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send_with_report(&[1, 2, 3], 3, false).await;
+        (response, async_device)
+    });
+    // Trigger beginning of RX1
+    timer.fire_most_recent().await;
+    let tx_config = radio.get_last_uplink().await.get_tx_config();
+    // Trigger end of RX1
+    radio.handle_timeout().await;
+    // Trigger start of RX2
+    timer.fire_most_recent().await;
+    // Trigger end of RX2
+    radio.handle_timeout().await;
+
+    let (response, async_device) = async_device.await.unwrap();
+    let (response, report) = response.unwrap();
+    assert!(matches!(response, SendResponse::RxComplete));
+    assert_eq!(report.fport, Some(3));
+    assert_eq!(report.rf, tx_config.rf);
+    assert_eq!(report.datarate, datarate);
+    assert_eq!(report.power, tx_config.pw);
+    // 3 bytes of payload in a 16 byte frame
+    assert_eq!(report.time_on_air_us, tx_config.rf.bb.time_on_air_us(Some(8), true, 16));
+    assert_eq!(report.retries, 0);
+    // the test timer doesn't tell the elapsed time
+    assert_eq!((report.tx_start_ms, report.tx_done_ms), (None, None));
+    assert_eq!(async_device.last_uplink_report(), Some(report));
+}
+
+#[tokio::test]
+async fn test_radio_error_propagated() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    let fcnt_up = async_device.get_session().unwrap().fcnt_up;
+
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+        (response, async_device)
+    });
+    // Trigger beginning of RX1
+    timer.fire_most_recent().await;
+    radio.handle_error().await;
+
+    let (response, mut async_device) = async_device.await.unwrap();
+    assert!(matches!(response, Err(Error::Radio("RX failed"))));
+    // the uplink has been sent, so its frame counter is used up
+    assert_eq!(async_device.get_session().unwrap().fcnt_up, fcnt_up + 1);
+}
+
+#[tokio::test]
+async fn test_radio_error_recovered() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    async_device
+        .set_radio_error_policy(RadioErrorPolicy::Recover { max_retries: 1, retry_delay_ms: 1000 });
+    let fcnt_up = async_device.get_session().unwrap().fcnt_up;
+    radio.fail_tx(1).await;
 
-    // Trigger beginning of RX1
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+        (response, async_device)
+    });
+    // Trigger the retry of the failed transmission
     timer.fire_most_recent().await;
-    // Trigger handling of JoinAccept
-    radio.handle_rxtx(handle_join_request::<3>).await;
+    // Trigger beginning of RX1, which fails and is skipped
+    timer.fire_most_recent().await;
+    radio.handle_error().await;
+    // Trigger start of RX2
+    timer.fire_most_recent().await;
+    // Trigger end of RX2
+    radio.handle_timeout().await;
 
-    // Await the device to return and verify state
-    if let Ok(JoinResponse::JoinSuccess) = async_device.await.unwrap() {
-        assert_eq!(1, timer.get_armed_count().await);
-    } else {
-        panic!();
-    }
+    let (response, mut async_device) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::RxComplete)));
+    assert_eq!(async_device.get_session().unwrap().fcnt_up, fcnt_up + 1);
+    assert_eq!(timer.get_armed_count().await, 3);
+
+    // the error is returned once the retries are used up
+    radio.fail_tx(2).await;
+    let async_device = tokio::spawn(async move { async_device.send(&[1, 2, 3], 3, false).await });
+    timer.fire_most_recent().await;
+    assert!(matches!(async_device.await.unwrap(), Err(Error::Radio("TX failed"))));
 }
 
 #[tokio::test]
-async fn test_join_rx2() {
+async fn test_dev_nonce_exhaustion() {
     let (radio, timer, mut async_device) = setup();
-    // Run the device
-    let async_device =
-        tokio::spawn(async move { async_device.join(&get_otaa_credentials()).await });
-
+    async_device.set_dev_nonce_counter(Some(0xffff));
+    let async_device = tokio::spawn(async move {
+        let response = async_device.join(&get_otaa_credentials()).await;
+        (async_device, response)
+    });
     // Trigger beginning of RX1
     timer.fire_most_recent().await;
-    // Trigger end of RX1
-    radio.handle_timeout().await;
-    // Trigger start of RX2
-    timer.fire_most_recent().await;
-    // Pass the join request handler
-    radio.handle_rxtx(handle_join_request::<4>).await;
+    radio.handle_rxtx(handle_join_request::<3>).await;
 
-    // Await the device to return and verify state
-    if async_device.await.unwrap().is_ok() {
-        assert_eq!(2, timer.get_armed_count().await);
-    } else {
-        panic!();
-    }
+    let (mut async_device, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(JoinResponse::JoinSuccess)));
+    assert_eq!(
+        async_device.take_security_event(),
+        Some(mac::SecurityEvent::DevNonceLow { remaining: 0 })
+    );
+    assert_eq!(async_device.dev_nonces_remaining(), Some(0));
+    assert!(matches!(
+        async_device.join(&get_otaa_credentials()).await,
+        Err(Error::Mac(mac::Error::DevNonceExhausted))
+    ));
 }
 
 #[tokio::test]
-async fn test_no_join_accept() {
+async fn test_join_abp_with_fcnt() {
     let (radio, timer, mut async_device) = setup();
-    // Run the device
-    let async_device =
-        tokio::spawn(async move { async_device.join(&get_otaa_credentials()).await });
+    let join = |device: &mut Device, devaddr, fcnt_up, fcnt_down| {
+        device.join_abp_with_fcnt(get_key().into(), get_key().into(), devaddr, fcnt_up, fcnt_down)
+    };
+    assert!(matches!(
+        join(&mut async_device, get_dev_addr(), u32::MAX, 0),
+        Err(Error::Mac(mac::Error::FcntExhausted))
+    ));
+    assert!(async_device.get_session().is_none());
+    join(&mut async_device, get_dev_addr(), 0x1_0005, 20).unwrap();
 
-    // Trigger beginning of RX1
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+        (async_device, response)
+    });
     timer.fire_most_recent().await;
-    // Trigger end of RX1
+    let mut uplink = radio.get_last_uplink().await;
+    let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() else {
+        panic!("Did not decode PhyPayload::Data!");
+    };
+    assert_eq!(data.fhdr().fcnt(), 5);
+    assert!(data.validate_mic(&get_key().into(), 0x1_0005, &DefaultFactory));
     radio.handle_timeout().await;
-    // Trigger start of RX2
     timer.fire_most_recent().await;
-    // Trigger end of RX2
     radio.handle_timeout().await;
 
-    // Await the device to return and verify state
-    let response = async_device.await.unwrap();
-    if let Ok(JoinResponse::NoJoinAccept) = response {
-        assert_eq!(2, timer.get_armed_count().await);
-    } else {
-        panic!("Unexpected response: {response:?}");
-    }
+    let (mut async_device, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::RxComplete)));
+    // the counters of the session with the same DevAddr can't be rewound
+    assert!(matches!(
+        join(&mut async_device, get_dev_addr(), 0x1_0005, 20),
+        Err(Error::Mac(mac::Error::FcntRewound { fcnt_up: 0x1_0006, fcnt_down: 20 }))
+    ));
+    assert!(matches!(
+        join(&mut async_device, get_dev_addr(), 0x1_0006, 19),
+        Err(Error::Mac(mac::Error::FcntRewound { .. }))
+    ));
+    join(&mut async_device, get_dev_addr(), 0x1_0006, 20).unwrap();
+    // a new DevAddr starts a new session
+    join(&mut async_device, crate::DevAddr::from(1), 0, 0).unwrap();
+    assert_eq!(async_device.get_session().unwrap().fcnt_up, 0);
 }
 
 #[tokio::test]
-async fn test_unconfirmed_uplink_no_downlink() {
+async fn test_battery_policy_when_low() {
     let (radio, timer, mut async_device) = setup_with_session();
-    let send_await_complete = Arc::new(Mutex::new(false));
-
-    // Run the device
-    let complete = send_await_complete.clone();
-    let async_device = tokio::spawn(async move {
-        let response = async_device.send(&[1, 2, 3], 3, false).await;
+    async_device.set_battery_policy(Some(mac::BatteryPolicy {
+        low: 50,
+        recovered: 100,
+        min_data_rate: DR::_3,
+        max_eirp: 10,
+    }));
+    async_device.set_battery_level(mac::BatteryLevel::Level(30));
+    assert!(async_device.is_battery_low());
 
-        let mut complete = complete.lock().await;
-        *complete = true;
-        response
-    });
+    let async_device = tokio::spawn(async move { async_device.send(&[1, 2, 3], 3, false).await });
     // Trigger beginning of RX1
     timer.fire_most_recent().await;
-    assert!(!*send_await_complete.lock().await);
+    let tx_config = radio.get_last_uplink().await.get_tx_config();
+    assert_eq!(tx_config.pw, 10);
+    assert_eq!(tx_config.rf.bb.sf, lora_modulation::SpreadingFactor::_7);
     // Trigger end of RX1
     radio.handle_timeout().await;
     // Trigger start of RX2
     timer.fire_most_recent().await;
-    assert!(!*send_await_complete.lock().await);
     // Trigger end of RX2
     radio.handle_timeout().await;
 
@@ -128,7 +1084,6 @@ async fn test_unconfirmed_uplink_no_downlink() {
         Ok(SendResponse::RxComplete) => (),
         _ => panic!(),
     }
-    assert!(*send_await_complete.lock().await);
 }
 
 #[tokio::test]
@@ -225,6 +1180,377 @@ async fn test_confirmed_uplink_with_ack_rx2() {
     }
 }
 
+#[tokio::test]
+async fn test_late_downlink_window() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    async_device.set_late_downlink_window(Some(2000));
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, true).await;
+        (async_device, response)
+    });
+    // RX1 and RX2 pass without a downlink
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    // Trigger start of the late window
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<0, 0>).await;
+
+    let (async_device, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(_))));
+    assert_eq!(async_device.get_late_downlink_window(), Some(2000));
+}
+
+#[tokio::test]
+async fn test_rx_window_hook() {
+    static EVENTS: std::sync::Mutex<std::vec::Vec<RxWindowEvent>> =
+        std::sync::Mutex::new(std::vec::Vec::new());
+    fn hook(event: RxWindowEvent) {
+        EVENTS.lock().unwrap().push(event);
+    }
+
+    let (radio, timer, mut async_device) = setup_with_session();
+    async_device.set_late_downlink_window(Some(2000));
+    async_device.set_rx_window_hook(Some(hook));
+    let async_device = tokio::spawn(async move { async_device.send(&[1, 2, 3], 3, true).await });
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<0, 0>).await;
+    assert!(matches!(async_device.await.unwrap(), Ok(SendResponse::DownlinkReceived(_))));
+
+    use RxWindowEvent::{Closed, Opening};
+    assert_eq!(
+        *EVENTS.lock().unwrap(),
+        [
+            Opening(RxWindow::Rx1),
+            Closed(RxWindow::Rx1),
+            Opening(RxWindow::Rx2),
+            Closed(RxWindow::Rx2),
+            Opening(RxWindow::Late),
+            Closed(RxWindow::Late),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_immediate_ack_for_confirmed_downlink() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    async_device.set_ack_policy(AckPolicy::Immediate);
+
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, true).await;
+        (async_device, response)
+    });
+    // Trigger beginning of RX1
+    timer.fire_most_recent().await;
+    // Send a confirmed downlink
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<0, 0>).await;
+
+    // The device immediately sends an empty uplink acknowledging the downlink
+    tokio::time::sleep(tokio::time::Duration::from_millis(5)).await;
+    timer.fire_most_recent().await;
+    let mut uplink = radio.get_last_uplink().await;
+    use lorawan::parser::{DataHeader, DataPayload, PhyPayload};
+    if let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() {
+        assert!(data.fhdr().fctrl().ack());
+        assert_eq!(data.fhdr().fcnt(), 1);
+        assert_eq!(data.f_port(), None);
+    } else {
+        panic!("Unable to parse data uplink");
+    }
+    radio.handle_timeout().await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+
+    let (device, response) = async_device.await.unwrap();
+    match response {
+        Ok(SendResponse::DownlinkReceived(0)) => (),
+        _ => panic!(),
+    }
+    assert!(!device.is_ack_pending());
+}
+
+#[tokio::test]
+async fn test_immediate_ack_failure_keeps_downlink() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    async_device.set_ack_policy(AckPolicy::Immediate);
+
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, true).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    // The transmission of the ACK uplink fails
+    radio.fail_tx(1).await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<0, 0>).await;
+
+    let (mut device, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(0))));
+    assert!(matches!(device.take_stack_error(), Some(Error::Radio(_))));
+    assert!(device.take_stack_error().is_none());
+}
+
+#[tokio::test]
+async fn test_immediate_ack_respects_duty_cycle() {
+    let (radio, timer, mut async_device) = setup_with_session();
+    async_device.set_ack_policy(AckPolicy::Immediate);
+    async_device.set_duty_cycle_limit(Some(100));
+
+    let async_device = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, true).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<0, 0>).await;
+    // The ACK is delayed by the off time of the first uplink
+    tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+    timer.fire_most_recent().await;
+    timer.fire_most_recent().await;
+    let mut uplink = radio.get_last_uplink().await;
+    let PhyPayload::Data(DataPayload::Encrypted(data)) = uplink.get_payload() else {
+        panic!("Unable to parse data uplink");
+    };
+    assert!(data.fhdr().fctrl().ack());
+    assert_eq!(data.fhdr().fcnt(), 1);
+    radio.handle_timeout().await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+
+    let (device, response) = async_device.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(0))));
+    assert!(!device.is_ack_pending());
+    assert_eq!(device.pending_stack_uplink(), None);
+    // RX1 of the uplink, duty cycle delay, RX1 and RX2 of the ACK
+    assert_eq!(timer.get_armed_count().await, 4);
+}
+
+#[tokio::test]
+async fn test_fcnt_down_rollover() {
+    let (radio, timer, mut async_device) = setup_with_fcnt(0, 0x1_FFFF);
+    let async_device = tokio::spawn(async move { async_device.send(&[1, 2, 3], 3, false).await });
+    // Trigger beginning of RX1
+    timer.fire_most_recent().await;
+    // Only the 16 LSBs (0x0001) are transmitted
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<0, 0x2_0001>).await;
+    match async_device.await.unwrap() {
+        Ok(SendResponse::DownlinkReceived(0x2_0001)) => (),
+        r => panic!("Unexpected response: {r:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_replayed_downlink_dropped() {
+    let (radio, timer, mut async_device) = setup_with_fcnt(0, 5);
+    let task = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+        (async_device, response)
+    });
+    // Trigger beginning of RX1
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<0, 5>).await;
+    // Trigger start of RX2
+    tokio::time::sleep(tokio::time::Duration::from_millis(5)).await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    let (mut device, response) = task.await.unwrap();
+    match response {
+        Ok(SendResponse::RxComplete) => (),
+        r => panic!("Unexpected response: {r:?}"),
+    }
+    assert_eq!(device.get_security_counters().replayed_downlinks, 1);
+    assert_eq!(
+        device.take_security_event(),
+        Some(mac::SecurityEvent::ReplayedDownlink { fcnt: 5 })
+    );
+    assert_eq!(device.take_security_event(), None);
+}
+
+#[tokio::test]
+async fn test_replayed_first_downlink_dropped() {
+    let (radio, timer, async_device) = setup_with_session();
+    let task = tokio::spawn(async move {
+        let mut device = async_device;
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<0, 0>).await;
+    let (mut device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(0))));
+
+    // The same downlink is replayed after the next uplink
+    let task = tokio::spawn(async move {
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<1, 0>).await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(5)).await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    let (mut device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::RxComplete)));
+    assert_eq!(
+        device.take_security_event(),
+        Some(mac::SecurityEvent::ReplayedDownlink { fcnt: 0 })
+    );
+}
+
+#[tokio::test]
+async fn test_fcnt_gap_policy() {
+    let (radio, timer, mut async_device) = setup_with_fcnt(0, 5);
+    let task = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<0, 20_005>).await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(5)).await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    let (mut device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::RxComplete)));
+    assert_eq!(
+        device.take_security_event(),
+        Some(mac::SecurityEvent::FcntGapExceeded { fcnt: 20_005 })
+    );
+
+    device.set_fcnt_gap_policy(mac::FcntGapPolicy::Accept { max_gap: mac::MAX_FCNT_GAP });
+    let task = tokio::spawn(async move {
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<1, 20_005>).await;
+    let (mut device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(20_005))));
+    // the gap is still reported
+    assert_eq!(
+        device.take_security_event(),
+        Some(mac::SecurityEvent::FcntGapExceeded { fcnt: 20_005 })
+    );
+    assert_eq!(device.get_security_counters().fcnt_gaps_exceeded, 2);
+}
+
+#[tokio::test]
+async fn test_resync_fcnt_down() {
+    let (_radio, _timer, mut async_device) = setup();
+    assert!(matches!(async_device.resync_fcnt_down(), Err(mac::Error::NotJoined)));
+
+    // The network lost the frame counters and starts over
+    let (radio, timer, mut async_device) = setup_with_fcnt(0, 40_000);
+    async_device.resync_fcnt_down().unwrap();
+    assert_eq!(
+        async_device.take_security_event(),
+        Some(mac::SecurityEvent::FcntDownResynced { previous: 40_000 })
+    );
+    let task = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<0, 3>).await;
+    let (mut device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::DownlinkReceived(3))));
+    assert_eq!(device.take_security_event(), None);
+    assert_eq!(device.get_session().unwrap().fcnt_down, 3);
+}
+
+#[tokio::test]
+async fn test_mic_diagnostics() {
+    // The network server uses FCnt 1 while the device expects 0x1_0001
+    let (radio, timer, mut async_device) = setup_with_fcnt(0, 0x1_0000);
+    async_device.set_mic_diagnostics(true);
+    let task = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<0, 1>).await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(5)).await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    let (mut device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::RxComplete)));
+    assert_eq!(device.get_security_counters().mic_failures, 1);
+    assert_eq!(
+        device.take_security_event(),
+        Some(mac::SecurityEvent::MicFailureDiagnosed {
+            fcnt: 0x1_0001,
+            reason: mac::MicFailureReason::FcntOutOfWindow { fcnt: 1 }
+        })
+    );
+    assert_eq!(device.take_security_event(), None);
+}
+
+#[tokio::test]
+async fn test_rx_errors_count_mic_failures_only() {
+    fn rx_errors(device: &Device) -> u16 {
+        device.get_channel_stats().iter().map(|(_, stats)| stats.rx_errors).sum()
+    }
+
+    // A replayed downlink has a valid MIC and says nothing about the channel
+    let (radio, timer, async_device) = setup_with_fcnt(0, 5);
+    let task = tokio::spawn(async move {
+        let mut device = async_device;
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<0, 5>).await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(5)).await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    let (device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::RxComplete)));
+    assert_eq!(device.get_security_counters().replayed_downlinks, 1);
+    assert_eq!(rx_errors(&device), 0);
+
+    // The MIC doesn't verify with the expected frame counter
+    let (radio, timer, async_device) = setup_with_fcnt(0, 0x1_0000);
+    let task = tokio::spawn(async move {
+        let mut device = async_device;
+        let response = device.send(&[1, 2, 3], 3, false).await;
+        (device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_rxtx(handle_data_uplink_with_link_adr_req::<0, 1>).await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(5)).await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    let (device, response) = task.await.unwrap();
+    assert!(matches!(response, Ok(SendResponse::RxComplete)));
+    assert_eq!(rx_errors(&device), 1);
+}
+
+#[tokio::test]
+async fn test_fcnt_up_exhausted_expires_session() {
+    let (radio, timer, mut async_device) = setup_with_fcnt(0xFFFF_FFFF, 0);
+    let task = tokio::spawn(async move {
+        let response = async_device.send(&[1, 2, 3], 3, false).await;
+        (async_device, response)
+    });
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    timer.fire_most_recent().await;
+    radio.handle_timeout().await;
+    let (mut device, response) = task.await.unwrap();
+    match response {
+        Ok(SendResponse::SessionExpired) => (),
+        r => panic!("Unexpected response: {r:?}"),
+    }
+    assert!(device.get_session().is_none());
+    assert_eq!(device.take_security_event(), Some(mac::SecurityEvent::SessionExpired));
+    assert!(matches!(
+        device.send(&[1, 2, 3], 3, false).await,
+        Err(Error::Mac(mac::Error::NotJoined))
+    ));
+}
+
 #[tokio::test]
 async fn test_link_adr_ans() {
     let (radio, timer, mut async_device) = setup_with_session();
@@ -307,3 +1633,12 @@ async fn invalid_maccommands_in_frmpayload() {
         }
     }
 }
+
+#[test]
+fn test_yield_now() {
+    use futures::FutureExt;
+
+    let mut fut = yield_now::yield_now();
+    assert!((&mut fut).now_or_never().is_none());
+    assert!(fut.now_or_never().is_some());
+}
@@ -1,5 +1,5 @@
 use super::*;
-use crate::async_device::radio::{PhyRxTx, RxConfig, RxStatus};
+use crate::async_device::radio::{ChannelActivity, PhyRxTx, RxConfig, RxStatus};
 use std::sync::Arc;
 use tokio::{
     sync::{mpsc, Mutex},
@@ -10,21 +10,35 @@ impl TestRadio {
         let (tx, rx) = mpsc::channel(2);
         let last_rxconfig = Arc::new(Mutex::new(None));
         let last_uplink = Arc::new(Mutex::new(None));
+        let tx_failures = Arc::new(Mutex::new(0));
+        let channel_activity = Arc::new(Mutex::new(None));
         (
             RadioChannel {
                 tx,
                 last_uplink: last_uplink.clone(),
                 last_rxconfig: last_rxconfig.clone(),
+                tx_failures: tx_failures.clone(),
+                channel_activity: channel_activity.clone(),
+            },
+            Self {
+                rx,
+                last_rxconfig,
+                last_uplink,
+                tx_failures,
+                channel_activity,
+                current_config: None,
+                snr: 0,
             },
-            Self { rx, last_rxconfig, last_uplink, current_config: None, snr: 0 },
         )
     }
 
+    #[allow(unused)]
     pub fn set_snr(&mut self, snr: i8) {
         self.snr = snr
     }
 
     /// Return snr in a 6-bit scaled format as in DevStatusAns
+    #[allow(unused)]
     pub fn snr_scaled(&self) -> u8 {
         ((self.snr << 2) as u8) >> 2
     }
@@ -34,16 +48,21 @@ impl TestRadio {
 enum Msg {
     RxTx(RxTxHandler),
     Timeout,
+    Error,
 }
 
 pub struct TestRadio {
     current_config: Option<RxConfig>,
     last_rxconfig: Arc<Mutex<Option<RxConfig>>>,
     last_uplink: Arc<Mutex<Option<Uplink>>>,
+    tx_failures: Arc<Mutex<usize>>,
+    channel_activity: Arc<Mutex<Option<ChannelActivityHandler>>>,
     rx: mpsc::Receiver<Msg>,
     snr: i8,
 }
 
+type ChannelActivityHandler = fn(RfConfig) -> ChannelActivity;
+
 impl PhyRxTx for TestRadio {
     type PhyError = &'static str;
 
@@ -53,6 +72,11 @@ impl PhyRxTx for TestRadio {
 
     async fn tx(&mut self, config: TxConfig, buffer: &[u8]) -> Result<u32, Self::PhyError> {
         let length = buffer.len();
+        let mut tx_failures = self.tx_failures.lock().await;
+        if *tx_failures > 0 {
+            *tx_failures -= 1;
+            return Err("TX failed");
+        }
         // stash the uplink, to be consumed by channel or by rx handler
         let mut last_uplink = self.last_uplink.lock().await;
         *last_uplink = Some(Uplink::new(buffer, config).map_err(|_| "Parse error")?);
@@ -85,6 +109,7 @@ impl PhyRxTx for TestRadio {
                 }
             }
             Msg::Timeout => Err("Unexpected Timeout"),
+            Msg::Error => Err("RX failed"),
         }
     }
     async fn rx_single(&mut self, rx_buf: &mut [u8]) -> Result<RxStatus, Self::PhyError> {
@@ -102,8 +127,16 @@ impl PhyRxTx for TestRadio {
                 }
             }
             Msg::Timeout => Ok(RxStatus::RxTimeout),
+            Msg::Error => Err("RX failed"),
         }
     }
+
+    async fn sample_channel(
+        &mut self,
+        config: RfConfig,
+    ) -> Result<Option<ChannelActivity>, Self::PhyError> {
+        Ok(self.channel_activity.lock().await.map(|handler| handler(config)))
+    }
 }
 
 impl Timings for TestRadio {
@@ -117,6 +150,8 @@ pub struct RadioChannel {
     #[allow(unused)]
     last_rxconfig: Arc<Mutex<Option<RxConfig>>>,
     last_uplink: Arc<Mutex<Option<Uplink>>>,
+    tx_failures: Arc<Mutex<usize>>,
+    channel_activity: Arc<Mutex<Option<ChannelActivityHandler>>>,
     tx: mpsc::Sender<Msg>,
 }
 
@@ -130,6 +165,26 @@ impl RadioChannel {
         self.tx.send(Msg::Timeout).await.unwrap();
     }
 
+    /// Let the ongoing reception fail with a radio error.
+    #[allow(unused)]
+    pub async fn handle_error(&self) {
+        tokio::time::sleep(time::Duration::from_millis(5)).await;
+        self.tx.send(Msg::Error).await.unwrap();
+    }
+
+    /// Let the next `count` transmissions fail with a radio error.
+    #[allow(unused)]
+    pub async fn fail_tx(&self, count: usize) {
+        *self.tx_failures.lock().await = count;
+    }
+
+    /// Let the radio sample channels, reporting the activity returned by the handler.
+    #[allow(unused)]
+    pub async fn set_channel_activity(&self, handler: ChannelActivityHandler) {
+        *self.channel_activity.lock().await = Some(handler);
+    }
+
+    #[allow(unused)]
     pub async fn get_rxconfig(&self) -> Option<RxConfig> {
         let rxconf = self.last_rxconfig.lock().await;
         *rxconf
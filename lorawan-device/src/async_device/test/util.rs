@@ -15,6 +15,8 @@ fn default_session() -> Session {
         devaddr: get_dev_addr(),
         fcnt_up: 0,
         fcnt_down: 0,
+        adr_ack_cnt: 0,
+        downlink_received: false,
         confirmed: false,
         uplink: Default::default(),
         #[cfg(feature = "certification")]
@@ -57,6 +59,10 @@ pub fn setup_with_session() -> (RadioChannel, TimerChannel, Device) {
     setup_internal(Some(default_session()))
 }
 
+pub fn setup_with_fcnt(fcnt_up: u32, fcnt_down: u32) -> (RadioChannel, TimerChannel, Device) {
+    setup_internal(Some(Session { fcnt_up, fcnt_down, ..default_session() }))
+}
+
 /// Handle an uplink and respond with two LinkAdrReq on Port 0
 pub fn handle_class_c_uplink_after_join(
     uplink: Option<Uplink>,
@@ -0,0 +1,34 @@
+//! Cooperative yield points for single-core executors.
+//!
+//! Preparing an uplink (MIC and FRMPayload encryption) and handling a downlink (MIC check,
+//! decryption and MAC command processing) run synchronously and, with software AES over a
+//! maximum-size payload, can take a few milliseconds on small MCUs. The device yields to the
+//! executor right after each of these sections so other tasks get a chance to run before the
+//! next radio operation. Radio payload transfers are a single SPI transaction of at most 255
+//! bytes and are left to the radio driver.
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// Returns `Pending` once (after waking the task) and `Ready` when polled again.
+pub(crate) struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Let the executor run other tasks before continuing.
+pub(crate) fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
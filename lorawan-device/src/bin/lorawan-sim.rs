@@ -0,0 +1,458 @@
+//! Host simulator of a LoRaWAN device: the [`async_device::Device`] runs against a virtual radio
+//! connected to a minimal network server, and frames are decoded and printed as they go over the
+//! air. Useful for exploring the protocol and for support triage without a devkit.
+//!
+//! ```sh
+//! cargo run -p lorawan-device --features std --bin lorawan-sim -- [region]
+//! ```
+//!
+//! Time is simulated: the receive windows open immediately, and the network server answers in
+//! RX1 whenever it has something to send (a JoinAccept, an ACK or a queued downlink).
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::{self, BufRead, Write};
+use std::pin::pin;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
+
+use lorawan::creator::{DataPayloadCreator, JoinAcceptCreator};
+use lorawan::default_crypto::DefaultFactory;
+use lorawan::maccommands::{parse_downlink_mac_commands, parse_uplink_mac_commands};
+use lorawan::parser::{parse, DataHeader, DataPayload, FRMPayload, JoinAcceptPayload, PhyPayload};
+use lorawan_device::async_device::radio::{
+    PhyRxTx, RxConfig, RxQuality, RxStatus, Timer, TxConfig,
+};
+use lorawan_device::async_device::{Device, JoinMode, SendResponse, Timings};
+use lorawan_device::mac::{NetworkCredentials, Session};
+use lorawan_device::{region, AppEui, AppKey, DevEui, Region};
+
+const DEV_EUI: [u8; 8] = [0x70, 0xb3, 0xd5, 0x7e, 0xd0, 0x00, 0x00, 0x01];
+const APP_EUI: [u8; 8] = [0; 8];
+const APP_KEY: [u8; 16] = [
+    0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c,
+];
+const DEV_ADDR: [u8; 4] = [0x26, 0x01, 0x1b, 0xda];
+
+type DownlinkQueue = VecDeque<(u8, Vec<u8>)>;
+
+/// Network server answering the uplinks of a single device.
+struct NetworkServer {
+    credentials: NetworkCredentials,
+    session: Option<Session>,
+    app_nonce: u32,
+    /// Application downlinks queued for the next uplinks.
+    queue: Rc<RefCell<DownlinkQueue>>,
+}
+
+impl NetworkServer {
+    fn new(queue: Rc<RefCell<DownlinkQueue>>) -> Self {
+        Self {
+            credentials: NetworkCredentials::new(
+                AppEui::from(APP_EUI),
+                DevEui::from(DEV_EUI),
+                AppKey::from(APP_KEY),
+            ),
+            session: None,
+            app_nonce: 0,
+            queue,
+        }
+    }
+
+    /// Decode an uplink and build the downlink answering it, if any.
+    fn handle_uplink(&mut self, uplink: &[u8]) -> Option<Vec<u8>> {
+        let mut frame = uplink.to_vec();
+        match parse(&mut frame[..]) {
+            Ok(PhyPayload::JoinRequest(join_request)) => {
+                let mic_ok =
+                    join_request.validate_mic(self.credentials.appkey().inner(), &DefaultFactory);
+                println!(
+                    "  JoinRequest DevEUI {} JoinEUI {} DevNonce {:04x} (MIC {})",
+                    hex(join_request.dev_eui().as_ref()),
+                    hex(join_request.app_eui().as_ref()),
+                    u16::from(join_request.dev_nonce().to_owned()),
+                    flag(mic_ok, "ok", "invalid")
+                );
+                if !mic_ok {
+                    return None;
+                }
+                let dev_nonce = join_request.dev_nonce().to_owned();
+                self.join_accept(dev_nonce)
+            }
+            Ok(PhyPayload::Data(DataPayload::Encrypted(encrypted))) => {
+                let Some(session) = &mut self.session else {
+                    println!("  Data uplink of an unknown device");
+                    return None;
+                };
+                let fcnt = encrypted.fhdr().fcnt() as u32;
+                let Ok(data) = encrypted.decrypt_with_session_keys(
+                    &session.nwkskey,
+                    &session.appskey,
+                    session.fcnt_up.max(fcnt),
+                    &DefaultFactory,
+                ) else {
+                    println!("  Data uplink with invalid MIC");
+                    return None;
+                };
+                let fhdr = data.fhdr();
+                let fctrl = fhdr.fctrl();
+                println!(
+                    "  {} DevAddr {} FCnt {} FPort {:?}{}{}",
+                    flag(data.is_confirmed(), "ConfirmedDataUp", "UnconfirmedDataUp"),
+                    hex(fhdr.dev_addr().as_ref()),
+                    fhdr.fcnt(),
+                    data.f_port(),
+                    flag(fctrl.adr(), " ADR", ""),
+                    flag(fctrl.ack(), " ACK", ""),
+                );
+                for cmd in parse_uplink_mac_commands(fhdr.data()) {
+                    println!("  FOpts {:?}", cmd);
+                }
+                match data.frm_payload() {
+                    FRMPayload::Data(payload) => println!("  FRMPayload {}", hex(payload)),
+                    FRMPayload::MACCommands(cmds) => {
+                        for cmd in parse_uplink_mac_commands(cmds.data()) {
+                            println!("  FRMPayload {:?}", cmd);
+                        }
+                    }
+                    FRMPayload::None => (),
+                }
+                session.fcnt_up = fcnt + 1;
+                let confirmed = data.is_confirmed();
+                if confirmed || !self.queue.borrow().is_empty() {
+                    self.data_downlink(confirmed)
+                } else {
+                    None
+                }
+            }
+            Ok(_) => {
+                println!("  Unexpected frame");
+                None
+            }
+            Err(e) => {
+                println!("  Unparseable frame: {:?}", e);
+                None
+            }
+        }
+    }
+
+    fn join_accept(&mut self, dev_nonce: lorawan::parser::DevNonce<[u8; 2]>) -> Option<Vec<u8>> {
+        self.app_nonce += 1;
+        let mut buffer = [0; 17];
+        let mut creator = JoinAcceptCreator::new(&mut buffer[..]).ok()?;
+        let [app_nonce @ .., _] = self.app_nonce.to_le_bytes();
+        creator.set_app_nonce(&app_nonce);
+        creator.set_net_id(&[0x13, 0, 0]);
+        creator.set_dev_addr(&DEV_ADDR);
+        let join_accept =
+            creator.build(self.credentials.appkey().inner(), &DefaultFactory).ok()?.to_vec();
+
+        let mut copy = join_accept.clone();
+        if let Ok(PhyPayload::JoinAccept(JoinAcceptPayload::Encrypted(encrypted))) =
+            parse(&mut copy[..])
+        {
+            let decrypted = encrypted.decrypt(self.credentials.appkey(), &DefaultFactory);
+            self.session = Some(Session::derive_new(&decrypted, dev_nonce, &self.credentials));
+        }
+        Some(join_accept)
+    }
+
+    fn data_downlink(&mut self, ack: bool) -> Option<Vec<u8>> {
+        let session = self.session.as_mut()?;
+        let (fport, payload) = self.queue.borrow_mut().pop_front().unzip();
+        let mut buffer = [0; 256];
+        let mut creator = DataPayloadCreator::new(&mut buffer[..]).ok()?;
+        creator.set_uplink(false);
+        creator.set_dev_addr(&DEV_ADDR);
+        creator.set_fcnt(session.fcnt_down);
+        if ack {
+            let mut fctrl = lorawan::parser::FCtrl::new(0, false);
+            fctrl.set_ack();
+            creator.set_fctrl(&fctrl);
+        }
+        if let Some(fport) = fport {
+            creator.set_f_port(fport);
+        }
+        let downlink = creator
+            .build(
+                payload.as_deref().unwrap_or_default(),
+                [],
+                &session.nwkskey,
+                &session.appskey,
+                &DefaultFactory,
+            )
+            .ok()?
+            .to_vec();
+        session.fcnt_down += 1;
+        Some(downlink)
+    }
+}
+
+/// Radio which hands the uplinks over to the network server and answers in the first receive
+/// window with its downlink.
+struct VirtualRadio {
+    server: NetworkServer,
+    downlink: Option<Vec<u8>>,
+    rx_config: Option<RxConfig>,
+}
+
+impl PhyRxTx for VirtualRadio {
+    type PhyError = &'static str;
+
+    const MAX_RADIO_POWER: u8 = 14;
+
+    async fn tx(&mut self, config: TxConfig, buf: &[u8]) -> Result<u32, Self::PhyError> {
+        let time_on_air = config.rf.bb.time_on_air_us(Some(8), true, buf.len() as u8) / 1000;
+        println!(
+            "TX {} Hz {:?} {:?} {} dBm, {} bytes, {} ms: {}",
+            config.rf.frequency,
+            config.rf.bb.sf,
+            config.rf.bb.bw,
+            config.pw,
+            buf.len(),
+            time_on_air,
+            hex(buf)
+        );
+        self.downlink = self.server.handle_uplink(buf);
+        Ok(time_on_air)
+    }
+
+    async fn setup_rx(&mut self, config: RxConfig) -> Result<(), Self::PhyError> {
+        self.rx_config = Some(config);
+        Ok(())
+    }
+
+    async fn rx_continuous(
+        &mut self,
+        _rx_buf: &mut [u8],
+    ) -> Result<(usize, RxQuality), Self::PhyError> {
+        // there is no Class C traffic in the simulation
+        core::future::pending().await
+    }
+
+    async fn rx_single(&mut self, buf: &mut [u8]) -> Result<RxStatus, Self::PhyError> {
+        let Some(downlink) = self.downlink.take() else {
+            return Ok(RxStatus::RxTimeout);
+        };
+        if let Some(config) = self.rx_config {
+            println!(
+                "RX {} Hz {:?} {:?}, {} bytes: {}",
+                config.rf.frequency,
+                config.rf.bb.sf,
+                config.rf.bb.bw,
+                downlink.len(),
+                hex(&downlink)
+            );
+        }
+        print_downlink(&downlink);
+        buf[..downlink.len()].copy_from_slice(&downlink);
+        Ok(RxStatus::Rx(downlink.len(), RxQuality::new(-60, 8)))
+    }
+}
+
+impl Timings for VirtualRadio {
+    fn get_rx_window_lead_time_ms(&self) -> u32 {
+        0
+    }
+}
+
+fn print_downlink(downlink: &[u8]) {
+    let mut frame = downlink.to_vec();
+    match parse(&mut frame[..]) {
+        Ok(PhyPayload::JoinAccept(_)) => println!("  JoinAccept DevAddr {}", hex(&DEV_ADDR)),
+        Ok(PhyPayload::Data(DataPayload::Encrypted(data))) => {
+            let fhdr = data.fhdr();
+            println!(
+                "  {} FCnt {} FPort {:?}{}",
+                flag(data.is_confirmed(), "ConfirmedDataDown", "UnconfirmedDataDown"),
+                fhdr.fcnt(),
+                data.f_port(),
+                flag(fhdr.fctrl().ack(), " ACK", ""),
+            );
+            for cmd in parse_downlink_mac_commands(fhdr.data()) {
+                println!("  FOpts {:?}", cmd);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Simulated time, which jumps to the start of the receive windows.
+#[derive(Default)]
+struct VirtualTimer {
+    now: u64,
+    start: u64,
+}
+
+impl Timer for VirtualTimer {
+    fn reset(&mut self) {
+        self.start = self.now;
+    }
+
+    async fn at(&mut self, millis: u64) {
+        self.now = self.now.max(self.start + millis);
+    }
+
+    async fn delay_ms(&mut self, millis: u64) {
+        self.now += millis;
+    }
+
+    fn elapsed_ms(&self) -> Option<u64> {
+        Some(self.now - self.start)
+    }
+}
+
+/// Run a future to completion on the current thread.
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct ThreadWaker(std::thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Arc::new(ThreadWaker(std::thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
+fn flag(value: bool, set: &'static str, unset: &'static str) -> &'static str {
+    if value {
+        set
+    } else {
+        unset
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn parse_region(name: &str) -> Option<Region> {
+    match name.to_ascii_uppercase().as_str() {
+        #[cfg(feature = "region-as923-1")]
+        "AS923-1" => Some(Region::AS923_1),
+        #[cfg(feature = "region-as923-2")]
+        "AS923-2" => Some(Region::AS923_2),
+        #[cfg(feature = "region-as923-3")]
+        "AS923-3" => Some(Region::AS923_3),
+        #[cfg(feature = "region-as923-4")]
+        "AS923-4" => Some(Region::AS923_4),
+        #[cfg(feature = "region-au915")]
+        "AU915" => Some(Region::AU915),
+        #[cfg(feature = "region-eu868")]
+        "EU868" => Some(Region::EU868),
+        #[cfg(feature = "region-eu433")]
+        "EU433" => Some(Region::EU433),
+        #[cfg(feature = "region-in865")]
+        "IN865" => Some(Region::IN865),
+        #[cfg(feature = "region-us915")]
+        "US915" => Some(Region::US915),
+        _ => None,
+    }
+}
+
+/// FPort and payload of `send`, `confirmed` and `downlink` commands.
+fn parse_data(args: &[&str]) -> Option<(u8, Vec<u8>)> {
+    let fport = args.first()?.parse().ok()?;
+    let data = match args.get(1) {
+        Some(data) => parse_hex(data)?,
+        None => Vec::new(),
+    };
+    Some((fport, data))
+}
+
+const HELP: &str = "\
+Commands:
+  join                      OTAA join
+  send <fport> [hex]        unconfirmed uplink
+  confirmed <fport> [hex]   confirmed uplink
+  downlink <fport> [hex]    queue a downlink at the network server
+  session                   show the session of the device
+  quit";
+
+fn main() {
+    let region_name = std::env::args().nth(1).unwrap_or_else(|| "EU868".into());
+    let Some(region) = parse_region(&region_name) else {
+        eprintln!("Unknown or disabled region {}", region_name);
+        std::process::exit(1);
+    };
+    let queue = Rc::new(RefCell::new(DownlinkQueue::new()));
+    let server = NetworkServer::new(queue.clone());
+    let radio = VirtualRadio { server, downlink: None, rx_config: None };
+    let mut device: Device<_, _, _> = Device::new_with_seed(
+        region::Configuration::new(region),
+        radio,
+        VirtualTimer::default(),
+        0x1234_5678,
+    );
+    let join_mode = JoinMode::OTAA {
+        deveui: DevEui::from(DEV_EUI),
+        appeui: AppEui::from(APP_EUI),
+        appkey: AppKey::from(APP_KEY),
+    };
+    println!("LoRaWAN device simulator ({})", region_name.to_ascii_uppercase());
+    println!("DevEUI {} JoinEUI {} AppKey {}", hex(&DEV_EUI), hex(&APP_EUI), hex(&APP_KEY));
+    println!("{}", HELP);
+
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            break;
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else {
+            continue;
+        };
+        match command {
+            "join" => println!("{:?}", block_on(device.join(&join_mode))),
+            "send" | "confirmed" => {
+                let Some((fport, data)) = parse_data(args) else {
+                    println!("Usage: {} <fport> [hex]", command);
+                    continue;
+                };
+                let response = block_on(device.send(&data, fport, command == "confirmed"));
+                println!("{:?}", response);
+                if let Ok(SendResponse::DownlinkReceived(_)) = response {
+                    while let Some(downlink) = device.take_downlink() {
+                        println!("Downlink FPort {}: {}", downlink.fport, hex(&downlink.data));
+                    }
+                }
+            }
+            "downlink" => match parse_data(args) {
+                Some(downlink) => queue.borrow_mut().push_back(downlink),
+                None => println!("Usage: downlink <fport> [hex]"),
+            },
+            "session" => match device.get_session() {
+                Some(session) => println!(
+                    "DevAddr {} FCntUp {} FCntDown {}",
+                    hex(session.devaddr().as_ref()),
+                    session.fcnt_up,
+                    session.fcnt_down
+                ),
+                None => println!("Not joined"),
+            },
+            "help" => println!("{}", HELP),
+            "quit" | "exit" => break,
+            _ => println!("Unknown command {}, try help", command),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! Collections of the devices whose capacity is a matter of configuration rather than protocol
+//! limits: the downlinks waiting for the application and the uplink queue. They are bounded
+//! `heapless` collections, unless the `alloc` feature is enabled, in which case they grow on the
+//! heap, eg: for gateways and simulators which shouldn't be constrained by fixed capacities.
+//! Events waiting for the application are kept in an [`EventQueue`], which is always bounded.
+
+/// Vector of up to `N` items, or any number with the `alloc` feature.
+#[cfg(not(feature = "alloc"))]
+pub(crate) type Vec<T, const N: usize> = heapless::Vec<T, N>;
+#[cfg(feature = "alloc")]
+pub(crate) type Vec<T, const N: usize> = alloc::vec::Vec<T>;
+
+/// Double-ended queue of up to `N` items, or any number with the `alloc` feature.
+#[cfg(not(feature = "alloc"))]
+pub(crate) type Deque<T, const N: usize> = heapless::Deque<T, N>;
+#[cfg(feature = "alloc")]
+pub(crate) type Deque<T, const N: usize> = alloc::collections::VecDeque<T>;
+
+/// Adding to a collection of either backend.
+pub(crate) trait Collection<T> {
+    /// Append the item, handing it back if the collection is full.
+    fn try_push(&mut self, item: T) -> Result<(), T>;
+    /// Whether no further item can be appended.
+    fn is_at_capacity(&self) -> bool;
+}
+
+impl<T, const N: usize> Collection<T> for heapless::Vec<T, N> {
+    fn try_push(&mut self, item: T) -> Result<(), T> {
+        self.push(item)
+    }
+
+    fn is_at_capacity(&self) -> bool {
+        self.is_full()
+    }
+}
+
+impl<T, const N: usize> Collection<T> for heapless::Deque<T, N> {
+    fn try_push(&mut self, item: T) -> Result<(), T> {
+        self.push_back(item)
+    }
+
+    fn is_at_capacity(&self) -> bool {
+        self.is_full()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Collection<T> for alloc::vec::Vec<T> {
+    fn try_push(&mut self, item: T) -> Result<(), T> {
+        self.push(item);
+        Ok(())
+    }
+
+    fn is_at_capacity(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Collection<T> for alloc::collections::VecDeque<T> {
+    fn try_push(&mut self, item: T) -> Result<(), T> {
+        self.push_back(item);
+        Ok(())
+    }
+
+    fn is_at_capacity(&self) -> bool {
+        false
+    }
+}
+
+/// Events waiting to be taken by the application. When full, the oldest event is discarded.
+#[derive(Debug)]
+pub(crate) struct EventQueue<T, const N: usize>(heapless::Deque<T, N>);
+
+impl<T, const N: usize> EventQueue<T, N> {
+    pub(crate) const fn new() -> Self {
+        Self(heapless::Deque::new())
+    }
+
+    pub(crate) fn push(&mut self, event: T) {
+        if self.0.is_full() {
+            self.0.pop_front();
+        }
+        let _ = self.0.push_back(event);
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        self.0.pop_front()
+    }
+}
+
+impl<T, const N: usize> Default for EventQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn capacity() {
+        let mut vec: Vec<u8, 1> = Default::default();
+        let mut deque: Deque<u8, 1> = Default::default();
+        assert_eq!(vec.try_push(1), Ok(()));
+        assert_eq!(deque.try_push(1), Ok(()));
+        if cfg!(feature = "alloc") {
+            assert_eq!(vec.try_push(2), Ok(()));
+            assert_eq!(deque.try_push(2), Ok(()));
+            assert_eq!(vec.len(), 2);
+            assert!(!vec.is_at_capacity() && !deque.is_at_capacity());
+        } else {
+            assert_eq!(vec.try_push(2), Err(2));
+            assert_eq!(deque.try_push(2), Err(2));
+            assert_eq!(vec.len(), 1);
+            assert!(vec.is_at_capacity() && deque.is_at_capacity());
+        }
+    }
+
+    #[test]
+    fn events_drop_oldest() {
+        let mut events: EventQueue<u8, 2> = EventQueue::new();
+        events.push(1);
+        events.push(2);
+        events.push(3);
+        assert_eq!(events.pop(), Some(2));
+        assert_eq!(events.pop(), Some(3));
+        assert_eq!(events.pop(), None);
+    }
+}
@@ -6,12 +6,17 @@
 #![doc = document_features::document_features!()]
 #![doc = include_str!("../README.md")]
 
-// This must go FIRST so that all the other modules see its macros.
-pub(crate) mod fmt;
+// Logging macros (trace!, debug!, info!, warn!, error!), see the `lora-log` crate.
+#[macro_use]
+extern crate lora_log;
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
 
 use core::default::Default;
 use heapless::Vec;
 
+mod collections;
 mod radio;
 
 pub mod mac;
@@ -28,9 +33,15 @@ pub mod async_device;
 pub mod nb_device;
 use nb_device::state::State;
 
+pub mod provisioning;
+
+pub mod uplink;
+pub use uplink::{AppFPort, Uplink};
+
 pub use lorawan::{
     keys::{AppEui, AppKey, AppSKey, CryptoFactory, DevEui, NwkSKey},
     parser::DevAddr,
+    types::DeviceClass,
 };
 
 #[deprecated(since = "0.12.2", note = "Please use `NwkSKey` instead")]
@@ -69,6 +80,28 @@ pub trait Timings {
     fn get_rx_window_duration_ms(&self) -> u32;
 }
 
+/// Class of operation of a device. Class B is not supported: unlike [`DeviceClass`], which is
+/// limited to the classes DeviceModeInd can signal, it can be expressed here to be rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum Class {
+    A,
+    B,
+    C,
+}
+
+impl TryFrom<Class> for DeviceClass {
+    type Error = mac::Error;
+
+    fn try_from(class: Class) -> Result<Self, Self::Error> {
+        match class {
+            Class::A => Ok(DeviceClass::A),
+            Class::B => Err(mac::Error::UnsupportedClass),
+            Class::C => Ok(DeviceClass::C),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Join the network using either OTAA or ABP.
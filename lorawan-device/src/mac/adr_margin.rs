@@ -0,0 +1,112 @@
+//! Confirmation of the link margin before ADR raises the data rate. The SNR of recent downlinks
+//! is compared against the demodulation floor of the spreading factor of the requested data
+//! rate, so that the device does not switch back and forth between data rates on a marginal link.
+use lora_modulation::SpreadingFactor;
+
+/// Largest number of recent downlinks whose SNR is kept.
+pub const MAX_SNR_SAMPLES: usize = 8;
+
+/// SNR margin required before a LinkADRReq raising the data rate is accepted. Otherwise, the data
+/// rate is rejected (the whole LinkADRReq is then not applied, as required by the specification).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct AdrMargin {
+    /// Margin (in dB) of the SNR above the demodulation floor of the requested data rate.
+    pub min_margin_db: i8,
+    /// Number of recent downlinks (at most [`MAX_SNR_SAMPLES`]) which all need to have the margin.
+    pub samples: u8,
+}
+
+impl Default for AdrMargin {
+    fn default() -> Self {
+        Self { min_margin_db: 5, samples: 3 }
+    }
+}
+
+/// Demodulation floor (in tenths of dB) of a spreading factor.
+fn snr_floor(spreading_factor: SpreadingFactor) -> i16 {
+    match spreading_factor {
+        SpreadingFactor::_5 => -25,
+        SpreadingFactor::_6 => -50,
+        SpreadingFactor::_7 => -75,
+        SpreadingFactor::_8 => -100,
+        SpreadingFactor::_9 => -125,
+        SpreadingFactor::_10 => -150,
+        SpreadingFactor::_11 => -175,
+        SpreadingFactor::_12 => -200,
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub(crate) struct SnrHistory {
+    policy: Option<AdrMargin>,
+    snr: [i8; MAX_SNR_SAMPLES],
+    len: u8,
+    next: u8,
+}
+
+impl SnrHistory {
+    pub(crate) fn policy(&self) -> Option<AdrMargin> {
+        self.policy
+    }
+
+    pub(crate) fn set_policy(&mut self, policy: Option<AdrMargin>) {
+        self.policy = policy;
+    }
+
+    pub(crate) fn record(&mut self, snr: i8) {
+        self.snr[self.next as usize] = snr;
+        self.next = (self.next + 1) % MAX_SNR_SAMPLES as u8;
+        self.len = (self.len + 1).min(MAX_SNR_SAMPLES as u8);
+    }
+
+    /// SNR of the recent downlinks, most recent first.
+    pub(crate) fn recent(&self) -> impl Iterator<Item = i8> + '_ {
+        (1..=self.len as usize)
+            .map(|i| self.snr[(self.next as usize + MAX_SNR_SAMPLES - i) % MAX_SNR_SAMPLES])
+    }
+
+    /// Whether the recent downlinks confirm the margin required to use `spreading_factor`.
+    pub(crate) fn allows(&self, spreading_factor: SpreadingFactor) -> bool {
+        let Some(policy) = self.policy else {
+            return true;
+        };
+        let samples = (policy.samples as usize).clamp(1, MAX_SNR_SAMPLES);
+        if (self.len as usize) < samples {
+            return false;
+        }
+        let floor = snr_floor(spreading_factor) + i16::from(policy.min_margin_db) * 10;
+        self.recent().take(samples).all(|snr| i16::from(snr) * 10 >= floor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn margin_over_recent_downlinks() {
+        let mut history = SnrHistory::default();
+        assert!(history.allows(SpreadingFactor::_7));
+
+        history.set_policy(Some(AdrMargin { min_margin_db: 5, samples: 2 }));
+        history.record(-1);
+        // not enough downlinks yet
+        assert!(!history.allows(SpreadingFactor::_7));
+        history.record(-3);
+        // SF7: -7.5 dB + 5 dB
+        assert!(!history.allows(SpreadingFactor::_7));
+        assert!(history.allows(SpreadingFactor::_8));
+        history.record(0);
+        assert!(!history.allows(SpreadingFactor::_7));
+        history.record(-2);
+        assert!(history.allows(SpreadingFactor::_7));
+
+        for snr in 0..10 {
+            history.record(snr);
+        }
+        assert_eq!(history.recent().count(), MAX_SNR_SAMPLES);
+        assert_eq!(history.recent().next(), Some(9));
+    }
+}
@@ -0,0 +1,152 @@
+//! Battery level reported to the network (DevStatusAns) and an optional policy which favours
+//! shorter time on air and lower TX power over the data rate and TX power requested by the network
+//! (ADR) while the battery is low. Low and recovered thresholds differ, so that a battery level
+//! hovering around a single threshold does not flip the policy on every uplink.
+use crate::region;
+use lorawan::types::DR;
+
+/// Battery level, encoded as in DevStatusAns.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum BatteryLevel {
+    /// The device is connected to an external power source.
+    External,
+    /// From 1 (empty) to 254 (full).
+    Level(u8),
+    /// The device is not able to measure the battery level.
+    #[default]
+    Unknown,
+}
+
+impl BatteryLevel {
+    pub(crate) fn dev_status(self) -> u8 {
+        match self {
+            BatteryLevel::External => 0,
+            BatteryLevel::Level(level) => level.clamp(1, 254),
+            BatteryLevel::Unknown => 255,
+        }
+    }
+}
+
+/// Uplink settings applied while the battery is low.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct BatteryPolicy {
+    /// The battery is low once its level drops to or below this level.
+    pub low: u8,
+    /// The battery is no longer low once its level rises to or above this level.
+    pub recovered: u8,
+    /// Data uplinks use at least this data rate, if the region supports it.
+    pub min_data_rate: DR,
+    /// EIRP ceiling (in dBm) of data uplinks.
+    pub max_eirp: i8,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub(crate) struct Battery {
+    level: BatteryLevel,
+    policy: Option<BatteryPolicy>,
+    low: bool,
+}
+
+impl Battery {
+    pub(crate) fn level(&self) -> BatteryLevel {
+        self.level
+    }
+
+    pub(crate) fn set_level(&mut self, level: BatteryLevel) {
+        self.level = level;
+        self.update();
+    }
+
+    pub(crate) fn policy(&self) -> Option<BatteryPolicy> {
+        self.policy
+    }
+
+    pub(crate) fn set_policy(&mut self, policy: Option<BatteryPolicy>) {
+        self.policy = policy;
+        self.low = false;
+        self.update();
+    }
+
+    pub(crate) fn is_low(&self) -> bool {
+        self.low
+    }
+
+    fn update(&mut self) {
+        let Some(policy) = self.policy else {
+            return;
+        };
+        let low = match self.level {
+            BatteryLevel::External => false,
+            BatteryLevel::Level(level) if level <= policy.low => true,
+            BatteryLevel::Level(level) if level >= policy.recovered => false,
+            // between the thresholds or unknown: keep the current state
+            BatteryLevel::Level(_) | BatteryLevel::Unknown => self.low,
+        };
+        if low != self.low {
+            debug!("Battery low: {}", low);
+        }
+        self.low = low;
+    }
+
+    /// The data rate of data uplinks, given the data rate requested by the network.
+    pub(crate) fn data_rate(&self, data_rate: DR, region: &region::Configuration) -> DR {
+        match self.policy {
+            Some(policy)
+                if self.low
+                    && policy.min_data_rate as u8 > data_rate as u8
+                    && region.get_datarate(policy.min_data_rate as u8).is_some() =>
+            {
+                policy.min_data_rate
+            }
+            _ => data_rate,
+        }
+    }
+
+    /// EIRP ceiling (in dBm) of data uplinks.
+    pub(crate) fn max_eirp(&self) -> Option<i8> {
+        self.policy.filter(|_| self.low).map(|policy| policy.max_eirp)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hysteresis() {
+        let region = region::EU868::new_eu868().into();
+        let mut battery = Battery::default();
+        battery.set_level(BatteryLevel::Level(10));
+        assert!(!battery.is_low());
+
+        battery.set_policy(Some(BatteryPolicy {
+            low: 50,
+            recovered: 100,
+            min_data_rate: DR::_4,
+            max_eirp: 8,
+        }));
+        assert!(battery.is_low());
+        assert_eq!(battery.data_rate(DR::_0, &region), DR::_4);
+        assert_eq!(battery.data_rate(DR::_5, &region), DR::_5);
+        assert_eq!(battery.max_eirp(), Some(8));
+
+        battery.set_level(BatteryLevel::Level(80));
+        assert!(battery.is_low());
+        battery.set_level(BatteryLevel::Level(100));
+        assert!(!battery.is_low());
+        assert_eq!(battery.data_rate(DR::_0, &region), DR::_0);
+        assert_eq!(battery.max_eirp(), None);
+        battery.set_level(BatteryLevel::Level(80));
+        assert!(!battery.is_low());
+
+        battery.set_level(BatteryLevel::Level(20));
+        assert!(battery.is_low());
+        battery.set_level(BatteryLevel::External);
+        assert!(!battery.is_low());
+        assert_eq!(battery.level().dev_status(), 0);
+        assert_eq!(BatteryLevel::Level(255).dev_status(), 254);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::mac;
 use crate::radio::RadioBuffer;
 use lorawan::certification::parse_downlink_certification_messages;
+use lorawan::keys::CryptoFactory;
 
 /// Certification protocol uses `fport = 224`
 pub(crate) const CERTIFICATION_PORT: u8 = 224;
@@ -91,9 +92,11 @@ impl Certification {
         CERTIFICATION_PORT == fport
     }
 
-    pub(crate) fn setup_send<const N: usize>(
+    pub(crate) fn setup_send<C: CryptoFactory, const N: usize>(
         &mut self,
+        crypto: &C,
         mut state: &mut mac::State,
+        configuration: &mac::Configuration,
         buf: &mut RadioBuffer<N>,
     ) -> mac::Result<mac::FcntUp> {
         let send_data = mac::SendData {
@@ -102,7 +105,9 @@ impl Certification {
             confirmed: false,
         };
         match &mut state {
-            mac::State::Joined(ref mut session) => Ok(session.prepare_buffer::<N>(&send_data, buf)),
+            mac::State::Joined(ref mut session) => {
+                Ok(session.prepare_buffer(crypto, configuration, &send_data, buf))
+            }
             mac::State::Otaa(_) => Err(mac::Error::NotJoined),
             mac::State::Unjoined => Err(mac::Error::NotJoined),
         }
@@ -0,0 +1,69 @@
+//! DevNonce generation. LoRaWAN 1.0.4 and 1.1 require the DevNonce to be a counter which is never
+//! reused with the same JoinEUI and DevEUI, so once all 65536 values have been used the device
+//! can't join anymore until it is re-provisioned. Wrapping around instead would let the network
+//! reject (or worse, accept replayed) join requests, so the stack refuses to join and warns in
+//! advance via [`SecurityEvent::DevNonceLow`](super::SecurityEvent::DevNonceLow).
+use rand_core::RngCore;
+
+/// Number of remaining DevNonces at or below which every join request is reported with
+/// [`SecurityEvent::DevNonceLow`](super::SecurityEvent::DevNonceLow).
+pub const DEV_NONCE_LOW_THRESHOLD: u32 = 1024;
+
+const DEV_NONCE_COUNT: u32 = 1 << 16;
+
+#[derive(Debug, Default)]
+pub(crate) struct DevNonces {
+    /// The DevNonce of the next join request in counter mode, `DEV_NONCE_COUNT` once exhausted.
+    next: Option<u32>,
+}
+
+impl DevNonces {
+    pub(crate) fn counter(&self) -> Option<u32> {
+        self.next
+    }
+
+    pub(crate) fn set_counter(&mut self, next: Option<u32>) {
+        self.next = next.map(|next| next.min(DEV_NONCE_COUNT));
+    }
+
+    pub(crate) fn remaining(&self) -> Option<u32> {
+        self.next.map(|next| DEV_NONCE_COUNT - next)
+    }
+
+    /// The DevNonce of the next join request, or `None` if the counter is exhausted.
+    pub(crate) fn take<G: RngCore>(&mut self, rng: &mut G) -> Option<u16> {
+        match &mut self.next {
+            None => Some(rng.next_u32() as u16),
+            Some(next) if *next >= DEV_NONCE_COUNT => None,
+            Some(next) => {
+                let dev_nonce = *next as u16;
+                *next += 1;
+                Some(dev_nonce)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counter_exhaustion() {
+        let mut rng = rand::rngs::OsRng;
+        let mut dev_nonces = DevNonces::default();
+        assert!(dev_nonces.take(&mut rng).is_some());
+        assert_eq!(dev_nonces.remaining(), None);
+
+        dev_nonces.set_counter(Some(0xfffe));
+        assert_eq!(dev_nonces.take(&mut rng), Some(0xfffe));
+        assert_eq!(dev_nonces.remaining(), Some(1));
+        assert_eq!(dev_nonces.take(&mut rng), Some(0xffff));
+        assert_eq!(dev_nonces.remaining(), Some(0));
+        assert_eq!(dev_nonces.take(&mut rng), None);
+        assert_eq!(dev_nonces.counter(), Some(0x1_0000));
+
+        dev_nonces.set_counter(Some(u32::MAX));
+        assert_eq!(dev_nonces.counter(), Some(0x1_0000));
+    }
+}
@@ -0,0 +1,159 @@
+//! Periodic session health check: after a number of consecutive uplinks without any downlink,
+//! the next uplink asks the network for a response (LinkCheckReq or a confirmed uplink). When
+//! several of these checks fail in a row, the application is told to rejoin, allowing products to
+//! recover from a session the network has lost.
+use crate::collections::EventQueue;
+
+/// Number of events retained until taken by the application. When full, the oldest event is
+/// discarded.
+const MAX_EVENTS: usize = 4;
+
+/// Configures when the session health is checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct HealthCheck {
+    /// Number of consecutive uplinks without any downlink after which a check is sent.
+    pub uplinks: u16,
+    /// Number of consecutive failed checks after which a rejoin is required.
+    pub max_failed_checks: u8,
+    /// Check by sending the uplink as confirmed instead of adding a LinkCheckReq.
+    pub confirmed: bool,
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        Self { uplinks: 16, max_failed_checks: 3, confirmed: false }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum HealthEvent {
+    /// A health check has been added to an uplink.
+    CheckSent,
+    /// The network responded to the health check.
+    CheckPassed,
+    /// The network did not respond to the health check.
+    CheckFailed { failed_checks: u8 },
+    /// `max_failed_checks` checks failed in a row; the device should join again.
+    RejoinRequired,
+}
+
+/// How the pending uplink needs to be modified for a health check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Check {
+    LinkCheckReq,
+    Confirmed,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Health {
+    policy: Option<HealthCheck>,
+    uplinks_without_downlink: u16,
+    check_pending: bool,
+    failed_checks: u8,
+    events: EventQueue<HealthEvent, MAX_EVENTS>,
+}
+
+impl Health {
+    pub(crate) fn policy(&self) -> Option<HealthCheck> {
+        self.policy
+    }
+
+    pub(crate) fn set_policy(&mut self, policy: Option<HealthCheck>) {
+        self.policy = policy;
+        self.reset();
+    }
+
+    pub(crate) fn take_event(&mut self) -> Option<HealthEvent> {
+        self.events.pop()
+    }
+
+    /// A new session has been established.
+    pub(crate) fn reset(&mut self) {
+        self.uplinks_without_downlink = 0;
+        self.check_pending = false;
+        self.failed_checks = 0;
+    }
+
+    /// Called before an application uplink is prepared; returns the check to add to it, if any.
+    pub(crate) fn uplink(&mut self) -> Option<Check> {
+        let policy = self.policy?;
+        if self.check_pending || self.uplinks_without_downlink < policy.uplinks {
+            return None;
+        }
+        self.check_pending = true;
+        self.event(HealthEvent::CheckSent);
+        Some(if policy.confirmed {
+            Check::Confirmed
+        } else {
+            Check::LinkCheckReq
+        })
+    }
+
+    pub(crate) fn downlink(&mut self) {
+        if self.check_pending {
+            self.event(HealthEvent::CheckPassed);
+        }
+        self.reset();
+    }
+
+    pub(crate) fn no_downlink(&mut self) {
+        let Some(policy) = self.policy else {
+            return;
+        };
+        self.uplinks_without_downlink = self.uplinks_without_downlink.saturating_add(1);
+        if !self.check_pending {
+            return;
+        }
+        self.check_pending = false;
+        self.uplinks_without_downlink = 0;
+        self.failed_checks = self.failed_checks.saturating_add(1);
+        self.event(HealthEvent::CheckFailed { failed_checks: self.failed_checks });
+        if self.failed_checks >= policy.max_failed_checks {
+            self.failed_checks = 0;
+            self.event(HealthEvent::RejoinRequired);
+        }
+    }
+
+    fn event(&mut self, event: HealthEvent) {
+        debug!("Health event: {}", event);
+        self.events.push(event);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rejoin_after_failed_checks() {
+        let mut health = Health::default();
+        health.set_policy(Some(HealthCheck { uplinks: 2, max_failed_checks: 2, confirmed: false }));
+        for failed_checks in 1..=2 {
+            for _ in 0..2 {
+                assert_eq!(health.uplink(), None);
+                health.no_downlink();
+            }
+            assert_eq!(health.uplink(), Some(Check::LinkCheckReq));
+            health.no_downlink();
+            assert_eq!(health.take_event(), Some(HealthEvent::CheckSent));
+            assert_eq!(health.take_event(), Some(HealthEvent::CheckFailed { failed_checks }));
+        }
+        assert_eq!(health.take_event(), Some(HealthEvent::RejoinRequired));
+        assert_eq!(health.take_event(), None);
+    }
+
+    #[test]
+    fn downlink_passes_check() {
+        let mut health = Health::default();
+        health.set_policy(Some(HealthCheck { uplinks: 1, max_failed_checks: 2, confirmed: true }));
+        health.no_downlink();
+        assert_eq!(health.uplink(), Some(Check::Confirmed));
+        health.downlink();
+        assert_eq!(health.uplink(), None);
+        assert_eq!(health.take_event(), Some(HealthEvent::CheckSent));
+        assert_eq!(health.take_event(), Some(HealthEvent::CheckPassed));
+        assert_eq!(health.take_event(), None);
+    }
+}
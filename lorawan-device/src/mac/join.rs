@@ -0,0 +1,98 @@
+//! Data rates of consecutive join attempts. The regional parameters recommend alternating the data
+//! rate of join requests, so that a device out of reach at a fast data rate eventually joins at a
+//! slow one, while a device close to a gateway doesn't occupy the channel with the slowest data
+//! rate on every attempt. Fixed channel plans (US915, AU915) alternate by themselves between the
+//! 125 kHz channels at DR0 and the 500 kHz channels at DR4, see
+//! [`JoinStrategy`](crate::region::JoinStrategy).
+use crate::radio::RfConfig;
+use lorawan::types::DR;
+
+/// Data rates used by consecutive join attempts in dynamic channel plans.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum JoinDatarates {
+    /// Every join request uses the configured data rate.
+    #[default]
+    Configured,
+    /// Step down from `fastest` to `slowest` by one data rate per attempt, then start over (eg:
+    /// DR5 to DR0 for EU868).
+    Alternate { fastest: DR, slowest: DR },
+}
+
+impl JoinDatarates {
+    fn datarate(&self, configured: DR, attempt: u16) -> DR {
+        match *self {
+            JoinDatarates::Configured => configured,
+            JoinDatarates::Alternate { fastest, slowest } => {
+                let (fastest, slowest) = (fastest as u16, slowest as u16);
+                let steps = fastest.saturating_sub(slowest) + 1;
+                DR::try_from((fastest - attempt % steps) as u8).unwrap()
+            }
+        }
+    }
+}
+
+/// Parameters of a join request which has been sent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct JoinAttempt {
+    /// Number of join requests sent before this one since the last successful join.
+    pub attempt: u16,
+    /// Frequency and modulation of the join request.
+    pub rf: RfConfig,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Join {
+    datarates: JoinDatarates,
+    attempts: u16,
+    last_attempt: Option<JoinAttempt>,
+}
+
+impl Join {
+    pub(crate) fn datarates(&self) -> JoinDatarates {
+        self.datarates
+    }
+
+    pub(crate) fn set_datarates(&mut self, datarates: JoinDatarates) {
+        self.datarates = datarates;
+        self.attempts = 0;
+    }
+
+    pub(crate) fn last_attempt(&self) -> Option<JoinAttempt> {
+        self.last_attempt
+    }
+
+    /// The data rate of the next join request.
+    pub(crate) fn datarate(&self, configured: DR) -> DR {
+        self.datarates.datarate(configured, self.attempts)
+    }
+
+    pub(crate) fn attempted(&mut self, rf: RfConfig) {
+        let attempt = JoinAttempt { attempt: self.attempts, rf };
+        debug!("Join attempt: {}", attempt);
+        self.last_attempt = Some(attempt);
+        self.attempts = self.attempts.wrapping_add(1);
+    }
+
+    pub(crate) fn joined(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn alternate_datarates() {
+        let datarates = JoinDatarates::Alternate { fastest: DR::_5, slowest: DR::_0 };
+        let sequence: [DR; 8] = core::array::from_fn(|i| datarates.datarate(DR::_3, i as u16));
+        assert_eq!(sequence, [DR::_5, DR::_4, DR::_3, DR::_2, DR::_1, DR::_0, DR::_5, DR::_4]);
+        assert_eq!(JoinDatarates::Configured.datarate(DR::_3, 4), DR::_3);
+
+        // a reversed range only uses the fastest data rate
+        let datarates = JoinDatarates::Alternate { fastest: DR::_0, slowest: DR::_5 };
+        assert_eq!(datarates.datarate(DR::_3, 1), DR::_0);
+    }
+}
@@ -2,15 +2,20 @@
 //! Manages state internally while providing client with transmit and receive frequencies, while writing to and
 //! decrypting from send and receive buffers.
 
+use crate::collections::Collection;
 use crate::{
     radio::{self, RadioBuffer, RfConfig, RxConfig, RxMode},
     region, AppSKey, Downlink, NwkSKey,
 };
 use heapless::Vec;
 use lora_modulation::BaseBandModulationParams;
+use lorawan::keys::CryptoFactory;
+use lorawan::maccommandcreator::LinkCheckReqCreator;
+#[cfg(feature = "certification")]
 use lorawan::maccommands::SerializableMacCommand;
-use lorawan::parser::DevAddr;
-use lorawan::types::DR;
+use lorawan::packet_length::phy::mac::fhdr::FOPTS_MAX_LEN;
+use lorawan::parser::{DevAddr, FCtrl};
+use lorawan::types::{DeviceClass, DR};
 
 pub type FcntDown = u32;
 pub type FcntUp = u32;
@@ -22,6 +27,36 @@ pub use session::{Session, SessionKeys};
 mod otaa;
 pub use otaa::NetworkCredentials;
 
+mod security;
+pub use security::{
+    FcntGapPolicy, MicFailureReason, SecurityCounters, SecurityEvent, MAX_FCNT_GAP,
+};
+
+mod health;
+pub use health::{HealthCheck, HealthEvent};
+
+mod dev_nonce;
+pub use dev_nonce::DEV_NONCE_LOW_THRESHOLD;
+
+mod join;
+pub use join::{JoinAttempt, JoinDatarates};
+
+mod adr_margin;
+pub use adr_margin::{AdrMargin, MAX_SNR_SAMPLES};
+mod battery;
+pub use battery::{BatteryLevel, BatteryPolicy};
+
+mod regional_state;
+pub use regional_state::{RegionalState, RegionalStateError};
+
+mod rx2_profiles;
+pub use rx2_profiles::{Rx2Profile, MAX_RX2_PROFILES};
+
+#[cfg(feature = "test-mode")]
+mod rx_delays;
+#[cfg(feature = "test-mode")]
+pub use rx_delays::RxDelayOverrides;
+
 use crate::async_device;
 use crate::nb_device;
 
@@ -55,15 +90,133 @@ pub struct Configuration {
     join_accept_delay2: u32,
 
     pub(crate) tx_power: Option<u8>,
+    /// Number of transmissions of each unconfirmed uplink (NbTrans of LinkADRReq).
+    pub(crate) nb_trans: u8,
     pub(crate) rx1_dr_offset: u8,
     pub(crate) rx2_data_rate: Option<DR>,
     pub(crate) rx2_frequency: Option<u32>,
+
+    pub(crate) adr: bool,
+    pub(crate) adr_ack_limit: u16,
+    pub(crate) adr_ack_delay: u16,
+    /// Whether uplinks are limited to 400 ms time on air (eg: AS923 in some countries), which
+    /// lowers the maximum payload size.
+    pub(crate) uplink_dwell_time: bool,
+    /// MaxEIRP in dBm set by the network via TxParamSetupReq, `None` for the regional default.
+    pub(crate) network_max_eirp: Option<u8>,
+
+    /// Class indicated by DeviceModeInd, pending confirmation by the network.
+    pub(crate) device_mode_ind: Option<DeviceClass>,
+    /// Class confirmed by DeviceModeConf, not yet taken by the application.
+    pub(crate) device_mode_conf: Option<DeviceClass>,
+    /// Whether ResetInd is sent until confirmed by ResetConf.
+    pub(crate) reset_ind: bool,
+
+    pub(crate) class_b: ClassBSettings,
+    /// Battery level reported in DevStatusAns and the low battery policy.
+    pub(crate) battery: battery::Battery,
+    /// Number of FOpts bytes which may be taken by MAC commands on data uplinks.
+    pub(crate) max_fopts_len: u8,
+    /// SNR of recent downlinks and the margin required before ADR raises the data rate.
+    pub(crate) snr_history: adr_margin::SnrHistory,
+    /// Settings chosen by the application, which take the place of the regional defaults.
+    pub(crate) app_defaults: AppDefaults,
+}
+
+/// Data rate, RX2 and dwell time settings of the application (or of the provisioning), which are
+/// restored instead of the regional defaults on every join or reset.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub(crate) struct AppDefaults {
+    pub(crate) data_rate: Option<DR>,
+    pub(crate) rx2: Option<(u32, DR)>,
+    pub(crate) uplink_dwell_time: bool,
+}
+
+/// Class B channel settings requested by the network via BeaconFreqReq and PingSlotChannelReq.
+/// `None` stands for the regional default.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct ClassBSettings {
+    pub beacon_frequency: Option<u32>,
+    pub ping_slot_frequency: Option<u32>,
+    pub ping_slot_data_rate: Option<DR>,
+}
+
+impl Configuration {
+    fn new(region: &region::Configuration) -> Self {
+        Self {
+            data_rate: region.get_default_datarate(),
+            rx1_delay: region::constants::RECEIVE_DELAY1,
+            rx1_dr_offset: 0,
+            join_accept_delay1: region::constants::JOIN_ACCEPT_DELAY1,
+            join_accept_delay2: region::constants::JOIN_ACCEPT_DELAY2,
+            rx2_data_rate: None,
+            rx2_frequency: None,
+            tx_power: None,
+            nb_trans: 1,
+            adr: false,
+            adr_ack_limit: region::constants::ADR_ACK_LIMIT as u16,
+            adr_ack_delay: region::constants::ADR_ACK_DELAY as u16,
+            uplink_dwell_time: false,
+            network_max_eirp: None,
+            device_mode_ind: None,
+            device_mode_conf: None,
+            reset_ind: false,
+            class_b: ClassBSettings::default(),
+            battery: battery::Battery::default(),
+            max_fopts_len: FOPTS_MAX_LEN as u8,
+            snr_history: adr_margin::SnrHistory::default(),
+            app_defaults: AppDefaults::default(),
+        }
+    }
+
+    /// Restore the regional defaults of the channels and of the settings controlled by the
+    /// network (data rate, TX power, RX parameters, ADR backoff, dwell time, class settings),
+    /// as after a join. Settings of the application (ADR, battery level, FOpts limit, ADR margin
+    /// and the [`AppDefaults`]) are kept.
+    pub(crate) fn restore_regional_defaults(&mut self, region: &mut region::Configuration) {
+        region.restore_defaults();
+        let app_defaults = self.app_defaults;
+        *self = Self {
+            adr: self.adr,
+            battery: self.battery,
+            max_fopts_len: self.max_fopts_len,
+            snr_history: self.snr_history,
+            app_defaults,
+            ..Self::new(region)
+        };
+        if let Some(data_rate) = app_defaults.data_rate {
+            self.data_rate = data_rate;
+        }
+        if let Some((frequency, data_rate)) = app_defaults.rx2 {
+            self.rx2_frequency = Some(frequency);
+            self.rx2_data_rate = Some(data_rate);
+        }
+        self.uplink_dwell_time = app_defaults.uplink_dwell_time;
+    }
 }
 
 pub(crate) struct Mac {
     pub configuration: Configuration,
     pub region: region::Configuration,
     board_eirp: BoardEirp,
+    security: security::Security,
+    health: health::Health,
+    join: join::Join,
+    dev_nonces: dev_nonce::DevNonces,
+    /// Whether the session keys are handed to the application after an OTAA join.
+    export_session_keys: bool,
+    exported_session_keys: Option<SessionKeys>,
+    /// Whether the last downlink had the FPending bit set and no uplink has been sent since.
+    frame_pending: bool,
+    /// Repetitions of the last unconfirmed uplink which are still to be transmitted, see
+    /// [`Mac::plan_repetitions`].
+    repetitions: u8,
+    rx2_profiles: rx2_profiles::Rx2Profiles,
+    fport_policy: FPortPolicy,
+    #[cfg(feature = "test-mode")]
+    rx_delay_overrides: RxDelayOverrides,
     state: State,
     #[cfg(feature = "certification")]
     certification: certification::Certification,
@@ -74,6 +227,7 @@ pub(crate) struct Mac {
 struct BoardEirp {
     max_power: u8,
     antenna_gain: i8,
+    max_eirp: Option<i8>,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -87,10 +241,48 @@ pub(crate) enum State {
 #[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
 pub enum Error {
     NotJoined,
+    /// The application payload exceeds the maximum of `max_len` bytes for the current data rate,
+    /// dwell time setting and pending MAC commands.
+    PayloadTooLarge {
+        max_len: usize,
+    },
+    /// The FPort is reserved (FPort 0 with data, or 224 and above) and not allowed by the
+    /// [`FPortPolicy`].
+    ReservedFPort {
+        fport: u8,
+    },
+    /// All DevNonces of the counter have been used, so the device can't join anymore with its
+    /// current JoinEUI and DevEUI.
+    DevNonceExhausted,
+    /// The initial frame counters of an ABP session are exhausted, ie: `0xFFFF_FFFF`.
+    FcntExhausted,
+    /// The initial frame counters of an ABP session are lower than those of the current session
+    /// with the same DevAddr, which would reuse frame counters with the same session keys.
+    FcntRewound {
+        fcnt_up: u32,
+        fcnt_down: u32,
+    },
+    /// The device class is not supported by the stack (Class B).
+    UnsupportedClass,
     #[cfg(feature = "multicast")]
     Multicast(multicast::Error),
 }
 
+/// Determines which FPorts the application may send uplinks on. Uplinks of the stack itself (eg:
+/// packages and the certification protocol) are not restricted.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum FPortPolicy {
+    /// FPorts 1 to 223. FPort 0 is only accepted without data, which sends the pending MAC
+    /// commands in the FRMPayload.
+    #[default]
+    Application,
+    /// Any FPort, eg: for test applications or application layer protocols implemented outside of
+    /// the stack. FPort 224 is reserved for the certification protocol and FPort 0 for MAC
+    /// commands, so such uplinks may be misinterpreted by the network.
+    AllowReserved,
+}
+
 pub struct SendData<'a> {
     pub data: &'a [u8],
     pub fport: u8,
@@ -101,21 +293,23 @@ pub(crate) type Result<T = ()> = core::result::Result<T, Error>;
 
 impl Mac {
     pub(crate) fn new(region: region::Configuration, max_power: u8, antenna_gain: i8) -> Self {
-        let data_rate = region.get_default_datarate();
         Self {
-            board_eirp: BoardEirp { max_power, antenna_gain },
+            board_eirp: BoardEirp { max_power, antenna_gain, max_eirp: None },
+            security: security::Security::default(),
+            health: health::Health::default(),
+            join: join::Join::default(),
+            dev_nonces: dev_nonce::DevNonces::default(),
+            export_session_keys: false,
+            exported_session_keys: None,
+            frame_pending: false,
+            repetitions: 0,
+            rx2_profiles: rx2_profiles::Rx2Profiles::default(),
+            fport_policy: FPortPolicy::Application,
+            #[cfg(feature = "test-mode")]
+            rx_delay_overrides: RxDelayOverrides::default(),
+            configuration: Configuration::new(&region),
             region,
             state: State::Unjoined,
-            configuration: Configuration {
-                data_rate,
-                rx1_delay: region::constants::RECEIVE_DELAY1,
-                rx1_dr_offset: 0,
-                join_accept_delay1: region::constants::JOIN_ACCEPT_DELAY1,
-                join_accept_delay2: region::constants::JOIN_ACCEPT_DELAY2,
-                rx2_data_rate: None,
-                rx2_frequency: None,
-                tx_power: None,
-            },
             #[cfg(feature = "certification")]
             certification: certification::Certification::new(),
             #[cfg(feature = "multicast")]
@@ -124,20 +318,30 @@ impl Mac {
     }
 
     /// Prepare the radio buffer with transmitting a join request frame and provides the radio
-    /// configuration for the transmission.
-    pub(crate) fn join_otaa<RNG: RngCore, const N: usize>(
+    /// configuration for the transmission. Returns an error if the DevNonce counter is exhausted.
+    pub(crate) fn join_otaa<C: CryptoFactory, RNG: RngCore, const N: usize>(
         &mut self,
+        crypto: &C,
         rng: &mut RNG,
         credentials: NetworkCredentials,
         buf: &mut RadioBuffer<N>,
-    ) -> (radio::TxConfig, u16) {
+    ) -> Result<(radio::TxConfig, u16)> {
+        let dev_nonce = self.dev_nonces.take(rng).ok_or(Error::DevNonceExhausted)?;
+        if let Some(remaining) =
+            self.dev_nonces.remaining().filter(|remaining| *remaining <= DEV_NONCE_LOW_THRESHOLD)
+        {
+            self.security.report(SecurityEvent::DevNonceLow { remaining });
+        }
         let mut otaa = otaa::Otaa::new(credentials);
-        let dev_nonce = otaa.prepare_buffer::<RNG, N>(rng, buf);
+        let dev_nonce = otaa.prepare_buffer(crypto, dev_nonce, buf);
+        trace!("Join request prepared with DevNonce {}", dev_nonce);
         self.state = State::Otaa(otaa);
-        let mut tx_config =
-            self.region.create_tx_config(rng, self.configuration.data_rate, &Frame::Join);
-        tx_config.adjust_power(self.board_eirp.max_power, self.board_eirp.antenna_gain);
-        (tx_config, dev_nonce)
+        self.rx2_profiles.unlock();
+        let datarate = self.join.datarate(self.configuration.data_rate);
+        let mut tx_config = self.region.create_tx_config(rng, datarate, &Frame::Join);
+        self.adjust_tx_power(&mut tx_config, self.board_eirp.max_power);
+        self.join.attempted(tx_config.rf);
+        Ok((tx_config, dev_nonce))
     }
 
     /// Join via ABP. This does not transmit a join request frame, but instead sets the session.
@@ -147,36 +351,310 @@ impl Mac {
         appskey: AppSKey,
         devaddr: DevAddr<[u8; 4]>,
     ) {
+        self.health.reset();
+        self.rx2_profiles.unlock();
         self.state = State::Joined(Session::new(nwkskey, appskey, devaddr));
     }
 
+    /// Join via ABP with the frame counters of an existing session, eg: of a device migrated from
+    /// another stack. `fcnt_up` is the counter of the next uplink and `fcnt_down` the counter of
+    /// the last downlink. If the device is already joined with the same DevAddr, the counters may
+    /// not go backwards.
+    pub(crate) fn join_abp_with_fcnt(
+        &mut self,
+        nwkskey: NwkSKey,
+        appskey: AppSKey,
+        devaddr: DevAddr<[u8; 4]>,
+        fcnt_up: u32,
+        fcnt_down: u32,
+    ) -> Result<()> {
+        if fcnt_up == u32::MAX || fcnt_down == u32::MAX {
+            return Err(Error::FcntExhausted);
+        }
+        if let State::Joined(session) = &self.state {
+            if session.devaddr == devaddr
+                && (fcnt_up < session.fcnt_up || fcnt_down < session.fcnt_down)
+            {
+                return Err(Error::FcntRewound {
+                    fcnt_up: session.fcnt_up,
+                    fcnt_down: session.fcnt_down,
+                });
+            }
+        }
+        self.join_abp(nwkskey, appskey, devaddr);
+        self.state = State::Joined(Session {
+            fcnt_up,
+            fcnt_down,
+            ..Session::new(nwkskey, appskey, devaddr)
+        });
+        Ok(())
+    }
+
     /// Join via ABP. This does not transmit a join request frame, but instead sets the session.
     pub(crate) fn set_session(&mut self, session: Session) {
+        self.health.reset();
         self.state = State::Joined(session);
     }
 
     /// Prepare the radio buffer for transmitting a data frame and provide the radio configuration
     /// for the transmission. Returns an error if the device is not joined.
-    pub(crate) fn send<RNG: RngCore, const N: usize>(
+    pub(crate) fn send<C: CryptoFactory, RNG: RngCore, const N: usize>(
         &mut self,
+        crypto: &C,
         rng: &mut RNG,
         buf: &mut RadioBuffer<N>,
         send_data: &SendData<'_>,
     ) -> Result<(radio::TxConfig, FcntUp)> {
+        self.adr_backoff();
+        self.frame_pending = false;
+        self.repetitions = 0;
+        let max_frm_payload_len = self.get_max_frm_payload_len();
+        let mut confirmed = send_data.confirmed;
+        if let State::Joined(session) = &mut self.state {
+            match self.health.uplink() {
+                Some(health::Check::LinkCheckReq) => {
+                    session.uplink.add_mac_command(LinkCheckReqCreator::new())
+                }
+                Some(health::Check::Confirmed) => confirmed = true,
+                None => (),
+            }
+        }
+        let send_data = &SendData { confirmed, ..*send_data };
+        // Checked once the ADR backoff settled the data rate and the MAC commands for FOpts are
+        // queued, as both shrink the room left for the application payload
+        let max_len = self.max_app_payload_len();
+        if send_data.data.len() > max_len {
+            return Err(Error::PayloadTooLarge { max_len });
+        }
         let fcnt = match &mut self.state {
-            State::Joined(ref mut session) => Ok(session.prepare_buffer::<N>(send_data, buf)),
+            // An empty uplink on FPort 0 carries the pending MAC commands in its FRMPayload
+            State::Joined(ref mut session) if send_data.fport == 0 && send_data.data.is_empty() => {
+                Ok(session.prepare_mac_commands_buffer(
+                    crypto,
+                    &self.configuration,
+                    send_data.confirmed,
+                    max_frm_payload_len,
+                    buf,
+                ))
+            }
+            State::Joined(ref mut session) => {
+                Ok(session.prepare_buffer(crypto, &self.configuration, send_data, buf))
+            }
+            State::Otaa(_) => Err(Error::NotJoined),
+            State::Unjoined => Err(Error::NotJoined),
+        }?;
+        let tx_config = self.data_tx_config(rng);
+        trace!(
+            "Uplink FCnt {} prepared: {} bytes on FPort {}, {}",
+            fcnt,
+            send_data.data.len(),
+            send_data.fport,
+            tx_config
+        );
+        Ok((tx_config, fcnt))
+    }
+
+    /// Prepare the radio buffer for transmitting an empty uplink which acknowledges the last
+    /// confirmed downlink. Returns an error if the device is not joined.
+    pub(crate) fn send_ack<C: CryptoFactory, RNG: RngCore, const N: usize>(
+        &mut self,
+        crypto: &C,
+        rng: &mut RNG,
+        buf: &mut RadioBuffer<N>,
+    ) -> Result<(radio::TxConfig, FcntUp)> {
+        self.adr_backoff();
+        self.frame_pending = false;
+        self.repetitions = 0;
+        let fcnt = match &mut self.state {
+            State::Joined(ref mut session) => {
+                Ok(session.prepare_ack_buffer(crypto, &self.configuration, buf))
+            }
             State::Otaa(_) => Err(Error::NotJoined),
             State::Unjoined => Err(Error::NotJoined),
         }?;
+        let tx_config = self.data_tx_config(rng);
+        trace!("ACK uplink FCnt {} prepared: {}", fcnt, tx_config);
+        Ok((tx_config, fcnt))
+    }
+
+    /// ADR backoff: once ADR_ACK_DELAY uplinks with the ADRACKReq bit set went unanswered, try to
+    /// regain connectivity by first restoring the default TX power, then lowering the data rate one
+    /// step at a time and finally re-enabling all channels. One step is taken every ADR_ACK_DELAY
+    /// uplinks until a downlink is received.
+    fn adr_backoff(&mut self) {
+        let adr_ack_cnt = match &self.state {
+            State::Joined(session) if self.configuration.adr => session.adr_ack_cnt,
+            _ => return,
+        };
+        let limit = self.configuration.adr_ack_limit as u32;
+        let delay = self.configuration.adr_ack_delay as u32;
+        if adr_ack_cnt < limit + delay || (adr_ack_cnt - limit) % delay != 0 {
+            return;
+        }
+        let data_rate = self.configuration.data_rate;
+        let lower_data_rate = data_rate.offset_sub(1);
+        if self.configuration.tx_power.is_some() || self.configuration.nb_trans > 1 {
+            debug!("ADR backoff: restoring default TX power and NbTrans");
+            self.configuration.tx_power = None;
+            self.configuration.nb_trans = 1;
+        } else if lower_data_rate != data_rate
+            && self.region.get_datarate(lower_data_rate as u8).is_some()
+        {
+            debug!("ADR backoff: lowering data rate to {}", lower_data_rate);
+            self.configuration.data_rate = lower_data_rate;
+        } else {
+            debug!("ADR backoff: re-enabling all channels");
+            self.region.channel_mask_reset();
+        }
+    }
+
+    /// Whether the ADR bit is set in uplinks, allowing the network to control the data rate and
+    /// TX power.
+    pub(crate) fn set_adr(&mut self, enabled: bool) {
+        self.configuration.adr = enabled;
+    }
+
+    pub(crate) fn get_adr(&self) -> bool {
+        self.configuration.adr
+    }
+
+    /// Inform the network of a class switch by sending DeviceModeInd with every uplink until it is
+    /// confirmed by DeviceModeConf.
+    pub(crate) fn indicate_device_mode(&mut self, class: DeviceClass) -> Result<()> {
+        match &mut self.state {
+            State::Joined(ref mut session) => {
+                self.configuration.device_mode_ind = Some(class);
+                self.configuration.device_mode_conf = None;
+                session.add_pending_indications(&self.configuration);
+                Ok(())
+            }
+            State::Otaa(_) => Err(Error::NotJoined),
+            State::Unjoined => Err(Error::NotJoined),
+        }
+    }
+
+    pub(crate) fn take_device_mode_conf(&mut self) -> Option<DeviceClass> {
+        self.configuration.device_mode_conf.take()
+    }
+
+    /// Revert the MAC layer to its default settings and inform the network about it by sending
+    /// ResetInd with every uplink until it is confirmed by ResetConf. Required after a reboot of
+    /// a LoRaWAN 1.1 ABP device.
+    pub(crate) fn indicate_reset(&mut self) -> Result<()> {
+        match &mut self.state {
+            State::Joined(ref mut session) => {
+                self.configuration.restore_regional_defaults(&mut self.region);
+                self.configuration.reset_ind = true;
+                session.add_pending_indications(&self.configuration);
+                Ok(())
+            }
+            State::Otaa(_) => Err(Error::NotJoined),
+            State::Unjoined => Err(Error::NotJoined),
+        }
+    }
+
+    pub(crate) fn restore_regional_defaults(&mut self) {
+        self.configuration.restore_regional_defaults(&mut self.region);
+    }
+
+    pub(crate) fn is_reset_ind_pending(&self) -> bool {
+        self.configuration.reset_ind
+    }
+
+    /// Maximum FRMPayload length at the current data rate (MACPayload without FHDR and FPort).
+    fn get_max_frm_payload_len(&self) -> usize {
+        let max_payload_len = self.region.get_max_payload_length(
+            self.uplink_data_rate(),
+            false,
+            self.configuration.uplink_dwell_time,
+        );
+        (max_payload_len as usize).saturating_sub(8)
+    }
+
+    /// Largest application payload the next uplink can carry at the current data rate, leaving
+    /// room for the MAC commands pending for FOpts.
+    pub(crate) fn max_app_payload_len(&self) -> usize {
+        let fopts_len = match &self.state {
+            State::Joined(session) => session.fopts_len(&self.configuration),
+            State::Otaa(_) | State::Unjoined => 0,
+        };
+        self.get_max_frm_payload_len().saturating_sub(fopts_len)
+    }
+
+    #[cfg(feature = "test-mode")]
+    pub(crate) fn set_rx_delay_overrides(&mut self, overrides: RxDelayOverrides) {
+        self.rx_delay_overrides = overrides;
+    }
+
+    #[cfg(feature = "test-mode")]
+    pub(crate) fn get_rx_delay_overrides(&self) -> RxDelayOverrides {
+        self.rx_delay_overrides
+    }
+
+    pub(crate) fn set_uplink_dwell_time(&mut self, enabled: bool) {
+        self.configuration.uplink_dwell_time = enabled;
+        self.configuration.app_defaults.uplink_dwell_time = enabled;
+    }
+
+    /// Set the data rate of uplinks until changed by the network (LinkADRReq) or the ADR backoff.
+    /// The data rate is restored on every join.
+    pub(crate) fn set_datarate(&mut self, data_rate: DR) {
+        self.configuration.data_rate = data_rate;
+        self.configuration.app_defaults.data_rate = Some(data_rate);
+    }
+
+    pub(crate) fn get_uplink_dwell_time(&self) -> bool {
+        self.configuration.uplink_dwell_time
+    }
+
+    pub(crate) fn set_downlink_dwell_time(&mut self, enabled: bool) {
+        self.region.set_downlink_dwell_time(enabled);
+    }
+
+    pub(crate) fn get_downlink_dwell_time(&self) -> bool {
+        self.region.get_downlink_dwell_time()
+    }
+
+    /// Data rate of data uplinks: the one set by the application or the network, unless raised by
+    /// the [`BatteryPolicy`].
+    pub(crate) fn uplink_data_rate(&self) -> DR {
+        self.configuration.battery.data_rate(self.configuration.data_rate, &self.region)
+    }
+
+    /// Plan the repetitions of the uplink just prepared: an unconfirmed uplink is transmitted
+    /// NbTrans times, with the same FCnt, unless a downlink is received in the meantime. Returns
+    /// the number of repetitions, each of which is expected to be followed by RX windows and
+    /// [`Mac::rx2_complete`].
+    pub(crate) fn plan_repetitions(&mut self) -> u8 {
+        self.repetitions = match &self.state {
+            State::Joined(session) if !session.confirmed => {
+                self.configuration.nb_trans.saturating_sub(1)
+            }
+            _ => 0,
+        };
+        self.repetitions
+    }
+
+    /// Radio configuration for repeating the uplink, on a newly selected channel.
+    pub(crate) fn repetition_tx_config<RNG: RngCore>(&mut self, rng: &mut RNG) -> radio::TxConfig {
+        self.data_tx_config(rng)
+    }
+
+    fn data_tx_config<RNG: RngCore>(&mut self, rng: &mut RNG) -> radio::TxConfig {
         let mut tx_config =
-            self.region.create_tx_config(rng, self.configuration.data_rate, &Frame::Data);
-        tx_config.adjust_power(
+            self.region.create_tx_config(rng, self.uplink_data_rate(), &Frame::Data);
+        self.adjust_tx_power(
+            &mut tx_config,
             self.configuration.tx_power.unwrap_or(self.board_eirp.max_power),
-            self.board_eirp.antenna_gain,
         );
-        Ok((tx_config, fcnt))
+        if let Some(max_eirp) = self.configuration.battery.max_eirp() {
+            tx_config.pw =
+                core::cmp::min(tx_config.pw, max_eirp.saturating_sub(self.board_eirp.antenna_gain));
+        }
+        tx_config
     }
 
+    #[cfg(feature = "certification")]
     pub(crate) fn add_uplink<M: SerializableMacCommand>(&mut self, cmd: M) -> Result<()> {
         let _fcnt = match &mut self.state {
             State::Joined(ref mut session) => {
@@ -190,37 +668,177 @@ impl Mac {
     }
 
     #[cfg(feature = "multicast")]
-    pub(crate) fn multicast_setup_send<RNG: RngCore, const N: usize>(
+    pub(crate) fn multicast_setup_send<C: CryptoFactory, RNG: RngCore, const N: usize>(
         &mut self,
+        crypto: &C,
         rng: &mut RNG,
         buf: &mut RadioBuffer<N>,
     ) -> Result<(radio::TxConfig, FcntUp)> {
-        self.multicast.setup_send::<N>(&mut self.state, buf).map(|fcnt_up| {
-            let mut tx_config =
-                self.region.create_tx_config(rng, self.configuration.data_rate, &Frame::Data);
-            tx_config.adjust_power(
-                self.configuration.tx_power.unwrap_or(self.board_eirp.max_power),
-                self.board_eirp.antenna_gain,
-            );
-            (tx_config, fcnt_up)
-        })
+        self.multicast.setup_send(crypto, &mut self.state, &self.configuration, buf).map(
+            |fcnt_up| {
+                let mut tx_config =
+                    self.region.create_tx_config(rng, self.configuration.data_rate, &Frame::Data);
+                self.adjust_tx_power(
+                    &mut tx_config,
+                    self.configuration.tx_power.unwrap_or(self.board_eirp.max_power),
+                );
+                (tx_config, fcnt_up)
+            },
+        )
     }
 
     #[cfg(feature = "certification")]
-    pub(crate) fn certification_setup_send<RNG: RngCore, const N: usize>(
+    pub(crate) fn certification_setup_send<C: CryptoFactory, RNG: RngCore, const N: usize>(
         &mut self,
+        crypto: &C,
         rng: &mut RNG,
         buf: &mut RadioBuffer<N>,
     ) -> Result<(radio::TxConfig, FcntUp)> {
-        self.certification.setup_send::<N>(&mut self.state, buf).map(|fcnt_up| {
-            let mut tx_config =
-                self.region.create_tx_config(rng, self.configuration.data_rate, &Frame::Data);
-            tx_config.adjust_power(self.board_eirp.max_power, self.board_eirp.antenna_gain);
-            (tx_config, fcnt_up)
-        })
+        self.certification.setup_send(crypto, &mut self.state, &self.configuration, buf).map(
+            |fcnt_up| {
+                let mut tx_config =
+                    self.region.create_tx_config(rng, self.configuration.data_rate, &Frame::Data);
+                self.adjust_tx_power(&mut tx_config, self.board_eirp.max_power);
+                (tx_config, fcnt_up)
+            },
+        )
+    }
+
+    /// Limit the EIRP used for any transmission, regardless of what the region or the network
+    /// (via LinkADRReq) would otherwise allow.
+    pub(crate) fn set_max_eirp(&mut self, max_eirp: Option<i8>) {
+        self.board_eirp.max_eirp = max_eirp;
+    }
+
+    pub(crate) fn get_max_eirp(&self) -> Option<i8> {
+        self.board_eirp.max_eirp
+    }
+
+    pub(crate) fn get_battery_level(&self) -> BatteryLevel {
+        self.configuration.battery.level()
+    }
+
+    pub(crate) fn set_battery_level(&mut self, level: BatteryLevel) {
+        self.configuration.battery.set_level(level);
+    }
+
+    pub(crate) fn get_battery_policy(&self) -> Option<BatteryPolicy> {
+        self.configuration.battery.policy()
+    }
+
+    pub(crate) fn set_battery_policy(&mut self, policy: Option<BatteryPolicy>) {
+        self.configuration.battery.set_policy(policy);
+    }
+
+    pub(crate) fn get_adr_margin(&self) -> Option<AdrMargin> {
+        self.configuration.snr_history.policy()
+    }
+
+    pub(crate) fn set_adr_margin(&mut self, margin: Option<AdrMargin>) {
+        self.configuration.snr_history.set_policy(margin);
+    }
+
+    pub(crate) fn get_max_fopts_len(&self) -> u8 {
+        self.configuration.max_fopts_len
+    }
+
+    pub(crate) fn set_max_fopts_len(&mut self, len: u8) {
+        self.configuration.max_fopts_len = len.min(FOPTS_MAX_LEN as u8);
+    }
+
+    pub(crate) fn is_battery_low(&self) -> bool {
+        self.configuration.battery.is_low()
+    }
+
+    pub(crate) fn get_health_check(&self) -> Option<HealthCheck> {
+        self.health.policy()
+    }
+
+    pub(crate) fn set_health_check(&mut self, policy: Option<HealthCheck>) {
+        self.health.set_policy(policy);
+    }
+
+    pub(crate) fn take_health_event(&mut self) -> Option<HealthEvent> {
+        self.health.take_event()
+    }
+
+    pub(crate) fn get_class_b_settings(&self) -> ClassBSettings {
+        self.configuration.class_b
+    }
+
+    /// Override the RX2 frequency and data rate until changed by the network (RXParamSetupReq).
+    /// The override is restored on every join.
+    pub(crate) fn set_rx2(&mut self, frequency: u32, data_rate: DR) {
+        self.configuration.rx2_frequency = Some(frequency);
+        self.configuration.rx2_data_rate = Some(data_rate);
+        self.configuration.app_defaults.rx2 = Some((frequency, data_rate));
+    }
+
+    pub(crate) fn get_fport_policy(&self) -> FPortPolicy {
+        self.fport_policy
+    }
+
+    pub(crate) fn set_fport_policy(&mut self, policy: FPortPolicy) {
+        self.fport_policy = policy;
+    }
+
+    /// Check an FPort selected by the application against the [`FPortPolicy`].
+    pub(crate) fn check_fport(&self, fport: u8, data: &[u8]) -> Result {
+        let reserved = match fport {
+            0 => !data.is_empty(),
+            1..=223 => false,
+            224.. => true,
+        };
+        if reserved && self.fport_policy == FPortPolicy::Application {
+            return Err(Error::ReservedFPort { fport });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_rx2_profiles(&mut self, profiles: Vec<Rx2Profile, MAX_RX2_PROFILES>) {
+        self.rx2_profiles.set(profiles);
+    }
+
+    pub(crate) fn get_rx2_profiles(&self) -> &[Rx2Profile] {
+        self.rx2_profiles.profiles()
+    }
+
+    pub(crate) fn get_rx2_profile(&self) -> Option<Rx2Profile> {
+        self.rx2_profiles.current()
+    }
+
+    /// Summary of the region with the RX2 settings in use, which the network (RXParamSetupReq)
+    /// or an RX2 profile may have changed from the regional defaults.
+    pub(crate) fn describe_region(&self) -> region::RegionSummary {
+        let mut summary = self.region.describe();
+        let rx2_profile = self.rx2_profiles.current();
+        summary.rx2_frequency = self
+            .configuration
+            .rx2_frequency
+            .or(rx2_profile.map(|profile| profile.frequency))
+            .unwrap_or(summary.rx2_frequency);
+        summary.rx2_datarate = self
+            .configuration
+            .rx2_data_rate
+            .or(rx2_profile.map(|profile| profile.data_rate))
+            .unwrap_or(summary.rx2_datarate);
+        summary
+    }
+
+    fn adjust_tx_power(&self, tx_config: &mut radio::TxConfig, max_power: u8) {
+        tx_config.adjust_power(max_power, self.board_eirp.antenna_gain);
+        let network_max_eirp = self.configuration.network_max_eirp.map(|eirp| eirp as i8);
+        for max_eirp in [self.board_eirp.max_eirp, network_max_eirp].into_iter().flatten() {
+            tx_config.pw =
+                core::cmp::min(tx_config.pw, max_eirp.saturating_sub(self.board_eirp.antenna_gain));
+        }
     }
 
     pub(crate) fn get_rx_delay(&self, frame: &Frame, window: &Window) -> u32 {
+        #[cfg(feature = "test-mode")]
+        if let Some(delay) = self.rx_delay_overrides.get(frame, window) {
+            return delay;
+        }
         match frame {
             Frame::Join => match window {
                 Window::_1 => self.configuration.join_accept_delay1,
@@ -239,31 +857,67 @@ impl Mac {
     /// verification. Upon successful join, provides Response::JoinSuccess. Upon successful data
     /// rx, provides Response::DownlinkReceived. User must take the downlink from vec for
     /// application data.
-    pub(crate) fn handle_rx<const N: usize, const D: usize>(
+    pub(crate) fn handle_rx<C: CryptoFactory, const N: usize>(
         &mut self,
+        crypto: &C,
         buf: &mut RadioBuffer<N>,
-        dl: &mut Vec<Downlink, D>,
+        dl: &mut dyn Collection<Downlink>,
         snr: i8,
         rf_config: &RfConfig,
+        window: &Window,
     ) -> Response {
+        let frame = match self.state {
+            State::Otaa(_) => Frame::Join,
+            _ => Frame::Data,
+        };
+        // The MAC commands of the downlink may change the RX2 parameters
+        let (_, data_rate) = self.rx_parameters(&frame, window);
         match &mut self.state {
-            State::Joined(ref mut session) => session.handle_rx::<N, D>(
-                &mut self.region,
-                &mut self.configuration,
-                #[cfg(feature = "certification")]
-                &mut self.certification,
-                #[cfg(feature = "multicast")]
-                &mut self.multicast,
-                buf,
-                dl,
-                rf_config.max_payload_len,
-                snr,
-                false,
-            ),
+            State::Joined(ref mut session) => {
+                let rx1 = matches!(window, Window::_1);
+                self.security.take_mic_failure();
+                let response = session.handle_rx(
+                    crypto,
+                    &mut self.region,
+                    &mut self.configuration,
+                    #[cfg(feature = "certification")]
+                    &mut self.certification,
+                    #[cfg(feature = "multicast")]
+                    &mut self.multicast,
+                    &mut self.security,
+                    buf.as_mut_for_read(),
+                    Some(dl),
+                    rf_config.max_payload_len,
+                    snr,
+                    false,
+                );
+                trace!("Downlink handled: {}", response);
+                match response {
+                    Response::DownlinkReceived(_) => {
+                        self.region.channel_stats_mut().downlink(rx1);
+                        self.health.downlink();
+                        self.frame_pending = frame_pending(buf.as_ref_for_read());
+                        self.rx2_profiles.received(window, rf_config.frequency, data_rate);
+                    }
+                    // Frames for other devices and replays don't tell anything about the channel
+                    Response::NoUpdate if self.security.take_mic_failure() => {
+                        self.region.channel_stats_mut().rx_error()
+                    }
+                    Response::SessionExpired => self.expire_session(),
+                    _ => (),
+                }
+                response
+            }
             State::Otaa(ref mut otaa) => {
                 if let Some(session) =
-                    otaa.handle_rx::<N>(&mut self.region, &mut self.configuration, buf)
+                    otaa.handle_rx(crypto, &mut self.region, &mut self.configuration, buf)
                 {
+                    self.rx2_profiles.received(window, rf_config.frequency, data_rate);
+                    self.health.reset();
+                    self.join.joined();
+                    if self.export_session_keys {
+                        self.exported_session_keys = session.get_session_keys();
+                    }
                     self.state = State::Joined(session);
                     Response::JoinSuccess
                 } else {
@@ -276,42 +930,145 @@ impl Mac {
 
     /// Handles a received RF frame during RXC window. Returns None if unparseable, fails decryption,
     /// or fails MIC verification. Upon successful data rx, provides Response::DownlinkReceived.
-    /// User must later call `take_downlink()` on the device to get the application data.
+    /// User must later call `take_downlink()` on the device to get the application data, or, if
+    /// no `dl` queue is given, read it from the frame which is decrypted in place (see
+    /// [`frm_payload`]).
     #[cfg(feature = "class-c")]
-    pub(crate) fn handle_rxc<const N: usize, const D: usize>(
+    pub(crate) fn handle_rxc<C: CryptoFactory>(
         &mut self,
-        buf: &mut RadioBuffer<N>,
-        dl: &mut Vec<Downlink, D>,
+        crypto: &C,
+        frame: &mut [u8],
+        dl: Option<&mut dyn Collection<Downlink>>,
         snr: i8,
         rf_config: &RfConfig,
     ) -> Result<Response> {
         match &mut self.state {
-            State::Joined(ref mut session) => Ok(session.handle_rx::<N, D>(
-                &mut self.region,
-                &mut self.configuration,
-                #[cfg(feature = "certification")]
-                &mut self.certification,
-                #[cfg(feature = "multicast")]
-                &mut self.multicast,
-                buf,
-                dl,
-                rf_config.max_payload_len,
-                snr,
-                true,
-            )),
+            State::Joined(ref mut session) => {
+                let response = session.handle_rx(
+                    crypto,
+                    &mut self.region,
+                    &mut self.configuration,
+                    #[cfg(feature = "certification")]
+                    &mut self.certification,
+                    #[cfg(feature = "multicast")]
+                    &mut self.multicast,
+                    &mut self.security,
+                    frame,
+                    dl,
+                    rf_config.max_payload_len,
+                    snr,
+                    true,
+                );
+                match response {
+                    Response::DownlinkReceived(_) => {
+                        self.health.downlink();
+                        self.frame_pending = frame_pending(frame);
+                    }
+                    Response::SessionExpired => self.expire_session(),
+                    _ => (),
+                }
+                Ok(response)
+            }
             State::Otaa(_) => Err(Error::NotJoined),
             State::Unjoined => Err(Error::NotJoined),
         }
     }
 
     pub(crate) fn rx2_complete(&mut self) -> Response {
+        trace!("RX windows complete");
         match &mut self.state {
-            State::Joined(session) => session.rx2_complete(),
-            State::Otaa(otaa) => otaa.rx2_complete(),
+            State::Joined(session) => {
+                let repeat = self.repetitions > 0;
+                self.repetitions = self.repetitions.saturating_sub(1);
+                let response = session.rx2_complete(repeat);
+                match response {
+                    Response::NoAck => {
+                        self.region.channel_stats_mut().no_downlink();
+                        self.health.no_downlink();
+                        self.rx2_profiles.missed();
+                    }
+                    Response::RxComplete => {
+                        self.health.no_downlink();
+                        self.rx2_profiles.missed();
+                    }
+                    Response::SessionExpired => self.expire_session(),
+                    _ => (),
+                }
+                response
+            }
+            State::Otaa(otaa) => {
+                self.rx2_profiles.missed();
+                otaa.rx2_complete()
+            }
             State::Unjoined => Response::NoUpdate,
         }
     }
 
+    /// A frame counter has been exhausted, so the session must not be used anymore and the
+    /// device needs to join again.
+    fn expire_session(&mut self) {
+        self.security.report(SecurityEvent::SessionExpired);
+        self.state = State::Unjoined;
+    }
+
+    pub(crate) fn get_dev_nonce_counter(&self) -> Option<u32> {
+        self.dev_nonces.counter()
+    }
+
+    pub(crate) fn set_dev_nonce_counter(&mut self, next: Option<u32>) {
+        self.dev_nonces.set_counter(next);
+    }
+
+    pub(crate) fn dev_nonces_remaining(&self) -> Option<u32> {
+        self.dev_nonces.remaining()
+    }
+
+    pub(crate) fn get_security_counters(&self) -> SecurityCounters {
+        self.security.counters()
+    }
+
+    pub(crate) fn get_join_datarates(&self) -> JoinDatarates {
+        self.join.datarates()
+    }
+
+    pub(crate) fn set_join_datarates(&mut self, datarates: JoinDatarates) {
+        self.join.set_datarates(datarates);
+    }
+
+    pub(crate) fn last_join_attempt(&self) -> Option<JoinAttempt> {
+        self.join.last_attempt()
+    }
+
+    pub(crate) fn set_mic_diagnostics(&mut self, enabled: bool) {
+        self.security.set_mic_diagnostics(enabled);
+    }
+
+    pub(crate) fn get_fcnt_gap_policy(&self) -> FcntGapPolicy {
+        self.security.fcnt_gap_policy()
+    }
+
+    pub(crate) fn set_fcnt_gap_policy(&mut self, policy: FcntGapPolicy) {
+        self.security.set_fcnt_gap_policy(policy);
+    }
+
+    /// Reset the downlink frame counter of the session, so that the frame counter of the next
+    /// downlink is accepted whatever its gap.
+    pub(crate) fn resync_fcnt_down(&mut self) -> Result<()> {
+        match &mut self.state {
+            State::Joined(session) => {
+                let previous = core::mem::take(&mut session.fcnt_down);
+                session.downlink_received = false;
+                self.security.fcnt_down_resync(previous);
+                Ok(())
+            }
+            State::Otaa(_) | State::Unjoined => Err(Error::NotJoined),
+        }
+    }
+
+    pub(crate) fn take_security_event(&mut self) -> Option<SecurityEvent> {
+        self.security.take_event()
+    }
+
     pub(crate) fn get_session_keys(&self) -> Option<SessionKeys> {
         match &self.state {
             State::Joined(session) => session.get_session_keys(),
@@ -320,6 +1077,21 @@ impl Mac {
         }
     }
 
+    pub(crate) fn set_session_key_export(&mut self, enabled: bool) {
+        self.export_session_keys = enabled;
+        if !enabled {
+            self.exported_session_keys = None;
+        }
+    }
+
+    pub(crate) fn get_session_key_export(&self) -> bool {
+        self.export_session_keys
+    }
+
+    pub(crate) fn take_exported_session_keys(&mut self) -> Option<SessionKeys> {
+        self.exported_session_keys.take()
+    }
+
     pub(crate) fn get_session(&self) -> Option<&Session> {
         match &self.state {
             State::Joined(session) => Some(session),
@@ -332,6 +1104,30 @@ impl Mac {
         matches!(&self.state, State::Joined(_))
     }
 
+    /// Whether the network has more downlinks queued (FPending bit of the last downlink) and no
+    /// uplink has been sent since to open the RX windows for them.
+    pub(crate) fn is_frame_pending(&self) -> bool {
+        self.frame_pending
+    }
+
+    /// Whether a confirmed downlink has been received which has not been acknowledged yet.
+    pub(crate) fn is_ack_pending(&self) -> bool {
+        match &self.state {
+            State::Joined(session) => session.is_ack_pending(),
+            State::Otaa(_) => false,
+            State::Unjoined => false,
+        }
+    }
+
+    /// Whether pending MAC commands exceed what a single uplink can carry in FOpts.
+    pub(crate) fn mac_commands_exceed_fopts(&self) -> bool {
+        match &self.state {
+            State::Joined(session) => session.mac_commands_exceed_fopts(&self.configuration),
+            State::Otaa(_) => false,
+            State::Unjoined => false,
+        }
+    }
+
     pub(crate) fn get_fcnt_up(&self) -> Option<FcntUp> {
         match &self.state {
             State::Joined(session) => Some(session.fcnt_up),
@@ -340,14 +1136,15 @@ impl Mac {
         }
     }
 
-    /// Build RfConfig for given `Frame` and `Window` and apply
-    /// network-specific overrides.
-    pub(crate) fn get_rf_config(&self, frame: &Frame, window: &Window) -> RfConfig {
-        let (frequency, dr) = match window {
+    /// Frequency and data rate of the given RX window, with the network-specific overrides and
+    /// the RX2 profile in use applied.
+    fn rx_parameters(&self, frame: &Frame, window: &Window) -> (u32, DR) {
+        let rx2_profile = self.rx2_profiles.current();
+        match window {
             Window::_1 => (
                 self.region.get_rx_frequency(frame, window),
                 self.region.get_rx_datarate(
-                    self.configuration.data_rate,
+                    self.uplink_data_rate(),
                     self.configuration.rx1_dr_offset,
                     window,
                 ),
@@ -357,18 +1154,28 @@ impl Mac {
                     // RX2 frequency override
                     self.configuration
                         .rx2_frequency
+                        .or(rx2_profile.map(|profile| profile.frequency))
                         .unwrap_or_else(|| self.region.get_rx_frequency(frame, window)),
                     // RX2 datarate override
-                    self.configuration.rx2_data_rate.unwrap_or_else(|| {
-                        self.region.get_rx_datarate(
-                            self.configuration.data_rate,
-                            self.configuration.rx1_dr_offset,
-                            window,
-                        )
-                    }),
+                    self.configuration
+                        .rx2_data_rate
+                        .or(rx2_profile.map(|profile| profile.data_rate))
+                        .unwrap_or_else(|| {
+                            self.region.get_rx_datarate(
+                                self.uplink_data_rate(),
+                                self.configuration.rx1_dr_offset,
+                                window,
+                            )
+                        }),
                 )
             }
-        };
+        }
+    }
+
+    /// Build RfConfig for given `Frame` and `Window` and apply
+    /// network-specific overrides.
+    pub(crate) fn get_rf_config(&self, frame: &Frame, window: &Window) -> RfConfig {
+        let (frequency, dr) = self.rx_parameters(frame, window);
 
         // Handle possibly unsupported datarates by falling back to RX2 datarate
         let datarate = match self.region.get_datarate(dr as u8) {
@@ -376,11 +1183,13 @@ impl Mac {
             None => {
                 warn!(
                     "Unsupported DR: {:?} (TX DR: {:?}, Window: {:?})",
-                    dr, self.configuration.data_rate, window
+                    dr,
+                    self.uplink_data_rate(),
+                    window
                 );
                 self.region
                     .get_datarate(self.region.get_rx_datarate(
-                        self.configuration.data_rate,
+                        self.uplink_data_rate(),
                         self.configuration.rx1_dr_offset,
                         &Window::_2,
                     ) as u8)
@@ -419,6 +1228,7 @@ pub(crate) enum Response {
     JoinSuccess,
     NoUpdate,
     RxComplete,
+    #[cfg(feature = "certification")]
     LinkCheckReq,
     #[cfg(feature = "certification")]
     UplinkPrepared,
@@ -447,6 +1257,7 @@ impl From<Response> for nb_device::Response {
             Response::JoinSuccess => nb_device::Response::JoinSuccess,
             Response::NoUpdate => nb_device::Response::NoUpdate,
             Response::RxComplete => nb_device::Response::RxComplete,
+            #[cfg(feature = "certification")]
             Response::LinkCheckReq => unimplemented!(),
             #[cfg(feature = "certification")]
             Response::UplinkPrepared => unimplemented!(),
@@ -502,3 +1313,24 @@ fn del_to_delay_ms(del: u8) -> u32 {
         _ => region::constants::RECEIVE_DELAY1,
     }
 }
+
+/// FPending bit of the data downlink in the buffer. The FHDR is not encrypted, so it can still be
+/// read after the FRMPayload has been decrypted in place.
+fn frame_pending(frame: &[u8]) -> bool {
+    // MHDR and DevAddr precede FCtrl
+    frame.get(5).is_some_and(|&fctrl| FCtrl::new(fctrl, false).f_pending())
+}
+
+/// The FPort and application payload of a data frame which has been decrypted in place by
+/// [`Mac::handle_rxc`]. Returns `None` for frames without application payload.
+#[cfg(feature = "class-c")]
+pub(crate) fn frm_payload(frame: &[u8]) -> Option<(u8, &[u8])> {
+    use lorawan::packet_length::phy::{mac::FPORT_LEN, MHDR_LEN, MIC_LEN};
+    use lorawan::parser::{DataHeader, EncryptedDataPayload};
+
+    // Only the layout of the frame is parsed, which is the same before and after decryption
+    let payload = EncryptedDataPayload::new(frame).ok()?;
+    let fport = payload.f_port().filter(|fport| *fport != 0)?;
+    let start = MHDR_LEN + payload.fhdr_length() + FPORT_LEN;
+    Some((fport, &frame[start..frame.len() - MIC_LEN]))
+}
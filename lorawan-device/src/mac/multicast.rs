@@ -1,11 +1,11 @@
+use crate::collections::Collection;
 use crate::mac::FcntDown;
 use crate::radio::RadioBuffer;
 use crate::Downlink;
 use crate::{async_device, mac};
 use core::fmt::Debug;
 use core::ops::RangeInclusive;
-use lorawan::default_crypto::DefaultFactory;
-use lorawan::keys::McKEKey;
+use lorawan::keys::{CryptoFactory, McKEKey};
 pub use lorawan::multicast::{self, Session};
 use lorawan::multicast::{
     parse_downlink_multicast_messages, DownlinkRemoteSetup, McGroupDeleteAnsCreator,
@@ -62,15 +62,16 @@ impl Multicast {
         }
     }
 
-    pub(crate) fn handle_rx<const D: usize>(
+    pub(crate) fn handle_rx<C: CryptoFactory>(
         &mut self,
-        dl: &mut heapless::Vec<Downlink, D>,
+        crypto: &C,
+        dl: Option<&mut dyn Collection<Downlink>>,
         encrypted_data: EncryptedDataPayload<&mut [u8]>,
     ) -> Response {
         let mc_addr = encrypted_data.fhdr().mc_addr();
         if let Some((group_id, session)) = self.matching_session(mc_addr) {
             let fcnt = encrypted_data.fhdr().fcnt() as u32;
-            if encrypted_data.validate_mic(session.mc_net_s_key().inner(), fcnt, &DefaultFactory)
+            if encrypted_data.validate_mic(session.mc_net_s_key().inner(), fcnt, crypto)
                 && (fcnt > session.fcnt_down || fcnt == 0)
             {
                 return {
@@ -81,21 +82,21 @@ impl Multicast {
                             Some(session.mc_net_s_key().inner()),
                             Some(session.mc_app_s_key().inner()),
                             session.fcnt_down,
-                            &DefaultFactory,
+                            crypto,
                         )
                         .unwrap();
                     if session.fcnt_down == session.max_fcnt_down() {
                         // if the FCnt is used up, the session has expired
                         Response::SessionExpired { group_id }
                     } else {
-                        if let (Some(fport), FRMPayload::Data(data)) =
-                            (decrypted.f_port(), decrypted.frm_payload())
+                        if let (Some(dl), Some(fport), FRMPayload::Data(data)) =
+                            (dl, decrypted.f_port(), decrypted.frm_payload())
                         {
                             // heapless Vec from slice fails only if slice is too large.
                             // A data FRM payload will never exceed 256 bytes.
                             let data = heapless::Vec::from_slice(data).unwrap();
                             // TODO: propagate error when heapless vec is full?
-                            let _ = dl.push(Downlink { data, fport });
+                            let _ = dl.try_push(Downlink { data, fport });
                         }
                         Response::DownlinkReceived { group_id, fcnt }
                     }
@@ -125,7 +126,11 @@ impl Multicast {
         self.remote_setup_port == port
     }
 
-    pub(crate) fn handle_setup_message(&mut self, data: &[u8]) -> Response {
+    pub(crate) fn handle_setup_message<C: CryptoFactory>(
+        &mut self,
+        crypto: &C,
+        data: &[u8],
+    ) -> Response {
         if self.mc_k_e_key.is_none() {
             return Response::NoUpdate;
         }
@@ -135,9 +140,7 @@ impl Multicast {
         for message in messages {
             match message {
                 DownlinkRemoteSetup::McGroupSetupReq(mc_group_setup_req) => {
-                    let crypto = DefaultFactory;
-                    let (group_id, session) =
-                        mc_group_setup_req.derive_session(&crypto, mc_k_e_key);
+                    let (group_id, session) = mc_group_setup_req.derive_session(crypto, mc_k_e_key);
                     self.sessions[group_id as usize] = Some(session);
                     let mut ans = McGroupSetupAnsCreator::new();
                     ans.mc_group_id_header(group_id);
@@ -197,9 +200,11 @@ impl Multicast {
         }
     }
 
-    pub(crate) fn setup_send<const N: usize>(
+    pub(crate) fn setup_send<C: CryptoFactory, const N: usize>(
         &mut self,
+        crypto: &C,
         mut state: &mut mac::State,
+        configuration: &mac::Configuration,
         buf: &mut RadioBuffer<N>,
     ) -> mac::Result<mac::FcntUp> {
         let send_data = mac::SendData {
@@ -209,7 +214,7 @@ impl Multicast {
         };
         match &mut state {
             mac::State::Joined(ref mut session) => {
-                let response = session.prepare_buffer::<N>(&send_data, buf);
+                let response = session.prepare_buffer(crypto, configuration, &send_data, buf);
                 self.pending_uplinks.clear();
                 Ok(response)
             }
@@ -2,12 +2,11 @@ use super::{del_to_delay_ms, session::Session, Response};
 use crate::radio::RadioBuffer;
 use crate::region::Configuration;
 use crate::{AppEui, AppKey, DevEui};
-use lorawan::default_crypto::DefaultFactory;
 use lorawan::{
     creator::JoinRequestCreator,
+    keys::CryptoFactory,
     parser::{parse as lorawan_parse, *},
 };
-use rand_core::RngCore;
 
 pub(crate) type DevNonce = lorawan::parser::DevNonce<[u8; 2]>;
 
@@ -30,25 +29,26 @@ impl Otaa {
 
     /// Prepare a join request to be sent. This populates the radio buffer with the request to be
     /// sent, and returns the radio config to use for transmitting.
-    pub(crate) fn prepare_buffer<G: RngCore, const N: usize>(
+    pub(crate) fn prepare_buffer<C: CryptoFactory, const N: usize>(
         &mut self,
-        rng: &mut G,
+        crypto: &C,
+        dev_nonce: u16,
         buf: &mut RadioBuffer<N>,
     ) -> u16 {
-        self.dev_nonce = DevNonce::from(rng.next_u32() as u16);
+        self.dev_nonce = DevNonce::from(dev_nonce);
         buf.clear();
         let mut phy = JoinRequestCreator::new(buf.as_mut()).unwrap();
         phy.set_app_eui(self.network_credentials.appeui)
             .set_dev_eui(self.network_credentials.deveui)
             .set_dev_nonce(self.dev_nonce);
-        let crypto_factory = DefaultFactory;
-        let len = phy.build(&self.network_credentials.appkey, &crypto_factory).len();
+        let len = phy.build_in_place(&self.network_credentials.appkey, crypto);
         buf.set_pos(len);
         u16::from(self.dev_nonce)
     }
 
-    pub(crate) fn handle_rx<const N: usize>(
+    pub(crate) fn handle_rx<C: CryptoFactory, const N: usize>(
         &mut self,
+        crypto: &C,
         region: &mut Configuration,
         configuration: &mut super::Configuration,
         rx: &mut RadioBuffer<N>,
@@ -56,15 +56,18 @@ impl Otaa {
         if let Ok(PhyPayload::JoinAccept(JoinAcceptPayload::Encrypted(encrypted))) =
             lorawan_parse(rx.as_mut_for_read())
         {
-            let decrypt = encrypted.decrypt(&self.network_credentials.appkey, &DefaultFactory);
-            region.process_join_accept(&decrypt);
-            // TODO: dlsettings (rx1_dr_offset / rx2_datarate)
-            configuration.rx1_delay = del_to_delay_ms(decrypt.rx_delay());
-            if decrypt.validate_mic(&self.network_credentials.appkey, &DefaultFactory) {
-                return Some(Session::derive_new(
+            let decrypt = encrypted.decrypt(&self.network_credentials.appkey, crypto);
+            if decrypt.validate_mic(&self.network_credentials.appkey, crypto) {
+                // the settings of a previous session don't carry over to the new one
+                configuration.restore_regional_defaults(region);
+                region.process_join_accept(&decrypt);
+                // TODO: dlsettings (rx1_dr_offset / rx2_datarate)
+                configuration.rx1_delay = del_to_delay_ms(decrypt.rx_delay());
+                return Some(Session::derive_new_with_crypto(
                     &decrypt,
                     self.dev_nonce,
                     &self.network_credentials,
+                    crypto,
                 ));
             }
         }
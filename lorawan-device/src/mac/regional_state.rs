@@ -0,0 +1,124 @@
+//! Long-lived regional state set up by the network (enabled channels, dwell time and RX settings),
+//! kept apart from the session keys and frame counters. The LoRaWAN specification requires ABP
+//! devices to persist this state across resets, which is possible with [`RegionalState`] even
+//! when the frame counters are not persisted.
+use super::Mac;
+use crate::region::{ChannelSettings, Region};
+use lorawan::types::{ChannelMask, DR};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct RegionalState {
+    /// The region the state has been taken in; it can only be restored in the same region.
+    pub region: Region,
+    pub channel_mask: ChannelMask<9>,
+    /// Channels of dynamic channel plans (CFList, NewChannelReq and DlChannelReq). Always `None`
+    /// for fixed channel plans (US915, AU915).
+    pub channels: [Option<ChannelSettings>; 16],
+    /// Dwell time and MaxEIRP (in dBm) of TxParamSetupReq; `None` stands for the regional default
+    /// EIRP.
+    pub uplink_dwell_time: bool,
+    pub downlink_dwell_time: bool,
+    pub max_eirp: Option<u8>,
+    /// RX1 delay in milliseconds (JoinAccept or RXTimingSetupReq).
+    pub rx1_delay: u32,
+    pub rx1_dr_offset: u8,
+    /// RX2 settings of RXParamSetupReq; `None` stands for the regional default.
+    pub rx2_frequency: Option<u32>,
+    pub rx2_data_rate: Option<DR>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum RegionalStateError {
+    /// The state has been taken in another region than the one of the device.
+    RegionMismatch,
+}
+
+impl Mac {
+    pub(crate) fn get_regional_state(&self) -> RegionalState {
+        let configuration = &self.configuration;
+        RegionalState {
+            region: self.region.get_current_region(),
+            channel_mask: self.region.channel_mask_get(),
+            channels: self.region.channels_get(),
+            uplink_dwell_time: configuration.uplink_dwell_time,
+            downlink_dwell_time: self.region.get_downlink_dwell_time(),
+            max_eirp: configuration.network_max_eirp,
+            rx1_delay: configuration.rx1_delay,
+            rx1_dr_offset: configuration.rx1_dr_offset,
+            rx2_frequency: configuration.rx2_frequency,
+            rx2_data_rate: configuration.rx2_data_rate,
+        }
+    }
+
+    pub(crate) fn restore_regional_state(
+        &mut self,
+        state: &RegionalState,
+    ) -> Result<(), RegionalStateError> {
+        if state.region != self.region.get_current_region() {
+            return Err(RegionalStateError::RegionMismatch);
+        }
+        self.region.channels_set(&state.channels);
+        self.region.channel_mask_set(state.channel_mask.clone());
+        self.region.set_downlink_dwell_time(state.downlink_dwell_time);
+        let configuration = &mut self.configuration;
+        configuration.uplink_dwell_time = state.uplink_dwell_time;
+        configuration.network_max_eirp = state.max_eirp;
+        configuration.rx1_delay = state.rx1_delay;
+        configuration.rx1_dr_offset = state.rx1_dr_offset;
+        configuration.rx2_frequency = state.rx2_frequency;
+        configuration.rx2_data_rate = state.rx2_data_rate;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mac::{Frame, Window};
+    use crate::region;
+    use lorawan::types::DataRateRange;
+
+    #[test]
+    fn state_survives_restart() {
+        let mut mac = Mac::new(region::EU868::new_eu868().into(), 14, 0);
+        let defaults = mac.get_regional_state();
+        let data_rates = DataRateRange::new_range(DR::_0, DR::_5);
+        mac.region.handle_new_channel(3, 867_100_000, Some(data_rates));
+        mac.region.channel_mask_set(ChannelMask::new_from_raw(&[0b1001, 0, 0, 0, 0, 0, 0, 0, 0]));
+        mac.set_rx2(869_525_000, DR::_3);
+        mac.configuration.rx1_delay = 5000;
+        mac.configuration.network_max_eirp = Some(14);
+        mac.region.set_downlink_dwell_time(true);
+        let state = mac.get_regional_state();
+        assert_ne!(state, defaults);
+        assert_eq!(state.channels[3].map(|channel| channel.frequency), Some(867_100_000));
+
+        let mut restarted = Mac::new(region::EU868::new_eu868().into(), 14, 0);
+        restarted.restore_regional_state(&state).unwrap();
+        assert_eq!(restarted.get_regional_state(), state);
+        assert_eq!(restarted.get_rx_delay(&Frame::Data, &Window::_1), 5000);
+        assert_eq!(restarted.configuration.network_max_eirp, Some(14));
+        assert!(restarted.region.get_downlink_dwell_time());
+
+        let mut us915 = Mac::new(region::US915::new().into(), 14, 0);
+        assert_eq!(us915.restore_regional_state(&state), Err(RegionalStateError::RegionMismatch));
+        assert!(us915.get_regional_state().channels.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn describe_network_settings() {
+        let mut mac = Mac::new(region::EU868::new_eu868().into(), 14, 0);
+        let data_rates = DataRateRange::new_range(DR::_0, DR::_5);
+        mac.region.handle_new_channel(3, 867_100_000, Some(data_rates));
+        mac.set_rx2(869_525_000, DR::_3);
+        let summary = mac.describe_region();
+        assert_eq!(summary.channels.len(), 4);
+        assert_eq!(summary.channels[3].index, 3);
+        assert_eq!(summary.channels[3].frequency, 867_100_000);
+        assert_eq!((summary.rx2_frequency, summary.rx2_datarate), (869_525_000, DR::_3));
+        assert_eq!(mac.region.describe().rx2_datarate, DR::_0);
+    }
+}
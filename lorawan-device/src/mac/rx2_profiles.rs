@@ -0,0 +1,125 @@
+//! Ordered RX2 candidates for networks which changed their RX2 parameters over time (eg: Helium),
+//! so that devices keep receiving downlinks during the migration whichever parameters the network
+//! uses.
+//!
+//! The candidates replace the regional RX2 default, but not the parameters set by the network via
+//! RXParamSetupReq (or the provisioned ones). Whenever the RX windows pass without a downlink, the
+//! next candidate is used for the following uplink. Once a downlink is received in RX2, the device
+//! sticks to that candidate until the next join or until the candidates are replaced.
+use super::Window;
+use heapless::Vec;
+use lorawan::types::DR;
+
+/// Maximum number of RX2 candidates.
+pub const MAX_RX2_PROFILES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Rx2Profile {
+    pub frequency: u32,
+    pub data_rate: DR,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Rx2Profiles {
+    profiles: Vec<Rx2Profile, MAX_RX2_PROFILES>,
+    current: usize,
+    locked: bool,
+}
+
+impl Rx2Profiles {
+    pub(crate) fn set(&mut self, profiles: Vec<Rx2Profile, MAX_RX2_PROFILES>) {
+        *self = Self { profiles, ..Default::default() };
+    }
+
+    pub(crate) fn profiles(&self) -> &[Rx2Profile] {
+        &self.profiles
+    }
+
+    pub(crate) fn current(&self) -> Option<Rx2Profile> {
+        self.profiles.get(self.current).copied()
+    }
+
+    /// A downlink has been received in `window` on `frequency` at `data_rate`. Only a downlink in
+    /// RX2 with exactly the parameters of the current candidate confirms it.
+    pub(crate) fn received(&mut self, window: &Window, frequency: u32, data_rate: DR) {
+        if !matches!(window, Window::_2) {
+            return;
+        }
+        let received = Rx2Profile { frequency, data_rate };
+        if let Some(profile) = self.current().filter(|profile| *profile == received) {
+            if !self.locked {
+                debug!("Using RX2 profile {}", profile);
+            }
+            self.locked = true;
+        }
+    }
+
+    /// The RX windows passed without a downlink.
+    pub(crate) fn missed(&mut self) {
+        if !self.locked && !self.profiles.is_empty() {
+            self.current = (self.current + 1) % self.profiles.len();
+        }
+    }
+
+    /// Try all candidates again, eg: as the device may have joined another network.
+    pub(crate) fn unlock(&mut self) {
+        self.locked = false;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{Frame, Mac, Window};
+    use super::*;
+    use crate::region;
+
+    const OLD: Rx2Profile = Rx2Profile { frequency: 869_525_000, data_rate: DR::_0 };
+    const NEW: Rx2Profile = Rx2Profile { frequency: 869_525_000, data_rate: DR::_3 };
+    const OTHER: Rx2Profile = Rx2Profile { frequency: 869_100_000, data_rate: DR::_8 };
+
+    #[test]
+    fn candidates_rotate_until_received() {
+        let mut profiles = Rx2Profiles::default();
+        profiles.set(Vec::from_slice(&[OLD, OTHER]).unwrap());
+        assert_eq!(profiles.current(), Some(OLD));
+        profiles.missed();
+        assert_eq!(profiles.current(), Some(OTHER));
+        profiles.missed();
+        assert_eq!(profiles.current(), Some(OLD));
+        // a downlink in RX1
+        profiles.received(&Window::_1, 868_100_000, DR::_5);
+        profiles.missed();
+        assert_eq!(profiles.current(), Some(OTHER));
+        // RX1 on the frequency of the candidate
+        profiles.received(&Window::_1, OTHER.frequency, OTHER.data_rate);
+        profiles.missed();
+        assert_eq!(profiles.current(), Some(OLD));
+        // RX2 at another data rate than the candidate
+        profiles.received(&Window::_2, NEW.frequency, NEW.data_rate);
+        profiles.missed();
+        assert_eq!(profiles.current(), Some(OTHER));
+
+        profiles.received(&Window::_2, OTHER.frequency, OTHER.data_rate);
+        profiles.missed();
+        assert_eq!(profiles.current(), Some(OTHER));
+        profiles.unlock();
+        profiles.missed();
+        assert_eq!(profiles.current(), Some(OLD));
+    }
+
+    #[test]
+    fn network_override_takes_precedence() {
+        let mut mac = Mac::new(region::EU868::new_eu868().into(), 14, 0);
+        let rx2 = |mac: &Mac| mac.get_rf_config(&Frame::Data, &Window::_2);
+        let sf12 = rx2(&mac).bb;
+
+        mac.set_rx2_profiles(Vec::from_slice(&[NEW, OLD]).unwrap());
+        assert_eq!(mac.get_rx2_profile(), Some(NEW));
+        assert_eq!(rx2(&mac).frequency, NEW.frequency);
+        assert_ne!(rx2(&mac).bb, sf12);
+
+        mac.set_rx2(869_525_000, DR::_0);
+        assert_eq!(rx2(&mac).bb, sf12);
+    }
+}
@@ -0,0 +1,50 @@
+//! Overrides of the receive delays (RECEIVE_DELAY1/2 and JOIN_ACCEPT_DELAY1/2) for test networks
+//! and protocol experiments. A device using them is not LoRaWAN compliant, which is why they are
+//! only available with the `test-mode` feature.
+use super::{Frame, Window};
+
+/// Receive delays replacing the LoRaWAN ones, in milliseconds from the end of the uplink. `None`
+/// keeps the regular delay.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct RxDelayOverrides {
+    /// Replaces RECEIVE_DELAY1 as well as the delay set by the network (RXTimingSetupReq and
+    /// JoinAccept).
+    pub rx1_ms: Option<u32>,
+    /// Replaces RECEIVE_DELAY2, which is otherwise one second after RX1.
+    pub rx2_ms: Option<u32>,
+    pub join_accept1_ms: Option<u32>,
+    pub join_accept2_ms: Option<u32>,
+}
+
+impl RxDelayOverrides {
+    pub(crate) fn get(&self, frame: &Frame, window: &Window) -> Option<u32> {
+        match (frame, window) {
+            (Frame::Join, Window::_1) => self.join_accept1_ms,
+            (Frame::Join, Window::_2) => self.join_accept2_ms,
+            (Frame::Data, Window::_1) => self.rx1_ms,
+            (Frame::Data, Window::_2) => self.rx2_ms,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::Mac;
+    use super::*;
+    use crate::region;
+
+    #[test]
+    fn overrides_replace_delays() {
+        let mut mac = Mac::new(region::EU868::new_eu868().into(), 14, 0);
+        mac.set_rx_delay_overrides(RxDelayOverrides {
+            rx2_ms: Some(1500),
+            join_accept1_ms: Some(2000),
+            ..Default::default()
+        });
+        assert_eq!(mac.get_rx_delay(&Frame::Data, &Window::_1), 1000);
+        assert_eq!(mac.get_rx_delay(&Frame::Data, &Window::_2), 1500);
+        assert_eq!(mac.get_rx_delay(&Frame::Join, &Window::_1), 2000);
+        assert_eq!(mac.get_rx_delay(&Frame::Join, &Window::_2), 6000);
+    }
+}
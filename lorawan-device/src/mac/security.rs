@@ -0,0 +1,159 @@
+//! Security relevant events which cause downlinks to be dropped or the session to be expired,
+//! made visible to the application (eg: for logging in security-sensitive deployments).
+use crate::collections::EventQueue;
+
+/// Number of events retained until taken by the application. When full, the oldest event is
+/// discarded.
+const MAX_EVENTS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum SecurityEvent {
+    /// A downlink with a valid MIC, but a frame counter which has already been received.
+    ReplayedDownlink { fcnt: u32 },
+    /// A downlink with a valid MIC, but a frame counter which advanced by at least the maximum
+    /// gap of the [`FcntGapPolicy`]. The downlink is dropped unless the policy accepts it.
+    FcntGapExceeded { fcnt: u32 },
+    /// The downlink frame counter has been reset by the application (`resync_fcnt_down`), so the
+    /// frame counter of the next downlink is accepted whatever its gap.
+    FcntDownResynced { previous: u32 },
+    /// A downlink addressed to this device which failed MIC verification.
+    MicFailure { fcnt: u32 },
+    /// Reported instead of [`SecurityEvent::MicFailure`] while MIC diagnostics are enabled.
+    MicFailureDiagnosed { fcnt: u32, reason: MicFailureReason },
+    /// A frame counter has been exhausted and the session can't be used anymore.
+    SessionExpired,
+    /// A join request has been sent with one of the last DevNonces of the counter; the device
+    /// needs to be re-provisioned (new JoinEUI or DevEUI) before it runs out.
+    DevNonceLow { remaining: u32 },
+}
+
+/// Likely cause of a MIC failure, determined by trying to verify the MIC with other frame counters
+/// and keys. Meant to speed up finding key and frame counter mistakes during bring-up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum MicFailureReason {
+    /// The MIC is valid for another frame counter, so the frame counters of the device and the
+    /// network are out of sync (eg: after a reset without persisting the session).
+    FcntOutOfWindow { fcnt: u32 },
+    /// The MIC is valid with the AppSKey, so NwkSKey and AppSKey have been swapped.
+    KeysSwapped,
+    /// The MIC is not valid with any of the tried frame counters or keys, most likely the NwkSKey
+    /// is wrong (eg: provisioned with the wrong byte order).
+    WrongKey,
+}
+
+/// Maximum gap between the frame counters of consecutive downlinks recommended by LoRaWAN 1.0.
+pub const MAX_FCNT_GAP: u32 = crate::region::constants::MAX_FCNT_GAP as u32;
+
+/// Determines how downlinks whose frame counter advanced by a large gap are handled, see
+/// [`SecurityEvent::FcntGapExceeded`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum FcntGapPolicy {
+    /// Drop downlinks whose frame counter advanced by `max_gap` or more.
+    Reject { max_gap: u32 },
+    /// Accept downlinks whose frame counter advanced by `max_gap` or more (but didn't overflow),
+    /// still reporting the gap. Meant for networks which are known to lose downlinks in bulk.
+    Accept { max_gap: u32 },
+}
+
+impl Default for FcntGapPolicy {
+    fn default() -> Self {
+        FcntGapPolicy::Reject { max_gap: MAX_FCNT_GAP }
+    }
+}
+
+impl FcntGapPolicy {
+    pub(crate) fn max_gap(self) -> u32 {
+        match self {
+            FcntGapPolicy::Reject { max_gap } | FcntGapPolicy::Accept { max_gap } => max_gap,
+        }
+    }
+}
+
+/// Number of security events since the device was created.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct SecurityCounters {
+    pub replayed_downlinks: u16,
+    pub fcnt_gaps_exceeded: u16,
+    pub mic_failures: u16,
+    pub sessions_expired: u16,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Security {
+    counters: SecurityCounters,
+    events: EventQueue<SecurityEvent, MAX_EVENTS>,
+    mic_diagnostics: bool,
+    fcnt_gap_policy: FcntGapPolicy,
+    /// Whether the frame counter of the next downlink is accepted whatever its gap.
+    fcnt_down_resync: bool,
+    /// Whether a MIC failure has been reported since the last `take_mic_failure`.
+    mic_failed: bool,
+}
+
+impl Security {
+    pub(crate) fn counters(&self) -> SecurityCounters {
+        self.counters
+    }
+
+    pub(crate) fn mic_diagnostics(&self) -> bool {
+        self.mic_diagnostics
+    }
+
+    pub(crate) fn set_mic_diagnostics(&mut self, enabled: bool) {
+        self.mic_diagnostics = enabled;
+    }
+
+    pub(crate) fn fcnt_gap_policy(&self) -> FcntGapPolicy {
+        self.fcnt_gap_policy
+    }
+
+    pub(crate) fn set_fcnt_gap_policy(&mut self, policy: FcntGapPolicy) {
+        self.fcnt_gap_policy = policy;
+    }
+
+    /// Whether a downlink whose frame counter exceeded the maximum gap is accepted.
+    pub(crate) fn accepts_fcnt_gap(&self) -> bool {
+        self.fcnt_down_resync || matches!(self.fcnt_gap_policy, FcntGapPolicy::Accept { .. })
+    }
+
+    pub(crate) fn fcnt_down_resync(&mut self, previous: u32) {
+        self.fcnt_down_resync = true;
+        self.report(SecurityEvent::FcntDownResynced { previous });
+    }
+
+    /// A downlink has been accepted, so its frame counter is in sync.
+    pub(crate) fn fcnt_down_synced(&mut self) {
+        self.fcnt_down_resync = false;
+    }
+
+    /// Whether a downlink addressed to this device failed MIC verification since the last call.
+    pub(crate) fn take_mic_failure(&mut self) -> bool {
+        core::mem::take(&mut self.mic_failed)
+    }
+
+    pub(crate) fn take_event(&mut self) -> Option<SecurityEvent> {
+        self.events.pop()
+    }
+
+    pub(crate) fn report(&mut self, event: SecurityEvent) {
+        warn!("Security event: {}", event);
+        let counter = match event {
+            SecurityEvent::ReplayedDownlink { .. } => Some(&mut self.counters.replayed_downlinks),
+            SecurityEvent::FcntGapExceeded { .. } => Some(&mut self.counters.fcnt_gaps_exceeded),
+            SecurityEvent::MicFailure { .. } | SecurityEvent::MicFailureDiagnosed { .. } => {
+                self.mic_failed = true;
+                Some(&mut self.counters.mic_failures)
+            }
+            SecurityEvent::SessionExpired => Some(&mut self.counters.sessions_expired),
+            SecurityEvent::DevNonceLow { .. } | SecurityEvent::FcntDownResynced { .. } => None,
+        };
+        if let Some(counter) = counter {
+            *counter = counter.saturating_add(1);
+        }
+        self.events.push(event);
+    }
+}
@@ -1,18 +1,25 @@
 use super::{
     otaa::{DevNonce, NetworkCredentials},
+    security::{MicFailureReason, Security, SecurityEvent},
     uplink, FcntUp, Response, SendData,
 };
+use crate::collections::Collection;
 use crate::radio::RadioBuffer;
 use crate::{region, AppSKey, Downlink, NwkSKey};
 use heapless::Vec;
 use lorawan::maccommandcreator::{
-    DevStatusAnsCreator, DlChannelAnsCreator, LinkADRAnsCreator, NewChannelAnsCreator,
-    RXParamSetupAnsCreator, RXTimingSetupAnsCreator,
+    ADRParamSetupAnsCreator, BeaconFreqAnsCreator, DevStatusAnsCreator, DeviceModeIndCreator,
+    DlChannelAnsCreator, LinkADRAnsCreator, NewChannelAnsCreator, PingSlotChannelAnsCreator,
+    RXParamSetupAnsCreator, RXTimingSetupAnsCreator, ResetIndCreator, TXParamSetupAnsCreator,
+};
+use lorawan::maccommands::{
+    parse_downlink_mac_commands_validated, parse_uplink_mac_commands, DownlinkMacCommand,
+    SerializableMacCommand, ValidatedMacCommandIterator,
 };
-use lorawan::maccommands::{DownlinkMacCommand, MacCommandIterator};
 use lorawan::{
     creator::DataPayloadCreator,
     default_crypto::DefaultFactory,
+    keys::CryptoFactory,
     packet_length::phy::{MHDR_LEN, MIC_LEN},
     parser::{parse as lorawan_parse, *},
     types::DR,
@@ -32,7 +39,12 @@ pub struct Session {
     pub devaddr: DevAddr<[u8; 4]>,
     pub fcnt_up: u32,
     pub fcnt_down: u32,
-    // TODO: ADR handling
+    /// Number of uplinks since the last downlink (ADR_ACK_CNT)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub adr_ack_cnt: u32,
+    /// Whether a downlink has been accepted, after which a downlink with FCnt 0 is a replay
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub downlink_received: bool,
     #[cfg(feature = "certification")]
     /// Whether to force ADR bit for subsequent frames
     pub override_adr: bool,
@@ -63,10 +75,21 @@ impl Session {
         decrypt: &DecryptedJoinAcceptPayload<T>,
         devnonce: DevNonce,
         credentials: &NetworkCredentials,
+    ) -> Self {
+        Self::derive_new_with_crypto(decrypt, devnonce, credentials, &DefaultFactory)
+    }
+
+    /// Like [`derive_new`](Session::derive_new), deriving the session keys with the given
+    /// crypto backend.
+    pub fn derive_new_with_crypto<T: AsRef<[u8]>, C: CryptoFactory>(
+        decrypt: &DecryptedJoinAcceptPayload<T>,
+        devnonce: DevNonce,
+        credentials: &NetworkCredentials,
+        crypto: &C,
     ) -> Self {
         Self::new(
-            decrypt.derive_nwkskey(&devnonce, credentials.appkey(), &DefaultFactory),
-            decrypt.derive_appskey(&devnonce, credentials.appkey(), &DefaultFactory),
+            decrypt.derive_nwkskey(&devnonce, credentials.appkey(), crypto),
+            decrypt.derive_appskey(&devnonce, credentials.appkey(), crypto),
             DevAddr::new([
                 decrypt.dev_addr().as_ref()[0],
                 decrypt.dev_addr().as_ref()[1],
@@ -85,6 +108,8 @@ impl Session {
             confirmed: false,
             fcnt_down: 0,
             fcnt_up: 0,
+            adr_ack_cnt: 0,
+            downlink_received: false,
             uplink: uplink::Uplink::default(),
 
             #[cfg(feature = "certification")]
@@ -118,21 +143,21 @@ impl Session {
 
 impl Session {
     #[allow(clippy::too_many_arguments)]
-    pub(crate) fn handle_rx<const N: usize, const D: usize>(
+    pub(crate) fn handle_rx<C: CryptoFactory>(
         &mut self,
+        crypto: &C,
         region: &mut region::Configuration,
         configuration: &mut super::Configuration,
         #[cfg(feature = "certification")] certification: &mut super::certification::Certification,
         #[cfg(feature = "multicast")] multicast: &mut super::multicast::Multicast,
-        rx: &mut RadioBuffer<N>,
-        dl: &mut Vec<Downlink, D>,
+        security: &mut Security,
+        rx: &mut [u8],
+        dl: Option<&mut dyn Collection<Downlink>>,
         max_payload_len: u8,
         snr: i8,
         ignore_mac: bool,
     ) -> Response {
-        if let Ok(PhyPayload::Data(DataPayload::Encrypted(encrypted_data))) =
-            lorawan_parse(rx.as_mut_for_read())
-        {
+        if let Ok(PhyPayload::Data(DataPayload::Encrypted(encrypted_data))) = lorawan_parse(rx) {
             {
                 // Drop oversized packets which exceed the maximum allowed
                 // transmission time defined by PHY layer.
@@ -141,14 +166,14 @@ impl Session {
                 let payload_len = encrypted_data.as_bytes().len();
                 if payload_len > max_payload_len as usize + MHDR_LEN + MIC_LEN {
                     info!("Dropping oversized payload.");
-                    return self.rx2_complete();
+                    return self.rx2_complete(false);
                 }
             }
 
             // If ignore_mac is false, we're dealing with Class A downlink and
             // therefore can clear uplinks which need to be retained for acknowledgment
             if !ignore_mac {
-                self.uplink.clear_mac_commands(false);
+                self.uplink.clear_mac_commands();
             }
 
             #[cfg(feature = "certification")]
@@ -160,109 +185,189 @@ impl Session {
             #[cfg(feature = "multicast")]
             if let Some(port) = encrypted_data.f_port() {
                 if multicast.is_in_range(port) {
-                    return multicast.handle_rx(dl, encrypted_data).into();
+                    return multicast.handle_rx(crypto, dl, encrypted_data).into();
                 }
             }
-            let fcnt = encrypted_data.fhdr().fcnt() as u32;
+            // Frames addressed to other devices are silently ignored
+            if encrypted_data.fhdr().dev_addr().as_ref() != self.devaddr.as_ref() {
+                return Response::NoUpdate;
+            }
+            let (fcnt, fcnt_error) = self.reconstruct_fcnt_down(
+                encrypted_data.fhdr().fcnt(),
+                security.fcnt_gap_policy().max_gap(),
+            );
+            if !encrypted_data.validate_mic(self.nwkskey().inner(), fcnt, crypto) {
+                let event = if security.mic_diagnostics() {
+                    let reason = self.diagnose_mic_failure(crypto, &encrypted_data, fcnt);
+                    SecurityEvent::MicFailureDiagnosed { fcnt, reason }
+                } else {
+                    SecurityEvent::MicFailure { fcnt }
+                };
+                security.report(event);
+                return Response::NoUpdate;
+            }
+            if let Some(event) = fcnt_error {
+                let accept = match event {
+                    // a frame counter which overflowed is never accepted
+                    SecurityEvent::FcntGapExceeded { fcnt } => {
+                        fcnt > self.fcnt_down && security.accepts_fcnt_gap()
+                    }
+                    _ => false,
+                };
+                security.report(event);
+                if !accept {
+                    return Response::NoUpdate;
+                }
+            }
+            security.fcnt_down_synced();
             let confirmed = encrypted_data.is_confirmed();
-            if encrypted_data.validate_mic(self.nwkskey().inner(), fcnt, &DefaultFactory)
-                && (fcnt > self.fcnt_down || fcnt == 0)
-            {
-                self.fcnt_down = fcnt;
-                // We can safely unwrap here because we already validated the MIC
-                let decrypted = encrypted_data
-                    .decrypt(
-                        Some(self.nwkskey().inner()),
-                        Some(self.appskey().inner()),
-                        self.fcnt_down,
-                        &DefaultFactory,
-                    )
-                    .unwrap();
-
-                if !ignore_mac {
-                    // MAC commands may be in the FHDR or the FRMPayload
+            self.fcnt_down = fcnt;
+            self.downlink_received = true;
+            self.adr_ack_cnt = 0;
+            // We can safely unwrap here because we already validated the MIC
+            let decrypted = encrypted_data
+                .decrypt(
+                    Some(self.nwkskey().inner()),
+                    Some(self.appskey().inner()),
+                    self.fcnt_down,
+                    crypto,
+                )
+                .unwrap();
+
+            configuration.snr_history.record(snr);
+            if !ignore_mac {
+                // MAC commands may be in the FHDR or the FRMPayload
+                self.handle_downlink_macs(
+                    configuration,
+                    region,
+                    decrypted.fhdr().downlink_mac_commands(),
+                    snr,
+                );
+                if let FRMPayload::MACCommands(mac_cmds) = decrypted.frm_payload() {
                     self.handle_downlink_macs(
                         configuration,
                         region,
-                        MacCommandIterator::<DownlinkMacCommand<'_>>::new(decrypted.fhdr().data()),
+                        parse_downlink_mac_commands_validated(mac_cmds.data()),
                         snr,
                     );
-                    if let FRMPayload::MACCommands(mac_cmds) = decrypted.frm_payload() {
-                        self.handle_downlink_macs(
-                            configuration,
-                            region,
-                            MacCommandIterator::<DownlinkMacCommand<'_>>::new(mac_cmds.data()),
-                            snr,
-                        );
-                    }
                 }
+                // Keep sending indications until they are confirmed
+                self.add_pending_indications(configuration);
+            }
 
-                if confirmed {
-                    self.uplink.set_downlink_confirmation();
-                }
+            if confirmed {
+                self.uplink.set_downlink_confirmation();
+            }
 
-                return if self.fcnt_up == 0xFFFF_FFFF {
-                    // if the FCnt is used up, the session has expired
-                    Response::SessionExpired
-                } else {
-                    // we can always increment fcnt_up when we receive a downlink
-                    self.fcnt_up += 1;
-                    if let (Some(fport), FRMPayload::Data(data)) =
-                        (decrypted.f_port(), decrypted.frm_payload())
-                    {
-                        #[cfg(feature = "certification")]
-                        if certification.fport(fport) {
-                            use crate::mac::certification::Response::*;
-                            match certification
-                                .handle_message(data, self.fcnt_down.try_into().unwrap())
-                            {
-                                AdrBitChange(adr) => {
-                                    self.override_adr = adr;
-                                }
-                                DutJoinReq => {
-                                    return Response::DeviceHandler(DeviceEvent::ResetMac)
-                                }
-                                DutResetReq => {
-                                    return Response::DeviceHandler(DeviceEvent::ResetDevice)
-                                }
-                                LinkCheckReq => {
-                                    return Response::LinkCheckReq;
-                                }
-                                TxFramesCtrlReq(ftype) => {
-                                    // None is a no-op, allowing network to trigger uplinks
-                                    if ftype.is_some() {
-                                        self.override_confirmed = ftype
-                                    }
-                                }
-                                TxPeriodicityChange(periodicity) => {
-                                    return Response::DeviceHandler(
-                                        DeviceEvent::TxPeriodicityChange { periodicity },
-                                    )
+            return if self.fcnt_up == 0xFFFF_FFFF || self.fcnt_down == 0xFFFF_FFFF {
+                // if either FCnt is used up, the session has expired
+                Response::SessionExpired
+            } else {
+                // we can always increment fcnt_up when we receive a downlink
+                self.fcnt_up += 1;
+                if let (Some(fport), FRMPayload::Data(data)) =
+                    (decrypted.f_port(), decrypted.frm_payload())
+                {
+                    #[cfg(feature = "certification")]
+                    if certification.fport(fport) {
+                        use crate::mac::certification::Response::*;
+                        match certification.handle_message(data, self.fcnt_down as u16) {
+                            AdrBitChange(adr) => {
+                                self.override_adr = adr;
+                            }
+                            DutJoinReq => return Response::DeviceHandler(DeviceEvent::ResetMac),
+                            DutResetReq => {
+                                return Response::DeviceHandler(DeviceEvent::ResetDevice)
+                            }
+                            LinkCheckReq => {
+                                return Response::LinkCheckReq;
+                            }
+                            TxFramesCtrlReq(ftype) => {
+                                // None is a no-op, allowing network to trigger uplinks
+                                if ftype.is_some() {
+                                    self.override_confirmed = ftype
                                 }
-                                UplinkPrepared => return Response::UplinkPrepared,
-                                NoUpdate => return Response::NoUpdate,
                             }
+                            TxPeriodicityChange(periodicity) => {
+                                return Response::DeviceHandler(DeviceEvent::TxPeriodicityChange {
+                                    periodicity,
+                                })
+                            }
+                            UplinkPrepared => return Response::UplinkPrepared,
+                            NoUpdate => return Response::NoUpdate,
                         }
-                        #[cfg(feature = "multicast")]
-                        if multicast.is_remote_setup_port(fport) {
-                            return multicast.handle_setup_message(data).into();
-                        }
+                    }
+                    #[cfg(feature = "multicast")]
+                    if multicast.is_remote_setup_port(fport) {
+                        return multicast.handle_setup_message(crypto, data).into();
+                    }
 
+                    // Without a queue, the application reads the payload from the decrypted frame
+                    if let Some(dl) = dl {
                         // heapless Vec from slice fails only if slice is too large.
                         // A data FRM payload will never exceed 256 bytes.
                         let data = Vec::from_slice(data).unwrap();
                         // TODO: propagate error type when heapless vec is full?
-                        let _ = dl.push(Downlink { data, fport });
+                        let _ = dl.try_push(Downlink { data, fport });
                     }
-                    Response::DownlinkReceived(fcnt)
-                };
-            }
+                }
+                Response::DownlinkReceived(fcnt)
+            };
         }
         Response::NoUpdate
     }
 
-    pub(crate) fn rx2_complete(&mut self) -> Response {
-        // Until we handle NbTrans, there is no case where we should not increment FCntUp.
+    /// Reconstruct the 32-bit downlink frame counter from the 16 LSBs transmitted over the air.
+    /// Also returns an event if the frame counter did not advance (ie: a replayed frame), advanced
+    /// by `max_gap` or more or would overflow.
+    fn reconstruct_fcnt_down(&self, fcnt: u16, max_gap: u32) -> (u32, Option<SecurityEvent>) {
+        // The very first downlink of a session may use FCnt 0
+        if !self.downlink_received && self.fcnt_down == 0 && fcnt == 0 {
+            return (0, None);
+        }
+        let gap = fcnt.wrapping_sub(self.fcnt_down as u16) as u32;
+        match self.fcnt_down.checked_add(gap) {
+            _ if gap == 0 => {
+                (self.fcnt_down, Some(SecurityEvent::ReplayedDownlink { fcnt: self.fcnt_down }))
+            }
+            Some(fcnt) if gap < max_gap => (fcnt, None),
+            _ => {
+                let fcnt = self.fcnt_down.wrapping_add(gap);
+                (fcnt, Some(SecurityEvent::FcntGapExceeded { fcnt }))
+            }
+        }
+    }
+
+    fn diagnose_mic_failure<T: AsRef<[u8]>, C: CryptoFactory>(
+        &self,
+        crypto: &C,
+        encrypted_data: &EncryptedDataPayload<T>,
+        fcnt: u32,
+    ) -> MicFailureReason {
+        // the same 16 bit FCnt in the previous, the current and the next 16 bit epoch
+        let fcnt16 = fcnt & 0xFFFF;
+        let epoch = self.fcnt_down & 0xFFFF_0000;
+        for candidate in
+            [fcnt16, epoch.wrapping_sub(0x1_0000) | fcnt16, epoch.wrapping_add(0x1_0000) | fcnt16]
+        {
+            if candidate != fcnt
+                && encrypted_data.validate_mic(self.nwkskey().inner(), candidate, crypto)
+            {
+                return MicFailureReason::FcntOutOfWindow { fcnt: candidate };
+            }
+        }
+        if encrypted_data.validate_mic(self.appskey().inner(), fcnt, crypto) {
+            return MicFailureReason::KeysSwapped;
+        }
+        MicFailureReason::WrongKey
+    }
+
+    /// The RX windows of an uplink closed without a downlink. `repeat` is set if the uplink is
+    /// going to be repeated (NbTrans), with the same FCntUp.
+    pub(crate) fn rx2_complete(&mut self, repeat: bool) -> Response {
+        if repeat {
+            return Response::RxComplete;
+        }
         if self.fcnt_up == 0xFFFF_FFFF {
             // if the FCnt is used up, the session has expired
             return Response::SessionExpired;
@@ -276,15 +381,100 @@ impl Session {
         }
     }
 
-    pub(crate) fn prepare_buffer<const N: usize>(
+    pub(crate) fn prepare_buffer<C: CryptoFactory, const N: usize>(
         &mut self,
+        crypto: &C,
+        configuration: &super::Configuration,
         data: &SendData<'_>,
         tx_buffer: &mut RadioBuffer<N>,
+    ) -> FcntUp {
+        self.build_uplink(
+            crypto,
+            configuration,
+            data.data,
+            Some(data.fport),
+            data.confirmed,
+            configuration.max_fopts_len as usize,
+            tx_buffer,
+        )
+    }
+
+    /// Prepare an empty unconfirmed uplink without FPort, which acknowledges the last confirmed
+    /// downlink and carries any pending MAC commands in FOpts.
+    pub(crate) fn prepare_ack_buffer<C: CryptoFactory, const N: usize>(
+        &mut self,
+        crypto: &C,
+        configuration: &super::Configuration,
+        tx_buffer: &mut RadioBuffer<N>,
+    ) -> FcntUp {
+        let max_len = configuration.max_fopts_len as usize;
+        self.build_uplink(crypto, configuration, &[], None, false, max_len, tx_buffer)
+    }
+
+    /// Prepare an uplink on FPort 0 which carries up to `max_len` bytes of pending MAC commands
+    /// in its FRMPayload.
+    pub(crate) fn prepare_mac_commands_buffer<C: CryptoFactory, const N: usize>(
+        &mut self,
+        crypto: &C,
+        configuration: &super::Configuration,
+        confirmed: bool,
+        max_len: usize,
+        tx_buffer: &mut RadioBuffer<N>,
+    ) -> FcntUp {
+        self.build_uplink(crypto, configuration, &[], Some(0), confirmed, max_len, tx_buffer)
+    }
+
+    /// Length of the pending MAC commands which are sent in the FOpts of the next uplink.
+    pub(crate) fn fopts_len(&self, configuration: &super::Configuration) -> usize {
+        self.uplink.mac_commands_len(configuration.max_fopts_len as usize)
+    }
+
+    /// Whether pending MAC commands exceed what a single uplink can carry in FOpts.
+    pub(crate) fn mac_commands_exceed_fopts(&self, configuration: &super::Configuration) -> bool {
+        self.fopts_len(configuration) < self.uplink.mac_commands().len()
+    }
+
+    /// Queue the indications which have not been confirmed by the network yet.
+    pub(crate) fn add_pending_indications(&mut self, configuration: &super::Configuration) {
+        if configuration.reset_ind {
+            let mut cmd = ResetIndCreator::new();
+            // LoRaWAN 1.1
+            let _ = cmd.set_minor(1);
+            self.add_indication(cmd);
+        }
+        if let Some(class) = configuration.device_mode_ind {
+            let mut cmd = DeviceModeIndCreator::new();
+            cmd.set_class(class);
+            self.add_indication(cmd);
+        }
+    }
+
+    /// Queue an indication, unless one with the same CID is pending already.
+    fn add_indication<M: SerializableMacCommand>(&mut self, cmd: M) {
+        if !parse_uplink_mac_commands(self.uplink.mac_commands()).any(|c| c.cid() == cmd.cid()) {
+            self.uplink.add_mac_command(cmd);
+        }
+    }
+
+    pub(crate) fn is_ack_pending(&self) -> bool {
+        self.uplink.confirms_downlink()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn build_uplink<C: CryptoFactory, const N: usize>(
+        &mut self,
+        crypto: &C,
+        configuration: &super::Configuration,
+        data: &[u8],
+        fport: Option<u8>,
+        confirmed: bool,
+        mac_commands_max_len: usize,
+        tx_buffer: &mut RadioBuffer<N>,
     ) -> FcntUp {
         tx_buffer.clear();
         let fcnt = self.fcnt_up;
-        let mut buf = [0u8; 256];
-        let mut phy = DataPayloadCreator::new(&mut buf).unwrap();
+        // The frame is assembled directly in the radio buffer
+        let mut phy = DataPayloadCreator::new(tx_buffer.as_mut()).unwrap();
 
         let mut fctrl = FCtrl(0x0, true);
         if self.uplink.confirms_downlink() {
@@ -292,12 +482,19 @@ impl Session {
             self.uplink.clear_downlink_confirmation();
         }
 
+        if configuration.adr {
+            fctrl.set_adr();
+            if self.adr_ack_cnt >= configuration.adr_ack_limit as u32 {
+                fctrl.set_adr_ack_req();
+            }
+        }
+
         #[cfg(feature = "certification")]
         if self.override_adr {
             fctrl.set_adr()
         }
 
-        self.confirmed = data.confirmed;
+        self.confirmed = confirmed;
         #[cfg(feature = "certification")]
         if let Some(v) = self.override_confirmed {
             self.confirmed = v;
@@ -305,22 +502,24 @@ impl Session {
 
         phy.set_confirmed(self.confirmed)
             .set_fctrl(&fctrl)
-            .set_f_port(data.fport)
             .set_dev_addr(self.devaddr)
             .set_fcnt(fcnt);
+        if let Some(fport) = fport {
+            phy.set_f_port(fport);
+        }
 
-        let crypto_factory = DefaultFactory;
-        match phy.build(
-            data.data,
-            self.uplink.mac_commands(),
+        let mac_commands_len = self.uplink.mac_commands_len(mac_commands_max_len);
+        match phy.build_in_place(
+            data,
+            &self.uplink.mac_commands()[..mac_commands_len],
             &self.nwkskey,
             &self.appskey,
-            &crypto_factory,
+            crypto,
         ) {
-            Ok(packet) => {
-                self.uplink.clear_mac_commands(true);
-                tx_buffer.clear();
-                tx_buffer.extend_from_slice(packet).unwrap();
+            Ok(len) => {
+                self.uplink.mac_commands_transmitted(mac_commands_len);
+                self.adr_ack_cnt = self.adr_ack_cnt.saturating_add(1);
+                tx_buffer.set_pos(len);
             }
             Err(e) => panic!("Error assembling packet! {:?} ", e),
         }
@@ -331,7 +530,7 @@ impl Session {
         &mut self,
         configuration: &mut super::Configuration,
         region: &mut region::Configuration,
-        cmds: MacCommandIterator<'_, DownlinkMacCommand<'_>>,
+        cmds: ValidatedMacCommandIterator<'_, DownlinkMacCommand<'_>>,
         snr: i8,
     ) {
         use DownlinkMacCommand::*;
@@ -339,13 +538,19 @@ impl Session {
         let mut cmd_iter = cmds.into_iter().peekable();
         let mut num_adrreq = 0;
         while let Some(cmd) = cmd_iter.next() {
+            let cmd = match cmd {
+                Ok(cmd) => cmd,
+                Err(malformed) => {
+                    // the commands before are processed, the tail can't be interpreted
+                    warn!("Ignoring malformed MAC commands: {}", malformed);
+                    break;
+                }
+            };
             match cmd {
                 DevStatusReq(..) => {
-                    // TODO: Battery information should come from device/application
-                    // Battery: (255 - unable to measure, 1..254 - battery level, 0 - external power source)
-                    // For now we just return dummy value of "255"
                     let mut cmd = DevStatusAnsCreator::new();
-                    let _ = cmd.set_battery(255).set_margin(snr);
+                    let battery = configuration.battery.level().dev_status();
+                    let _ = cmd.set_battery(battery).set_margin(snr);
                     self.uplink.add_mac_command(cmd);
                 }
                 DlChannelReq(payload) => {
@@ -381,7 +586,7 @@ impl Session {
                     );
 
                     // Check whether LinkADRReq commands continue...
-                    if let Some(LinkADRReq(..)) = cmd_iter.peek() {
+                    if let Some(Ok(LinkADRReq(..))) = cmd_iter.peek() {
                         continue;
                     }
 
@@ -391,13 +596,20 @@ impl Session {
                     // Handle DataRate
                     let dr = match payload.data_rate() {
                         DR::_15 => Some(configuration.data_rate),
-                        n => {
-                            if region.get_datarate(n as u8).is_some() {
-                                Some(n)
-                            } else {
+                        n => match region.get_datarate(n as u8) {
+                            // A higher data rate requires a confirmed SNR margin
+                            Some(datarate)
+                                if n as u8 > configuration.data_rate as u8
+                                    && !configuration
+                                        .snr_history
+                                        .allows(datarate.spreading_factor) =>
+                            {
+                                debug!("Rejecting {} without SNR margin", n);
                                 None
                             }
-                        }
+                            Some(_) => Some(n),
+                            None => None,
+                        },
                     };
                     // Handle TxPower
                     let pw = match payload.tx_power() {
@@ -407,10 +619,14 @@ impl Session {
 
                     let cm_ack = region.channel_mask_validate(&channel_mask, dr);
 
-                    if dr.is_some() && pw.is_some() && cm_ack {
-                        // TODO: handle nbtrans
-                        configuration.data_rate = dr.unwrap();
-                        configuration.tx_power = pw.unwrap();
+                    if let (Some(dr), Some(pw), true) = (dr, pw, cm_ack) {
+                        configuration.data_rate = dr;
+                        configuration.tx_power = pw;
+                        // NbTrans 0 keeps the current value
+                        match payload.redundancy().number_of_transmissions() {
+                            0 => (),
+                            nb_trans => configuration.nb_trans = nb_trans,
+                        }
                         region.channel_mask_set(channel_mask.clone());
                     }
 
@@ -463,10 +679,12 @@ impl Session {
                             }
                         }
                     };
-                    if freq_ack && rx2_dr.is_some() && rx1_dr_offset.is_some() {
-                        configuration.rx2_data_rate = rx2_dr.unwrap();
+                    if let (true, Some(rx2_dr), Some(rx1_dr_offset)) =
+                        (freq_ack, rx2_dr, rx1_dr_offset)
+                    {
+                        configuration.rx2_data_rate = rx2_dr;
                         configuration.rx2_frequency = Some(freq);
-                        configuration.rx1_dr_offset = rx1_dr_offset.unwrap();
+                        configuration.rx1_dr_offset = rx1_dr_offset;
                     }
 
                     let mut cmd = RXParamSetupAnsCreator::new();
@@ -485,6 +703,60 @@ impl Session {
                     configuration.rx1_delay = super::del_to_delay_ms(payload.delay());
                     self.uplink.add_mac_command(RXTimingSetupAnsCreator::new());
                 }
+                TXParamSetupReq(payload) => {
+                    if !region.supports_tx_param_setup() {
+                        // Regions which don't implement TxParamSetupReq ignore this command
+                        continue;
+                    }
+                    configuration.uplink_dwell_time = payload.uplink_dwell_time();
+                    region.set_downlink_dwell_time(payload.downlink_dwell_time());
+                    configuration.network_max_eirp = Some(payload.max_eirp());
+                    self.uplink.add_mac_command(TXParamSetupAnsCreator::new());
+                }
+                ADRParamSetupReq(payload) => {
+                    configuration.adr_ack_limit = payload.adr_ack_limit();
+                    configuration.adr_ack_delay = payload.adr_ack_delay();
+                    self.uplink.add_mac_command(ADRParamSetupAnsCreator::new());
+                }
+                ResetConf(payload) => {
+                    if configuration.reset_ind {
+                        debug!("ResetConf received (LoRaWAN 1.{})", payload.minor());
+                        configuration.reset_ind = false;
+                    }
+                }
+                BeaconFreqReq(payload) => {
+                    let frequency = payload.frequency().value();
+                    let ack = frequency == 0 || region.frequency_valid(frequency);
+                    if ack {
+                        configuration.class_b.beacon_frequency =
+                            (frequency != 0).then_some(frequency);
+                    }
+                    let mut cmd = BeaconFreqAnsCreator::new();
+                    cmd.set_beacon_frequency_ack(ack);
+                    self.uplink.add_mac_command(cmd);
+                }
+                PingSlotChannelReq(payload) => {
+                    let frequency = payload.frequency().value();
+                    let data_rate = payload.data_rate();
+                    let freq_ack = frequency == 0 || region.frequency_valid(frequency);
+                    let dr_ack = region.is_valid_downlink_dr(data_rate);
+                    if freq_ack && dr_ack {
+                        configuration.class_b.ping_slot_frequency =
+                            (frequency != 0).then_some(frequency);
+                        configuration.class_b.ping_slot_data_rate = Some(data_rate);
+                    }
+                    let mut cmd = PingSlotChannelAnsCreator::new();
+                    cmd.set_channel_frequency_ack(freq_ack).set_data_rate_ack(dr_ack);
+                    self.uplink.add_mac_command(cmd);
+                }
+                DeviceModeConf(payload) => {
+                    if let Ok(class) = payload.class() {
+                        if configuration.device_mode_ind == Some(class) {
+                            configuration.device_mode_ind = None;
+                            configuration.device_mode_conf = Some(class);
+                        }
+                    }
+                }
                 _ => (),
             }
         }
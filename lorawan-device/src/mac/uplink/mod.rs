@@ -1,12 +1,18 @@
 use lorawan::maccommands::{parse_uplink_mac_commands, SerializableMacCommand, UplinkMacCommand};
-use lorawan::packet_length::phy::mac::fhdr::FOPTS_MAX_LEN;
 
 #[cfg(feature = "serde")]
 mod serde;
 
+/// Maximum length of MAC commands which may be pending for transmission. Commands exceeding the
+/// FOpts capacity are either spread over multiple uplinks or sent in an FPort 0 uplink.
+pub(crate) const MAC_COMMANDS_MAX_LEN: usize = 32;
+
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct Uplink {
-    pending: heapless::Vec<u8, FOPTS_MAX_LEN>,
+    pending: heapless::Vec<u8, MAC_COMMANDS_MAX_LEN>,
+    /// Number of leading `pending` bytes which have already been transmitted and are retained
+    /// until a downlink is received.
+    transmitted: usize,
     confirmed: bool,
 }
 
@@ -22,33 +28,55 @@ impl Uplink {
     }
     pub fn add_mac_command<M: SerializableMacCommand>(&mut self, cmd: M) {
         // Check that there's still enough room for MAC commands
-        if self.pending.len() + cmd.payload_len() < FOPTS_MAX_LEN {
+        if self.pending.len() + cmd.payload_len() < MAC_COMMANDS_MAX_LEN {
             let _ = self.pending.push(cmd.cid());
             self.pending.extend_from_slice(cmd.payload_bytes()).unwrap();
         }
     }
-    pub fn clear_mac_commands(&mut self, retain_acks: bool) {
-        // Certain commands have to be retained until their acknowledgment is confirmed
-        if retain_acks {
-            use UplinkMacCommand::*;
-            let mut data: heapless::Vec<u8, FOPTS_MAX_LEN> = heapless::Vec::new();
-            let _: heapless::Vec<_, FOPTS_MAX_LEN> = parse_uplink_mac_commands(&self.pending)
-                .filter(|cmd| {
-                    matches!(cmd, DlChannelAns(_) | RXParamSetupAns(_) | RXTimingSetupAns(_))
-                })
-                .map(|c| {
-                    let _ = data.push(c.cid());
-                    data.extend_from_slice(c.payload_bytes()).unwrap();
-                })
-                .collect();
-            self.pending = data;
-        } else {
-            self.pending.clear();
+    /// A downlink has been received, so any transmitted MAC commands have been delivered.
+    pub fn clear_mac_commands(&mut self) {
+        self.pending = heapless::Vec::from_slice(&self.pending[self.transmitted..]).unwrap();
+        self.transmitted = 0;
+    }
+    /// The first `len` bytes of the pending MAC commands have been transmitted. Certain commands
+    /// have to be retained until their acknowledgment is confirmed by a downlink.
+    pub fn mac_commands_transmitted(&mut self, len: usize) {
+        use UplinkMacCommand::*;
+        let mut data: heapless::Vec<u8, MAC_COMMANDS_MAX_LEN> = heapless::Vec::new();
+        for cmd in parse_uplink_mac_commands(&self.pending[..len]) {
+            if matches!(
+                cmd,
+                DlChannelAns(_)
+                    | RXParamSetupAns(_)
+                    | RXTimingSetupAns(_)
+                    | ResetInd(_)
+                    | DeviceModeInd(_)
+            ) {
+                let _ = data.push(cmd.cid());
+                data.extend_from_slice(cmd.payload_bytes()).unwrap();
+            }
         }
+        self.transmitted = data.len();
+        data.extend_from_slice(&self.pending[len..]).unwrap();
+        self.pending = data;
     }
+    /// All pending MAC commands.
     pub fn mac_commands(&self) -> &[u8] {
         &self.pending
     }
+    /// Length of the leading pending MAC commands which fit into `max_len` bytes without
+    /// splitting a command.
+    pub fn mac_commands_len(&self, max_len: usize) -> usize {
+        let mut len = 0;
+        for cmd in parse_uplink_mac_commands(&self.pending) {
+            let cmd_len = 1 + cmd.payload_len();
+            if len + cmd_len > max_len {
+                break;
+            }
+            len += cmd_len;
+        }
+        len
+    }
 }
 
 #[cfg(feature = "defmt-03")]
@@ -67,6 +95,7 @@ impl defmt::Format for Uplink {
 mod test {
     use super::*;
     use lorawan::maccommands::{parse_uplink_mac_commands, LinkADRAnsCreator, UplinkMacCommand};
+    use lorawan::packet_length::phy::mac::fhdr::FOPTS_MAX_LEN;
     #[test]
     fn two_link_adr_ans() {
         let mut uplink = Uplink::default();
@@ -77,4 +106,31 @@ mod test {
         assert!(matches!(mac_commands.next().unwrap(), UplinkMacCommand::LinkADRAns(_)));
         assert!(mac_commands.next().is_none());
     }
+
+    #[test]
+    fn mac_commands_spread_over_uplinks() {
+        use lorawan::maccommands::{DlChannelAnsCreator, LinkADRAnsCreator};
+        let mut uplink = Uplink::default();
+        for _ in 0..4 {
+            uplink.add_mac_command(DlChannelAnsCreator::new());
+            uplink.add_mac_command(LinkADRAnsCreator::new());
+        }
+        assert_eq!(uplink.mac_commands().len(), 16);
+        // Only complete commands fit into the FOpts
+        let len = uplink.mac_commands_len(FOPTS_MAX_LEN);
+        assert_eq!(len, 14);
+        uplink.mac_commands_transmitted(len);
+        // DlChannelAns is retained until a downlink is received
+        let mut mac_commands = parse_uplink_mac_commands(uplink.mac_commands());
+        for _ in 0..4 {
+            assert!(matches!(mac_commands.next().unwrap(), UplinkMacCommand::DlChannelAns(_)));
+        }
+        assert!(matches!(mac_commands.next().unwrap(), UplinkMacCommand::LinkADRAns(_)));
+        assert!(mac_commands.next().is_none());
+
+        uplink.clear_mac_commands();
+        let mut mac_commands = parse_uplink_mac_commands(uplink.mac_commands());
+        assert!(matches!(mac_commands.next().unwrap(), UplinkMacCommand::LinkADRAns(_)));
+        assert!(mac_commands.next().is_none());
+    }
 }
@@ -1,7 +1,11 @@
-use crate::mac::uplink::Uplink;
-use lorawan::packet_length::phy::mac::fhdr::FOPTS_MAX_LEN;
+use crate::mac::uplink::{Uplink, MAC_COMMANDS_MAX_LEN};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+/// Length of `pending_data` before the pending MAC commands could exceed the FOpts capacity.
+/// Shorter MAC commands are still padded to it, so that sessions remain readable by releases
+/// using the fixed length, and sessions persisted by such releases remain readable.
+const LEGACY_PENDING_LEN: usize = 15;
+
 #[cfg(feature = "serde")]
 impl Serialize for Uplink {
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
@@ -9,9 +13,11 @@ impl Serialize for Uplink {
         let mut state = serializer.serialize_struct("Uplink", 3)?;
         state.serialize_field("confirmed", &self.confirmed)?;
         state.serialize_field("pending_len", &(self.pending.len() as u8))?;
-        let mut full_array = [0u8; FOPTS_MAX_LEN];
-        full_array[..self.pending.len()].copy_from_slice(&self.pending);
-        state.serialize_field("pending_data", &full_array)?;
+        let mut pending_data = self.pending.clone();
+        if pending_data.len() < LEGACY_PENDING_LEN {
+            pending_data.resize(LEGACY_PENDING_LEN, 0).unwrap();
+        }
+        state.serialize_field("pending_data", &pending_data)?;
         state.end()
     }
 }
@@ -48,7 +54,7 @@ impl<'de> Deserialize<'de> for Uplink {
             {
                 let mut confirmed: Option<bool> = None;
                 let mut pending_len: Option<u8> = None;
-                let mut pending_data: Option<[u8; FOPTS_MAX_LEN]> = None;
+                let mut pending_data: Option<heapless::Vec<u8, MAC_COMMANDS_MAX_LEN>> = None;
 
                 while let Some(key) = map.next_key::<Field>()? {
                     match key {
@@ -79,8 +85,8 @@ impl<'de> Deserialize<'de> for Uplink {
                 let pending_data =
                     pending_data.ok_or_else(|| de::Error::missing_field("pending_data"))?;
 
-                if pending_len as usize > FOPTS_MAX_LEN {
-                    return Err(de::Error::custom("pending_len exceeds maximum size"));
+                if pending_len as usize > pending_data.len() {
+                    return Err(de::Error::custom("pending_len exceeds pending_data"));
                 }
 
                 let mut pending = heapless::Vec::new();
@@ -88,7 +94,7 @@ impl<'de> Deserialize<'de> for Uplink {
                     .extend_from_slice(&pending_data[..pending_len as usize])
                     .map_err(|_| de::Error::custom("failed to create heapless::Vec"))?;
 
-                Ok(Uplink { pending, confirmed })
+                Ok(Uplink { pending, transmitted: 0, confirmed })
             }
         }
 
@@ -128,7 +134,7 @@ mod tests {
     #[test]
     fn test_serde_max_size() {
         let mut uplink = Uplink::default();
-        let max_data = [42u8; FOPTS_MAX_LEN];
+        let max_data = [42u8; MAC_COMMANDS_MAX_LEN];
         uplink.pending.extend_from_slice(&max_data).unwrap();
 
         let json = serde_json::to_string(&uplink).unwrap();
@@ -136,4 +142,24 @@ mod tests {
         assert!(!decoded.confirms_downlink());
         assert_eq!(decoded.mac_commands(), &max_data);
     }
+
+    #[test]
+    fn test_serde_legacy_layout() {
+        let mut uplink = Uplink::default();
+        uplink.pending.extend_from_slice(&[1, 2, 3, 4]).unwrap();
+        let json = serde_json::to_string(&uplink).unwrap();
+        assert_eq!(
+            json,
+            r#"{"confirmed":false,"pending_len":4,"pending_data":[1,2,3,4,0,0,0,0,0,0,0,0,0,0,0]}"#
+        );
+
+        let legacy =
+            r#"{"confirmed":true,"pending_len":2,"pending_data":[5,6,0,0,0,0,0,0,0,0,0,0,0,0,0]}"#;
+        let decoded: Uplink = serde_json::from_str(legacy).unwrap();
+        assert!(decoded.confirms_downlink());
+        assert_eq!(decoded.mac_commands(), &[5, 6]);
+
+        let truncated = r#"{"confirmed":true,"pending_len":3,"pending_data":[5,6]}"#;
+        assert!(serde_json::from_str::<Uplink>(truncated).is_err());
+    }
 }
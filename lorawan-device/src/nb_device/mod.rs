@@ -36,7 +36,7 @@ where
                 rng,
                 tx_buffer: RadioBuffer::new(),
                 mac: Mac::new(region, R::MAX_RADIO_POWER, R::ANTENNA_GAIN),
-                downlink: Vec::new(),
+                downlink: Default::default(),
             },
         }
     }
@@ -53,6 +53,26 @@ where
         }
     }
 
+    /// Join via ABP, continuing the frame counters of an existing session instead of starting
+    /// from zero, eg: when migrating a device from another stack. `fcnt_up` is the counter of the
+    /// next uplink and `fcnt_down` the counter of the last downlink, as supplied by the network
+    /// server.
+    ///
+    /// Fails with [`mac::Error::FcntRewound`] if the device is already joined with the same
+    /// DevAddr and either counter is lower than the current one, and with
+    /// [`mac::Error::FcntExhausted`] if a counter is exhausted.
+    pub fn join_abp_with_fcnt(
+        &mut self,
+        nwkskey: NwkSKey,
+        appskey: AppSKey,
+        devaddr: DevAddr<[u8; 4]>,
+        fcnt_up: u32,
+        fcnt_down: u32,
+    ) -> Result<Response, Error<R>> {
+        self.shared.mac.join_abp_with_fcnt(nwkskey, appskey, devaddr, fcnt_up, fcnt_down)?;
+        Ok(Response::JoinSuccess)
+    }
+
     pub fn get_radio(&mut self) -> &mut R {
         &mut self.shared.radio
     }
@@ -61,8 +81,340 @@ where
         self.shared.mac.configuration.data_rate
     }
 
+    /// Number of transmissions of each unconfirmed uplink, as requested by the network with the
+    /// NbTrans field of LinkADRReq (1 by default). Unlike the async device, this device doesn't
+    /// repeat uplinks: each is transmitted once.
+    pub fn get_nb_trans(&self) -> u8 {
+        self.shared.mac.configuration.nb_trans
+    }
+
     pub fn set_datarate(&mut self, datarate: region::DR) {
-        self.shared.mac.configuration.data_rate = datarate
+        self.shared.mac.set_datarate(datarate)
+    }
+
+    pub fn get_channel_stats(&self) -> &region::ChannelStats {
+        self.shared.mac.region.channel_stats()
+    }
+
+    /// Temporarily deprioritize channels which consistently fail when picking the next uplink
+    /// channel. Disabled by default.
+    pub fn set_channel_avoidance(&mut self, avoidance: Option<region::ChannelAvoidance>) {
+        self.shared.mac.region.set_channel_avoidance(avoidance)
+    }
+
+    /// Only use the channels on which downlinks have been received in RX1 (if any) when picking
+    /// the next uplink channel. Disabled by default.
+    pub fn set_channel_pruning(&mut self, pruning: bool) {
+        self.shared.mac.region.set_channel_pruning(pruning)
+    }
+
+    /// Returns `true` if a confirmed downlink has been received which will be acknowledged by the
+    /// next uplink.
+    pub fn is_ack_pending(&self) -> bool {
+        self.shared.mac.is_ack_pending()
+    }
+
+    /// Returns `true` if the last downlink had the FPending bit set, ie: the network has more
+    /// downlinks queued for the device, and no uplink has been sent since.
+    pub fn is_frame_pending(&self) -> bool {
+        self.shared.mac.is_frame_pending()
+    }
+
+    /// Switch to DevNonce counter mode (LoRaWAN 1.0.4 and 1.1), where `next` is the DevNonce of
+    /// the next join request, or back to random DevNonces with `None`. The counter must be
+    /// persisted across resets (see [`Device::get_dev_nonce_counter`]), as DevNonces must never
+    /// be reused. Joining fails with [`mac::Error::DevNonceExhausted`] once all DevNonces have been
+    /// used.
+    pub fn set_dev_nonce_counter(&mut self, next: Option<u32>) {
+        self.shared.mac.set_dev_nonce_counter(next)
+    }
+
+    /// The DevNonce of the next join request in counter mode (`65536` once exhausted).
+    pub fn get_dev_nonce_counter(&self) -> Option<u32> {
+        self.shared.mac.get_dev_nonce_counter()
+    }
+
+    /// Number of join requests left in counter mode before the device needs to be re-provisioned.
+    /// Falling to [`mac::DEV_NONCE_LOW_THRESHOLD`] is reported by [`mac::SecurityEvent::DevNonceLow`].
+    pub fn dev_nonces_remaining(&self) -> Option<u32> {
+        self.shared.mac.dev_nonces_remaining()
+    }
+
+    /// Number of dropped downlinks and expired sessions by reason.
+    pub fn get_security_counters(&self) -> mac::SecurityCounters {
+        self.shared.mac.get_security_counters()
+    }
+
+    /// Diagnose downlinks failing MIC verification: instead of
+    /// [`SecurityEvent::MicFailure`](mac::SecurityEvent::MicFailure), a
+    /// [`SecurityEvent::MicFailureDiagnosed`](mac::SecurityEvent::MicFailureDiagnosed) with the
+    /// likely cause is reported. Meant for test benches and bring-up, as every MIC failure costs
+    /// several additional MIC computations. The frames are still dropped.
+    pub fn set_mic_diagnostics(&mut self, enabled: bool) {
+        self.shared.mac.set_mic_diagnostics(enabled);
+    }
+
+    /// Take the oldest security event (eg: a replayed downlink or a MIC failure) which has not
+    /// been taken yet. Only the most recent events are retained.
+    pub fn take_security_event(&mut self) -> Option<mac::SecurityEvent> {
+        self.shared.mac.take_security_event()
+    }
+
+    /// Choose whether downlinks whose frame counter advanced by a large gap are dropped (the
+    /// default, with a gap of [`mac::MAX_FCNT_GAP`]) or accepted, see [`mac::FcntGapPolicy`].
+    pub fn set_fcnt_gap_policy(&mut self, policy: mac::FcntGapPolicy) {
+        self.shared.mac.set_fcnt_gap_policy(policy);
+    }
+
+    pub fn get_fcnt_gap_policy(&self) -> mac::FcntGapPolicy {
+        self.shared.mac.get_fcnt_gap_policy()
+    }
+
+    /// Reset the downlink frame counter of the session, so that the next downlink is accepted
+    /// whatever its frame counter, eg: after the network lost the frame counters of an ABP
+    /// device. Reported as [`SecurityEvent::FcntDownResynced`](mac::SecurityEvent::FcntDownResynced),
+    /// since it weakens the replay protection until the next downlink.
+    pub fn resync_fcnt_down(&mut self) -> Result<(), mac::Error> {
+        self.shared.mac.resync_fcnt_down()
+    }
+
+    /// Periodically check whether the network still responds after consecutive uplinks without
+    /// downlink, see [`HealthCheck`](mac::HealthCheck). Disabled (`None`) by default.
+    pub fn set_health_check(&mut self, policy: Option<mac::HealthCheck>) {
+        self.shared.mac.set_health_check(policy);
+    }
+
+    pub fn get_health_check(&self) -> Option<mac::HealthCheck> {
+        self.shared.mac.get_health_check()
+    }
+
+    /// Take the oldest session health event which has not been taken yet. On
+    /// [`HealthEvent::RejoinRequired`](mac::HealthEvent::RejoinRequired), the application should
+    /// join again.
+    pub fn take_health_event(&mut self) -> Option<mac::HealthEvent> {
+        self.shared.mac.take_health_event()
+    }
+
+    /// Choose the data rates of consecutive OTAA join requests, see
+    /// [`JoinDatarates`](mac::JoinDatarates). By default, the configured data rate is used.
+    pub fn set_join_datarates(&mut self, datarates: mac::JoinDatarates) {
+        self.shared.mac.set_join_datarates(datarates);
+    }
+
+    pub fn get_join_datarates(&self) -> mac::JoinDatarates {
+        self.shared.mac.get_join_datarates()
+    }
+
+    /// The number, frequency and modulation of the most recent join request.
+    pub fn last_join_attempt(&self) -> Option<mac::JoinAttempt> {
+        self.shared.mac.last_join_attempt()
+    }
+
+    /// Hand the session keys and DevAddr derived by every successful OTAA join to the
+    /// application via `take_exported_session_keys`, eg: to mirror the session in a gateway
+    /// bridge or migration tool. Disabled by default, as the keys should not leave the device
+    /// unless necessary.
+    pub fn set_session_key_export(&mut self, enabled: bool) {
+        self.shared.mac.set_session_key_export(enabled)
+    }
+
+    /// Take the session keys of the last OTAA join, if session key export is enabled and they
+    /// have not been taken yet.
+    pub fn take_exported_session_keys(&mut self) -> Option<mac::SessionKeys> {
+        self.shared.mac.take_exported_session_keys()
+    }
+
+    /// Class B channel settings requested by the network.
+    pub fn get_class_b_settings(&self) -> mac::ClassBSettings {
+        self.shared.mac.get_class_b_settings()
+    }
+
+    /// Retrieve the application-defined EIRP ceiling (in dBm), if any.
+    pub fn get_max_eirp(&self) -> Option<i8> {
+        self.shared.mac.get_max_eirp()
+    }
+
+    /// Limit the EIRP (in dBm) of all transmissions below the regional limit. The limit also
+    /// applies when the network requests a higher TX power via LinkADRReq.
+    pub fn set_max_eirp(&mut self, max_eirp: Option<i8>) {
+        self.shared.mac.set_max_eirp(max_eirp)
+    }
+
+    pub fn get_battery_level(&self) -> mac::BatteryLevel {
+        self.shared.mac.get_battery_level()
+    }
+
+    /// Update the battery level, which is reported to the network in DevStatusAns and drives the
+    /// [`mac::BatteryPolicy`].
+    pub fn set_battery_level(&mut self, level: mac::BatteryLevel) {
+        self.shared.mac.set_battery_level(level)
+    }
+
+    pub fn get_battery_policy(&self) -> Option<mac::BatteryPolicy> {
+        self.shared.mac.get_battery_policy()
+    }
+
+    /// While the battery is low, raise the data rate of data uplinks and limit their EIRP below
+    /// what the network requested via LinkADRReq, trading range for a longer battery life. Pass
+    /// `None` to always follow the network.
+    pub fn set_battery_policy(&mut self, policy: Option<mac::BatteryPolicy>) {
+        self.shared.mac.set_battery_policy(policy)
+    }
+
+    /// Whether the [`mac::BatteryPolicy`] currently considers the battery low.
+    pub fn is_battery_low(&self) -> bool {
+        self.shared.mac.is_battery_low()
+    }
+
+    /// Limit the MAC commands piggybacked in the FOpts of data uplinks to `len` bytes (at most 15,
+    /// the default). The remaining MAC commands are spread over subsequent uplinks, or sent in
+    /// the FRMPayload of an uplink with empty data on FPort 0.
+    pub fn set_max_fopts_len(&mut self, len: u8) {
+        self.shared.mac.set_max_fopts_len(len)
+    }
+
+    pub fn get_max_fopts_len(&self) -> u8 {
+        self.shared.mac.get_max_fopts_len()
+    }
+
+    /// Require the SNR of recent downlinks to confirm a margin before accepting a LinkADRReq
+    /// which raises the data rate, avoiding back and forth between data rates on marginal links.
+    /// Disabled (`None`) by default.
+    pub fn set_adr_margin(&mut self, margin: Option<mac::AdrMargin>) {
+        self.shared.mac.set_adr_margin(margin)
+    }
+
+    pub fn get_adr_margin(&self) -> Option<mac::AdrMargin> {
+        self.shared.mac.get_adr_margin()
+    }
+
+    /// Largest application payload which can be sent by the next uplink, given the current data
+    /// rate, dwell time setting and MAC commands pending for FOpts. Sending a larger payload fails
+    /// with [`mac::Error::PayloadTooLarge`].
+    pub fn max_payload_len(&self) -> usize {
+        self.shared.mac.max_app_payload_len()
+    }
+
+    /// Replace the LoRaWAN receive delays, eg: for test networks with unusual latencies. This
+    /// breaks interoperability with regular networks.
+    #[cfg(feature = "test-mode")]
+    pub fn set_rx_delay_overrides(&mut self, overrides: mac::RxDelayOverrides) {
+        self.shared.mac.set_rx_delay_overrides(overrides)
+    }
+
+    #[cfg(feature = "test-mode")]
+    pub fn get_rx_delay_overrides(&self) -> mac::RxDelayOverrides {
+        self.shared.mac.get_rx_delay_overrides()
+    }
+
+    /// Set an ordered list of RX2 frequency and data rate candidates replacing the regional RX2
+    /// default, for networks which changed their RX2 parameters (eg: Helium). The next candidate is
+    /// tried whenever the RX windows pass without a downlink, until a downlink is received in RX2.
+    /// Parameters set by the network via RXParamSetupReq take precedence.
+    pub fn set_rx2_profiles(
+        &mut self,
+        profiles: heapless::Vec<mac::Rx2Profile, { mac::MAX_RX2_PROFILES }>,
+    ) {
+        self.shared.mac.set_rx2_profiles(profiles);
+    }
+
+    pub fn get_rx2_profiles(&self) -> &[mac::Rx2Profile] {
+        self.shared.mac.get_rx2_profiles()
+    }
+
+    /// The RX2 candidate currently in use, if any are set.
+    pub fn get_rx2_profile(&self) -> Option<mac::Rx2Profile> {
+        self.shared.mac.get_rx2_profile()
+    }
+
+    /// Summary of the current regional configuration (enabled channels, data rate limits, RX2
+    /// settings and duty cycle limits), eg: to report it for remote diagnostics.
+    pub fn describe_region(&self) -> region::RegionSummary {
+        self.shared.mac.describe_region()
+    }
+
+    /// The regional state set up by the network (enabled channels, dwell time and RX settings),
+    /// to be persisted separately from the session, eg: by ABP devices which don't persist their
+    /// frame counters.
+    pub fn get_regional_state(&self) -> mac::RegionalState {
+        self.shared.mac.get_regional_state()
+    }
+
+    /// Restore the regional state after a reset, before or after joining via ABP.
+    pub fn restore_regional_state(
+        &mut self,
+        state: &mac::RegionalState,
+    ) -> Result<(), mac::RegionalStateError> {
+        self.shared.mac.restore_regional_state(state)
+    }
+
+    /// Determines which FPorts [`send`](Self::send) accepts, see [`mac::FPortPolicy`].
+    pub fn set_fport_policy(&mut self, policy: mac::FPortPolicy) {
+        self.shared.mac.set_fport_policy(policy);
+    }
+
+    pub fn get_fport_policy(&self) -> mac::FPortPolicy {
+        self.shared.mac.get_fport_policy()
+    }
+
+    pub fn get_uplink_dwell_time(&self) -> bool {
+        self.shared.mac.get_uplink_dwell_time()
+    }
+
+    /// Limit uplinks to 400 ms time on air as required by some regulations (eg: AS923 in Japan),
+    /// which lowers the maximum payload size. Disabled by default.
+    pub fn set_uplink_dwell_time(&mut self, enabled: bool) {
+        self.shared.mac.set_uplink_dwell_time(enabled)
+    }
+
+    pub fn get_downlink_dwell_time(&self) -> bool {
+        self.shared.mac.get_downlink_dwell_time()
+    }
+
+    /// Limit downlinks to 400 ms time on air, which raises the lowest RX1 data rate in AS923 to
+    /// DR2. Disabled by default.
+    pub fn set_downlink_dwell_time(&mut self, enabled: bool) {
+        self.shared.mac.set_downlink_dwell_time(enabled)
+    }
+
+    pub fn get_adr(&self) -> bool {
+        self.shared.mac.get_adr()
+    }
+
+    /// Set the ADR bit in uplinks, allowing the network to control data rate and TX power. While
+    /// enabled, the device backs off when the network stops responding. Disabled by default.
+    pub fn set_adr(&mut self, enabled: bool) {
+        self.shared.mac.set_adr(enabled)
+    }
+
+    /// Inform the network that the application switches to the given class (LoRaWAN 1.1). The
+    /// switch is confirmed once [`Device::take_device_mode_conf`] returns the class.
+    pub fn indicate_device_mode(&mut self, class: DeviceClass) -> Result<(), mac::Error> {
+        self.shared.mac.indicate_device_mode(class)
+    }
+
+    /// Take the class confirmed by the network via DeviceModeConf since the last call, if any.
+    pub fn take_device_mode_conf(&mut self) -> Option<DeviceClass> {
+        self.shared.mac.take_device_mode_conf()
+    }
+
+    /// Restore the regional defaults of the channels, data rate, TX power, RX parameters and the
+    /// other settings controlled by the network, as is done on every join. Application settings
+    /// (eg: ADR, battery level, the data rate, RX2 and uplink dwell time it set) and the session
+    /// are kept.
+    pub fn restore_regional_defaults(&mut self) {
+        self.shared.mac.restore_regional_defaults();
+    }
+
+    /// To be called by LoRaWAN 1.1 ABP devices after a reboot, once the session has been
+    /// restored. Reverts the MAC layer to its default settings and sends ResetInd until the
+    /// network confirms the reset.
+    pub fn indicate_reset(&mut self) -> Result<(), mac::Error> {
+        self.shared.mac.indicate_reset()
+    }
+
+    pub fn is_reset_ind_pending(&self) -> bool {
+        self.shared.mac.is_reset_ind_pending()
     }
 
     pub fn ready_to_send_data(&self) -> bool {
@@ -70,9 +422,15 @@ where
     }
 
     pub fn send(&mut self, data: &[u8], fport: u8, confirmed: bool) -> Result<Response, Error<R>> {
+        self.shared.mac.check_fport(fport, data)?;
         self.handle_event(Event::SendDataRequest(SendData { data, fport, confirmed }))
     }
 
+    /// Like [`send`](Self::send), with the FPort, payload and confirmation of an [`Uplink`].
+    pub fn send_uplink(&mut self, uplink: &Uplink<'_>) -> Result<Response, Error<R>> {
+        self.send(uplink.payload(), uplink.fport().value(), uplink.is_confirmed())
+    }
+
     pub fn get_fcnt_up(&self) -> Option<u32> {
         self.shared.mac.get_fcnt_up()
     }
@@ -94,7 +452,7 @@ where
     }
 
     pub fn handle_event(&mut self, event: Event<'_, R>) -> Result<Response, Error<R>> {
-        let (new_state, result) = self.state.handle_event::<R, RNG, N, D>(
+        let (new_state, result) = self.state.handle_event::<R, RNG, N>(
             &mut self.shared.mac,
             &mut self.shared.radio,
             &mut self.shared.rng,
@@ -112,7 +470,7 @@ pub(crate) struct Shared<R: PhyRxTx + Timings, RNG: RngCore, const N: usize, con
     pub(crate) rng: RNG,
     pub(crate) tx_buffer: RadioBuffer<N>,
     pub(crate) mac: Mac,
-    pub(crate) downlink: Vec<Downlink, D>,
+    pub(crate) downlink: collections::Vec<Downlink, D>,
 }
 
 #[derive(Debug)]
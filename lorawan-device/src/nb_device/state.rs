@@ -43,6 +43,8 @@ use super::{
     mac::{Frame, Mac, Window},
     radio, Event, RadioBuffer, Response, Timings,
 };
+use crate::collections::Collection;
+use lorawan::default_crypto::DefaultFactory;
 
 #[derive(Copy, Clone)]
 pub enum State {
@@ -101,25 +103,20 @@ impl<R: radio::PhyRxTx> From<Error> for super::Error<R> {
 }
 
 impl State {
-    pub(crate) fn handle_event<
-        R: radio::PhyRxTx + Timings,
-        RNG: RngCore,
-        const N: usize,
-        const D: usize,
-    >(
+    pub(crate) fn handle_event<R: radio::PhyRxTx + Timings, RNG: RngCore, const N: usize>(
         self,
         mac: &mut Mac,
         radio: &mut R,
         rng: &mut RNG,
         buf: &mut RadioBuffer<N>,
-        dl: &mut Vec<Downlink, D>,
+        dl: &mut dyn Collection<Downlink>,
         event: Event<'_, R>,
     ) -> (Self, Result<Response, super::Error<R>>) {
         match self {
             State::Idle(s) => s.handle_event::<R, RNG, N>(mac, radio, rng, buf, event),
             State::SendingData(s) => s.handle_event::<R, N>(mac, radio, event),
             State::WaitingForRxWindow(s) => s.handle_event::<R, N>(mac, radio, event),
-            State::WaitingForRx(s) => s.handle_event::<R, N, D>(mac, radio, buf, event, dl),
+            State::WaitingForRx(s) => s.handle_event::<R, N>(mac, radio, buf, event, dl),
         }
     }
 }
@@ -143,16 +140,18 @@ impl Idle {
 
         let response = match event {
             // tolerate unexpected timeout
-            Event::Join(creds) => {
-                let (tx_config, dev_nonce) = mac.join_otaa::<RNG, N>(rng, creds, buf);
-                IntermediateResponse::RadioTx((Frame::Join, tx_config, dev_nonce as u32))
-            }
+            Event::Join(creds) => match mac.join_otaa(&DefaultFactory, rng, creds, buf) {
+                Err(e) => IntermediateResponse::EarlyReturn(Err(e.into())),
+                Ok((tx_config, dev_nonce)) => {
+                    IntermediateResponse::RadioTx((Frame::Join, tx_config, dev_nonce as u32))
+                }
+            },
             Event::TimeoutFired => IntermediateResponse::EarlyReturn(Ok(Response::NoUpdate)),
             Event::RadioEvent(_radio_event) => {
                 IntermediateResponse::EarlyReturn(Err(Error::RadioEventWhileIdle.into()))
             }
             Event::SendDataRequest(send_data) => {
-                let tx_config = mac.send::<RNG, N>(rng, buf, &send_data);
+                let tx_config = mac.send(&DefaultFactory, rng, buf, &send_data);
                 match tx_config {
                     Err(e) => IntermediateResponse::EarlyReturn(Err(e.into())),
                     Ok((tx_config, fcnt_up)) => {
@@ -303,13 +302,13 @@ pub struct WaitingForRx {
 }
 
 impl WaitingForRx {
-    pub(crate) fn handle_event<R: radio::PhyRxTx + Timings, const N: usize, const D: usize>(
+    pub(crate) fn handle_event<R: radio::PhyRxTx + Timings, const N: usize>(
         self,
         mac: &mut Mac,
         radio: &mut R,
         buf: &mut RadioBuffer<N>,
         event: Event<'_, R>,
-        dl: &mut Vec<Downlink, D>,
+        dl: &mut dyn Collection<Downlink>,
     ) -> (State, Result<Response, super::Error<R>>) {
         match event {
             // we are waiting for the async tx to complete
@@ -328,7 +327,14 @@ impl WaitingForRx {
                                     Err(Error::BufferTooSmall.into()),
                                 );
                             }
-                            match mac.handle_rx::<N, D>(buf, dl, quality.snr(), &self.rf_config) {
+                            match mac.handle_rx(
+                                &DefaultFactory,
+                                buf,
+                                dl,
+                                quality.snr(),
+                                &self.rf_config,
+                                &self.window.into(),
+                            ) {
                                 // NoUpdate can occur when a stray radio packet is received. Maintain state
                                 mac::Response::NoUpdate => {
                                     (State::WaitingForRx(self), Ok(Response::NoUpdate))
@@ -0,0 +1,367 @@
+//! Compact, versioned binary format for provisioning credentials and network configuration,
+//! eg: for writing them to flash during factory programming instead of compiling them into the
+//! firmware. Use [`Device::from_provisioning`](crate::async_device::Device::from_provisioning) to
+//! create a device from such a blob.
+//!
+//! Layout of version 1 (multi-byte integers are little endian):
+//!
+//! | Length  | Field                                                                         |
+//! |---------|-------------------------------------------------------------------------------|
+//! | 1       | Version (`1`)                                                                 |
+//! | 1       | Flags: bit 0: ABP (otherwise OTAA), bit 1: Class C, bit 2: custom RX2         |
+//! | 1       | Region (see [`Provisioning::region`])                                         |
+//! | 1       | Sub-band (`1..=8`) for US915/AU915, `0` if not set                            |
+//! | 32      | OTAA: DevEUI (LSB), JoinEUI (LSB), AppKey                                     |
+//! | 36      | ABP: DevAddr, NwkSKey, AppSKey                                                |
+//! | 5       | RX2 frequency (u32) and data rate (u8), only present if flagged               |
+//! | 2       | CRC-16/CCITT-FALSE (u16) of all preceding bytes                               |
+use crate::region::{self, Region, DR};
+use crate::{AppEui, AppKey, AppSKey, DevAddr, DevEui, JoinMode, NwkSKey};
+
+/// Current version of the provisioning format.
+pub const VERSION: u8 = 1;
+
+/// Maximum length of an encoded provisioning blob.
+pub const MAX_LEN: usize = 4 + 36 + 5 + 2;
+
+const FLAG_ABP: u8 = 1 << 0;
+const FLAG_CLASS_C: u8 = 1 << 1;
+const FLAG_RX2: u8 = 1 << 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum Error {
+    BufferTooShort,
+    UnsupportedVersion(u8),
+    /// The region is unknown or not enabled via crate features.
+    UnsupportedRegion(u8),
+    InvalidSubband(u8),
+    InvalidDataRate(u8),
+    /// Class C was requested, but the `class-c` feature is not enabled.
+    ClassCUnsupported,
+    InvalidChecksum,
+}
+
+/// Custom RX2 window settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rx2 {
+    pub frequency: u32,
+    pub data_rate: DR,
+}
+
+/// Credentials and network configuration of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Provisioning {
+    pub join_mode: JoinMode,
+    /// Encoded as AS923_1: 0, AS923_2: 1, AS923_3: 2, AS923_4: 3, AU915: 4, EU868: 5, EU433: 6,
+    /// IN865: 7, US915: 8.
+    pub region: Region,
+    /// Sub-band (`1..=8`) to bias the join process on for US915/AU915.
+    pub subband: Option<u8>,
+    pub class_c: bool,
+    pub rx2: Option<Rx2>,
+}
+
+impl Provisioning {
+    /// Decode a provisioning blob, verifying its version and checksum.
+    pub fn decode(blob: &[u8]) -> Result<Self, Error> {
+        let (&version, _) = blob.split_first().ok_or(Error::BufferTooShort)?;
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        if blob.len() < 4 {
+            return Err(Error::BufferTooShort);
+        }
+        let flags = blob[1];
+        let len =
+            4 + if flags & FLAG_ABP != 0 {
+                36
+            } else {
+                32
+            } + if flags & FLAG_RX2 != 0 {
+                5
+            } else {
+                0
+            };
+        if blob.len() < len + 2 {
+            return Err(Error::BufferTooShort);
+        }
+        if crc16(&blob[..len]) != u16::from_le_bytes([blob[len], blob[len + 1]]) {
+            return Err(Error::InvalidChecksum);
+        }
+
+        let region = region_from_id(blob[2])?;
+        let subband = match blob[3] {
+            0 => None,
+            n => Some(n),
+        };
+        let mut reader = Reader(&blob[4..len]);
+        let join_mode = if flags & FLAG_ABP != 0 {
+            JoinMode::ABP {
+                devaddr: DevAddr::from(reader.array::<4>()),
+                nwkskey: NwkSKey::from(reader.array::<16>()),
+                appskey: AppSKey::from(reader.array::<16>()),
+            }
+        } else {
+            JoinMode::OTAA {
+                deveui: DevEui::from(reader.array::<8>()),
+                appeui: AppEui::from(reader.array::<8>()),
+                appkey: AppKey::from(reader.array::<16>()),
+            }
+        };
+        let rx2 = if flags & FLAG_RX2 != 0 {
+            let frequency = u32::from_le_bytes(reader.array::<4>());
+            let [data_rate] = reader.array::<1>();
+            if data_rate > 15 {
+                return Err(Error::InvalidDataRate(data_rate));
+            }
+            Some(Rx2 { frequency, data_rate: DR::try_from(data_rate).unwrap() })
+        } else {
+            None
+        };
+        let provisioning =
+            Self { join_mode, region, subband, class_c: flags & FLAG_CLASS_C != 0, rx2 };
+        provisioning.validate()?;
+        Ok(provisioning)
+    }
+
+    /// Encode into `buf`, returning the length of the blob. A buffer of [`MAX_LEN`] bytes is
+    /// always sufficient.
+    pub fn encode(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.validate()?;
+        let mut flags = 0;
+        let mut data = [0u8; MAX_LEN];
+        let mut len = 4;
+        let mut write = |bytes: &[u8]| {
+            data[len..len + bytes.len()].copy_from_slice(bytes);
+            len += bytes.len();
+        };
+        match &self.join_mode {
+            JoinMode::OTAA { deveui, appeui, appkey } => {
+                write(deveui.as_ref());
+                write(appeui.as_ref());
+                write(appkey.as_ref());
+            }
+            JoinMode::ABP { nwkskey, appskey, devaddr } => {
+                flags |= FLAG_ABP;
+                write(devaddr.as_ref());
+                write(nwkskey.as_ref());
+                write(appskey.as_ref());
+            }
+        }
+        if let Some(rx2) = &self.rx2 {
+            flags |= FLAG_RX2;
+            write(&rx2.frequency.to_le_bytes());
+            write(&[rx2.data_rate as u8]);
+        }
+        if self.class_c {
+            flags |= FLAG_CLASS_C;
+        }
+        data[..4].copy_from_slice(&[
+            VERSION,
+            flags,
+            region_id(self.region),
+            self.subband.unwrap_or(0),
+        ]);
+        let crc = crc16(&data[..len]);
+        data[len..len + 2].copy_from_slice(&crc.to_le_bytes());
+        len += 2;
+
+        let buf = buf.get_mut(..len).ok_or(Error::BufferTooShort)?;
+        buf.copy_from_slice(&data[..len]);
+        Ok(len)
+    }
+
+    /// The region configuration, with the join process biased to the sub-band (if any).
+    pub fn region_configuration(&self) -> region::Configuration {
+        match (self.region, self.subband) {
+            #[cfg(feature = "region-au915")]
+            (Region::AU915, Some(subband)) => {
+                let mut au915 = region::AU915::new();
+                if let Some(subband) = region::Subband::new(subband) {
+                    au915.set_join_bias(subband);
+                }
+                au915.into()
+            }
+            #[cfg(feature = "region-us915")]
+            (Region::US915, Some(subband)) => {
+                let mut us915 = region::US915::new();
+                if let Some(subband) = region::Subband::new(subband) {
+                    us915.set_join_bias(subband);
+                }
+                us915.into()
+            }
+            (region, _) => region::Configuration::new(region),
+        }
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if let Some(subband) = self.subband {
+            if !(1..=8).contains(&subband) || !self.region_configuration().has_fixed_channel_plan()
+            {
+                return Err(Error::InvalidSubband(subband));
+            }
+        }
+        if let Some(rx2) = &self.rx2 {
+            if self.region_configuration().get_datarate(rx2.data_rate as u8).is_none() {
+                return Err(Error::InvalidDataRate(rx2.data_rate as u8));
+            }
+        }
+        if self.class_c && !cfg!(feature = "class-c") {
+            return Err(Error::ClassCUnsupported);
+        }
+        Ok(())
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn array<const L: usize>(&mut self) -> [u8; L] {
+        let (head, tail) = self.0.split_at(L);
+        self.0 = tail;
+        head.try_into().unwrap()
+    }
+}
+
+fn region_id(region: Region) -> u8 {
+    match region {
+        #[cfg(feature = "region-as923-1")]
+        Region::AS923_1 => 0,
+        #[cfg(feature = "region-as923-2")]
+        Region::AS923_2 => 1,
+        #[cfg(feature = "region-as923-3")]
+        Region::AS923_3 => 2,
+        #[cfg(feature = "region-as923-4")]
+        Region::AS923_4 => 3,
+        #[cfg(feature = "region-au915")]
+        Region::AU915 => 4,
+        #[cfg(feature = "region-eu868")]
+        Region::EU868 => 5,
+        #[cfg(feature = "region-eu433")]
+        Region::EU433 => 6,
+        #[cfg(feature = "region-in865")]
+        Region::IN865 => 7,
+        #[cfg(feature = "region-us915")]
+        Region::US915 => 8,
+    }
+}
+
+fn region_from_id(id: u8) -> Result<Region, Error> {
+    match id {
+        #[cfg(feature = "region-as923-1")]
+        0 => Ok(Region::AS923_1),
+        #[cfg(feature = "region-as923-2")]
+        1 => Ok(Region::AS923_2),
+        #[cfg(feature = "region-as923-3")]
+        2 => Ok(Region::AS923_3),
+        #[cfg(feature = "region-as923-4")]
+        3 => Ok(Region::AS923_4),
+        #[cfg(feature = "region-au915")]
+        4 => Ok(Region::AU915),
+        #[cfg(feature = "region-eu868")]
+        5 => Ok(Region::EU868),
+        #[cfg(feature = "region-eu433")]
+        6 => Ok(Region::EU433),
+        #[cfg(feature = "region-in865")]
+        7 => Ok(Region::IN865),
+        #[cfg(feature = "region-us915")]
+        8 => Ok(Region::US915),
+        _ => Err(Error::UnsupportedRegion(id)),
+    }
+}
+
+/// CRC-16/CCITT-FALSE
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, b| {
+        (0..8).fold(crc ^ ((*b as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn otaa() -> Provisioning {
+        Provisioning {
+            join_mode: JoinMode::OTAA {
+                deveui: DevEui::from([1, 2, 3, 4, 5, 6, 7, 8]),
+                appeui: AppEui::from([9, 10, 11, 12, 13, 14, 15, 16]),
+                appkey: AppKey::from([0xAA; 16]),
+            },
+            region: Region::US915,
+            subband: Some(2),
+            class_c: false,
+            rx2: None,
+        }
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut buf = [0; MAX_LEN];
+        let provisioning = otaa();
+        let len = provisioning.encode(&mut buf).unwrap();
+        assert_eq!(len, 4 + 32 + 2);
+        assert_eq!(&buf[..4], [VERSION, 0, 8, 2]);
+        assert_eq!(Provisioning::decode(&buf[..len]), Ok(provisioning));
+    }
+
+    fn abp_class_c() -> Provisioning {
+        Provisioning {
+            join_mode: JoinMode::ABP {
+                nwkskey: NwkSKey::from([0x11; 16]),
+                appskey: AppSKey::from([0x22; 16]),
+                devaddr: DevAddr::from([1, 2, 3, 4]),
+            },
+            region: Region::EU868,
+            subband: None,
+            class_c: true,
+            rx2: Some(Rx2 { frequency: 869_525_000, data_rate: DR::_3 }),
+        }
+    }
+
+    #[cfg(feature = "class-c")]
+    #[test]
+    fn round_trip_class_c() {
+        let mut buf = [0; MAX_LEN];
+        let provisioning = abp_class_c();
+        let len = provisioning.encode(&mut buf).unwrap();
+        assert_eq!(len, MAX_LEN);
+        assert_eq!(Provisioning::decode(&buf[..len]), Ok(provisioning));
+    }
+
+    #[cfg(not(feature = "class-c"))]
+    #[test]
+    fn class_c_unsupported() {
+        let mut buf = [0; MAX_LEN];
+        assert_eq!(abp_class_c().encode(&mut buf), Err(Error::ClassCUnsupported));
+    }
+
+    #[test]
+    fn invalid_blobs() {
+        let mut buf = [0; MAX_LEN];
+        let len = otaa().encode(&mut buf).unwrap();
+        assert_eq!(Provisioning::decode(&buf[..len - 1]), Err(Error::BufferTooShort));
+
+        buf[10] ^= 0xFF;
+        assert_eq!(Provisioning::decode(&buf[..len]), Err(Error::InvalidChecksum));
+
+        buf[0] = 2;
+        assert_eq!(Provisioning::decode(&buf[..len]), Err(Error::UnsupportedVersion(2)));
+
+        let provisioning = Provisioning { region: Region::EU868, ..otaa() };
+        assert_eq!(provisioning.encode(&mut buf), Err(Error::InvalidSubband(2)));
+        assert_eq!(otaa().encode(&mut buf[..10]), Err(Error::BufferTooShort));
+    }
+}
@@ -60,6 +60,7 @@ impl RxQuality {
     }
 }
 
+#[derive(Clone)]
 pub(crate) struct RadioBuffer<const N: usize> {
     packet: [u8; N],
     pos: usize,
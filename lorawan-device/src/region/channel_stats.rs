@@ -0,0 +1,318 @@
+//! Per-channel uplink statistics, optionally used to deprioritize channels which consistently
+//! fail (eg: due to localized interference) when picking the next uplink channel, or to only use
+//! the channels on which downlinks have been received in RX1 (eg: for a private network with a
+//! single gateway serving a subset of the default channels).
+
+/// Highest number of uplink channels of any supported region (US915 and AU915).
+const MAX_CHANNELS: usize = 72;
+
+/// Number of random picks which may be rejected because the channel is being avoided before
+/// falling back to the regular channel selection.
+const MAX_AVOIDED_PICKS: u8 = 16;
+
+/// Number of uplinks (on any channel) after which the RX1 downlink counts are halved, so that
+/// pruning follows changes of the network coverage rather than the lifetime average.
+pub const RX1_DOWNLINK_WINDOW: u16 = 128;
+
+/// Length of the record of a single channel in [`ChannelStats::export`].
+pub const CHANNEL_STATS_RECORD_LEN: usize = 5;
+
+/// Statistics gathered for a single uplink channel.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct ChannelStatistics {
+    /// Number of data uplinks transmitted on this channel.
+    pub uplinks: u16,
+    /// Number of confirmed uplinks which were not acknowledged by the network.
+    pub no_downlink: u16,
+    /// Number of frames addressed to this device received in the RX windows which failed MIC
+    /// verification.
+    pub rx_errors: u16,
+    /// Number of downlinks received in RX1 for uplinks on this channel, halved every
+    /// [`RX1_DOWNLINK_WINDOW`] uplinks.
+    pub rx1_downlinks: u16,
+    /// Number of downlinks received in either window for uplinks on this channel.
+    pub downlinks: u16,
+    consecutive_failures: u8,
+    avoided_for: u8,
+}
+
+impl ChannelStatistics {
+    /// Returns `true` while the channel is temporarily deprioritized.
+    pub fn is_avoided(&self) -> bool {
+        self.avoided_for > 0
+    }
+}
+
+/// Configures how consistently failing channels are deprioritized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct ChannelAvoidance {
+    /// Number of consecutive failures after which a channel is deprioritized.
+    pub max_failures: u8,
+    /// Number of uplinks (on any channel) during which a deprioritized channel is avoided.
+    pub uplinks: u8,
+}
+
+impl Default for ChannelAvoidance {
+    fn default() -> Self {
+        Self { max_failures: 3, uplinks: 32 }
+    }
+}
+
+/// Uplink statistics of all channels of a region.
+#[derive(Debug, Clone)]
+pub struct ChannelStats {
+    channels: [ChannelStatistics; MAX_CHANNELS],
+    last_channel: Option<u8>,
+    avoidance: Option<ChannelAvoidance>,
+    pruning: bool,
+    window_uplinks: u16,
+}
+
+impl Default for ChannelStats {
+    fn default() -> Self {
+        Self {
+            channels: [ChannelStatistics::default(); MAX_CHANNELS],
+            last_channel: None,
+            avoidance: None,
+            pruning: false,
+            window_uplinks: 0,
+        }
+    }
+}
+
+impl ChannelStats {
+    /// Statistics for the given channel index, if it exists.
+    pub fn get(&self, channel: u8) -> Option<&ChannelStatistics> {
+        self.channels.get(channel as usize)
+    }
+
+    /// Iterate over all channels which have been used for at least one uplink.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &ChannelStatistics)> {
+        self.channels.iter().enumerate().filter(|(_, s)| s.uplinks > 0).map(|(i, s)| (i as u8, s))
+    }
+
+    /// Export the uplink and downlink counts of the channels used so far into `buf`, eg: to
+    /// report channel coverage to the application server. Each channel takes
+    /// [`CHANNEL_STATS_RECORD_LEN`] bytes: the channel index (u8), then the number of uplinks and of
+    /// downlinks (u16, little endian). Channels which don't fit into `buf` are left out. Returns
+    /// the number of bytes written.
+    pub fn export(&self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        for ((channel, stats), record) in
+            self.iter().zip(buf.chunks_exact_mut(CHANNEL_STATS_RECORD_LEN))
+        {
+            record[0] = channel;
+            record[1..3].copy_from_slice(&stats.uplinks.to_le_bytes());
+            record[3..5].copy_from_slice(&stats.downlinks.to_le_bytes());
+            len += CHANNEL_STATS_RECORD_LEN;
+        }
+        len
+    }
+
+    /// The current channel avoidance settings; `None` when channel avoidance is disabled.
+    pub fn avoidance(&self) -> Option<ChannelAvoidance> {
+        self.avoidance
+    }
+
+    pub(crate) fn set_avoidance(&mut self, avoidance: Option<ChannelAvoidance>) {
+        if avoidance.is_none() {
+            self.channels.iter_mut().for_each(|s| s.avoided_for = 0);
+        }
+        self.avoidance = avoidance;
+    }
+
+    /// Whether channels without any RX1 downlink are pruned.
+    pub fn pruning(&self) -> bool {
+        self.pruning
+    }
+
+    pub(crate) fn set_pruning(&mut self, pruning: bool) {
+        self.pruning = pruning;
+    }
+
+    pub(crate) fn is_avoided(&self, channel: u8) -> bool {
+        self.avoidance.is_some() && self.channels[channel as usize].is_avoided()
+    }
+
+    /// Pick a channel using `next`, skipping channels which aren't `usable`, channels without any
+    /// RX1 downlink when pruning (as long as a usable channel with one remains) as well as (for a
+    /// bounded number of attempts) channels which are currently being avoided.
+    pub(crate) fn select_channel(
+        &self,
+        mut next: impl FnMut() -> u8,
+        usable: impl Fn(u8) -> bool,
+    ) -> u8 {
+        let prune = self.pruning
+            && self
+                .channels
+                .iter()
+                .enumerate()
+                .any(|(i, s)| s.rx1_downlinks > 0 && usable(i as u8));
+        let mut avoided = 0;
+        loop {
+            let channel = next();
+            if !usable(channel) || prune && self.channels[channel as usize].rx1_downlinks == 0 {
+                continue;
+            }
+            if avoided < MAX_AVOIDED_PICKS && self.is_avoided(channel) {
+                avoided += 1;
+                continue;
+            }
+            return channel;
+        }
+    }
+
+    pub(crate) fn uplink(&mut self, channel: u8) {
+        self.channels.iter_mut().for_each(|s| s.avoided_for = s.avoided_for.saturating_sub(1));
+        let stats = &mut self.channels[channel as usize];
+        stats.uplinks = stats.uplinks.saturating_add(1);
+        self.last_channel = Some(channel);
+        self.window_uplinks += 1;
+        if self.window_uplinks >= RX1_DOWNLINK_WINDOW {
+            self.window_uplinks = 0;
+            self.channels.iter_mut().for_each(|s| s.rx1_downlinks /= 2);
+        }
+    }
+
+    /// A downlink has been received for the last uplink, in RX1 if `rx1`.
+    pub(crate) fn downlink(&mut self, rx1: bool) {
+        if let Some(channel) = self.last_channel.take() {
+            let stats = &mut self.channels[channel as usize];
+            stats.consecutive_failures = 0;
+            stats.downlinks = stats.downlinks.saturating_add(1);
+            if rx1 {
+                stats.rx1_downlinks = stats.rx1_downlinks.saturating_add(1);
+            }
+        }
+    }
+
+    /// The last uplink was confirmed but no acknowledgement has been received.
+    pub(crate) fn no_downlink(&mut self) {
+        if let Some(channel) = self.last_channel.take() {
+            let stats = &mut self.channels[channel as usize];
+            stats.no_downlink = stats.no_downlink.saturating_add(1);
+            self.failure(channel);
+        }
+    }
+
+    /// A frame addressed to this device has been received for the last uplink, but it failed MIC
+    /// verification.
+    pub(crate) fn rx_error(&mut self) {
+        if let Some(channel) = self.last_channel {
+            let stats = &mut self.channels[channel as usize];
+            stats.rx_errors = stats.rx_errors.saturating_add(1);
+            self.failure(channel);
+        }
+    }
+
+    fn failure(&mut self, channel: u8) {
+        let stats = &mut self.channels[channel as usize];
+        stats.consecutive_failures = stats.consecutive_failures.saturating_add(1);
+        if let Some(avoidance) = self.avoidance {
+            if stats.consecutive_failures >= avoidance.max_failures {
+                debug!("Avoiding channel {} for {} uplinks", channel, avoidance.uplinks);
+                stats.consecutive_failures = 0;
+                stats.avoided_for = avoidance.uplinks;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn failing_channel_is_avoided_temporarily() {
+        let mut stats = ChannelStats::default();
+        stats.set_avoidance(Some(ChannelAvoidance { max_failures: 2, uplinks: 2 }));
+        for _ in 0..2 {
+            stats.uplink(3);
+            stats.no_downlink();
+        }
+        assert_eq!(stats.get(3).unwrap().no_downlink, 2);
+        assert!(stats.is_avoided(3));
+
+        let mut candidates = [3, 3, 5].into_iter();
+        assert_eq!(stats.select_channel(|| candidates.next().unwrap(), |_| true), 5);
+
+        stats.uplink(5);
+        stats.downlink(false);
+        assert!(stats.is_avoided(3));
+        stats.uplink(5);
+        assert!(!stats.is_avoided(3));
+        assert_eq!(stats.iter().count(), 2);
+    }
+
+    #[test]
+    fn channels_without_rx1_downlinks_are_pruned() {
+        let mut stats = ChannelStats::default();
+        stats.set_pruning(true);
+        // no RX1 downlink yet: nothing is pruned
+        let mut candidates = [1, 2].into_iter();
+        assert_eq!(stats.select_channel(|| candidates.next().unwrap(), |_| true), 1);
+
+        stats.uplink(2);
+        stats.downlink(true);
+        stats.uplink(1);
+        stats.downlink(false);
+        assert_eq!(stats.get(2).unwrap().rx1_downlinks, 1);
+        let mut candidates = [1, 3, 2].into_iter();
+        assert_eq!(stats.select_channel(|| candidates.next().unwrap(), |_| true), 2);
+
+        // the only channel with RX1 downlinks is disabled
+        let mut candidates = [1, 2].into_iter();
+        assert_eq!(stats.select_channel(|| candidates.next().unwrap(), |c| c != 2), 1);
+
+        stats.set_pruning(false);
+        let mut candidates = [1, 2].into_iter();
+        assert_eq!(stats.select_channel(|| candidates.next().unwrap(), |_| true), 1);
+    }
+
+    #[test]
+    fn rx1_downlinks_decay() {
+        let mut stats = ChannelStats::default();
+        stats.set_pruning(true);
+        for _ in 0..4 {
+            stats.uplink(2);
+            stats.downlink(true);
+        }
+        stats.uplink(1);
+        stats.downlink(true);
+        assert_eq!(stats.get(2).unwrap().rx1_downlinks, 4);
+
+        // channel 1 stops being served in RX1 while channel 2 still is
+        for _ in 5..RX1_DOWNLINK_WINDOW {
+            stats.uplink(1);
+            stats.downlink(false);
+        }
+        assert_eq!(stats.get(1).unwrap().rx1_downlinks, 0);
+        assert_eq!(stats.get(2).unwrap().rx1_downlinks, 2);
+        let mut candidates = [1, 2].into_iter();
+        assert_eq!(stats.select_channel(|| candidates.next().unwrap(), |_| true), 2);
+        assert_eq!(stats.get(1).unwrap().uplinks, RX1_DOWNLINK_WINDOW - 4);
+    }
+
+    #[test]
+    fn export_uplink_and_downlink_counts() {
+        let mut stats = ChannelStats::default();
+        stats.uplink(2);
+        stats.downlink(true);
+        stats.uplink(7);
+        stats.downlink(false);
+        stats.uplink(7);
+        stats.no_downlink();
+        assert_eq!(stats.get(7).unwrap().downlinks, 1);
+
+        let mut buf = [0; 2 * CHANNEL_STATS_RECORD_LEN];
+        assert_eq!(stats.export(&mut buf), 2 * CHANNEL_STATS_RECORD_LEN);
+        assert_eq!(buf, [2, 1, 0, 1, 0, 7, 2, 0, 1, 0]);
+
+        // only whole records are written
+        let mut buf = [0; 2 * CHANNEL_STATS_RECORD_LEN - 1];
+        assert_eq!(stats.export(&mut buf), CHANNEL_STATS_RECORD_LEN);
+        assert_eq!(buf[..CHANNEL_STATS_RECORD_LEN], [2, 1, 0, 1, 0]);
+    }
+}
@@ -62,9 +62,20 @@ impl<const DEFAULT_RX2: u32, const OFFSET: u32> DynamicChannelRegion
         DEFAULT_RX2
     }
 
+    fn min_rx1_datarate(downlink_dwell_time: bool) -> DR {
+        if downlink_dwell_time {
+            DR::_2
+        } else {
+            DR::_0
+        }
+    }
+
+    fn supports_tx_param_setup() -> bool {
+        true
+    }
+
+    // Values correspond to DownlinkDwellTime = 0, see `min_rx1_datarate` for the MinDR otherwise.
     fn get_rx_datarate(tx_dr: DR, rx1_dr_offset: u8, window: &Window) -> DR {
-        // TODO: Handle DwellTime, current values correspond to Dwelltime = 0
-        // In case DwellTime is 1, minimum data rate is DR::_2
         match window {
             Window::_1 => match tx_dr {
                 DR::_0 | DR::_1 | DR::_2 | DR::_3 | DR::_4 | DR::_5 | DR::_6 | DR::_7 => {
@@ -129,7 +129,8 @@ pub(crate) const DATARATES: [Option<Datarate>; NUM_DATARATES as usize] = [
         max_mac_payload_size_with_dwell_time: 250,
     }),
     */
-    // TODO: DR7: FSK: 50 kbps
+    // TODO: DR7: FSK: 50 kbps, requires FSK support in lora-phy and the radio adapter. Until then,
+    // LinkADRReq commanding DR7 is rejected.
     None,
     // TODO: DR8: LR-FHSS CR1/3: 137 kHz BW
     None,
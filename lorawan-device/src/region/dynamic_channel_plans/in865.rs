@@ -52,7 +52,7 @@ impl DynamicChannelRegion for IN865Region {
         match window {
             Window::_1 => match tx_dr {
                 DR::_0 | DR::_1 | DR::_2 | DR::_3 | DR::_4 | DR::_5 | DR::_7 => {
-                    if rx1_dr_offset < 6 {
+                    let dr = if rx1_dr_offset < 6 {
                         tx_dr.offset_sub(rx1_dr_offset)
                     } else {
                         match tx_dr {
@@ -71,6 +71,12 @@ impl DynamicChannelRegion for IN865Region {
                             ))
                             .unwrap(),
                         }
+                    };
+                    // DR6 is RFU in this region
+                    if dr == DR::_6 {
+                        DR::_5
+                    } else {
+                        dr
                     }
                 }
                 DR::_6
@@ -107,6 +107,14 @@ pub(crate) trait DynamicChannelRegion: ChannelRegion {
     fn init_channels(channels: &mut ChannelPlan);
     fn default_rx2_freq() -> u32;
     fn get_rx_datarate(tx_datarate: DR, rx1_dr_offset: u8, window: &Window) -> DR;
+    /// Lowest RX1 data rate (MinDR), depending on the downlink dwell time.
+    fn min_rx1_datarate(_downlink_dwell_time: bool) -> DR {
+        DR::_0
+    }
+    /// Whether the region implements TxParamSetupReq.
+    fn supports_tx_param_setup() -> bool {
+        false
+    }
 }
 
 impl<R: DynamicChannelRegion> RegionHandler for DynamicChannelPlan<R> {
@@ -136,6 +144,12 @@ impl<R: DynamicChannelRegion> RegionHandler for DynamicChannelPlan<R> {
         }
     }
 
+    fn restore_defaults(&mut self) {
+        self.channels = [None; NUM_CHANNELS_DYNAMIC as usize];
+        R::init_channels(&mut self.channels);
+        self.channel_mask = Default::default();
+    }
+
     fn channel_mask_get(&self) -> ChannelMask<9> {
         self.channel_mask.clone()
     }
@@ -144,6 +158,27 @@ impl<R: DynamicChannelRegion> RegionHandler for DynamicChannelPlan<R> {
         self.channel_mask = channel_mask;
     }
 
+    fn channels_get(&self) -> ChannelSettingsList {
+        self.channels.map(|channel| {
+            channel.map(|channel| ChannelSettings {
+                frequency: channel.frequency,
+                dl_frequency: channel.dl_frequency,
+                min_datarate: DR::try_from(channel._datarates.min_data_rate()).unwrap(),
+                max_datarate: DR::try_from(channel._datarates.max_data_rate()).unwrap(),
+            })
+        })
+    }
+
+    fn channels_set(&mut self, channels: &ChannelSettingsList) {
+        self.channels = channels.map(|channel| {
+            channel.map(|channel| Channel {
+                frequency: channel.frequency,
+                _datarates: DataRateRange::new_range(channel.min_datarate, channel.max_datarate),
+                dl_frequency: channel.dl_frequency,
+            })
+        });
+    }
+
     fn channel_mask_update(
         &self,
         channel_mask: &mut ChannelMask<9>,
@@ -196,7 +231,7 @@ impl<R: DynamicChannelRegion> RegionHandler for DynamicChannelPlan<R> {
     }
 
     fn get_datarate(&self, dr: u8) -> Option<&Datarate> {
-        R::datarates()[dr as usize].as_ref()
+        R::datarates().get(dr as usize)?.as_ref()
     }
 
     fn get_tx_dr_and_frequency<RNG: RngCore>(
@@ -204,6 +239,7 @@ impl<R: DynamicChannelRegion> RegionHandler for DynamicChannelPlan<R> {
         rng: &mut RNG,
         datarate: DR,
         frame: &Frame,
+        channel_stats: &ChannelStats,
     ) -> (Datarate, u32) {
         match frame {
             Frame::Join => {
@@ -220,23 +256,25 @@ impl<R: DynamicChannelRegion> RegionHandler for DynamicChannelPlan<R> {
                 (R::datarates()[datarate as usize].clone().unwrap(), channel.frequency)
             }
             Frame::Data => {
-                let mut channel = self.get_random_in_range(rng);
-                loop {
-                    if self.channel_mask.is_enabled(channel).unwrap() {
-                        if let Some(ch) = self.channels[channel] {
-                            self.last_tx_channel = channel as u8;
-                            return (
-                                R::datarates()[datarate as usize].clone().unwrap(),
-                                ch.ul_frequency(),
-                            );
-                        }
-                    }
-                    channel = self.get_random_in_range(rng)
-                }
+                let channel = channel_stats.select_channel(
+                    || self.get_random_in_range(rng) as u8,
+                    |channel| {
+                        self.channel_mask.is_enabled(channel as usize).unwrap()
+                            && self.channels[channel as usize].is_some()
+                    },
+                );
+                self.last_tx_channel = channel;
+                // SAFETY: only channels which are present get selected
+                let ch = self.channels[channel as usize].unwrap();
+                (R::datarates()[datarate as usize].clone().unwrap(), ch.ul_frequency())
             }
         }
     }
 
+    fn get_last_tx_channel(&self) -> u8 {
+        self.last_tx_channel
+    }
+
     fn get_rx_frequency(&self, _frame: &Frame, window: &Window) -> u32 {
         match window {
             // SAFETY: self.last_tx_channel will be populated after correct channel is chosen
@@ -249,6 +287,14 @@ impl<R: DynamicChannelRegion> RegionHandler for DynamicChannelPlan<R> {
         R::get_rx_datarate(tx_datarate, rx1_dr_offset, window)
     }
 
+    fn min_rx1_datarate(&self, downlink_dwell_time: bool) -> DR {
+        R::min_rx1_datarate(downlink_dwell_time)
+    }
+
+    fn supports_tx_param_setup(&self) -> bool {
+        R::supports_tx_param_setup()
+    }
+
     fn check_tx_power(&self, tx_power: u8) -> Option<u8> {
         R::tx_power_adjust(tx_power)
     }
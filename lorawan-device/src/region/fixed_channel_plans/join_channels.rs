@@ -1,6 +1,21 @@
 use super::*;
 use core::cmp::Ordering;
 
+/// How join request channels are chosen in fixed channel plans once the attempts on a preferred
+/// subband (if any, see `set_join_bias`) are used up.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum JoinStrategy {
+    /// Each bank of 8 channels is attempted in turn, as described in the US915/AU915 regional
+    /// specifications. Suited to private networks where only one subband is served.
+    #[default]
+    RoundRobin,
+    /// Each join request uses a random channel among the channels enabled by the channel mask
+    /// (all 72 by default). Suited to dense networks with gateways listening on many subbands.
+    Random,
+}
+
 #[derive(Clone, Default)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub(crate) struct JoinChannels {
@@ -14,6 +29,10 @@ pub(crate) struct JoinChannels {
     pub(crate) available_channels: AvailableChannels,
     /// The channel used for the previous join request.
     pub(crate) previous_channel: u8,
+    /// Channel selection once the preferred subband has been exhausted.
+    strategy: JoinStrategy,
+    /// Order in which the round robin visits the subbands, eg: by observed activity.
+    order: Option<[Subband; 8]>,
 }
 
 impl JoinChannels {
@@ -47,6 +66,22 @@ impl JoinChannels {
         self.max_retries = max_retries;
     }
 
+    pub(crate) fn set_strategy(&mut self, strategy: JoinStrategy) {
+        self.strategy = strategy;
+    }
+
+    pub(crate) fn strategy(&self) -> JoinStrategy {
+        self.strategy
+    }
+
+    pub(crate) fn set_order(&mut self, order: Option<[Subband; 8]>) {
+        self.order = order;
+    }
+
+    pub(crate) fn order(&self) -> Option<[Subband; 8]> {
+        self.order
+    }
+
     pub(crate) fn clear_join_bias(&mut self) {
         self.preferred_subband = None;
         self.max_retries = 0;
@@ -58,7 +93,11 @@ impl JoinChannels {
         self.available_channels = AvailableChannels::default();
     }
 
-    pub(crate) fn get_next_channel(&mut self, rng: &mut impl RngCore) -> u8 {
+    pub(crate) fn get_next_channel(
+        &mut self,
+        rng: &mut impl RngCore,
+        channel_mask: &ChannelMask<9>,
+    ) -> u8 {
         match (self.preferred_subband, self.num_retries.cmp(&self.max_retries)) {
             (Some(sb), Ordering::Less) => {
                 self.num_retries += 1;
@@ -77,7 +116,19 @@ impl JoinChannels {
             }
             _ => {
                 self.num_retries += 1;
-                self.available_channels.get_next(rng)
+                match self.strategy {
+                    JoinStrategy::RoundRobin => {
+                        self.available_channels.get_next(rng, self.order.as_ref())
+                    }
+                    JoinStrategy::Random => {
+                        let enabled = (0..72u8)
+                            .filter(|channel| channel_mask.is_enabled((*channel).into()).unwrap());
+                        match enabled.clone().count() {
+                            0 => (rng.next_u32() % 72) as u8,
+                            count => enabled.clone().nth(rng.next_u32() as usize % count).unwrap(),
+                        }
+                    }
+                }
             }
         }
     }
@@ -101,13 +152,16 @@ impl AvailableChannels {
         true
     }
 
-    fn get_next(&mut self, rng: &mut impl RngCore) -> u8 {
+    fn get_next(&mut self, rng: &mut impl RngCore, order: Option<&[Subband; 8]>) -> u8 {
         // this guarantees that there will be _some_ open channel available
         if self.is_exhausted() {
             self.reset();
         }
 
-        let channel = self.get_next_channel_inner(rng);
+        let channel = match order.and_then(|order| self.get_next_ordered(order, rng)) {
+            Some(channel) => channel,
+            None => self.get_next_channel_inner(rng),
+        };
         // mark the channel invalid for future selection
         self.data.set_channel(channel.into(), false);
         self.previous = Some(channel);
@@ -153,6 +207,26 @@ impl AvailableChannels {
         }
     }
 
+    /// Visit the subbands in the given order, followed by the 500 kHz channels, picking a random
+    /// available channel of each. Returns `None` if the order leaves no channel available.
+    fn get_next_ordered(&self, order: &[Subband; 8], rng: &mut impl RngCore) -> Option<u8> {
+        let mut banks = [8; 9];
+        for (bank, subband) in banks.iter_mut().zip(order) {
+            *bank = *subband as u8 - 1;
+        }
+        let start = match self.previous {
+            Some(previous) => banks.iter().position(|bank| *bank == previous / 8)? + 1,
+            None => 0,
+        };
+        (0..banks.len()).find_map(|i| {
+            let bank = banks[(start + i) % banks.len()];
+            let available =
+                (bank * 8..bank * 8 + 8).filter(|c| self.data.is_enabled((*c).into()).unwrap());
+            let count = available.clone().count();
+            (count > 0).then(|| available.clone().nth(rng.next_u32() as usize % count).unwrap())
+        })
+    }
+
     fn reset(&mut self) {
         self.data = ChannelMask::default();
         self.previous = None;
@@ -201,6 +275,28 @@ macro_rules! impl_join_bias {
             pub fn clear_join_bias(&mut self) {
                 self.0.join_channels.clear_join_bias()
             }
+
+            /// Select how join channels are chosen after the attempts on the preferred subband
+            /// (if any). Defaults to [`JoinStrategy::RoundRobin`].
+            pub fn set_join_strategy(&mut self, strategy: JoinStrategy) {
+                self.0.join_channels.set_strategy(strategy)
+            }
+
+            pub fn get_join_strategy(&self) -> JoinStrategy {
+                self.0.join_channels.strategy()
+            }
+
+            /// Visit the subbands in the given order during the round robin of
+            /// [`JoinStrategy::RoundRobin`], eg: most active first as determined by a pre-join
+            /// scan. Each round still attempts every subband once. `None` restores the standard
+            /// order.
+            pub fn set_join_order(&mut self, order: Option<[Subband; 8]>) {
+                self.0.join_channels.set_order(order)
+            }
+
+            pub fn get_join_order(&self) -> Option<[Subband; 8]> {
+                self.0.join_channels.order()
+            }
         }
     };
 }
@@ -220,27 +316,29 @@ mod test {
         AppEui, AppKey, DevEui, NetworkCredentials,
     };
     use heapless::Vec;
+    use lorawan::default_crypto::DefaultFactory;
 
     #[test]
     fn test_join_channels_standard() {
         let mut rng = rand_core::OsRng;
+        let all = ChannelMask::default();
         // run the test a bunch of times due to the rng
         for _ in 0..100 {
             let mut join_channels = JoinChannels::default();
-            let first_channel = join_channels.get_next_channel(&mut rng);
+            let first_channel = join_channels.get_next_channel(&mut rng, &all);
             // the first channel is always in the bottom 64
             assert!(first_channel < 64);
-            let next_channel = join_channels.get_next_channel(&mut rng);
+            let next_channel = join_channels.get_next_channel(&mut rng, &all);
             // the next channel is always incremented by 8, since we always have
             // the fat bank (channels 64-71)
             assert_eq!(next_channel, first_channel + 8);
             // we generate 6 more channels
             for _ in 0..7 {
-                let c = join_channels.get_next_channel(&mut rng);
+                let c = join_channels.get_next_channel(&mut rng, &all);
                 assert!(c < 72);
             }
             // after 8 tries, we should be back at the original bank but on a different channel
-            let ninth_channel = join_channels.get_next_channel(&mut rng);
+            let ninth_channel = join_channels.get_next_channel(&mut rng, &all);
             assert_eq!(ninth_channel / 8, first_channel / 8);
             assert_ne!(ninth_channel, first_channel);
         }
@@ -249,18 +347,19 @@ mod test {
     #[test]
     fn test_join_channels_standard_exhausted() {
         let mut rng = rand_core::OsRng;
+        let all = ChannelMask::default();
 
         let mut join_channels = JoinChannels::default();
-        let first_channel = join_channels.get_next_channel(&mut rng);
+        let first_channel = join_channels.get_next_channel(&mut rng, &all);
         // the first channel is always in the bottom 64
         assert!(first_channel < 64);
-        let next_channel = join_channels.get_next_channel(&mut rng);
+        let next_channel = join_channels.get_next_channel(&mut rng, &all);
         // the next channel is always incremented by 8, since we always have
         // the fat bank (channels 64-71)
         assert_eq!(next_channel, first_channel + 8);
         // we generate 6000
         for _ in 0..6000 {
-            let c = join_channels.get_next_channel(&mut rng);
+            let c = join_channels.get_next_channel(&mut rng, &all);
             assert!(c < 72);
         }
     }
@@ -268,30 +367,82 @@ mod test {
     #[test]
     fn test_join_channels_biased() {
         let mut rng = rand_core::OsRng;
+        let all = ChannelMask::default();
         // run the test a bunch of times due to the rng
         for _ in 0..100 {
             let mut join_channels = JoinChannels::default();
             join_channels.set_join_bias(Subband::_2, 1);
-            let first_channel = join_channels.get_next_channel(&mut rng);
+            let first_channel = join_channels.get_next_channel(&mut rng, &all);
             // the first is on subband 2
             assert!(first_channel > 7);
             assert!(first_channel < 16);
-            let next_channel = join_channels.get_next_channel(&mut rng);
+            let next_channel = join_channels.get_next_channel(&mut rng, &all);
             // the next channel is always incremented by 8, since we always have
             // the fat bank (channels 64-71)
             assert_eq!(next_channel, first_channel + 8);
             // we generate 6 more channels
             for _ in 0..7 {
-                let c = join_channels.get_next_channel(&mut rng);
+                let c = join_channels.get_next_channel(&mut rng, &all);
                 assert!(c < 72);
             }
             // after 8 tries, we should be back at the biased bank but on a different channel
-            let ninth_channel = join_channels.get_next_channel(&mut rng);
+            let ninth_channel = join_channels.get_next_channel(&mut rng, &all);
             assert_eq!(ninth_channel / 8, first_channel / 8);
             assert_ne!(ninth_channel, first_channel);
         }
     }
 
+    #[test]
+    fn test_join_channels_random() {
+        let mut rng = crate::rng::Prng::new(7);
+        let all = ChannelMask::default();
+        let mut join_channels = JoinChannels::default();
+        join_channels.set_join_bias(Subband::_2, 1);
+        join_channels.set_strategy(JoinStrategy::Random);
+        let first_channel = join_channels.get_next_channel(&mut rng, &all);
+        // the bias is still honored first
+        assert_eq!(first_channel / 8, 1);
+        let mut banks = [false; 9];
+        for _ in 0..1000 {
+            let c = join_channels.get_next_channel(&mut rng, &all);
+            assert!(c < 72);
+            banks[c as usize / 8] = true;
+        }
+        // every bank gets used eventually
+        assert!(banks.iter().all(|b| *b));
+
+        // only enabled channels are picked
+        let mut subbands = ChannelMask::<9>::new_from_raw(&[0; 9]);
+        subbands.set_bank(1, 0xFF);
+        subbands.set_bank(8, 0b10);
+        let mut channels = [false; 72];
+        for _ in 0..1000 {
+            channels[join_channels.get_next_channel(&mut rng, &subbands) as usize] = true;
+        }
+        let picked: std::vec::Vec<usize> = (0..72).filter(|c| channels[*c]).collect();
+        assert_eq!(picked, [8, 9, 10, 11, 12, 13, 14, 15, 65]);
+    }
+
+    #[test]
+    fn test_join_channels_ordered() {
+        use Subband::*;
+        let mut rng = rand_core::OsRng;
+        let all = ChannelMask::default();
+        let mut join_channels = JoinChannels::default();
+        join_channels.set_order(Some([_3, _1, _2, _4, _5, _6, _7, _8]));
+        for _ in 0..2 {
+            let banks: std::vec::Vec<u8> =
+                (0..9).map(|_| join_channels.get_next_channel(&mut rng, &all) / 8).collect();
+            assert_eq!(banks, [2, 0, 1, 3, 4, 5, 6, 7, 8]);
+        }
+        // every channel is attempted before any is repeated
+        let mut channels: std::vec::Vec<u8> =
+            (0..54).map(|_| join_channels.get_next_channel(&mut rng, &all)).collect();
+        channels.sort();
+        channels.dedup();
+        assert_eq!(channels.len(), 54);
+    }
+
     #[test]
     fn test_full_mac_compliant_bias() {
         let mut us915 = US915::new();
@@ -299,15 +450,18 @@ mod test {
         let mut mac = Mac::new(us915.into(), 21, 2);
 
         let mut buf: RadioBuffer<255> = RadioBuffer::new();
-        let (tx_config, _len) = mac.join_otaa::<_, 255>(
-            &mut rand::rngs::OsRng,
-            NetworkCredentials::new(
-                AppEui::from([0x0; 8]),
-                DevEui::from([0x0; 8]),
-                AppKey::from(get_key()),
-            ),
-            &mut buf,
-        );
+        let (tx_config, _len) = mac
+            .join_otaa::<_, _, 255>(
+                &DefaultFactory,
+                &mut rand::rngs::OsRng,
+                NetworkCredentials::new(
+                    AppEui::from([0x0; 8]),
+                    DevEui::from([0x0; 8]),
+                    AppKey::from(get_key()),
+                ),
+                &mut buf,
+            )
+            .unwrap();
         // Confirm that the join request occurs on our subband
         assert!(
             tx_config.rf.frequency >= 903_900_000,
@@ -330,13 +484,15 @@ mod test {
         buf.extend_from_slice(&rx_buf[..len]).unwrap();
 
         let rx_config = mac.get_rx_config(0, &Frame::Data, &Window::_1);
-        let response = mac.handle_rx::<255, 3>(&mut buf, &mut downlinks, 0, &rx_config.rf);
+        let response =
+            mac.handle_rx(&DefaultFactory, &mut buf, &mut downlinks, 0, &rx_config.rf, &Window::_1);
         if let Response::JoinSuccess = response {
         } else {
             panic!("Did not receive join success");
         }
         let (tx_config, _len) = mac
-            .send::<_, 255>(
+            .send::<_, _, 255>(
+                &DefaultFactory,
                 &mut rand::rngs::OsRng,
                 &mut buf,
                 &SendData { fport: 1, data: &[0x0; 1], confirmed: false },
@@ -362,15 +518,18 @@ mod test {
         let mut mac = Mac::new(us915.into(), 21, 2);
 
         let mut buf: RadioBuffer<255> = RadioBuffer::new();
-        let (tx_config, _len) = mac.join_otaa::<_, 255>(
-            &mut rand::rngs::OsRng,
-            NetworkCredentials::new(
-                AppEui::from([0x0; 8]),
-                DevEui::from([0x0; 8]),
-                AppKey::from(get_key()),
-            ),
-            &mut buf,
-        );
+        let (tx_config, _len) = mac
+            .join_otaa::<_, _, 255>(
+                &DefaultFactory,
+                &mut rand::rngs::OsRng,
+                NetworkCredentials::new(
+                    AppEui::from([0x0; 8]),
+                    DevEui::from([0x0; 8]),
+                    AppKey::from(get_key()),
+                ),
+                &mut buf,
+            )
+            .unwrap();
         // Confirm that the join request occurs on our subband
         assert!(
             tx_config.rf.frequency >= 903_900_000,
@@ -392,14 +551,16 @@ mod test {
         buf.clear();
         buf.extend_from_slice(&rx_buf[..len]).unwrap();
         let rx_config = mac.get_rx_config(0, &Frame::Data, &Window::_1);
-        let response = mac.handle_rx::<255, 3>(&mut buf, &mut downlinks, 0, &rx_config.rf);
+        let response =
+            mac.handle_rx(&DefaultFactory, &mut buf, &mut downlinks, 0, &rx_config.rf, &Window::_1);
         if let Response::JoinSuccess = response {
         } else {
             panic!("Did not receive JoinSuccess")
         }
         for _ in 0..8 {
             let (tx_config, _len) = mac
-                .send::<_, 255>(
+                .send::<_, _, 255>(
+                    &DefaultFactory,
                     &mut rand::rngs::OsRng,
                     &mut buf,
                     &SendData { fport: 1, data: &[0x0; 1], confirmed: false },
@@ -4,6 +4,7 @@ use lorawan::maccommands::ChannelMask;
 
 mod join_channels;
 use join_channels::JoinChannels;
+pub use join_channels::JoinStrategy;
 
 #[cfg(feature = "region-au915")]
 mod au915;
@@ -20,6 +21,7 @@ pub use us915::US915;
 /// Each Subband holds 8 channels. eg: subband 1 contains: channels 0-7, subband 2: channels 8-15, etc.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
 #[repr(usize)]
 pub enum Subband {
     _1 = 1,
@@ -38,6 +40,112 @@ impl From<Subband> for usize {
     }
 }
 
+/// Rejects invalid const parameters at compile time: evaluating `VALID` fails the build (when
+/// the function referring to it is instantiated) unless `N` is in `MIN..=MAX`.
+struct InRange<const N: u8, const MIN: u8, const MAX: u8>;
+
+impl<const N: u8, const MIN: u8, const MAX: u8> InRange<N, MIN, MAX> {
+    const VALID: () = assert!(N >= MIN && N <= MAX, "out of range");
+}
+
+impl Subband {
+    /// All subbands, in order.
+    pub const ALL: [Subband; 8] = [
+        Subband::_1,
+        Subband::_2,
+        Subband::_3,
+        Subband::_4,
+        Subband::_5,
+        Subband::_6,
+        Subband::_7,
+        Subband::_8,
+    ];
+
+    /// Subband `N` (1 to 8), validated at compile time, eg: for the subband of a board
+    /// configuration:
+    ///
+    /// ```
+    /// use lorawan_device::region::Subband;
+    ///
+    /// const SUBBAND: Subband = Subband::from_const::<2>();
+    /// assert_eq!(SUBBAND, Subband::_2);
+    /// ```
+    ///
+    /// ```compile_fail
+    /// # use lorawan_device::region::Subband;
+    /// const SUBBAND: Subband = Subband::from_const::<9>();
+    /// ```
+    pub const fn from_const<const N: u8>() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = InRange::<N, 1, 8>::VALID;
+        Self::ALL[N as usize - 1]
+    }
+
+    /// Subband `n` (1 to 8), eg: as configured at runtime. `None` for any other number.
+    pub const fn new(n: u8) -> Option<Self> {
+        match n {
+            1..=8 => Some(Self::ALL[n as usize - 1]),
+            _ => None,
+        }
+    }
+
+    /// The 125 kHz channels of the subband.
+    pub fn channels(self) -> core::ops::Range<u8> {
+        let first = (self as u8 - 1) * 8;
+        first..first + 8
+    }
+}
+
+/// An uplink channel of a fixed channel plan: 0 to 63 for the 125 kHz channels, 64 to 71 for the
+/// 500 kHz channels. Converts into the channel index used by the channel mask.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct FixedChannel(u8);
+
+impl FixedChannel {
+    /// Channel `N`, validated at compile time:
+    ///
+    /// ```
+    /// use lorawan_device::region::{FixedChannel, Subband};
+    ///
+    /// const CHANNEL: FixedChannel = FixedChannel::from_const::<10>();
+    /// assert_eq!(CHANNEL.subband(), Some(Subband::_2));
+    /// ```
+    ///
+    /// ```compile_fail
+    /// # use lorawan_device::region::FixedChannel;
+    /// const CHANNEL: FixedChannel = FixedChannel::from_const::<72>();
+    /// ```
+    pub const fn from_const<const N: u8>() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = InRange::<N, 0, 71>::VALID;
+        Self(N)
+    }
+
+    /// Channel `n`, `None` if there is no such channel.
+    pub const fn new(n: u8) -> Option<Self> {
+        match n {
+            0..=71 => Some(Self(n)),
+            _ => None,
+        }
+    }
+
+    pub const fn index(self) -> u8 {
+        self.0
+    }
+
+    /// The subband of a 125 kHz channel, `None` for the 500 kHz channels.
+    pub const fn subband(self) -> Option<Subband> {
+        Subband::new(self.0 / 8 + 1)
+    }
+}
+
+impl From<FixedChannel> for u8 {
+    fn from(channel: FixedChannel) -> u8 {
+        channel.0
+    }
+}
+
 #[derive(Clone)]
 pub(crate) struct FixedChannelPlan<F: FixedChannelRegion> {
     last_tx_channel: u8,
@@ -84,6 +192,16 @@ impl<F: FixedChannelRegion> FixedChannelPlan<F> {
         // channel_mask.set_bank(9, extra_mask.get_index(1));
     }
 
+    /// Data rate and frequency of a join request on the channel.
+    pub(crate) fn join_channel(&self, channel: u8) -> (Datarate, u32) {
+        let dr = if channel < 64 {
+            DR::_0
+        } else {
+            DR::_4
+        };
+        (F::datarates()[dr as usize].clone().unwrap(), F::uplink_channels()[channel as usize])
+    }
+
     #[allow(unused)]
     pub fn get_max_payload_length(datarate: DR, repeater_compatible: bool, dwell_time: bool) -> u8 {
         F::get_max_payload_length(datarate, repeater_compatible, dwell_time)
@@ -96,6 +214,10 @@ pub(crate) trait FixedChannelRegion: ChannelRegion {
     fn downlink_channels() -> &'static [u32; 8];
     fn default_rx2_freq() -> u32;
     fn get_rx_datarate(tx_dr: DR, rx1_dr_offset: u8, window: &Window) -> DR;
+    /// Whether the region implements TxParamSetupReq.
+    fn supports_tx_param_setup() -> bool {
+        false
+    }
 }
 
 impl<F: FixedChannelRegion> RegionHandler for FixedChannelPlan<F> {
@@ -105,6 +227,12 @@ impl<F: FixedChannelRegion> RegionHandler for FixedChannelPlan<F> {
         }
     }
 
+    fn restore_defaults(&mut self) {
+        // unlike channel_mask_set, the state of the join channels is kept: after a join, the
+        // first data channel is picked from the subband the join succeeded on
+        self.channel_mask = Default::default();
+    }
+
     fn channel_mask_get(&self) -> ChannelMask<9> {
         self.channel_mask.clone()
     }
@@ -171,7 +299,28 @@ impl<F: FixedChannelRegion> RegionHandler for FixedChannelPlan<F> {
     }
 
     fn get_datarate(&self, dr: u8) -> Option<&Datarate> {
-        F::datarates()[dr as usize].as_ref()
+        F::datarates().get(dr as usize)?.as_ref()
+    }
+
+    fn channel_settings(&self, index: u8) -> Option<ChannelSettings> {
+        let frequency = *F::uplink_channels().get(index as usize)?;
+        let bandwidth = if index < 64 {
+            Bandwidth::_125KHz
+        } else {
+            Bandwidth::_500KHz
+        };
+        // DR8 and above are reserved for downlinks
+        let mut datarates = (0..8u8).filter(|dr| {
+            F::datarates()[*dr as usize].as_ref().is_some_and(|dr| dr.bandwidth == bandwidth)
+        });
+        let min_datarate = datarates.next()?;
+        let max_datarate = datarates.next_back().unwrap_or(min_datarate);
+        Some(ChannelSettings {
+            frequency,
+            dl_frequency: Some(F::downlink_channels()[index as usize % 8]),
+            min_datarate: DR::try_from(min_datarate).unwrap(),
+            max_datarate: DR::try_from(max_datarate).unwrap(),
+        })
     }
 
     fn get_tx_dr_and_frequency<RNG: RngCore>(
@@ -179,18 +328,13 @@ impl<F: FixedChannelRegion> RegionHandler for FixedChannelPlan<F> {
         rng: &mut RNG,
         datarate: DR,
         frame: &Frame,
+        channel_stats: &ChannelStats,
     ) -> (Datarate, u32) {
         match frame {
             Frame::Join => {
-                let channel = self.join_channels.get_next_channel(rng);
-                let dr = if channel < 64 {
-                    DR::_0
-                } else {
-                    DR::_4
-                };
+                let channel = self.join_channels.get_next_channel(rng, &self.channel_mask);
                 self.last_tx_channel = channel;
-                let data_rate = F::datarates()[dr as usize].clone().unwrap();
-                (data_rate, F::uplink_channels()[channel as usize])
+                self.join_channel(channel)
             }
             Frame::Data => {
                 // The join bias gets reset after receiving CFList in Join Frame
@@ -198,7 +342,7 @@ impl<F: FixedChannelRegion> RegionHandler for FixedChannelPlan<F> {
                 // If it has not been reset yet, we continue to use the bias for the data frames.
                 // We hope to acquire ChannelMask via LinkADRReq.
                 let (data_rate, channel) = if self.join_channels.has_bias_and_not_exhausted() {
-                    let channel = self.join_channels.get_next_channel(rng);
+                    let channel = self.join_channels.get_next_channel(rng, &self.channel_mask);
                     let dr = if channel < 64 {
                         DR::_0
                     } else {
@@ -214,21 +358,17 @@ impl<F: FixedChannelRegion> RegionHandler for FixedChannelPlan<F> {
                     // from. If the datarate bandwidth is 500 kHz, we must use
                     // channels 64..=71. Else, we must use 0-63
                     let datarate = F::datarates()[datarate as usize].clone().unwrap();
-                    if datarate.bandwidth == Bandwidth::_500KHz {
-                        let mut channel = (rng.next_u32() & 0b111) as u8;
-                        // keep selecting a random channel until we find one that is enabled
-                        while !self.channel_mask.is_enabled((channel + 64).into()).unwrap() {
-                            channel = (rng.next_u32() & 0b111) as u8;
-                        }
-                        (datarate, 64 + channel)
+                    let (first, mask) = if datarate.bandwidth == Bandwidth::_500KHz {
+                        (64, 0b111)
                     } else {
-                        let mut channel = (rng.next_u32() & 0b111111) as u8;
-                        // keep selecting a random channel until we find one that is enabled
-                        while !self.channel_mask.is_enabled(channel.into()).unwrap() {
-                            channel = (rng.next_u32() & 0b111111) as u8;
-                        }
-                        (datarate, channel)
-                    }
+                        (0, 0b111111)
+                    };
+                    // keep selecting a random channel until we find one that is enabled
+                    let channel = channel_stats.select_channel(
+                        || first + (rng.next_u32() & mask) as u8,
+                        |channel| self.channel_mask.is_enabled(channel.into()).unwrap(),
+                    );
+                    (datarate, channel)
                 };
                 self.last_tx_channel = channel;
                 (data_rate, F::uplink_channels()[channel as usize])
@@ -236,6 +376,10 @@ impl<F: FixedChannelRegion> RegionHandler for FixedChannelPlan<F> {
         }
     }
 
+    fn get_last_tx_channel(&self) -> u8 {
+        self.last_tx_channel
+    }
+
     fn get_rx_frequency(&self, _frame: &Frame, window: &Window) -> u32 {
         let channel = self.last_tx_channel % 8;
         match window {
@@ -260,6 +404,10 @@ impl<F: FixedChannelRegion> RegionHandler for FixedChannelPlan<F> {
         true
     }
 
+    fn supports_tx_param_setup(&self) -> bool {
+        F::supports_tx_param_setup()
+    }
+
     fn channel_dl_update(&mut self, _: u8, _: u32) -> (bool, bool) {
         unreachable!()
     }
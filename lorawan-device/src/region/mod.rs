@@ -7,6 +7,13 @@ use lorawan::{
 use rand_core::RngCore;
 
 use crate::mac::{Frame, Window};
+mod channel_stats;
+pub use channel_stats::{
+    ChannelAvoidance, ChannelStatistics, ChannelStats, CHANNEL_STATS_RECORD_LEN,
+    RX1_DOWNLINK_WINDOW,
+};
+mod summary;
+pub use summary::{ChannelSummary, RegionSummary, MAX_CHANNELS};
 pub(crate) mod constants;
 pub(crate) use crate::radio::*;
 use constants::*;
@@ -53,12 +60,12 @@ pub(crate) use dynamic_channel_plans::IN865;
 
 #[cfg(any(feature = "region-us915", feature = "region-au915"))]
 mod fixed_channel_plans;
-#[cfg(any(feature = "region-us915", feature = "region-au915"))]
-pub use fixed_channel_plans::Subband;
 #[cfg(feature = "region-au915")]
 pub use fixed_channel_plans::AU915;
 #[cfg(feature = "region-us915")]
 pub use fixed_channel_plans::US915;
+#[cfg(any(feature = "region-us915", feature = "region-au915"))]
+pub use fixed_channel_plans::{FixedChannel, JoinStrategy, Subband};
 
 pub(crate) trait ChannelRegion {
     fn datarates() -> &'static [Option<Datarate>; NUM_DATARATES as usize];
@@ -89,10 +96,15 @@ pub(crate) trait ChannelRegion {
 /// fine-tuning, like for example [`US915`] or [`AU915`].
 pub struct Configuration {
     state: State,
+    channel_stats: ChannelStats,
+    /// Whether downlinks are limited to 400 ms time on air, which raises the lowest RX1 data rate
+    /// in some regions (eg: AS923).
+    downlink_dwell_time: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
 /// Regions supported by this crate: AS923_1, AS923_2, AS923_3, AS923_4, AU915, EU868, EU433, IN865, US915.
 ///
 /// Each region is individually feature-gated (eg: `region-eu868`), however, by default, all regions are enabled.
@@ -345,7 +357,7 @@ impl Configuration {
     }
 
     fn with_state(state: State) -> Configuration {
-        Configuration { state }
+        Configuration { state, channel_stats: ChannelStats::default(), downlink_dwell_time: false }
     }
 
     pub fn get_max_payload_length(
@@ -363,6 +375,41 @@ impl Configuration {
         )
     }
 
+    /// Maximum MACPayload size (in bytes) for the given data rate, or `None` if the data rate is
+    /// not defined in this region.
+    pub fn max_payload(&self, datarate: DR, dwell_time: bool) -> Option<u8> {
+        self.get_datarate(datarate as u8)?;
+        Some(self.get_max_payload_length(datarate, false, dwell_time))
+    }
+
+    /// Whether the data rate is defined in this region and can be used for uplinks.
+    pub fn is_valid_uplink_dr(&self, datarate: DR) -> bool {
+        // US915 and AU915 reserve DR8 and above for downlinks.
+        self.get_datarate(datarate as u8).is_some()
+            && (!self.has_fixed_channel_plan() || (datarate as u8) < 8)
+    }
+
+    /// Whether the data rate is defined in this region and can be used for downlinks.
+    pub fn is_valid_downlink_dr(&self, datarate: DR) -> bool {
+        self.get_datarate(datarate as u8).is_some()
+            && (!self.has_fixed_channel_plan() || (datarate as u8) >= 8)
+    }
+
+    /// Whether the frequency (Hz) lies within the regional band edges.
+    pub fn is_valid_frequency(&self, frequency: u32) -> bool {
+        self.frequency_valid(frequency)
+    }
+
+    /// Whether the TXPower index (as used by LinkADRReq) is defined in this region.
+    pub fn is_valid_tx_power(&self, tx_power: u8) -> bool {
+        self.check_tx_power(tx_power).is_some()
+    }
+
+    /// The data rate used for uplinks until changed by the network.
+    pub fn default_datarate(&self) -> DR {
+        self.get_default_datarate()
+    }
+
     pub(crate) fn create_tx_config<RNG: RngCore>(
         &mut self,
         rng: &mut RNG,
@@ -370,6 +417,9 @@ impl Configuration {
         frame: &Frame,
     ) -> TxConfig {
         let (dr, frequency) = self.get_tx_dr_and_frequency(rng, datarate, frame);
+        if let Frame::Data = frame {
+            self.channel_stats.uplink(self.get_last_tx_channel());
+        }
         TxConfig {
             // We can do this safely, as default output power will be positive
             pw: self.check_tx_power(0).unwrap().unwrap() as i8,
@@ -399,7 +449,75 @@ impl Configuration {
         datarate: DR,
         frame: &Frame,
     ) -> (Datarate, u32) {
-        mut_region_dispatch!(self, get_tx_dr_and_frequency, rng, datarate, frame)
+        mut_region_dispatch!(
+            self,
+            get_tx_dr_and_frequency,
+            rng,
+            datarate,
+            frame,
+            &self.channel_stats
+        )
+    }
+
+    fn get_last_tx_channel(&self) -> u8 {
+        region_dispatch!(self, get_last_tx_channel)
+    }
+
+    /// Per-channel uplink statistics.
+    pub fn channel_stats(&self) -> &ChannelStats {
+        &self.channel_stats
+    }
+
+    pub(crate) fn channel_stats_mut(&mut self) -> &mut ChannelStats {
+        &mut self.channel_stats
+    }
+
+    /// Temporarily deprioritize channels which consistently fail when picking the next uplink
+    /// channel. Disabled (`None`) by default.
+    pub fn set_channel_avoidance(&mut self, avoidance: Option<ChannelAvoidance>) {
+        self.channel_stats.set_avoidance(avoidance);
+    }
+
+    /// Only use the channels on which downlinks have been received in RX1 (if any) when picking
+    /// the next uplink channel. Disabled by default, the statistics are kept when disabling it.
+    pub fn set_channel_pruning(&mut self, pruning: bool) {
+        self.channel_stats.set_pruning(pruning);
+    }
+
+    /// RF configuration of a join request on a channel of a fixed channel plan; `None` for
+    /// regions with a dynamic channel plan.
+    #[cfg(any(feature = "region-us915", feature = "region-au915"))]
+    pub(crate) fn join_channel_rf(&self, channel: u8) -> Option<RfConfig> {
+        let (dr, frequency) = match &self.state {
+            #[cfg(feature = "region-au915")]
+            State::AU915(state) => state.0.join_channel(channel),
+            #[cfg(feature = "region-us915")]
+            State::US915(state) => state.0.join_channel(channel),
+            #[allow(unreachable_patterns)]
+            _ => return None,
+        };
+        Some(RfConfig {
+            frequency,
+            bb: BaseBandModulationParams::new(
+                dr.spreading_factor,
+                dr.bandwidth,
+                self.get_coding_rate(),
+            ),
+            max_payload_len: dr.max_mac_payload_size,
+        })
+    }
+
+    /// See `US915::set_join_order`. Ignored by regions with a dynamic channel plan.
+    #[cfg(any(feature = "region-us915", feature = "region-au915"))]
+    pub(crate) fn set_join_order(&mut self, order: Option<[Subband; 8]>) {
+        match &mut self.state {
+            #[cfg(feature = "region-au915")]
+            State::AU915(state) => state.set_join_order(order),
+            #[cfg(feature = "region-us915")]
+            State::US915(state) => state.set_join_order(order),
+            #[allow(unreachable_patterns)]
+            _ => (),
+        }
     }
 
     pub(crate) fn process_join_accept<T: AsRef<[u8]>>(
@@ -413,10 +531,35 @@ impl Configuration {
         region_dispatch!(self, channel_mask_get)
     }
 
+    pub(crate) fn channels_get(&self) -> ChannelSettingsList {
+        region_dispatch!(self, channels_get)
+    }
+
+    pub(crate) fn channel_settings(&self, index: u8) -> Option<ChannelSettings> {
+        region_dispatch!(self, channel_settings, index)
+    }
+
+    pub(crate) fn channels_set(&mut self, channels: &ChannelSettingsList) {
+        mut_region_dispatch!(self, channels_set, channels)
+    }
+
     pub(crate) fn channel_mask_set(&mut self, channel_mask: ChannelMask<9>) {
         mut_region_dispatch!(self, channel_mask_set, channel_mask)
     }
 
+    pub(crate) fn restore_defaults(&mut self) {
+        mut_region_dispatch!(self, restore_defaults)
+    }
+
+    /// Re-enable all channels of the channel plan.
+    pub(crate) fn channel_mask_reset(&mut self) {
+        let mut channel_mask = self.channel_mask_get();
+        let all = ChannelMask::<2>::new_from_raw(&[0xFF, 0xFF]);
+        if self.channel_mask_update(&mut channel_mask, 6, all).is_some() {
+            self.channel_mask_set(channel_mask);
+        }
+    }
+
     pub(crate) fn channel_mask_update(
         &self,
         channel_mask: &mut ChannelMask<9>,
@@ -435,7 +578,52 @@ impl Configuration {
     }
 
     pub(crate) fn get_rx_datarate(&self, tx_dr: DR, rx1_dr_offset: u8, window: &Window) -> DR {
-        region_dispatch!(self, get_rx_datarate, tx_dr, rx1_dr_offset, window)
+        let dr = region_dispatch!(self, get_rx_datarate, tx_dr, rx1_dr_offset, window);
+        match window {
+            Window::_1 => self.clamp_rx1_datarate(dr, self.downlink_dwell_time),
+            Window::_2 => dr,
+        }
+    }
+
+    /// Raise an RX1 data rate to the regional MinDR.
+    fn clamp_rx1_datarate(&self, dr: DR, downlink_dwell_time: bool) -> DR {
+        let min_dr = region_dispatch!(self, min_rx1_datarate, downlink_dwell_time);
+        if (dr as u8) < (min_dr as u8) {
+            min_dr
+        } else {
+            dr
+        }
+    }
+
+    pub(crate) fn set_downlink_dwell_time(&mut self, enabled: bool) {
+        self.downlink_dwell_time = enabled;
+    }
+
+    pub(crate) fn supports_tx_param_setup(&self) -> bool {
+        region_dispatch!(self, supports_tx_param_setup)
+    }
+
+    pub(crate) fn get_downlink_dwell_time(&self) -> bool {
+        self.downlink_dwell_time
+    }
+
+    /// The RX1 data rate for an uplink sent at `tx_dr` with the given RX1DROffset, as defined by
+    /// the regional parameters. Returns `None` if either the data rate can't be used for uplinks
+    /// or the offset is not allowed in this region.
+    ///
+    /// `downlink_dwell_time` only affects AS923, where it raises the lowest RX1 data rate to DR2.
+    pub fn rx1_datarate(
+        &self,
+        tx_dr: DR,
+        rx1_dr_offset: u8,
+        downlink_dwell_time: bool,
+    ) -> Option<DR> {
+        if !self.is_valid_uplink_dr(tx_dr) {
+            return None;
+        }
+        let rx1_dr_offset = self.rx1_dr_offset_validate(rx1_dr_offset)?;
+        let dr = region_dispatch!(self, get_rx_datarate, tx_dr, rx1_dr_offset, &Window::_1);
+        Some(self.clamp_rx1_datarate(dr, downlink_dwell_time))
     }
 
     pub(crate) fn get_rx_frequency(&self, frame: &Frame, window: &Window) -> u32 {
@@ -454,7 +642,6 @@ impl Configuration {
         region_dispatch!(self, frequency_valid, f)
     }
 
-    #[allow(dead_code)]
     pub(crate) fn get_current_region(&self) -> super::region::Region {
         self.state.region()
     }
@@ -512,12 +699,44 @@ from_region!(US915);
 
 use lorawan::parser::DecryptedJoinAcceptPayload;
 
+/// A channel of a dynamic channel plan, as defined by the regional defaults, the CFList of the
+/// JoinAccept or the NewChannelReq and DlChannelReq MAC commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ChannelSettings {
+    pub frequency: u32,
+    /// RX1 frequency, if different from the uplink frequency.
+    pub dl_frequency: Option<u32>,
+    pub min_datarate: DR,
+    pub max_datarate: DR,
+}
+
+pub(crate) type ChannelSettingsList = [Option<ChannelSettings>; NUM_CHANNELS_DYNAMIC as usize];
+
 pub(crate) trait RegionHandler {
     fn process_join_accept<T: AsRef<[u8]>>(&mut self, join_accept: &DecryptedJoinAcceptPayload<T>);
 
+    /// Restore the default channels and channel mask of the region. Join settings (eg: the join
+    /// bias of fixed channel plans) are kept.
+    fn restore_defaults(&mut self);
+
     fn channel_mask_get(&self) -> ChannelMask<9>;
     fn channel_mask_set(&mut self, channel_mask: ChannelMask<9>);
 
+    /// Channels of dynamic channel plans; fixed channel plans are fully described by the channel
+    /// mask.
+    fn channels_get(&self) -> ChannelSettingsList {
+        [None; NUM_CHANNELS_DYNAMIC as usize]
+    }
+
+    fn channels_set(&mut self, _channels: &ChannelSettingsList) {}
+
+    /// Settings of a channel, whether it's enabled or not.
+    fn channel_settings(&self, index: u8) -> Option<ChannelSettings> {
+        self.channels_get().get(index as usize).copied().flatten()
+    }
+
     // TODO: Switch return type to Result
     fn channel_mask_update(
         &self,
@@ -548,8 +767,11 @@ pub(crate) trait RegionHandler {
         rng: &mut RNG,
         datarate: DR,
         frame: &Frame,
+        channel_stats: &ChannelStats,
     ) -> (Datarate, u32);
 
+    fn get_last_tx_channel(&self) -> u8;
+
     fn get_rx_datarate(&self, datarate: DR, rx1_dr_offset: u8, window: &Window) -> DR;
     fn get_rx_frequency(&self, frame: &Frame, window: &Window) -> u32;
     fn get_coding_rate(&self) -> CodingRate {
@@ -565,6 +787,16 @@ pub(crate) trait RegionHandler {
     fn has_fixed_channel_plan(&self) -> bool;
 
     fn rx1_dr_offset_validate(&self, value: u8) -> Option<u8>;
+
+    /// Lowest RX1 data rate (MinDR), depending on the downlink dwell time.
+    fn min_rx1_datarate(&self, _downlink_dwell_time: bool) -> DR {
+        DR::_0
+    }
+
+    /// Whether the region implements TxParamSetupReq; other regions ignore the command.
+    fn supports_tx_param_setup(&self) -> bool {
+        false
+    }
 }
 
 #[cfg(test)]
@@ -599,6 +831,28 @@ mod tests {
         assert_eq!(r.get_rx_datarate(DR::_7, 7, &Window::_1), DR::_7);
     }
 
+    #[test]
+    #[cfg(all(feature = "region-eu868", feature = "region-us915"))]
+    fn test_datarate_validation() {
+        let eu868 = Configuration::new(Region::EU868);
+        assert!(eu868.is_valid_uplink_dr(DR::_5));
+        assert!(eu868.is_valid_downlink_dr(DR::_5));
+        assert!(!eu868.is_valid_uplink_dr(DR::_12));
+        assert_eq!(eu868.max_payload(DR::_0, false), Some(59));
+        assert_eq!(eu868.max_payload(DR::_12, false), None);
+        assert!(eu868.is_valid_tx_power(7));
+        assert!(!eu868.is_valid_tx_power(8));
+
+        let us915 = Configuration::new(Region::US915);
+        assert!(us915.is_valid_uplink_dr(DR::_4));
+        assert!(!us915.is_valid_downlink_dr(DR::_4));
+        assert!(!us915.is_valid_uplink_dr(DR::_7));
+        assert!(!us915.is_valid_uplink_dr(DR::_8));
+        assert!(us915.is_valid_downlink_dr(DR::_8));
+        assert_eq!(us915.max_payload(DR::_0, false), Some(19));
+        assert_eq!(us915.default_datarate(), DR::_0);
+    }
+
     #[test]
     #[cfg(feature = "region-eu433")]
     fn test_rx1_dr_offset_eu433() {
@@ -622,6 +876,23 @@ mod tests {
         assert!(!r.frequency_valid(872_000_000));
     }
 
+    #[test]
+    #[cfg(feature = "region-eu868")]
+    fn test_restore_defaults_eu868() {
+        let mut r = Configuration::new(Region::EU868);
+        let data_rates = lorawan::types::DataRateRange::new_range(DR::_0, DR::_5);
+        assert_eq!(r.handle_new_channel(3, 867_100_000, Some(data_rates)), (true, true));
+        let mut channel_mask = r.channel_mask_get();
+        channel_mask.set_channel(0, false);
+        r.channel_mask_set(channel_mask);
+
+        r.restore_defaults();
+        assert_eq!(r.channel_mask_get(), ChannelMask::default());
+        let frequencies: std::vec::Vec<u32> =
+            r.channels_get().iter().flatten().map(|c| c.frequency).collect();
+        assert_eq!(frequencies, [868_100_000, 868_300_000, 868_500_000]);
+    }
+
     #[test]
     #[cfg(feature = "region-eu868")]
     fn test_rx1_dr_offset_eu868() {
@@ -715,4 +986,105 @@ mod tests {
         // Invalid DR should return DR::_8
         assert_eq!(r.get_rx_datarate(DR::_12, 0, &Window::_1), DR::_8);
     }
+
+    /// Check `rx1_datarate` against a table of RX1 data rates indexed by `[uplink DR][offset]`.
+    #[allow(dead_code)]
+    fn assert_rx1_table<const O: usize>(r: &Configuration, table: &[(DR, [DR; O])]) {
+        for (tx_dr, row) in table {
+            for (offset, rx1_dr) in row.iter().enumerate() {
+                assert_eq!(
+                    r.rx1_datarate(*tx_dr, offset as u8, false),
+                    Some(*rx1_dr),
+                    "{tx_dr:?} offset {offset}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "region-us915")]
+    fn test_rx1_datarate_us915_rp002() {
+        use DR::*;
+        let r = Configuration::new(Region::US915);
+        assert_rx1_table(
+            &r,
+            &[
+                (_0, [_10, _9, _8, _8]),
+                (_1, [_11, _10, _9, _8]),
+                (_2, [_12, _11, _10, _9]),
+                (_3, [_13, _12, _11, _10]),
+                (_4, [_13, _13, _12, _11]),
+            ],
+        );
+        assert_eq!(r.rx1_datarate(_0, 4, false), None);
+        assert_eq!(r.rx1_datarate(_8, 0, false), None);
+    }
+
+    #[test]
+    #[cfg(feature = "region-au915")]
+    fn test_rx1_datarate_au915_rp002() {
+        use DR::*;
+        let r = Configuration::new(Region::AU915);
+        assert_rx1_table(
+            &r,
+            &[
+                (_0, [_8, _8, _8, _8, _8, _8]),
+                (_1, [_9, _8, _8, _8, _8, _8]),
+                (_2, [_10, _9, _8, _8, _8, _8]),
+                (_3, [_11, _10, _9, _8, _8, _8]),
+                (_4, [_12, _11, _10, _9, _8, _8]),
+                (_5, [_13, _12, _11, _10, _9, _8]),
+                (_6, [_13, _13, _12, _11, _10, _9]),
+            ],
+        );
+        assert_eq!(r.rx1_datarate(_0, 6, false), None);
+    }
+
+    #[test]
+    #[cfg(feature = "region-in865")]
+    fn test_rx1_datarate_in865_rp002() {
+        use DR::*;
+        let r = Configuration::new(Region::IN865);
+        assert_rx1_table(
+            &r,
+            &[
+                (_0, [_0, _0, _0, _0, _0, _0, _1, _2]),
+                (_1, [_1, _0, _0, _0, _0, _0, _2, _3]),
+                (_2, [_2, _1, _0, _0, _0, _0, _3, _4]),
+                (_3, [_3, _2, _1, _0, _0, _0, _4, _5]),
+                (_4, [_4, _3, _2, _1, _0, _0, _5, _5]),
+                (_5, [_5, _4, _3, _2, _1, _0, _5, _7]),
+            ],
+        );
+        // DR7 (FSK) is not supported for uplinks, but the mapping skips the RFU DR6 all the same
+        assert_eq!(r.get_rx_datarate(_7, 1, &Window::_1), _5);
+    }
+
+    #[test]
+    #[cfg(feature = "region-as923-1")]
+    fn test_rx1_datarate_as923_min_dr() {
+        let r = Configuration::new(Region::AS923_1);
+        assert_eq!(r.rx1_datarate(DR::_1, 0, false), Some(DR::_1));
+        assert_eq!(r.rx1_datarate(DR::_5, 5, false), Some(DR::_0));
+        // MinDR is DR2 with downlink dwell time
+        assert_eq!(r.rx1_datarate(DR::_1, 0, true), Some(DR::_2));
+        assert_eq!(r.rx1_datarate(DR::_5, 5, true), Some(DR::_2));
+        assert_eq!(r.rx1_datarate(DR::_5, 1, true), Some(DR::_4));
+        assert_eq!(r.rx1_datarate(DR::_0, 8, false), None);
+    }
+
+    #[test]
+    #[cfg(feature = "region-as923-1")]
+    fn test_rx1_config_as923_min_dr() {
+        use crate::mac::{Frame, Mac};
+        let rx1 = |mac: &Mac| mac.get_rf_config(&Frame::Data, &Window::_1);
+        let mut mac = Mac::new(Configuration::new(Region::AS923_1), 14, 0);
+        mac.configuration.data_rate = DR::_2;
+        let dr2 = rx1(&mac);
+
+        mac.configuration.data_rate = DR::_1;
+        assert_ne!(rx1(&mac), dr2);
+        mac.set_downlink_dwell_time(true);
+        assert_eq!(rx1(&mac), dr2);
+    }
 }
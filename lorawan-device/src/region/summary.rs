@@ -0,0 +1,124 @@
+//! Structured summary of a regional configuration, eg: to print or report it for remote
+//! diagnostics.
+use super::{ChannelSettings, Configuration, Region, DR};
+use crate::async_device::airtime::{airtime_bands, AirtimeBand};
+use crate::mac::Window;
+use heapless::Vec;
+
+/// Maximum number of uplink channels of a region (US915 and AU915).
+pub const MAX_CHANNELS: usize = 72;
+
+/// An enabled uplink channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct ChannelSummary {
+    /// Index of the channel in the channel mask.
+    pub index: u8,
+    pub frequency: u32,
+    /// Frequency of the RX1 window after an uplink on the channel.
+    pub rx1_frequency: u32,
+    pub min_datarate: DR,
+    pub max_datarate: DR,
+}
+
+/// Summary of a regional configuration, see [`Configuration::describe`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionSummary {
+    pub region: Region,
+    /// Enabled uplink channels, in the order of the channel mask.
+    pub channels: Vec<ChannelSummary, MAX_CHANNELS>,
+    pub default_datarate: DR,
+    /// Lowest data rate which can be used for uplinks.
+    pub min_datarate: DR,
+    /// Highest data rate which can be used for uplinks.
+    pub max_datarate: DR,
+    pub rx2_frequency: u32,
+    pub rx2_datarate: DR,
+    /// Duty cycle limits of the regulatory bands of the region.
+    pub duty_cycle: &'static [AirtimeBand],
+}
+
+impl Configuration {
+    /// Summarize the enabled channels, data rate limits, RX2 settings and duty cycle limits. The
+    /// RX2 settings are the regional defaults; the devices also take the settings of the network
+    /// into account, see `describe_region` of the devices.
+    pub fn describe(&self) -> RegionSummary {
+        let channel_mask = self.channel_mask_get();
+        let channels = (0..MAX_CHANNELS as u8)
+            .filter(|index| channel_mask.is_enabled(*index as usize).unwrap_or(false))
+            .filter_map(|index| {
+                let ChannelSettings { frequency, dl_frequency, min_datarate, max_datarate } =
+                    self.channel_settings(index)?;
+                Some(ChannelSummary {
+                    index,
+                    frequency,
+                    rx1_frequency: dl_frequency.unwrap_or(frequency),
+                    min_datarate,
+                    max_datarate,
+                })
+            })
+            .collect();
+        let mut uplink_datarates = (0..super::constants::NUM_DATARATES)
+            .filter_map(|dr| DR::try_from(dr).ok())
+            .filter(|dr| self.is_valid_uplink_dr(*dr));
+        let min_datarate = uplink_datarates.next().unwrap_or(DR::_0);
+        let max_datarate = uplink_datarates.next_back().unwrap_or(min_datarate);
+        let default_datarate = self.get_default_datarate();
+        let region = self.get_current_region();
+        RegionSummary {
+            region,
+            channels,
+            default_datarate,
+            min_datarate,
+            max_datarate,
+            rx2_frequency: self.get_rx_frequency(&crate::mac::Frame::Data, &Window::_2),
+            rx2_datarate: self.get_rx_datarate(default_datarate, 0, &Window::_2),
+            duty_cycle: airtime_bands(region),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::region;
+
+    #[test]
+    fn describe_eu868() {
+        let summary = Configuration::new(Region::EU868).describe();
+        assert_eq!(summary.region, Region::EU868);
+        let frequencies: std::vec::Vec<u32> =
+            summary.channels.iter().map(|c| c.frequency).collect();
+        assert_eq!(frequencies, [868_100_000, 868_300_000, 868_500_000]);
+        assert_eq!(summary.channels[0].rx1_frequency, 868_100_000);
+        assert_eq!((summary.min_datarate, summary.max_datarate), (DR::_0, DR::_5));
+        assert_eq!((summary.rx2_frequency, summary.rx2_datarate), (869_525_000, DR::_0));
+        assert_eq!(summary.duty_cycle.len(), 6);
+    }
+
+    #[test]
+    fn describe_us915() {
+        let mut configuration: Configuration = region::US915::new().into();
+        let mut channel_mask = configuration.channel_mask_get();
+        let subband = lorawan::types::ChannelMask::<2>::new_from_raw(&[0, 0]);
+        configuration.channel_mask_update(&mut channel_mask, 7, subband).unwrap();
+        channel_mask.set_bank(1, 0xff);
+        channel_mask.set_channel(65, true);
+        configuration.channel_mask_set(channel_mask);
+
+        let summary = configuration.describe();
+        assert_eq!(summary.channels.len(), 9);
+        let first = summary.channels[0];
+        assert_eq!(
+            (first.index, first.frequency, first.rx1_frequency),
+            (8, 903_900_000, 923_300_000)
+        );
+        assert_eq!((first.min_datarate, first.max_datarate), (DR::_0, DR::_3));
+        let last = summary.channels[8];
+        assert_eq!((last.index, last.frequency), (65, 904_600_000));
+        assert_eq!((last.min_datarate, last.max_datarate), (DR::_4, DR::_4));
+        assert_eq!((summary.min_datarate, summary.max_datarate), (DR::_0, DR::_4));
+        assert_eq!((summary.rx2_frequency, summary.rx2_datarate), (923_300_000, DR::_8));
+        assert!(summary.duty_cycle[0].duty_cycle.is_none());
+    }
+}
@@ -26,7 +26,6 @@ use std::{
 #[derive(Debug, Clone)]
 pub struct Uplink {
     data: Vec<u8>,
-    #[allow(unused)]
     tx_config: TxConfig,
 }
 
@@ -39,6 +38,10 @@ impl Uplink {
         Ok(Self { data, tx_config })
     }
 
+    pub fn get_tx_config(&self) -> TxConfig {
+        self.tx_config
+    }
+
     pub fn get_payload(&mut self) -> PhyPayload<&mut [u8]> {
         match parse(self.data.as_mut_slice()) {
             Ok(p) => p,
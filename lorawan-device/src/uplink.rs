@@ -0,0 +1,149 @@
+//! Application uplinks with a validated FPort, as an alternative to the positional
+//! `(data, fport, confirmed)` arguments of `send`:
+//!
+//! ```
+//! use lorawan_device::{AppFPort, Uplink};
+//!
+//! let uplink = Uplink::builder(10).payload(&[1, 2, 3]).confirmed(true).build().unwrap();
+//! assert_eq!(uplink.fport(), AppFPort::new(10).unwrap());
+//! assert!(Uplink::builder(224).build().is_err());
+//! ```
+
+/// Largest application payload of any data rate in any region. The limit of the current data
+/// rate is usually lower, see `max_payload_len` of the devices.
+pub const MAX_PAYLOAD_LEN: usize = 242;
+
+/// An FPort for application data: 1 to 223. FPort 0 carries MAC commands and FPorts 224 and above
+/// are reserved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct AppFPort(u8);
+
+impl AppFPort {
+    /// `None` for FPorts which can't carry application data.
+    pub const fn new(fport: u8) -> Option<Self> {
+        match fport {
+            1..=223 => Some(Self(fport)),
+            _ => None,
+        }
+    }
+
+    pub const fn value(self) -> u8 {
+        self.0
+    }
+}
+
+impl TryFrom<u8> for AppFPort {
+    type Error = Error;
+
+    fn try_from(fport: u8) -> Result<Self, Error> {
+        Self::new(fport).ok_or(Error::ReservedFPort { fport })
+    }
+}
+
+impl From<AppFPort> for u8 {
+    fn from(fport: AppFPort) -> u8 {
+        fport.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum Error {
+    /// The FPort is not in the range of [`AppFPort`].
+    ReservedFPort { fport: u8 },
+    /// The payload is larger than [`MAX_PAYLOAD_LEN`].
+    PayloadTooLarge { len: usize },
+}
+
+/// An application uplink, sent with `send_uplink` of the devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Uplink<'a> {
+    fport: AppFPort,
+    payload: &'a [u8],
+    confirmed: bool,
+}
+
+impl<'a> Uplink<'a> {
+    /// An unconfirmed uplink.
+    pub fn new(fport: AppFPort, payload: &'a [u8]) -> Result<Self, Error> {
+        if payload.len() > MAX_PAYLOAD_LEN {
+            return Err(Error::PayloadTooLarge { len: payload.len() });
+        }
+        Ok(Self { fport, payload, confirmed: false })
+    }
+
+    /// Build an uplink on the FPort, which is validated by [`UplinkBuilder::build`].
+    pub fn builder(fport: u8) -> UplinkBuilder<'a> {
+        UplinkBuilder { fport, payload: &[], confirmed: false }
+    }
+
+    pub fn fport(&self) -> AppFPort {
+        self.fport
+    }
+
+    pub fn payload(&self) -> &'a [u8] {
+        self.payload
+    }
+
+    pub fn is_confirmed(&self) -> bool {
+        self.confirmed
+    }
+
+    /// The same uplink, requesting an acknowledgement from the network if `confirmed` is set.
+    pub fn with_confirmed(self, confirmed: bool) -> Self {
+        Self { confirmed, ..self }
+    }
+}
+
+/// Builder for an [`Uplink`], see [`Uplink::builder`]. The payload is empty and the uplink
+/// unconfirmed unless set otherwise.
+#[derive(Debug, Clone, Copy)]
+pub struct UplinkBuilder<'a> {
+    fport: u8,
+    payload: &'a [u8],
+    confirmed: bool,
+}
+
+impl<'a> UplinkBuilder<'a> {
+    pub fn payload(self, payload: &'a [u8]) -> Self {
+        Self { payload, ..self }
+    }
+
+    pub fn confirmed(self, confirmed: bool) -> Self {
+        Self { confirmed, ..self }
+    }
+
+    pub fn build(self) -> Result<Uplink<'a>, Error> {
+        Ok(Uplink::new(self.fport.try_into()?, self.payload)?.with_confirmed(self.confirmed))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fport_range() {
+        assert_eq!(AppFPort::new(0), None);
+        assert_eq!(AppFPort::new(1).map(AppFPort::value), Some(1));
+        assert_eq!(AppFPort::new(223).map(u8::from), Some(223));
+        assert_eq!(AppFPort::try_from(224), Err(Error::ReservedFPort { fport: 224 }));
+    }
+
+    #[test]
+    fn builder() {
+        let data = [0; MAX_PAYLOAD_LEN + 1];
+        let uplink = Uplink::builder(3).payload(&data[..MAX_PAYLOAD_LEN]).build().unwrap();
+        assert_eq!(uplink.fport().value(), 3);
+        assert_eq!(uplink.payload().len(), MAX_PAYLOAD_LEN);
+        assert!(!uplink.is_confirmed());
+        assert!(Uplink::builder(3).confirmed(true).build().unwrap().is_confirmed());
+        assert_eq!(
+            Uplink::builder(3).payload(&data).build(),
+            Err(Error::PayloadTooLarge { len: MAX_PAYLOAD_LEN + 1 })
+        );
+        assert_eq!(Uplink::builder(0).build(), Err(Error::ReservedFPort { fport: 0 }));
+    }
+}
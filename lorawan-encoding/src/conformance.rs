@@ -0,0 +1,218 @@
+//! Test vectors for MIC computation, key derivation, frame encoding and decoding and the RP002
+//! CFList encoding, as a conformance suite which can be run programmatically, eg: against another
+//! [`CryptoFactory`] implementation (hardware AES engine, secure element) or in a fork of this
+//! crate.
+//!
+//! ```
+//! use lorawan::{conformance, default_crypto::DefaultFactory};
+//!
+//! assert_eq!(conformance::run(&DefaultFactory), Ok(()));
+//! ```
+use crate::creator::{DataPayloadCreator, JoinAcceptCreator};
+use crate::keys::{AppKey, AppSKey, CryptoFactory, NwkSKey, AES128};
+use crate::parser::{
+    AppNonce, CfList, DataHeader, DevAddr, EncryptedDataPayload, EncryptedJoinAcceptPayload, FCtrl,
+    FRMPayload, JoinRequestPayload, NwkAddr,
+};
+use crate::types::Frequency;
+
+/// A vector which did not give the expected result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Failure {
+    /// Name of the check, one of [`CHECKS`].
+    pub check: &'static str,
+}
+
+/// Names of the checks, in the order run by [`run`].
+pub const CHECKS: [&str; 8] = [
+    "join_request_mic",
+    "join_accept_decrypt",
+    "join_accept_encode",
+    "session_keys",
+    "data_uplink_decrypt",
+    "data_uplink_encode",
+    "data_downlink_decrypt",
+    "cf_list",
+];
+
+const APP_KEY: [u8; 16] = [
+    0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff,
+];
+
+const JOIN_REQUEST: [u8; 23] = [
+    0x00, 0x04, 0x03, 0x02, 0x01, 0x04, 0x03, 0x02, 0x01, 0x05, 0x04, 0x03, 0x02, 0x05, 0x04, 0x03,
+    0x02, 0x2d, 0x10, 0x6a, 0x99, 0x0e, 0x12,
+];
+
+/// Encrypted with [`APP_KEY`]: AppNonce c7 0b 57, NetID 01 11 22, DevAddr 80 19 03 02.
+const JOIN_ACCEPT: [u8; 17] = [
+    0x20, 0x49, 0x3e, 0xeb, 0x51, 0xfb, 0xa2, 0x11, 0x6f, 0x81, 0x0e, 0xdb, 0x37, 0x42, 0x97, 0x51,
+    0x42,
+];
+
+/// Encrypted with a key of all ones, with the channels 867.1 to 867.9 MHz of EU868.
+const JOIN_ACCEPT_WITH_CF_LIST: [u8; 33] = [
+    0x20, 0xe4, 0x56, 0x73, 0xb6, 0x3c, 0xb4, 0xb9, 0xce, 0xcb, 0x2a, 0xa8, 0x3f, 0x03, 0x33, 0xe6,
+    0x15, 0xd2, 0xac, 0x89, 0xee, 0xa1, 0x65, 0x98, 0x37, 0xc3, 0xaa, 0x6d, 0xf9, 0x68, 0x98, 0x89,
+    0xcf,
+];
+
+/// "hello" on FPort 1, FCnt 1, ADR set, NwkSKey of all twos and AppSKey of all ones.
+const DATA_UPLINK: [u8; 18] = [
+    0x40, 0x04, 0x03, 0x02, 0x01, 0x80, 0x01, 0x00, 0x01, 0xa6, 0x94, 0x64, 0x26, 0x15, 0xd6, 0xc3,
+    0xb5, 0x82,
+];
+
+/// Confirmed "hello lora" on FPort 42, FCnt 76543 and AppSKey of all ones.
+const DATA_DOWNLINK: [u8; 23] = [
+    0xa0, 0x04, 0x03, 0x02, 0x01, 0x80, 0xff, 0x2a, 0x2a, 0x0a, 0xf1, 0xa3, 0x6a, 0x05, 0xd0, 0x12,
+    0x5f, 0x88, 0x5d, 0x88, 0x1d, 0x49, 0xe1,
+];
+
+/// Run all checks, stopping at the first failure.
+pub fn run<C: CryptoFactory>(crypto: &C) -> Result<(), Failure> {
+    join_request_mic(crypto)?;
+    join_accept_decrypt(crypto)?;
+    join_accept_encode(crypto)?;
+    session_keys(crypto)?;
+    data_uplink_decrypt(crypto)?;
+    data_uplink_encode(crypto)?;
+    data_downlink_decrypt(crypto)?;
+    cf_list(crypto)
+}
+
+fn check(ok: bool, check: &'static str) -> Result<(), Failure> {
+    if ok {
+        Ok(())
+    } else {
+        Err(Failure { check })
+    }
+}
+
+/// The MIC of a JoinRequest is computed with the AppKey.
+pub fn join_request_mic<C: CryptoFactory>(crypto: &C) -> Result<(), Failure> {
+    let ok = JoinRequestPayload::new(&JOIN_REQUEST[..]).is_ok_and(|join_request| {
+        join_request.validate_mic(&AES128([1; 16]), crypto)
+            && !join_request.validate_mic(&AES128([2; 16]), crypto)
+    });
+    check(ok, "join_request_mic")
+}
+
+/// A JoinAccept is decrypted with the AppKey (using AES encryption) and its MIC verified.
+pub fn join_accept_decrypt<C: CryptoFactory>(crypto: &C) -> Result<(), Failure> {
+    let ok = EncryptedJoinAcceptPayload::new(JOIN_ACCEPT).is_ok_and(|encrypted| {
+        let join_accept = encrypted.decrypt(&AppKey::from(APP_KEY), crypto);
+        join_accept.validate_mic(&AppKey::from(APP_KEY), crypto)
+            && join_accept.app_nonce() == AppNonce::new_from_raw(&[0xc7, 0x0b, 0x57][..])
+            && join_accept.net_id() == NwkAddr::new_from_raw(&[0x01, 0x11, 0x22][..])
+            && join_accept.dev_addr() == DevAddr::new_from_raw(&[0x80, 0x19, 0x03, 0x02][..])
+    });
+    check(ok, "join_accept_decrypt")
+}
+
+/// A JoinAccept is encrypted (using AES decryption) and signed with the AppKey.
+pub fn join_accept_encode<C: CryptoFactory>(crypto: &C) -> Result<(), Failure> {
+    let mut buf = [0; 17];
+    let ok = JoinAcceptCreator::new(&mut buf[..]).is_ok_and(|mut creator| {
+        creator
+            .set_app_nonce(&[0xc7, 0x0b, 0x57])
+            .set_net_id(&[0x01, 0x11, 0x22])
+            .set_dev_addr(&[0x80, 0x19, 0x03, 0x02])
+            .set_dl_settings(0)
+            .set_rx_delay(0);
+        creator.build(&AES128(APP_KEY), crypto) == Ok(&JOIN_ACCEPT[..])
+    });
+    check(ok, "join_accept_encode")
+}
+
+/// The session keys are derived from the AppKey, AppNonce, NetID and DevNonce.
+pub fn session_keys<C: CryptoFactory>(crypto: &C) -> Result<(), Failure> {
+    let key = AppKey::from(APP_KEY);
+    let ok = match (
+        JoinRequestPayload::new(&JOIN_REQUEST[..]),
+        EncryptedJoinAcceptPayload::new(JOIN_ACCEPT),
+    ) {
+        (Ok(join_request), Ok(join_accept)) => {
+            let join_accept = join_accept.decrypt(&key, crypto);
+            let dev_nonce = join_request.dev_nonce();
+            join_accept.derive_nwkskey(&dev_nonce, &key, crypto)
+                == NwkSKey::from([
+                    0x7b, 0xb2, 0x5f, 0x89, 0xe0, 0xd1, 0x37, 0x1e, 0x1f, 0xbf, 0x4d, 0x99, 0x7e,
+                    0x14, 0x68, 0xa3,
+                ])
+                && join_accept.derive_appskey(&dev_nonce, &key, crypto)
+                    == AppSKey::from([
+                        0x14, 0x88, 0x20, 0xdf, 0xb1, 0xe0, 0xc9, 0xd6, 0x28, 0x9c, 0xde, 0x16,
+                        0xc1, 0xaf, 0x24, 0x9f,
+                    ])
+        }
+        _ => false,
+    };
+    check(ok, "session_keys")
+}
+
+/// The MIC of an uplink is verified with the NwkSKey and its FRMPayload decrypted with the
+/// AppSKey.
+pub fn data_uplink_decrypt<C: CryptoFactory>(crypto: &C) -> Result<(), Failure> {
+    let ok = EncryptedDataPayload::new(DATA_UPLINK).is_ok_and(|encrypted| {
+        encrypted.decrypt_if_mic_ok(&AES128([2; 16]), &AES128([1; 16]), 1, crypto).is_ok_and(
+            |data| {
+                data.f_port() == Some(1)
+                    && data.fhdr().fctrl().adr()
+                    && data.frm_payload() == FRMPayload::Data(b"hello")
+            },
+        )
+    });
+    check(ok, "data_uplink_decrypt")
+}
+
+/// An uplink is encrypted with the AppSKey and signed with the NwkSKey.
+pub fn data_uplink_encode<C: CryptoFactory>(crypto: &C) -> Result<(), Failure> {
+    let mut buf = [0; 18];
+    let ok = DataPayloadCreator::new(&mut buf[..]).is_ok_and(|mut creator| {
+        creator
+            .set_confirmed(false)
+            .set_uplink(true)
+            .set_f_port(1)
+            .set_dev_addr(&[4, 3, 2, 1])
+            .set_fctrl(&FCtrl::new(0x80, true))
+            .set_fcnt(1);
+        creator.build(b"hello", [], &NwkSKey::from([2; 16]), &AppSKey::from([1; 16]), crypto)
+            == Ok(&DATA_UPLINK[..])
+    });
+    check(ok, "data_uplink_encode")
+}
+
+/// The FRMPayload of a downlink is decrypted with the AppSKey and the full 32-bit FCnt.
+pub fn data_downlink_decrypt<C: CryptoFactory>(crypto: &C) -> Result<(), Failure> {
+    let ok = EncryptedDataPayload::new(DATA_DOWNLINK).is_ok_and(|encrypted| {
+        encrypted.decrypt(None, Some(&AES128([1; 16])), 76543, crypto).is_ok_and(|data| {
+            data.is_confirmed()
+                && data.f_port() == Some(42)
+                && data.frm_payload() == FRMPayload::Data(b"hello lora")
+        })
+    });
+    check(ok, "data_downlink_decrypt")
+}
+
+/// The CFList of a dynamic channel plan (RP002) carries five frequencies in steps of 100 Hz.
+pub fn cf_list<C: CryptoFactory>(crypto: &C) -> Result<(), Failure> {
+    let ok = EncryptedJoinAcceptPayload::new(JOIN_ACCEPT_WITH_CF_LIST).is_ok_and(|encrypted| {
+        let join_accept = encrypted.decrypt(&AppKey::from([1; 16]), crypto);
+        match join_accept.c_f_list() {
+            Some(CfList::DynamicChannel(frequencies)) => {
+                join_accept.validate_mic(&AppKey::from([1; 16]), crypto)
+                    && frequencies.iter().map(Frequency::value).eq([
+                        867_100_000,
+                        867_300_000,
+                        867_500_000,
+                        867_700_000,
+                        867_900_000,
+                    ])
+            }
+            _ => false,
+        }
+    });
+    check(ok, "cf_list")
+}
@@ -192,6 +192,18 @@ impl<D: AsMut<[u8]>> JoinAcceptCreator<D> {
     ///
     /// * key - the key to be used for encryption and setting the MIC.
     pub fn build<F: CryptoFactory>(&mut self, key: &AES128, factory: &F) -> Result<&[u8], Error> {
+        let len = self.build_in_place(key, factory)?;
+        Ok(&self.data.as_mut()[..len])
+    }
+
+    /// Like [`JoinAcceptCreator::build`], but only returns the number of bytes written to the
+    /// start of the buffer, so that the buffer can be handed on directly (eg: to a DMA transfer)
+    /// without borrowing the creator.
+    pub fn build_in_place<F: CryptoFactory>(
+        &mut self,
+        key: &AES128,
+        factory: &F,
+    ) -> Result<usize, Error> {
         let required_len = if self.with_c_f_list {
             JOIN_ACCEPT_WITH_CFLIST_LEN
         } else {
@@ -214,7 +226,7 @@ impl<D: AsMut<[u8]>> JoinAcceptCreator<D> {
             }
             self.encrypted = true;
         }
-        Ok(&self.data.as_mut()[..required_len])
+        Ok(required_len)
     }
 }
 
@@ -307,9 +319,17 @@ impl<D: AsMut<[u8]>> JoinRequestCreator<D> {
     ///
     /// * key - the key to be used for setting the MIC.
     pub fn build<F: CryptoFactory>(&mut self, key: &AppKey, factory: &F) -> &[u8] {
+        let len = self.build_in_place(key, factory);
+        &self.data.as_mut()[..len]
+    }
+
+    /// Like [`JoinRequestCreator::build`], but only returns the number of bytes written to the
+    /// start of the buffer, so that the buffer can be handed on directly (eg: to a DMA transfer)
+    /// without borrowing the creator.
+    pub fn build_in_place<F: CryptoFactory>(&mut self, key: &AppKey, factory: &F) -> usize {
         let d = self.data.as_mut();
         set_mic(&mut d[..JOIN_REQUEST_LEN], &key.0, factory);
-        &d[..JOIN_REQUEST_LEN]
+        JOIN_REQUEST_LEN
     }
 }
 
@@ -485,6 +505,21 @@ impl<D: AsMut<[u8]>> DataPayloadCreator<D> {
         app_skey: &AppSKey,
         factory: &F,
     ) -> Result<&[u8], Error> {
+        let len = self.build_in_place(payload, mac_cmds, nwk_skey, app_skey, factory)?;
+        Ok(&self.data.as_mut()[..len])
+    }
+
+    /// Like [`DataPayloadCreator::build`], but only returns the number of bytes written to the
+    /// start of the buffer, so that the buffer can be handed on directly (eg: to a DMA transfer or
+    /// a radio driver) without borrowing the creator or copying the frame.
+    pub fn build_in_place<F: CryptoFactory, M: AsRef<[u8]>>(
+        &mut self,
+        payload: &[u8],
+        mac_cmds: M,
+        nwk_skey: &NwkSKey,
+        app_skey: &AppSKey,
+        factory: &F,
+    ) -> Result<usize, Error> {
         let d = self.data.as_mut();
         let mut last_filled = 8; // MHDR + FHDR without the FOpts
         let has_fport = self.data_f_port.is_some();
@@ -556,6 +591,6 @@ impl<D: AsMut<[u8]>> DataPayloadCreator<D> {
         );
         d[last_filled..last_filled + MIC_LEN].copy_from_slice(&mic.0);
 
-        Ok(&d[..last_filled + MIC_LEN])
+        Ok(last_filled + MIC_LEN)
     }
 }
@@ -0,0 +1,267 @@
+//! Entry points for fuzzing and property testing the parser, available with the `fuzzing`
+//! feature.
+//!
+//! [`parse`] never panics and accepts input of any length, reporting why a frame was rejected
+//! through [`Reason`]. [`exercise`] additionally walks every accessor of the parsed frame,
+//! decrypts it with a fixed key and parses the MAC commands and application layer packages it
+//! carries, which makes it a suitable body for a fuzz target:
+//!
+//! ```
+//! // fuzz_target!(|data: &[u8]| { let _ = lorawan::fuzzing::exercise(data); });
+//! let _ = lorawan::fuzzing::exercise(&[0x40, 0x04, 0x03, 0x02, 0x01, 0x80, 0x01, 0x00]);
+//! ```
+use crate::certification::parse_downlink_certification_messages;
+use crate::default_crypto::DefaultFactory;
+use crate::keys::{AppKey, AES128};
+use crate::maccommands::{parse_downlink_mac_commands, parse_uplink_mac_commands};
+use crate::multicast::{parse_downlink_multicast_messages, parse_uplink_multicast_messages};
+use crate::packet_length::phy::{join::*, PHY_PAYLOAD_MIN_LEN};
+use crate::parser::{
+    self, DataHeader, DataPayload, DevNonce, FRMPayload, JoinAcceptPayload, MType, Major,
+    PhyPayload, MHDR,
+};
+use core::fmt::Write;
+
+/// Maximum length of a PHYPayload, as limited by the LoRa physical layer.
+pub const MAX_LEN: usize = 255;
+
+/// The key used by [`exercise`] to check MICs, decrypt and derive session keys.
+pub const KEY: [u8; 16] = [0x2b; 16];
+
+/// Why the bytes were not accepted as a LoRaWAN frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum Reason {
+    /// Shorter than the smallest frame (MHDR, FHDR without FOpts and MIC).
+    TooShort { len: usize },
+    /// Longer than the LoRa physical layer allows.
+    TooLong { len: usize },
+    /// The major version in the MHDR is not LoRaWAN R1.
+    UnsupportedMajorVersion,
+    /// The message type in the MHDR is RFU or proprietary.
+    UnsupportedMessageType,
+    /// A JoinRequest which is not exactly 23 bytes long.
+    JoinRequestLength { len: usize },
+    /// A JoinAccept which is neither 17 bytes nor 33 bytes (with CFList) long.
+    JoinAcceptLength { len: usize },
+    /// The FOpts length in FCtrl exceeds the data frame.
+    FOptsOverflow { fopts_len: u8, len: usize },
+}
+
+impl From<Reason> for parser::Error {
+    fn from(reason: Reason) -> Self {
+        match reason {
+            Reason::TooShort { .. } | Reason::TooLong { .. } => parser::Error::InvalidPayload,
+            Reason::UnsupportedMajorVersion => parser::Error::UnsupportedMajorVersion,
+            Reason::UnsupportedMessageType => parser::Error::InvalidMessageType,
+            Reason::JoinRequestLength { .. }
+            | Reason::JoinAcceptLength { .. }
+            | Reason::FOptsOverflow { .. } => parser::Error::InvalidData,
+        }
+    }
+}
+
+/// Check whether [`parser::parse`] accepts the bytes, returning the message type if it does.
+pub fn check(data: &[u8]) -> Result<MType, Reason> {
+    let len = data.len();
+    if len < PHY_PAYLOAD_MIN_LEN {
+        return Err(Reason::TooShort { len });
+    }
+    if len > MAX_LEN {
+        return Err(Reason::TooLong { len });
+    }
+    let mhdr = MHDR::new(data[0]);
+    if mhdr.major() != Major::LoRaWANR1 {
+        return Err(Reason::UnsupportedMajorVersion);
+    }
+    let mtype = mhdr.mtype();
+    match mtype {
+        MType::JoinRequest if len != JOIN_REQUEST_LEN => Err(Reason::JoinRequestLength { len }),
+        MType::JoinAccept if len != JOIN_ACCEPT_LEN && len != JOIN_ACCEPT_WITH_CFLIST_LEN => {
+            Err(Reason::JoinAcceptLength { len })
+        }
+        MType::JoinRequest | MType::JoinAccept => Ok(mtype),
+        MType::UnconfirmedDataUp
+        | MType::ConfirmedDataUp
+        | MType::UnconfirmedDataDown
+        | MType::ConfirmedDataDown => {
+            let fopts_len = data[5] & 0x0f;
+            // MHDR, FHDR with FOpts and MIC
+            if len < PHY_PAYLOAD_MIN_LEN + fopts_len as usize {
+                Err(Reason::FOptsOverflow { fopts_len, len })
+            } else {
+                Ok(mtype)
+            }
+        }
+        MType::RFU | MType::Proprietary => Err(Reason::UnsupportedMessageType),
+    }
+}
+
+/// Parse the bytes like [`parser::parse`], with a detailed reason for rejected input.
+pub fn parse(data: &mut [u8]) -> Result<PhyPayload<&mut [u8]>, Reason> {
+    check(data)?;
+    // `check` rejects everything `parse` does, so this only fails if the two disagree.
+    parser::parse(data).map_err(|_| Reason::UnsupportedMessageType)
+}
+
+/// Parse a copy of the bytes and exercise everything which can be read from the frame: header
+/// fields, MIC validation and decryption with [`KEY`], JoinAccept fields and session key
+/// derivation, and the MAC commands, multicast and certification messages in FOpts and the
+/// FRMPayload.
+pub fn exercise(data: &[u8]) -> Result<(), Reason> {
+    check(data)?;
+    let mut buf = [0; MAX_LEN];
+    let buf = &mut buf[..data.len()];
+    buf.copy_from_slice(data);
+    let key = AES128(KEY);
+    let mut out = Sink;
+    match parse(buf)? {
+        PhyPayload::JoinRequest(join_request) => {
+            let _ = write!(
+                out,
+                "{:?} {:?} {:?}",
+                join_request.app_eui(),
+                join_request.dev_eui(),
+                join_request.dev_nonce()
+            );
+            join_request.validate_mic(&key, &DefaultFactory);
+        }
+        PhyPayload::JoinAccept(JoinAcceptPayload::Encrypted(join_accept)) => {
+            let app_key = AppKey(key);
+            let join_accept = join_accept.decrypt(&app_key, &DefaultFactory);
+            join_accept.validate_mic(&app_key, &DefaultFactory);
+            let _ = write!(
+                out,
+                "{:?} {:?} {:?} {:?} {} {:?}",
+                join_accept.app_nonce(),
+                join_accept.net_id(),
+                join_accept.dev_addr(),
+                join_accept.dl_settings(),
+                join_accept.rx_delay(),
+                join_accept.c_f_list()
+            );
+            let dev_nonce = DevNonce::from(0x1234);
+            join_accept.derive_nwkskey(&dev_nonce, &app_key, &DefaultFactory);
+            join_accept.derive_appskey(&dev_nonce, &app_key, &DefaultFactory);
+        }
+        PhyPayload::JoinAccept(JoinAcceptPayload::Decrypted(_)) => (),
+        PhyPayload::Data(DataPayload::Encrypted(data)) => {
+            let uplink = data.is_uplink();
+            let fhdr = data.fhdr();
+            let _ = write!(
+                out,
+                "{:?} {:?} {} {:?} {:?}",
+                fhdr.dev_addr(),
+                fhdr.fctrl(),
+                fhdr.fcnt(),
+                data.f_port(),
+                data.frm_payload_key()
+            );
+            mac_commands(&mut out, fhdr.data(), uplink);
+            let fcnt = fhdr.fcnt() as u32;
+            data.validate_mic(&key, fcnt, &DefaultFactory);
+            let Ok(data) = data.decrypt(Some(&key), Some(&key), fcnt, &DefaultFactory) else {
+                return Ok(());
+            };
+            match data.frm_payload() {
+                FRMPayload::MACCommands(commands) => {
+                    mac_commands(&mut out, commands.data(), uplink)
+                }
+                FRMPayload::Data(payload) if uplink => {
+                    for message in parse_uplink_multicast_messages(payload) {
+                        let _ = write!(out, "{:?}", message);
+                    }
+                }
+                FRMPayload::Data(payload) => {
+                    for message in parse_downlink_multicast_messages(payload) {
+                        let _ = write!(out, "{:?}", message);
+                    }
+                    for message in parse_downlink_certification_messages(payload) {
+                        let _ = write!(out, "{:?}", message);
+                    }
+                }
+                FRMPayload::None => (),
+            }
+        }
+        PhyPayload::Data(DataPayload::Decrypted(_)) => (),
+    }
+    Ok(())
+}
+
+fn mac_commands(out: &mut Sink, data: &[u8], uplink: bool) {
+    if uplink {
+        for command in parse_uplink_mac_commands(data) {
+            let _ = write!(out, "{:?}", command);
+        }
+    } else {
+        for command in parse_downlink_mac_commands(data) {
+            let _ = write!(out, "{:?}", command);
+        }
+    }
+}
+
+/// Formats the parsed values, which exercises their accessors, and discards the output.
+struct Sink;
+
+impl Write for Sink {
+    fn write_str(&mut self, _: &str) -> core::fmt::Result {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Deterministic pseudo-random frames, biased towards valid headers and lengths.
+    fn frames() -> impl Iterator<Item = ([u8; MAX_LEN], usize)> {
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        (0..20_000).map(move |i| {
+            let mut frame = [0; MAX_LEN];
+            for b in frame.iter_mut() {
+                *b = next() as u8;
+            }
+            // All message types with a valid major version
+            frame[0] = (i as u8) << 5;
+            let len = match i % 4 {
+                0 => JOIN_REQUEST_LEN,
+                1 => JOIN_ACCEPT_WITH_CFLIST_LEN,
+                2 => 12 + (next() % 24) as usize,
+                _ => (next() % (MAX_LEN as u64 + 1)) as usize,
+            };
+            (frame, len)
+        })
+    }
+
+    #[test]
+    fn arbitrary_frames_do_not_panic() {
+        for (frame, len) in frames() {
+            let data = &frame[..len];
+            let mut copy = frame;
+            let result = exercise(data);
+            assert_eq!(result.is_ok(), check(data).is_ok());
+            assert_eq!(parser::parse(&mut copy[..len]).is_ok(), result.is_ok(), "{:02x?}", data);
+        }
+    }
+
+    #[test]
+    fn reasons() {
+        assert_eq!(check(&[0x40; 11]), Err(Reason::TooShort { len: 11 }));
+        assert_eq!(check(&[0x40; 256]), Err(Reason::TooLong { len: 256 }));
+        assert_eq!(check(&[0x41; 12]), Err(Reason::UnsupportedMajorVersion));
+        assert_eq!(check(&[0xe0; 12]), Err(Reason::UnsupportedMessageType));
+        assert_eq!(check(&[0x00; 22]), Err(Reason::JoinRequestLength { len: 22 }));
+        assert_eq!(check(&[0x20; 18]), Err(Reason::JoinAcceptLength { len: 18 }));
+        let mut data = [0x40, 0x04, 0x03, 0x02, 0x01, 0x02, 0x01, 0x00, 0, 0, 0, 0, 0];
+        assert_eq!(check(&data), Err(Reason::FOptsOverflow { fopts_len: 2, len: 13 }));
+        data[5] = 0x01;
+        assert_eq!(check(&data), Ok(MType::UnconfirmedDataUp));
+        assert_eq!(parser::Error::from(Reason::TooShort { len: 0 }), parser::Error::InvalidPayload);
+    }
+}
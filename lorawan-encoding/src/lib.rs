@@ -5,6 +5,7 @@
 #![doc = include_str!("../README.md")]
 
 pub mod certification;
+pub mod conformance;
 pub mod creator;
 pub mod keys;
 pub mod maccommandcreator;
@@ -18,6 +19,9 @@ pub mod types;
 #[cfg(feature = "full")]
 pub mod extra;
 
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+
 pub mod default_crypto;
 
 mod securityhelpers;
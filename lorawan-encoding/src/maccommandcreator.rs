@@ -1,5 +1,5 @@
 use super::maccommands::{mac_commands_len, SerializableMacCommand};
-use crate::types::{ChannelMask, DLSettings, DataRateRange, Frequency, Redundancy};
+use crate::types::{ChannelMask, DLSettings, DataRateRange, DeviceClass, Frequency, Redundancy};
 
 #[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
@@ -10,6 +10,8 @@ pub enum Error {
     DelayOutOfRange,
     MaxEirpOutOfRange,
     NanoSecondsOutOfRange,
+    ExponentOutOfRange,
+    InvalidVersion,
     BufferTooShort,
 }
 
@@ -570,11 +572,221 @@ impl DlChannelAnsCreator {
     }
 }
 
+/// ResetIndCreator serves for creating ResetInd MacCommand.
+///
+/// # Examples
+///
+/// ```
+/// let mut creator = lorawan::maccommandcreator::ResetIndCreator::new();
+/// let res = creator.set_minor(1).unwrap().build();
+/// ```
+#[doc(inline)]
+pub use crate::maccommands::ResetIndCreator;
+
+impl ResetIndCreator {
+    /// Sets the minor version of the LoRaWAN specification implemented by the end-device.
+    pub fn set_minor(&mut self, minor: u8) -> Result<&mut Self, Error> {
+        if minor > 0x0F {
+            return Err(Error::InvalidVersion);
+        }
+        self.data[1] = minor;
+
+        Ok(self)
+    }
+}
+
+/// ResetConfCreator serves for creating ResetConf MacCommand.
+#[doc(inline)]
+pub use crate::maccommands::ResetConfCreator;
+
+impl ResetConfCreator {
+    /// Sets the minor version of the LoRaWAN specification implemented by the network server.
+    pub fn set_minor(&mut self, minor: u8) -> Result<&mut Self, Error> {
+        if minor > 0x0F {
+            return Err(Error::InvalidVersion);
+        }
+        self.data[1] = minor;
+
+        Ok(self)
+    }
+}
+
+/// ADRParamSetupReqCreator serves for creating ADRParamSetupReq MacCommand.
+///
+/// # Examples
+///
+/// ```
+/// let mut creator = lorawan::maccommandcreator::ADRParamSetupReqCreator::new();
+/// let res = creator.set_limit_exp(6).unwrap().set_delay_exp(5).unwrap().build();
+/// ```
+#[doc(inline)]
+pub use crate::maccommands::ADRParamSetupReqCreator;
+
+impl ADRParamSetupReqCreator {
+    /// Sets the exponent of ADR_ACK_LIMIT (`ADR_ACK_LIMIT = 2^limit_exp`).
+    pub fn set_limit_exp(&mut self, limit_exp: u8) -> Result<&mut Self, Error> {
+        if limit_exp > 0x0F {
+            return Err(Error::ExponentOutOfRange);
+        }
+        self.data[1] &= 0x0f;
+        self.data[1] |= limit_exp << 4;
+
+        Ok(self)
+    }
+
+    /// Sets the exponent of ADR_ACK_DELAY (`ADR_ACK_DELAY = 2^delay_exp`).
+    pub fn set_delay_exp(&mut self, delay_exp: u8) -> Result<&mut Self, Error> {
+        if delay_exp > 0x0F {
+            return Err(Error::ExponentOutOfRange);
+        }
+        self.data[1] &= 0xf0;
+        self.data[1] |= delay_exp;
+
+        Ok(self)
+    }
+}
+
+/// ADRParamSetupAnsCreator serves for creating ADRParamSetupAns MacCommand.
+///
+/// # Examples
+///
+/// ```
+/// let creator = lorawan::maccommandcreator::ADRParamSetupAnsCreator::new();
+/// let res = creator.build();
+/// ```
+#[doc(inline)]
+pub use crate::maccommands::ADRParamSetupAnsCreator;
+
 #[doc(inline)]
 pub use crate::maccommands::DeviceTimeAnsCreator;
 #[doc(inline)]
 pub use crate::maccommands::DeviceTimeReqCreator;
 
+/// DeviceModeIndCreator serves for creating DeviceModeInd MacCommand.
+///
+/// # Examples
+///
+/// ```
+/// let mut creator = lorawan::maccommandcreator::DeviceModeIndCreator::new();
+/// let res = creator.set_class(lorawan::types::DeviceClass::C).build();
+/// ```
+#[doc(inline)]
+pub use crate::maccommands::DeviceModeIndCreator;
+
+impl DeviceModeIndCreator {
+    pub fn set_class(&mut self, class: DeviceClass) -> &mut Self {
+        self.data[1] = class as u8;
+        self
+    }
+}
+
+/// DeviceModeConfCreator serves for creating DeviceModeConf MacCommand.
+#[doc(inline)]
+pub use crate::maccommands::DeviceModeConfCreator;
+
+impl DeviceModeConfCreator {
+    pub fn set_class(&mut self, class: DeviceClass) -> &mut Self {
+        self.data[1] = class as u8;
+        self
+    }
+}
+
+/// PingSlotChannelReqCreator serves for creating PingSlotChannelReq MacCommand.
+///
+/// # Examples
+///
+/// ```
+/// let mut creator = lorawan::maccommandcreator::PingSlotChannelReqCreator::new();
+/// let res = creator.set_frequency(&[0x12, 0x34, 0x56]).set_data_rate(0x03).unwrap().build();
+/// ```
+#[doc(inline)]
+pub use crate::maccommands::PingSlotChannelReqCreator;
+
+impl PingSlotChannelReqCreator {
+    /// Sets the ping slot frequency of the PingSlotChannelReq to the provided value.
+    pub fn set_frequency<'a, T: Into<Frequency<'a>>>(&mut self, frequency: T) -> &mut Self {
+        let converted = frequency.into();
+        self.data[1..4].copy_from_slice(converted.as_ref());
+
+        self
+    }
+
+    /// Sets the ping slot data rate of the PingSlotChannelReq to the provided value.
+    pub fn set_data_rate(&mut self, data_rate: u8) -> Result<&mut Self, Error> {
+        if data_rate > 0x0f {
+            return Err(Error::InvalidDataRate);
+        }
+        self.data[4] = data_rate;
+
+        Ok(self)
+    }
+}
+
+/// PingSlotChannelAnsCreator serves for creating PingSlotChannelAns MacCommand.
+///
+/// # Examples
+///
+/// ```
+/// let mut creator = lorawan::maccommandcreator::PingSlotChannelAnsCreator::new();
+/// let res = creator.set_channel_frequency_ack(true).set_data_rate_ack(true).build();
+/// ```
+#[doc(inline)]
+pub use crate::maccommands::PingSlotChannelAnsCreator;
+
+impl PingSlotChannelAnsCreator {
+    /// Sets the channel frequency acknowledgement of the PingSlotChannelAns to the provided
+    /// value.
+    pub fn set_channel_frequency_ack(&mut self, ack: bool) -> &mut Self {
+        self.data[1] &= 0xfe;
+        self.data[1] |= ack as u8;
+
+        self
+    }
+
+    /// Sets the data rate acknowledgement of the PingSlotChannelAns to the provided value.
+    pub fn set_data_rate_ack(&mut self, ack: bool) -> &mut Self {
+        self.data[1] &= 0xfd;
+        self.data[1] |= (ack as u8) << 1;
+
+        self
+    }
+}
+
+/// BeaconFreqReqCreator serves for creating BeaconFreqReq MacCommand.
+///
+/// # Examples
+///
+/// ```
+/// let mut creator = lorawan::maccommandcreator::BeaconFreqReqCreator::new();
+/// let res = creator.set_frequency(&[0x12, 0x34, 0x56]).build();
+/// ```
+#[doc(inline)]
+pub use crate::maccommands::BeaconFreqReqCreator;
+
+impl BeaconFreqReqCreator {
+    /// Sets the beacon frequency of the BeaconFreqReq to the provided value.
+    pub fn set_frequency<'a, T: Into<Frequency<'a>>>(&mut self, frequency: T) -> &mut Self {
+        let converted = frequency.into();
+        self.data[1..4].copy_from_slice(converted.as_ref());
+
+        self
+    }
+}
+
+/// BeaconFreqAnsCreator serves for creating BeaconFreqAns MacCommand.
+#[doc(inline)]
+pub use crate::maccommands::BeaconFreqAnsCreator;
+
+impl BeaconFreqAnsCreator {
+    /// Sets the beacon frequency acknowledgement of the BeaconFreqAns to the provided value.
+    pub fn set_beacon_frequency_ack(&mut self, ack: bool) -> &mut Self {
+        self.data[1] &= 0xfe;
+        self.data[1] |= ack as u8;
+
+        self
+    }
+}
+
 impl DeviceTimeAnsCreator {
     pub fn set_seconds(&mut self, seconds: u32) -> &mut Self {
         self.data[1..5].copy_from_slice(&seconds.to_le_bytes());
@@ -609,6 +821,25 @@ pub fn build_mac_commands<T: AsMut<[u8]>>(
     Ok(i)
 }
 
+/// Serialize the leading commands which fit into `out` without splitting a command, eg: answers
+/// into the 15 bytes of FOpts. The commands are packed back to back, in order: packing stops at
+/// the first command which doesn't fit, as answers must not be reordered. Returns the number of
+/// bytes written and the number of commands packed; the remaining commands are left for another
+/// frame.
+pub fn pack_mac_commands(cmds: &[&dyn SerializableMacCommand], out: &mut [u8]) -> (usize, usize) {
+    let mut len = 0;
+    for (count, mc) in cmds.iter().enumerate() {
+        let end = len + 1 + mc.payload_len();
+        if end > out.len() {
+            return (len, count);
+        }
+        out[len] = mc.cid();
+        out[len + 1..end].copy_from_slice(mc.payload_bytes());
+        len = end;
+    }
+    (len, cmds.len())
+}
+
 #[doc(inline)]
 pub use crate::maccommands::DownlinkMacCommandCreator;
 
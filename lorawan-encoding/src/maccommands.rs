@@ -2,7 +2,7 @@
 //!
 //! A MAC command consists of a command identifier (CID) of 1 octet followed
 //! by a possibly empty command-specific sequence of octets (payload).
-use crate::types::DR;
+use crate::types::{DeviceClass, DR};
 use core::marker::PhantomData;
 use lorawan_macros::CommandHandler;
 
@@ -89,10 +89,32 @@ pub enum DownlinkMacCommand<'a> {
     #[cmd(cid = 0x0A, len = 4)]
     DlChannelReq(DlChannelReqPayload<'a>),
 
+    // LoRaWAN 1.1+ commands
+    /// ResetConf payload handling (LoRaWAN 1.1+)
+    #[cmd(cid = 0x01, len = 1)]
+    ResetConf(ResetConfPayload<'a>),
+
+    /// ADRParamSetupReq payload handling (LoRaWAN 1.1+)
+    #[cmd(cid = 0x0C, len = 1)]
+    ADRParamSetupReq(ADRParamSetupReqPayload<'a>),
+
     // LoRaWAN 1.0.3+ commands
     /// DeviceTimeAns payload handling (LoRaWAN 1.0.3+)
     #[cmd(cid = 0x0D, len = 5)]
     DeviceTimeAns(DeviceTimeAnsPayload<'a>),
+
+    /// DeviceModeConf payload handling (LoRaWAN 1.1+)
+    #[cmd(cid = 0x20, len = 1)]
+    DeviceModeConf(DeviceModeConfPayload<'a>),
+
+    // Class B commands
+    /// PingSlotChannelReq payload handling (LoRaWAN 1.0.3+)
+    #[cmd(cid = 0x11, len = 4)]
+    PingSlotChannelReq(PingSlotChannelReqPayload<'a>),
+
+    /// BeaconFreqReq payload handling (LoRaWAN 1.0.3+)
+    #[cmd(cid = 0x13, len = 3)]
+    BeaconFreqReq(BeaconFreqReqPayload<'a>),
 }
 
 #[derive(Debug, PartialEq, CommandHandler)]
@@ -137,10 +159,32 @@ pub enum UplinkMacCommand<'a> {
     #[cmd(cid = 0x0A, len = 1)]
     DlChannelAns(DlChannelAnsPayload<'a>),
 
+    // LoRaWAN 1.1+ commands
+    /// ResetInd payload handling (LoRaWAN 1.1+)
+    #[cmd(cid = 0x01, len = 1)]
+    ResetInd(ResetIndPayload<'a>),
+
+    /// ADRParamSetupAns payload handling (LoRaWAN 1.1+)
+    #[cmd(cid = 0x0C, len = 0)]
+    ADRParamSetupAns(ADRParamSetupAnsPayload),
+
     // 1.0.3+
     /// DeviceTimeReq payload handling (LoRaWAN 1.0.3+)
     #[cmd(cid = 0x0D, len = 0)]
     DeviceTimeReq(DeviceTimeReqPayload),
+
+    /// DeviceModeInd payload handling (LoRaWAN 1.1+)
+    #[cmd(cid = 0x20, len = 1)]
+    DeviceModeInd(DeviceModeIndPayload<'a>),
+
+    // Class B commands
+    /// PingSlotChannelAns payload handling (LoRaWAN 1.0.3+)
+    #[cmd(cid = 0x11, len = 1)]
+    PingSlotChannelAns(PingSlotChannelAnsPayload<'a>),
+
+    /// BeaconFreqAns payload handling (LoRaWAN 1.0.3+)
+    #[cmd(cid = 0x13, len = 1)]
+    BeaconFreqAns(BeaconFreqAnsPayload<'a>),
 }
 
 macro_rules! create_ack_fn {
@@ -218,6 +262,92 @@ impl<'a, T> MacCommandIterator<'a, T> {
     }
 }
 
+/// Like [`parse_uplink_mac_commands`], reporting a malformed tail as an error item.
+///
+/// # Examples
+///
+/// ```
+/// use lorawan::maccommands::{parse_uplink_mac_commands_validated, MalformedMacCommand};
+/// // LinkADRAns followed by a truncated DevStatusAns
+/// let data = [0x03, 0x07, 0x06, 0xfe];
+/// let mut mac_cmds = parse_uplink_mac_commands_validated(&data);
+/// assert!(mac_cmds.next().unwrap().is_ok());
+/// assert_eq!(
+///     mac_cmds.next(),
+///     Some(Err(MalformedMacCommand::Truncated { offset: 2, cid: 0x06 }))
+/// );
+/// assert_eq!(mac_cmds.next(), None);
+/// ```
+pub fn parse_uplink_mac_commands_validated(
+    data: &[u8],
+) -> ValidatedMacCommandIterator<'_, UplinkMacCommand<'_>> {
+    ValidatedMacCommandIterator::new(data, UplinkMacCommand::is_known_cid)
+}
+
+/// Like [`parse_downlink_mac_commands`], reporting a malformed tail as an error item.
+pub fn parse_downlink_mac_commands_validated(
+    data: &[u8],
+) -> ValidatedMacCommandIterator<'_, DownlinkMacCommand<'_>> {
+    ValidatedMacCommandIterator::new(data, DownlinkMacCommand::is_known_cid)
+}
+
+/// Malformed tail of a sequence of MAC commands, see [`ValidatedMacCommandIterator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum MalformedMacCommand {
+    /// The byte at `offset` is not the CID of a known command.
+    UnknownCid { offset: usize, cid: u8 },
+    /// The command at `offset` is longer than the remaining data.
+    Truncated { offset: usize, cid: u8 },
+}
+
+/// Iterator over MAC commands which checks the length of every command. Where
+/// [`MacCommandIterator`] silently stops at a malformed tail, this iterator yields an error for
+/// it (and then stops), so that the tail can be reported rather than mistaken for the end of the
+/// commands.
+pub struct ValidatedMacCommandIterator<'a, T> {
+    data: &'a [u8],
+    index: usize,
+    is_known_cid: fn(u8) -> bool,
+    item: PhantomData<T>,
+}
+
+impl<'a, T> ValidatedMacCommandIterator<'a, T> {
+    /// Creation, with the function telling whether a CID is known, eg:
+    /// `DownlinkMacCommand::is_known_cid`.
+    pub fn new(data: &'a [u8], is_known_cid: fn(u8) -> bool) -> Self {
+        Self { data, index: 0, is_known_cid, item: PhantomData }
+    }
+}
+
+impl<'a, T> Iterator for ValidatedMacCommandIterator<'a, T>
+where
+    T: SerializableMacCommand,
+    MacCommandIterator<'a, T>: Iterator<Item = T>,
+{
+    type Item = Result<T, MalformedMacCommand>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let data = &self.data[self.index..];
+        let (&cid, payload) = data.split_first()?;
+        let offset = self.index;
+        match MacCommandIterator::<T>::new(data).next() {
+            Some(cmd) if cmd.payload_len() <= payload.len() => {
+                self.index += 1 + cmd.payload_len();
+                Some(Ok(cmd))
+            }
+            _ => {
+                self.index = self.data.len();
+                Some(Err(if (self.is_known_cid)(cid) {
+                    MalformedMacCommand::Truncated { offset, cid }
+                } else {
+                    MalformedMacCommand::UnknownCid { offset, cid }
+                }))
+            }
+        }
+    }
+}
+
 impl LinkCheckAnsPayload<'_> {
     create_value_reader_fn!(
         /// The link margin in dB of the last successfully received LinkCheckReq command.
@@ -466,6 +596,97 @@ impl DlChannelAnsPayload<'_> {
     }
 }
 
+impl ResetIndPayload<'_> {
+    /// Minor version of the LoRaWAN specification implemented by the end-device.
+    pub fn minor(&self) -> u8 {
+        self.0[0] & 0x0f
+    }
+}
+
+impl ResetConfPayload<'_> {
+    /// Minor version of the LoRaWAN specification implemented by the network server.
+    pub fn minor(&self) -> u8 {
+        self.0[0] & 0x0f
+    }
+}
+
+impl ADRParamSetupReqPayload<'_> {
+    /// Exponent of ADR_ACK_LIMIT, ie: `ADR_ACK_LIMIT = 2^limit_exp`.
+    pub fn limit_exp(&self) -> u8 {
+        self.0[0] >> 4
+    }
+
+    /// Exponent of ADR_ACK_DELAY, ie: `ADR_ACK_DELAY = 2^delay_exp`.
+    pub fn delay_exp(&self) -> u8 {
+        self.0[0] & 0x0f
+    }
+
+    /// Number of uplinks without downlink after which the device sets the ADRACKReq bit.
+    pub fn adr_ack_limit(&self) -> u16 {
+        1 << self.limit_exp()
+    }
+
+    /// Number of uplinks with the ADRACKReq bit set after which the device starts to back off.
+    pub fn adr_ack_delay(&self) -> u16 {
+        1 << self.delay_exp()
+    }
+}
+
+impl DeviceModeIndPayload<'_> {
+    /// The class the end-device switches to.
+    pub fn class(&self) -> Result<DeviceClass, Error> {
+        DeviceClass::try_from(self.0[0])
+    }
+}
+
+impl DeviceModeConfPayload<'_> {
+    /// The class confirmed by the network server.
+    pub fn class(&self) -> Result<DeviceClass, Error> {
+        DeviceClass::try_from(self.0[0])
+    }
+}
+
+impl PingSlotChannelReqPayload<'_> {
+    /// The frequency of the ping slots, `0` restores the regional default.
+    pub fn frequency(&self) -> Frequency<'_> {
+        Frequency::new_from_raw(&self.0[0..3])
+    }
+
+    /// The data rate of the ping slots.
+    pub fn data_rate(&self) -> DR {
+        DR::try_from(self.0[3] & 0x0f).unwrap()
+    }
+}
+
+impl PingSlotChannelAnsPayload<'_> {
+    create_ack_fn!(
+        /// Whether the ping slot frequency is usable by the device.
+        channel_freq_ack,
+        0
+    );
+
+    create_ack_fn!(
+        /// Whether the ping slot data rate is usable by the device.
+        data_rate_ack,
+        1
+    );
+}
+
+impl BeaconFreqReqPayload<'_> {
+    /// The frequency of the beacons, `0` restores the regional default.
+    pub fn frequency(&self) -> Frequency<'_> {
+        Frequency::new_from_raw(&self.0[0..3])
+    }
+}
+
+impl BeaconFreqAnsPayload<'_> {
+    create_ack_fn!(
+        /// Whether the beacon frequency is usable by the device.
+        beacon_freq_ack,
+        0
+    );
+}
+
 impl DeviceTimeAnsPayload<'_> {
     pub fn seconds(&self) -> u32 {
         u32::from_le_bytes([self.0[3], self.0[2], self.0[1], self.0[0]])
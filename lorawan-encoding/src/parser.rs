@@ -22,6 +22,10 @@
 //! ```
 
 use super::keys::{AppKey, AppSKey, CryptoFactory, Encrypter, NwkSKey, AES128, MIC};
+use crate::maccommands::{
+    parse_downlink_mac_commands_validated, parse_uplink_mac_commands_validated, DownlinkMacCommand,
+    UplinkMacCommand, ValidatedMacCommandIterator,
+};
 use crate::types::{ChannelMask, DLSettings, Frequency};
 
 use super::securityhelpers;
@@ -679,6 +683,16 @@ impl<T: DataHeader> AsPhyPayloadBytes for T {
 #[derive(Debug, PartialEq, Eq)]
 pub struct EncryptedDataPayload<T>(T);
 
+/// Session key which encrypts the FRMPayload of a data frame, determined by its FPort.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum FRMPayloadKey {
+    /// The FRMPayload carries MAC commands (FPort 0), or the frame has no FRMPayload at all.
+    NwkSKey,
+    /// The FRMPayload carries application data (FPort 1..=255).
+    AppSKey,
+}
+
 impl<T: AsRef<[u8]>> DataHeader for EncryptedDataPayload<T> {
     fn as_data_bytes(&self) -> &[u8] {
         self.0.as_ref()
@@ -714,6 +728,14 @@ impl<T: AsRef<[u8]>> EncryptedDataPayload<T> {
         )
     }
 
+    /// The session key which encrypts the FRMPayload of this frame.
+    pub fn frm_payload_key(&self) -> FRMPayloadKey {
+        match self.f_port() {
+            Some(fport) if fport != 0 => FRMPayloadKey::AppSKey,
+            _ => FRMPayloadKey::NwkSKey,
+        }
+    }
+
     /// Verifies that the DataPayload has correct MIC.
     pub fn validate_mic<C: CryptoFactory>(&self, key: &AES128, fcnt: u32, crypto: &C) -> bool {
         self.mic() == self.calculate_mic(key, fcnt, crypto)
@@ -765,10 +787,9 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> EncryptedDataPayload<T> {
         let fhdr_length = self.fhdr_length();
         let fhdr = self.fhdr();
         let full_fcnt = compute_fcnt(fcnt, fhdr.fcnt());
-        let key = if self.f_port().is_some() && self.f_port().unwrap() != 0 {
-            app_skey
-        } else {
-            nwk_skey
+        let key = match self.frm_payload_key() {
+            FRMPayloadKey::AppSKey => app_skey,
+            FRMPayloadKey::NwkSKey => nwk_skey,
         };
         if key.is_none() {
             return Err(Error::InvalidKey);
@@ -810,6 +831,41 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> EncryptedDataPayload<T> {
             Ok(self.decrypt(Some(nwk_skey), Some(app_skey), fcnt, crypto).unwrap())
         }
     }
+
+    /// Verifies the mic and decrypts the payload of a captured frame with the session keys of a
+    /// device, eg: in bridges or test harnesses. The FRMPayload is decrypted with the NwkSKey or
+    /// the AppSKey depending on the FPort (see [`EncryptedDataPayload::frm_payload_key`]).
+    ///
+    /// `fcnt` is the full 32-bit frame counter, or the last known one from which the upper 16 bits
+    /// are taken. If the mic does not match, the original EncryptedDataPayload is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// let data = vec![
+    ///     0x40, 0x04, 0x03, 0x02, 0x01, 0x80, 0x01, 0x00, 0x01, 0xa6, 0x94, 0x64, 0x26, 0x15, 0xd6,
+    ///     0xc3, 0xb5, 0x82,
+    /// ];
+    /// let nwk_skey = lorawan::keys::NwkSKey::from([2; 16]);
+    /// let app_skey = lorawan::keys::AppSKey::from([1; 16]);
+    /// let enc_phy = lorawan::parser::EncryptedDataPayload::new(data).unwrap();
+    /// assert_eq!(enc_phy.frm_payload_key(), lorawan::parser::FRMPayloadKey::AppSKey);
+    /// let dec_phy = enc_phy.decrypt_with_session_keys(
+    ///     &nwk_skey,
+    ///     &app_skey,
+    ///     1,
+    ///     &lorawan::default_crypto::DefaultFactory,
+    /// );
+    /// ```
+    pub fn decrypt_with_session_keys<C: CryptoFactory>(
+        self,
+        nwk_skey: &NwkSKey,
+        app_skey: &AppSKey,
+        fcnt: u32,
+        crypto: &C,
+    ) -> Result<DecryptedDataPayload<T>, Self> {
+        self.decrypt_if_mic_ok(nwk_skey.inner(), app_skey.inner(), fcnt, crypto)
+    }
 }
 
 fn compute_fcnt(old_fcnt: u32, fcnt: u16) -> u32 {
@@ -1100,6 +1156,16 @@ impl<'a> FHDR<'a> {
     pub fn data(&self) -> &[u8] {
         &self.0[7_usize..(7 + self.fopts_len()) as usize]
     }
+
+    /// MAC commands of the FOpts of an uplink, with validation of their lengths.
+    pub fn uplink_mac_commands(&self) -> ValidatedMacCommandIterator<'a, UplinkMacCommand<'a>> {
+        parse_uplink_mac_commands_validated(&self.0[7_usize..(7 + self.fopts_len()) as usize])
+    }
+
+    /// MAC commands of the FOpts of a downlink, with validation of their lengths.
+    pub fn downlink_mac_commands(&self) -> ValidatedMacCommandIterator<'a, DownlinkMacCommand<'a>> {
+        parse_downlink_mac_commands_validated(&self.0[7_usize..(7 + self.fopts_len()) as usize])
+    }
 }
 
 /// FCtrl represents the FCtrl from FHDR.
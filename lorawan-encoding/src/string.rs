@@ -158,12 +158,171 @@ fixed_len_struct_impl_string_lsb! {
     AppEui, 8;
 }
 
+/// Byte order of a hex string, eg: as printed on device labels (MSB) or as used in some
+/// provisioning tools (LSB).
+#[cfg(feature = "hex-format")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// Most significant byte first.
+    Msb,
+    /// Least significant byte first.
+    Lsb,
+}
+
+/// Characters which may separate the bytes of a hex string.
+#[cfg(feature = "hex-format")]
+const SEPARATORS: [char; 4] = [':', '-', ' ', '_'];
+
+/// Decode a hex string into `N` bytes in the given byte order. Separators (`:`, `-`, ` ` and `_`)
+/// between bytes and a leading `0x` are ignored.
+#[cfg(feature = "hex-format")]
+pub fn decode_hex<const N: usize>(s: &str, order: ByteOrder) -> Result<[u8; N], FromHexError> {
+    let s = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+    let mut res = [0; N];
+    let mut digits = s.chars().filter(|c| !SEPARATORS.contains(c));
+    for (index, byte) in res.iter_mut().enumerate() {
+        let mut pair = [0; 2];
+        for (i, b) in pair.iter_mut().enumerate() {
+            let c = digits.next().ok_or(match i {
+                0 => FromHexError::InvalidStringLength,
+                _ => FromHexError::OddLength,
+            })?;
+            *b = c.to_digit(16).ok_or(FromHexError::InvalidHexCharacter { c, index })? as u8;
+        }
+        *byte = (pair[0] << 4) | pair[1];
+    }
+    if digits.next().is_some() {
+        return Err(FromHexError::InvalidStringLength);
+    }
+    if order == ByteOrder::Lsb {
+        res.reverse();
+    }
+    Ok(res)
+}
+
+/// Formats bytes as a hex string in the given byte order, optionally separating bytes. Created by
+/// the `to_hex` methods of keys and EUIs. Works without `std`.
+#[cfg(feature = "hex-format")]
+#[derive(Debug, Clone, Copy)]
+pub struct HexDisplay<'a> {
+    bytes: &'a [u8],
+    order: ByteOrder,
+    separator: Option<char>,
+    uppercase: bool,
+}
+
+#[cfg(feature = "hex-format")]
+impl HexDisplay<'_> {
+    /// Use uppercase hex digits.
+    pub fn uppercase(mut self) -> Self {
+        self.uppercase = true;
+        self
+    }
+}
+
+#[cfg(feature = "hex-format")]
+impl core::fmt::Display for HexDisplay<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        use core::fmt::Write;
+        let mut write_byte = |i: usize, b: &u8| -> core::fmt::Result {
+            if let (Some(separator), true) = (self.separator, i > 0) {
+                f.write_char(separator)?;
+            }
+            match self.uppercase {
+                true => write!(f, "{:02X}", b),
+                false => write!(f, "{:02x}", b),
+            }
+        };
+        match self.order {
+            ByteOrder::Msb => self.bytes.iter().enumerate().try_for_each(|(i, b)| write_byte(i, b)),
+            ByteOrder::Lsb => {
+                self.bytes.iter().rev().enumerate().try_for_each(|(i, b)| write_byte(i, b))
+            }
+        }
+    }
+}
+
+/// Adds hex parsing/formatting with a selectable byte order as well as checked construction from
+/// slices. `$storage` is the byte order in which the type stores its bytes.
+macro_rules! fixed_len_struct_impl_hex_format {
+    (
+        $type:ident, $size:expr, $storage:ident;
+    ) => {
+        #[cfg(feature = "hex-format")]
+        impl $type {
+            /// Parse a hex string in the given byte order. Separators (`:`, `-`, ` ` and `_`)
+            /// between bytes and a leading `0x` are ignored.
+            pub fn from_hex(s: &str, order: ByteOrder) -> Result<Self, FromHexError> {
+                let mut res = decode_hex::<$size>(s, order)?;
+                if ByteOrder::$storage == ByteOrder::Lsb {
+                    res.reverse();
+                }
+                Ok(Self::from(res))
+            }
+
+            /// Format as a hex string in the given byte order, optionally separating the bytes.
+            pub fn to_hex(&self, order: ByteOrder, separator: Option<char>) -> HexDisplay<'_> {
+                let order = match (ByteOrder::$storage, order) {
+                    (ByteOrder::Msb, order) => order,
+                    (ByteOrder::Lsb, ByteOrder::Msb) => ByteOrder::Lsb,
+                    (ByteOrder::Lsb, ByteOrder::Lsb) => ByteOrder::Msb,
+                };
+                HexDisplay { bytes: self.as_ref(), order, separator, uppercase: false }
+            }
+        }
+
+        #[cfg(feature = "hex-format")]
+        impl TryFrom<&[u8]> for $type {
+            type Error = core::array::TryFromSliceError;
+
+            /// Checked construction from a slice in the same byte order as `From<[u8; N]>`.
+            fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+                Ok(Self::from(<[u8; $size]>::try_from(bytes)?))
+            }
+        }
+    };
+}
+
+fixed_len_struct_impl_hex_format! {
+    AppKey, 16, Msb;
+}
+
+fixed_len_struct_impl_hex_format! {
+    NwkSKey, 16, Msb;
+}
+
+fixed_len_struct_impl_hex_format! {
+    AppSKey, 16, Msb;
+}
+
+fixed_len_struct_impl_hex_format! {
+    GenAppKey, 16, Msb;
+}
+
+fixed_len_struct_impl_hex_format! {
+    McRootKey, 16, Msb;
+}
+
+fixed_len_struct_impl_hex_format! {
+    McKey, 16, Msb;
+}
+
+fixed_len_struct_impl_hex_format! {
+    DevEui, 8, Lsb;
+}
+
+fixed_len_struct_impl_hex_format! {
+    AppEui, 8, Lsb;
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    #[cfg(feature = "with-to-string")]
     use crate::extra::std::string::ToString;
     use core::str::FromStr;
 
+    #[cfg(feature = "with-to-string")]
     #[test]
     fn test_appskey_to_string() {
         let appskey = AppSKey::from([
@@ -185,6 +344,7 @@ mod test {
         );
     }
 
+    #[cfg(feature = "with-to-string")]
     #[test]
     fn test_deveui_to_string() {
         let deveui = DevEui::from([0xf0, 0xde, 0xbc, 0x9a, 0x78, 0x56, 0x34, 0x12]);
@@ -208,4 +368,62 @@ mod test {
         let result = DevEui::from_str("123456789abcdef000");
         assert_eq!(result, Err(FromHexError::InvalidStringLength));
     }
+
+    #[cfg(feature = "hex-format")]
+    #[test]
+    fn test_deveui_from_hex() {
+        let expected = DevEui::from([0xf0, 0xde, 0xbc, 0x9a, 0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(DevEui::from_hex("12:34:56:78:9A:BC:DE:F0", ByteOrder::Msb), Ok(expected));
+        assert_eq!(DevEui::from_hex("0x123456789abcdef0", ByteOrder::Msb), Ok(expected));
+        assert_eq!(DevEui::from_hex("f0-de-bc-9a-78-56-34-12", ByteOrder::Lsb), Ok(expected));
+        assert_eq!(
+            DevEui::from_hex("12:34:56:78:9a:bc:de", ByteOrder::Msb),
+            Err(FromHexError::InvalidStringLength)
+        );
+        assert_eq!(
+            DevEui::from_hex("12:34:56:78:9a:bc:de:f0:0", ByteOrder::Msb),
+            Err(FromHexError::InvalidStringLength)
+        );
+        assert_eq!(
+            DevEui::from_hex("12:34:56:78:9a:bc:de:f", ByteOrder::Msb),
+            Err(FromHexError::OddLength)
+        );
+        assert_eq!(
+            DevEui::from_hex("12:34:56:78:9a:bc:de:fg", ByteOrder::Msb),
+            Err(FromHexError::InvalidHexCharacter { c: 'g', index: 7 })
+        );
+    }
+
+    #[cfg(all(feature = "hex-format", feature = "with-to-string"))]
+    #[test]
+    fn test_deveui_to_hex() {
+        let deveui = DevEui::from([0xf0, 0xde, 0xbc, 0x9a, 0x78, 0x56, 0x34, 0x12]);
+        assert_eq!(deveui.to_hex(ByteOrder::Msb, None).to_string(), "123456789abcdef0");
+        assert_eq!(
+            deveui.to_hex(ByteOrder::Msb, Some(':')).uppercase().to_string(),
+            "12:34:56:78:9A:BC:DE:F0"
+        );
+        assert_eq!(deveui.to_hex(ByteOrder::Lsb, Some('-')).to_string(), "f0-de-bc-9a-78-56-34-12");
+    }
+
+    #[cfg(all(feature = "hex-format", feature = "with-to-string"))]
+    #[test]
+    fn test_appkey_hex_round_trip() {
+        let appkey = AppKey::from_hex("00112233445566778899aabbccddeeff", ByteOrder::Msb).unwrap();
+        assert_eq!(appkey, AppKey::from_str("00112233445566778899aabbccddeeff").unwrap());
+        let lsb = appkey.to_hex(ByteOrder::Lsb, None).to_string();
+        assert_eq!(lsb, "ffeeddccbbaa99887766554433221100");
+        assert_eq!(AppKey::from_hex(&lsb, ByteOrder::Lsb), Ok(appkey));
+    }
+
+    #[cfg(feature = "hex-format")]
+    #[test]
+    fn test_checked_from_slice() {
+        assert_eq!(
+            AppEui::try_from(&[1, 2, 3, 4, 5, 6, 7, 8][..]).unwrap(),
+            AppEui::from([1, 2, 3, 4, 5, 6, 7, 8])
+        );
+        assert!(AppEui::try_from(&[1, 2, 3][..]).is_err());
+        assert!(NwkSKey::try_from(&[0; 15][..]).is_err());
+    }
 }
@@ -4,6 +4,7 @@ use crate::maccommands::Error;
 
 /// ChannelMask represents the ChannelMask from LoRaWAN.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
 pub struct ChannelMask<const N: usize>([u8; N]);
 
 impl<const N: usize> Default for ChannelMask<N> {
@@ -145,6 +146,7 @@ impl<const N: usize> AsRef<[u8]> for ChannelMask<N> {
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 /// `DR` is a number from `0..=15` used to specify DataRate and TxPower
 /// fields in various MAC commands.
@@ -175,6 +177,7 @@ impl DR {
     }
 }
 
+#[allow(clippy::infallible_try_from)]
 impl TryFrom<u8> for DR {
     type Error = core::convert::Infallible;
 
@@ -202,6 +205,27 @@ impl TryFrom<u8> for DR {
     }
 }
 
+/// Device class as signaled by the `DeviceModeInd` and `DeviceModeConf` MAC commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[repr(u8)]
+pub enum DeviceClass {
+    A = 0x00,
+    C = 0x02,
+}
+
+impl TryFrom<u8> for DeviceClass {
+    type Error = Error;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        match v {
+            0x00 => Ok(DeviceClass::A),
+            0x02 => Ok(DeviceClass::C),
+            _ => Err(Error::RFU),
+        }
+    }
+}
+
 /// DataRateRange represents LoRaWAN DataRateRange.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct DataRateRange(u8);
@@ -0,0 +1,51 @@
+use lorawan::conformance::{self, Failure, CHECKS};
+use lorawan::default_crypto::DefaultFactory;
+use lorawan::keys::{CryptoFactory, Decrypter, Encrypter, AES128};
+
+#[test]
+fn default_crypto_conforms() {
+    assert_eq!(conformance::run(&DefaultFactory), Ok(()));
+}
+
+type Aes = <DefaultFactory as CryptoFactory>::E;
+
+/// AES with the directions swapped, a typical mistake when wiring up a hardware AES engine.
+struct Swapped(Aes);
+
+impl Encrypter for Swapped {
+    fn encrypt_block(&self, block: &mut [u8]) {
+        self.0.decrypt_block(block)
+    }
+}
+
+impl Decrypter for Swapped {
+    fn decrypt_block(&self, block: &mut [u8]) {
+        self.0.encrypt_block(block)
+    }
+}
+
+struct SwappedFactory;
+
+impl CryptoFactory for SwappedFactory {
+    type E = Swapped;
+    type D = Swapped;
+    type M = <DefaultFactory as CryptoFactory>::M;
+
+    fn new_enc(&self, key: &AES128) -> Self::E {
+        Swapped(DefaultFactory.new_enc(key))
+    }
+
+    fn new_dec(&self, key: &AES128) -> Self::D {
+        Swapped(DefaultFactory.new_dec(key))
+    }
+
+    fn new_mac(&self, key: &AES128) -> Self::M {
+        DefaultFactory.new_mac(key)
+    }
+}
+
+#[test]
+fn swapped_aes_is_detected() {
+    assert_eq!(conformance::join_request_mic(&SwappedFactory), Ok(()));
+    assert_eq!(conformance::run(&SwappedFactory), Err(Failure { check: CHECKS[1] }));
+}
@@ -369,6 +369,27 @@ fn test_complete_long_dataup_payload_frm_payload() {
     assert_eq!(decrypted.frm_payload(), FRMPayload::Data(&payload));
 }
 
+#[test]
+fn test_decrypt_with_session_keys() {
+    let phy = EncryptedDataPayload::new(phy_long_dataup_payload()).unwrap();
+    assert_eq!(phy.frm_payload_key(), FRMPayloadKey::AppSKey);
+    let nwk_skey = NwkSKey::from([2; 16]);
+    let app_skey = AppSKey::from([1; 16]);
+    // the MIC is not verified with the AppSKey
+    let phy = phy
+        .decrypt_with_session_keys(&NwkSKey::from([1; 16]), &app_skey, 0, &DefaultFactory)
+        .unwrap_err();
+    let decrypted =
+        phy.decrypt_with_session_keys(&nwk_skey, &app_skey, 0, &DefaultFactory).unwrap();
+    assert_eq!(decrypted.frm_payload(), FRMPayload::Data(long_data_payload().as_bytes()));
+}
+
+#[test]
+fn test_frm_payload_key_fport_zero() {
+    let phy = EncryptedDataPayload::new(data_payload_with_fport_zero()).unwrap();
+    assert_eq!(phy.frm_payload_key(), FRMPayloadKey::NwkSKey);
+}
+
 #[test]
 fn test_complete_datadown_payload_frm_payload() {
     let phy = EncryptedDataPayload::new(phy_datadown_payload()).unwrap();
@@ -399,6 +420,9 @@ fn mac_command_in_fopts() {
             _ => panic!("incorrect payload type: {:?}", cmd),
         }
     }
+    let validated: Vec<_> = fhdr.downlink_mac_commands().collect();
+    assert_eq!(validated.len(), 2);
+    assert!(validated.iter().all(|cmd| matches!(cmd, Ok(DownlinkMacCommand::LinkADRReq(_)))));
 }
 
 #[test]
@@ -501,6 +525,24 @@ fn test_data_payload_uplink_creator() {
     );
 }
 
+#[test]
+fn test_data_payload_uplink_creator_in_place() {
+    // exactly the size of the frame
+    let mut buf = [0xffu8; 18];
+    let mut phy = DataPayloadCreator::new(&mut buf).unwrap();
+    phy.set_confirmed(false)
+        .set_uplink(true)
+        .set_f_port(1)
+        .set_dev_addr(&[4, 3, 2, 1])
+        .set_fctrl(&FCtrl::new(0x80, true))
+        .set_fcnt(1);
+    let len = phy
+        .build_in_place(b"hello", [], &[2; 16].into(), &[1; 16].into(), &DefaultFactory)
+        .unwrap();
+
+    assert_eq!(&buf[..len], &phy_dataup_payload()[..]);
+}
+
 #[test]
 fn test_long_data_payload_uplink_creator() {
     let mut buf = [0u8; 256];
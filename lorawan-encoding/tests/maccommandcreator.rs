@@ -1,5 +1,6 @@
 use lorawan::maccommandcreator::*;
 use lorawan::maccommands::*;
+use lorawan::types::DeviceClass;
 
 #[test]
 fn test_link_check_req_creator() {
@@ -186,6 +187,94 @@ fn test_device_time_ans_creator() {
     assert_eq!(res, [DeviceTimeAnsPayload::cid(), 64, 226, 1, 0, 31]);
 }
 
+#[test]
+fn test_reset_ind_creator() {
+    let mut creator = ResetIndCreator::new();
+    let res = creator.set_minor(1).unwrap().build();
+    assert_eq!(res, [ResetIndPayload::cid(), 0x01]);
+    assert!(creator.set_minor(16).is_err());
+}
+
+#[test]
+fn test_reset_conf_creator() {
+    let mut creator = ResetConfCreator::new();
+    let res = creator.set_minor(1).unwrap().build();
+    assert_eq!(res, [ResetConfPayload::cid(), 0x01]);
+}
+
+#[test]
+fn test_adr_param_setup_req_creator() {
+    let mut creator = ADRParamSetupReqCreator::new();
+    let res = creator.set_limit_exp(6).unwrap().set_delay_exp(5).unwrap().build();
+    assert_eq!(res, [ADRParamSetupReqPayload::cid(), 0x65]);
+    assert!(creator.set_limit_exp(16).is_err());
+}
+
+#[test]
+fn test_adr_param_setup_ans_creator() {
+    let creator = ADRParamSetupAnsCreator::new();
+    let res = creator.build();
+    assert_eq!(res, [ADRParamSetupAnsPayload::cid()]);
+}
+
+#[test]
+fn test_device_mode_ind_creator() {
+    let mut creator = DeviceModeIndCreator::new();
+    let res = creator.set_class(DeviceClass::C).build();
+    assert_eq!(res, [DeviceModeIndPayload::cid(), 0x02]);
+}
+
+#[test]
+fn test_device_mode_conf_creator() {
+    let mut creator = DeviceModeConfCreator::new();
+    let res = creator.set_class(DeviceClass::A).build();
+    assert_eq!(res, [DeviceModeConfPayload::cid(), 0x00]);
+}
+
+#[test]
+fn test_ping_slot_channel_req_creator() {
+    let mut creator = PingSlotChannelReqCreator::new();
+    let res = creator.set_frequency(&[0x12, 0x34, 0x56]).set_data_rate(0x03).unwrap().build();
+    assert_eq!(res, [PingSlotChannelReqPayload::cid(), 0x12, 0x34, 0x56, 0x03]);
+    assert!(creator.set_data_rate(0x10).is_err());
+}
+
+#[test]
+fn test_ping_slot_channel_ans_creator() {
+    let mut creator = PingSlotChannelAnsCreator::new();
+    let res = creator.set_channel_frequency_ack(true).set_data_rate_ack(true).build();
+    assert_eq!(res, [PingSlotChannelAnsPayload::cid(), 0x03]);
+}
+
+#[test]
+fn test_beacon_freq_req_creator() {
+    let mut creator = BeaconFreqReqCreator::new();
+    let res = creator.set_frequency(&[0x12, 0x34, 0x56]).build();
+    assert_eq!(res, [BeaconFreqReqPayload::cid(), 0x12, 0x34, 0x56]);
+}
+
+#[test]
+fn test_beacon_freq_ans_creator() {
+    let mut creator = BeaconFreqAnsCreator::new();
+    let res = creator.set_beacon_frequency_ack(true).build();
+    assert_eq!(res, [BeaconFreqAnsPayload::cid(), 0x01]);
+}
+
+#[test]
+fn test_pack_mac_commands() {
+    let link_adr_ans = LinkADRAnsCreator::new();
+    let dev_status_ans = DevStatusAnsCreator::new();
+    let cmds: Vec<&dyn SerializableMacCommand> =
+        vec![&link_adr_ans, &dev_status_ans, &link_adr_ans];
+    let mut out = [0; 6];
+    // the second LinkADRAns doesn't fit
+    assert_eq!(pack_mac_commands(&cmds, &mut out), (5, 2));
+    assert_eq!(out[..5], [0x03, 0x00, 0x06, 0x00, 0x00]);
+    let mut out = [0; 7];
+    assert_eq!(pack_mac_commands(&cmds, &mut out), (7, 3));
+    assert_eq!(pack_mac_commands(&cmds, &mut out[..1]), (0, 0));
+}
+
 #[test]
 fn test_build_mac_commands() {
     let rx_timing_setup_req =
@@ -1,6 +1,6 @@
 use lorawan::maccommandcreator::*;
 use lorawan::maccommands::*;
-use lorawan::types::{DLSettings, DataRateRange, Frequency, Redundancy, DR};
+use lorawan::types::{DLSettings, DataRateRange, DeviceClass, Frequency, Redundancy, DR};
 
 macro_rules! test_helper {
     ( $cmd:ident, $data:ident, $name:ident, $type:ident, $size:expr, $( ( $method:ident, $val:expr ) ,)*) => {{
@@ -244,6 +244,123 @@ fn test_dl_channel_ans() {
     );
 }
 
+#[test]
+fn test_reset_ind() {
+    let data = [0x01];
+    test_helper!(UplinkMacCommand, data, ResetInd, ResetIndPayload, 1, (minor, 1),);
+}
+
+#[test]
+fn test_reset_conf() {
+    let data = [0x01];
+    test_helper!(DownlinkMacCommand, data, ResetConf, ResetConfPayload, 1, (minor, 1),);
+}
+
+#[test]
+fn test_adr_param_setup_req() {
+    let data = [0x65];
+    test_helper!(
+        DownlinkMacCommand,
+        data,
+        ADRParamSetupReq,
+        ADRParamSetupReqPayload,
+        1,
+        (limit_exp, 6),
+        (delay_exp, 5),
+        (adr_ack_limit, 64),
+        (adr_ack_delay, 32),
+    );
+}
+
+#[test]
+fn test_adr_param_setup_ans() {
+    test_helper!(UplinkMacCommand, ADRParamSetupAns, ADRParamSetupAnsPayload);
+}
+
+#[test]
+fn test_device_mode_ind() {
+    let data = [0x02];
+    test_helper!(
+        UplinkMacCommand,
+        data,
+        DeviceModeInd,
+        DeviceModeIndPayload,
+        1,
+        (class, Ok(DeviceClass::C)),
+    );
+    assert_eq!(
+        DeviceModeIndPayload::new(&[0x01]).unwrap().class(),
+        Err(lorawan::maccommands::Error::RFU)
+    );
+}
+
+#[test]
+fn test_device_mode_conf() {
+    let data = [0x00];
+    test_helper!(
+        DownlinkMacCommand,
+        data,
+        DeviceModeConf,
+        DeviceModeConfPayload,
+        1,
+        (class, Ok(DeviceClass::A)),
+    );
+}
+
+#[test]
+fn test_ping_slot_channel_req() {
+    let data = [0x12, 0x34, 0x56, 0x03];
+    test_helper!(
+        DownlinkMacCommand,
+        data,
+        PingSlotChannelReq,
+        PingSlotChannelReqPayload,
+        4,
+        (frequency, Frequency::new_from_raw(&data[0..3])),
+        (data_rate, DR::_3),
+    );
+}
+
+#[test]
+fn test_ping_slot_channel_ans() {
+    let data = [0x02];
+    test_helper!(
+        UplinkMacCommand,
+        data,
+        PingSlotChannelAns,
+        PingSlotChannelAnsPayload,
+        1,
+        (channel_freq_ack, false),
+        (data_rate_ack, true),
+    );
+}
+
+#[test]
+fn test_beacon_freq_req() {
+    let data = [0x12, 0x34, 0x56];
+    test_helper!(
+        DownlinkMacCommand,
+        data,
+        BeaconFreqReq,
+        BeaconFreqReqPayload,
+        3,
+        (frequency, Frequency::new_from_raw(&data[..])),
+    );
+}
+
+#[test]
+fn test_beacon_freq_ans() {
+    let data = [0x01];
+    test_helper!(
+        UplinkMacCommand,
+        data,
+        BeaconFreqAns,
+        BeaconFreqAnsPayload,
+        1,
+        (beacon_freq_ack, true),
+    );
+}
+
 #[test]
 fn test_parse_mac_commands_empty_downlink() {
     assert_eq!(parse_downlink_mac_commands(&[]).count(), 0);
@@ -297,6 +414,30 @@ fn test_parse_mac_commands_with_multiple_cmds_with_payloads() {
     );
 }
 
+#[test]
+fn test_parse_mac_commands_validated() {
+    // LinkADRReq, DevStatusReq, then an unknown CID
+    let data = [3, 0, 0, 0, 112, 6, 0xff, 1];
+    let mut commands = parse_downlink_mac_commands_validated(&data);
+    assert_eq!(
+        commands.next(),
+        Some(Ok(DownlinkMacCommand::LinkADRReq(LinkADRReqPayload::new(&[0, 0, 0, 112]).unwrap())))
+    );
+    assert_eq!(commands.next(), Some(Ok(DownlinkMacCommand::DevStatusReq(DevStatusReqPayload()))));
+    assert_eq!(
+        commands.next(),
+        Some(Err(MalformedMacCommand::UnknownCid { offset: 6, cid: 0xff }))
+    );
+    assert_eq!(commands.next(), None);
+
+    // NewChannelReq lacking a byte
+    let data = [7, 1, 2, 3, 4];
+    let mut commands = parse_downlink_mac_commands_validated(&data);
+    assert_eq!(commands.next(), Some(Err(MalformedMacCommand::Truncated { offset: 0, cid: 7 })));
+    assert_eq!(commands.next(), None);
+    assert_eq!(parse_downlink_mac_commands_validated(&[]).count(), 0);
+}
+
 fn mac_cmds_payload() -> Vec<u8> {
     vec![LinkCheckReqPayload::cid(), LinkADRAnsPayload::cid(), 0x00]
 }
@@ -38,6 +38,7 @@ pub fn derive_command_handler(input: proc_macro::TokenStream) -> proc_macro::Tok
     let mut impl_len = Vec::new();
     let mut impl_bytes = Vec::new();
     let mut impl_cid = Vec::new();
+    let mut known_cids = Vec::new();
     let mut impl_iter_next = Vec::new();
     let mut payload_struct_impls = Vec::new();
     let mut payload_struct_creator_impls = Vec::new();
@@ -69,6 +70,7 @@ pub fn derive_command_handler(input: proc_macro::TokenStream) -> proc_macro::Tok
         impl_cid.push(quote! {
             Self::#n(_) => #t::cid()
         });
+        known_cids.push(quote! { #t::cid() });
 
         // SerializableMacCommand::next()
         // Different iterator implementation for fixed and variable length
@@ -290,6 +292,10 @@ pub fn derive_command_handler(input: proc_macro::TokenStream) -> proc_macro::Tok
                     #( #impl_bytes, )*
                 }
             }
+            /// Whether the CID identifies one of the commands.
+            pub fn is_known_cid(cid: u8) -> bool {
+                #( cid == #known_cids )||*
+            }
         }
 
         impl #handler_lt SerializableMacCommand for #handler #handler_lt {
@@ -427,7 +433,7 @@ fn attr_handle_cmd(attr: &syn::Attribute) -> Option<(syn::Expr, Option<syn::Expr
                                 len = Some(v.value);
                             }
                             &_ => {
-                                panic!("Invalid argument: {}", &id);
+                                panic!("Invalid argument: {}", id);
                             }
                         }
                     } else {